@@ -267,7 +267,7 @@ export class Topic {
   publish(payload: any): void;
   publishAsync(payload: any): Promise<any>;
   publishBatch(payloads: Array<any>): void;
-  subscribe(callback: Function): number;
+  subscribe<T = unknown>(callback: MessageCallback<T>): number;
   unsubscribe(sub_id: number): boolean;
   unsubscribeAll(): number;
   
@@ -279,7 +279,7 @@ export class Topic {
   enableBuffer(capacity?: number): void;
   disableBuffer(): void;
   clearBuffer(): number;
-  getBufferedMessages(): Array<any>;
+  getBufferedMessages<T = unknown>(): RippleMessage<T>[];
   destroy(): boolean;
 }
 `;
@@ -328,9 +328,9 @@ if (fs.existsSync(dtsFile)) {
                 // Since MessageQueue is likely the last class or we search for the specific closing brace?
                 // A safer way is to replace the end of MessageQueue definition
                 // But simplified: search for last '}' in file might be risky if there are other exports after
-                // Let's find "subscribe(topic_id: number, callback: Function): number;" and insert after it
+                // Let's find the MessageQueue interface of the custom TypeScript section and insert into it
                 
-                const subMethod = 'subscribe(topic_id: number, callback: Function): number;';
+                const subMethod = 'export interface MessageQueue {';
                 const subIdx = content.indexOf(subMethod);
                 if (subIdx !== -1) {
                     const insertPos = subIdx + subMethod.length;
@@ -338,7 +338,7 @@ if (fs.existsSync(dtsFile)) {
                     fs.writeFileSync(dtsFile, content);
                     console.log('Updated wasm_ripple.d.ts with Topic definitions');
                 } else {
-                    console.error('Could not find subscribe method in wasm_ripple.d.ts');
+                    console.error('Could not find MessageQueue interface in wasm_ripple.d.ts');
                 }
             }
         } else {
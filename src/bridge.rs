@@ -0,0 +1,177 @@
+use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use js_sys::{Function, Reflect};
+use web_sys::{Event, MessageEvent, WebSocket};
+
+use crate::types::{InnerQueue, Subscriber};
+use crate::constants::BRIDGE_ORIGIN_PREFIX;
+use crate::channel::broadcast_message;
+use crate::dispatch::{deliver, report_error};
+use crate::js_utils::{bridge_frame, message_to_js, parse_js_message};
+use crate::queue::notify_backpressure;
+use crate::utils::set_timeout;
+
+/// A WebSocket forwarding local topics to a server and injecting its frames
+pub struct WebSocketBridge {
+    ws: WebSocket,
+    /// Internal raw subscribers forwarding messages, as (topic_id, sub_id)
+    subscriptions: Vec<(u32, u32)>,
+    /// Number of frames sent to the server
+    forwarded: Rc<Cell<u32>>,
+    forward: Closure<dyn FnMut(JsValue)>,
+    on_message: Closure<dyn FnMut(MessageEvent)>,
+    on_close: Closure<dyn FnMut(Event)>,
+}
+
+/// Start forwarding `topic_ids` over `ws` and publishing its inbound text frames
+/// Inbound messages are tagged with a `bridge:` origin, which no bridge forwards,
+/// so messages never loop between the server and the tabs.
+pub(crate) fn attach(inner: &Rc<RefCell<InnerQueue>>, ws: WebSocket, topic_ids: Vec<u32>, rebroadcast: bool) -> Result<(), JsValue> {
+    {
+        let queue = inner.borrow();
+        if queue.bridge.is_some() {
+            return Err(JsValue::from_str("WebSocket bridge already active"));
+        }
+        if topic_ids.iter().any(|&topic_id| !queue.has_topic_id(topic_id)) {
+            return Err(JsValue::from_str("Invalid topic ID"));
+        }
+    }
+
+    let origin = Rc::new(format!("{}{}", BRIDGE_ORIGIN_PREFIX, ws.url()));
+    let forwarded = Rc::new(Cell::new(0));
+    let weak = Rc::downgrade(inner);
+
+    // Raw subscribers receive {payload, topic_id, topic, timestamp, id, origin_id}
+    let forward = {
+        let ws = ws.clone();
+        let forwarded = forwarded.clone();
+        let weak = weak.clone();
+        Closure::wrap(Box::new(move |msg: JsValue| {
+            let from_bridge = Reflect::get(&msg, &"origin_id".into()).ok()
+                .and_then(|origin| origin.as_string())
+                .is_some_and(|origin| origin.starts_with(BRIDGE_ORIGIN_PREFIX));
+            if from_bridge {
+                return;
+            }
+            match bridge_frame(&msg) {
+                Ok(frame) => {
+                    if ws.send_with_str(&frame).is_ok() {
+                        forwarded.set(forwarded.get() + 1);
+                    }
+                }
+                Err(err) => {
+                    if let Some(inner) = weak.upgrade() {
+                        let topic_id = Reflect::get(&msg, &"topic_id".into()).ok()
+                            .and_then(|id| id.as_f64())
+                            .map(|id| id as u32);
+                        report_error(&inner, &err, topic_id, None, None);
+                    }
+                }
+            }
+        }) as Box<dyn FnMut(JsValue)>)
+    };
+
+    let on_message = {
+        let weak = weak.clone();
+        let origin = origin.clone();
+        Closure::wrap(Box::new(move |event: MessageEvent| {
+            let Some(inner) = weak.upgrade() else {
+                return;
+            };
+            let result = match event.data().as_string() {
+                Some(text) => receive_frame(&inner, &text, &origin, rebroadcast),
+                None => Err(JsValue::from_str("Binary WebSocket frames are not supported")),
+            };
+            if let Err(err) = result {
+                report_error(&inner, &err, None, None, None);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>)
+    };
+
+    let on_close = Closure::wrap(Box::new(move |_event: Event| {
+        if let Some(inner) = weak.upgrade() {
+            detach(&inner);
+        }
+    }) as Box<dyn FnMut(Event)>);
+
+    ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    ws.set_onerror(Some(on_close.as_ref().unchecked_ref()));
+
+    let mut queue = inner.borrow_mut();
+    let callback: &Function = forward.as_ref().unchecked_ref();
+    let subscriptions = topic_ids.into_iter()
+        .filter_map(|topic_id| {
+            let mut subscriber = Subscriber::new(callback.clone());
+            subscriber.raw = true;
+            let topic = queue.get_topic_by_id_mut(topic_id)?;
+            Some((topic_id, topic.add_subscriber(subscriber)))
+        })
+        .collect();
+
+    queue.bridge = Some(WebSocketBridge { ws, subscriptions, forwarded, forward, on_message, on_close });
+    Ok(())
+}
+
+/// Stop the bridge, leaving the socket open
+/// Returns the number of messages forwarded, or 0 without a bridge.
+pub(crate) fn detach(inner: &Rc<RefCell<InnerQueue>>) -> u32 {
+    let Some(bridge) = inner.borrow_mut().take_bridge() else {
+        return 0;
+    };
+    let forwarded = bridge.forwarded.get();
+
+    // Detaching may run inside one of the bridge's own handlers (e.g. onclose),
+    // so its closures are dropped once the current task has finished
+    let drop_later = Closure::once_into_js(move || drop(bridge));
+    let _ = set_timeout(drop_later.unchecked_ref(), 0.0);
+    forwarded
+}
+
+impl InnerQueue {
+    /// Remove the bridge's subscribers and socket handlers and hand it over for dropping
+    pub fn take_bridge(&mut self) -> Option<WebSocketBridge> {
+        let bridge = self.bridge.take()?;
+        for &(topic_id, sub_id) in &bridge.subscriptions {
+            if let Some(topic) = self.get_topic_by_id_mut(topic_id) {
+                topic.subscribers.remove(sub_id);
+            }
+        }
+        bridge.ws.set_onmessage(None);
+        bridge.ws.set_onclose(None);
+        bridge.ws.set_onerror(None);
+        Some(bridge)
+    }
+}
+
+/// Publish a message received as a JSON text frame
+/// Frames use the broadcast message format, with the ID as a number or string.
+fn receive_frame(inner: &Rc<RefCell<InnerQueue>>, text: &str, origin: &Rc<String>, rebroadcast: bool) -> Result<(), JsValue> {
+    let value = js_sys::JSON::parse(text).map_err(|_| JsValue::from_str("Invalid WebSocket frame"))?;
+    let (mut message, topic_name) = parse_js_message(&value)?;
+
+    let (topic_id, delivery, raw_msg) = {
+        let mut queue = inner.borrow_mut();
+        if !queue.seen_ids.insert(message.id) {
+            return Ok(());
+        }
+        message.topic_id = queue.get_or_create_valid_topic_id(&topic_name)?;
+        message.origin_id = origin.clone();
+        let message = Rc::new(message);
+        queue.record_stat(message.topic_id, |stats| stats.received += 1);
+
+        let delivery = queue.dispatch_local(&message);
+        let raw_msg = if rebroadcast && queue.broadcasts_topic(message.topic_id) {
+            Some(message_to_js(&message, &topic_name)?)
+        } else {
+            None
+        };
+        (message.topic_id, delivery, raw_msg)
+    };
+
+    let broadcast = raw_msg.map_or(Ok(()), |raw_msg| broadcast_message(inner, topic_id, raw_msg));
+    deliver(inner, delivery);
+    notify_backpressure(inner);
+    broadcast
+}
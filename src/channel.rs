@@ -0,0 +1,513 @@
+use wasm_bindgen::prelude::*;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use js_sys::{Array, Function, Promise, Uint8Array};
+
+use crate::types::{InnerQueue, Message, PendingSync, PendingSyncAnswer};
+use crate::constants::{PACKET_BATCH, PACKET_GOODBYE, PACKET_LEADER, PACKET_MIGRATE, PACKET_PRESENCE, PACKET_PUB, PACKET_RPC_REPLY, PACKET_RPC_REQUEST, PACKET_SYNC_REQ, PACKET_SYNC_RESP, PACKET_TOPIC_BATCH, SYNC_JITTER_MS};
+use crate::js_utils::{mark_retained, message_to_js};
+use crate::queue::{notify_backpressure, notify_gaps};
+use crate::dispatch::{deliver, notify_topic_events, report_receive_errors};
+use crate::utils::{clear_timeout, set_timeout};
+use crate::leader;
+use crate::presence;
+use crate::rpc;
+
+/// Handle a packet received on the BroadcastChannel
+/// `source` is the channel or port it arrived on (the event's `currentTarget`),
+/// undefined for localStorage; SYNC_REQs are answered there.
+pub(crate) fn handle_channel_message(inner: &Rc<RefCell<InnerQueue>>, data: JsValue, source: JsValue) {
+    let mut queue = inner.borrow_mut();
+    let mut deliveries = Vec::new();
+    let mut rpc_tasks = Vec::new();
+    let mut peer_changes = Vec::new();
+    let mut leader_tasks = Vec::new();
+    let mut closed_peers = Vec::new();
+
+    if data.is_array() {
+        let arr = Array::from(&data);
+        if let Some(type_val) = arr.get(0).as_f64() {
+            #[cfg(feature = "wasm-logging")]
+            if queue.logs_at(crate::constants::LOG_INFO) {
+                crate::logging::log_group(crate::constants::LOG_INFO, "BroadcastChannel packet received", &[
+                    ("type", JsValue::from(type_val)),
+                    ("channel", queue.channel_name.as_deref().map(JsValue::from_str).unwrap_or(JsValue::NULL)),
+                ]);
+            }
+
+            match type_val as u8 {
+                PACKET_PUB => { // PUB: [0, msg]
+                    deliveries.extend(queue.receive_broadcast(arr.get(1)));
+                },
+                PACKET_SYNC_REQ => { // SYNC_REQ: [1, origin_id, topic_names?, since_ts?]
+                    let origin_id = arr.get(1).as_string().unwrap_or_default();
+                    if origin_id != *queue.client_id {
+                        let topic_names = arr.get(2).dyn_into::<Array>().ok()
+                            .map(|names| names.iter().filter_map(|name| name.as_string()).collect::<Vec<_>>());
+                        let since = arr.get(3).as_f64();
+                        if queue.sync_respond_always {
+                            answer_sync_request(&queue, &source, &origin_id, topic_names.as_deref(), since);
+                        } else {
+                            schedule_sync_answer(inner, &mut queue, source, origin_id, topic_names, since);
+                        }
+                    }
+                },
+                PACKET_SYNC_RESP => { // SYNC_RESP: [2, requester_id, [msg1, msg2...], truncated?]
+                    // Older versions omit the requester ID
+                    let (requester_id, offset) = match arr.get(1).as_string() {
+                        Some(requester_id) => (Some(requester_id), 1),
+                        None => (None, 0),
+                    };
+                    // Another tab answered: drop our own pending answer to the same requester
+                    if let Some(requester_id) = &requester_id {
+                        queue.pending_sync_answers.retain(|_, answer| {
+                            let answered = answer.requester_id == *requester_id;
+                            if answered {
+                                clear_timeout(answer.timeout);
+                            }
+                            !answered
+                        });
+                    }
+
+                    let before = synced_counts(&queue);
+                    let msgs = Array::from(&arr.get(1 + offset));
+                    for i in 0..msgs.length() {
+                        deliveries.extend(queue.receive_broadcast(msgs.get(i)));
+                    }
+                    let after = synced_counts(&queue);
+                    for (sync, (before, after)) in queue.pending_syncs.iter_mut().zip(before.into_iter().zip(after)) {
+                        sync.received += after.saturating_sub(before);
+                        sync.extended = true;
+                    }
+
+                    // Ask for the rest of a truncated incremental response
+                    if arr.get(2 + offset).is_truthy() {
+                        request_next_page(&mut queue, &msgs);
+                    }
+                },
+                PACKET_BATCH => { // BATCH: [5, [msg1, msg2...]]
+                    let msgs = Array::from(&arr.get(1));
+                    for i in 0..msgs.length() {
+                        deliveries.extend(queue.receive_broadcast(msgs.get(i)));
+                    }
+                },
+                PACKET_TOPIC_BATCH => { // TOPIC_BATCH: [3, topic_name, [msg1, msg2...]]
+                    let msgs = Array::from(&arr.get(2));
+                    for i in 0..msgs.length() {
+                        deliveries.extend(queue.receive_broadcast(msgs.get(i)));
+                    }
+                },
+                PACKET_PRESENCE => { // PRESENCE: [4, client_id, kind]
+                    peer_changes.extend(presence::receive(&mut queue, &arr));
+                },
+                PACKET_LEADER => { // LEADER: [9, client_id, kind]
+                    leader_tasks.extend(leader::receive(&mut queue, &arr));
+                },
+                PACKET_GOODBYE => { // GOODBYE: [10, client_id]
+                    closed_peers.extend(presence::receive_goodbye(&mut queue, &arr));
+                },
+                PACKET_MIGRATE => { // MIGRATE: [6, old_name, new_name]
+                    if let (Some(old_name), Some(new_name)) = (arr.get(1).as_string(), arr.get(2).as_string()) {
+                        // Like other names announced by peers, invalid names are dropped
+                        if queue.validate_topic_name(&new_name).is_ok() {
+                            queue.rename_topic(&old_name, &new_name);
+                        }
+                    }
+                },
+                PACKET_RPC_REQUEST => {
+                    rpc_tasks.extend(rpc::receive_request(&queue, &arr));
+                },
+                PACKET_RPC_REPLY => {
+                    rpc_tasks.extend(rpc::receive_reply(&queue, &arr));
+                },
+                _ => {}
+            }
+        }
+    } else if data.is_instance_of::<Uint8Array>() {
+        // Binary PUB packet (see `wire`)
+        deliveries.extend(queue.receive_broadcast(data));
+    } else if data.is_object() {
+        // Fallback for backward compatibility
+        deliveries.extend(queue.receive_broadcast(data));
+    }
+
+    drop(queue);
+    deliver(inner, deliveries);
+    // Topics created by sync responses, which deliver nothing
+    notify_topic_events(inner);
+    report_receive_errors(inner);
+    notify_gaps(inner);
+    for task in rpc_tasks {
+        rpc::run_task(inner, task);
+    }
+    for (peer_id, orphaned) in closed_peers {
+        presence::notify_closed(inner, &peer_id, orphaned);
+    }
+    presence::notify(inner, peer_changes);
+    for task in leader_tasks {
+        leader::run_task(inner, task);
+    }
+    notify_backpressure(inner);
+}
+
+/// Answer a SYNC_REQ after a random delay of up to `SYNC_JITTER_MS`
+/// The answer is dropped if another tab answers the same requester first.
+fn schedule_sync_answer(
+    inner: &Rc<RefCell<InnerQueue>>,
+    queue: &mut InnerQueue,
+    source: JsValue,
+    requester_id: String,
+    topic_names: Option<Vec<String>>,
+    since: Option<f64>,
+) {
+    let id = queue.next_message_id();
+    let weak = Rc::downgrade(inner);
+    let requester = requester_id.clone();
+    let answer = Closure::once_into_js(move || {
+        if let Some(inner) = weak.upgrade() {
+            let mut queue = inner.borrow_mut();
+            if queue.pending_sync_answers.remove(&id).is_some() {
+                answer_sync_request(&queue, &source, &requester, topic_names.as_deref(), since);
+            }
+        }
+    });
+
+    let delay = js_sys::Math::random() * SYNC_JITTER_MS;
+    if let Ok(timeout) = set_timeout(answer.unchecked_ref(), delay) {
+        queue.pending_sync_answers.insert(id, PendingSyncAnswer { requester_id, timeout });
+    }
+}
+
+/// Send the messages answering a SYNC_REQ in a SYNC_RESP packet
+/// The packet goes back on `source`, the transport the request arrived on.
+fn answer_sync_request(queue: &InnerQueue, source: &JsValue, requester_id: &str, topic_names: Option<&[String]>, since: Option<f64>) {
+    let (msgs, truncated) = sync_response(queue, topic_names, since, js_sys::Date::now());
+    if msgs.is_empty() {
+        return;
+    }
+
+    let all_msgs = Array::new();
+    for (msg, topic_name, retained) in &msgs {
+        if let Ok(msg_js) = message_to_js(msg, topic_name) {
+            if !retained || mark_retained(&msg_js).is_ok() {
+                all_msgs.push(&msg_js);
+            }
+        }
+    }
+
+    let resp = Array::new();
+    resp.push(&JsValue::from(PACKET_SYNC_RESP));
+    resp.push(&JsValue::from_str(requester_id));
+    resp.push(&all_msgs);
+    if truncated {
+        resp.push(&JsValue::TRUE);
+    }
+    let _ = queue.post_reply(&resp, source);
+}
+
+/// Collect the unexpired buffered and retained messages answering a SYNC_REQ
+/// Returns (message, topic name, retained) entries and whether any were left out.
+/// With `topic_names`, only those topics are included; each topic's messages come
+/// oldest first. An incremental request (with `since`) only gets messages newer
+/// than `since`, sorted oldest first across topics and capped at the queue's
+/// sync response limit.
+pub(crate) fn sync_response(
+    queue: &InnerQueue,
+    topic_names: Option<&[String]>,
+    since: Option<f64>,
+    now: f64,
+) -> (Vec<(Rc<Message>, String, bool)>, bool) {
+    let mut msgs = Vec::new();
+    let requested = |name: &String| topic_names.is_none_or(|names| names.contains(name));
+    for topic in queue.topics.iter().filter(|topic| !topic.local_only && requested(&topic.name)) {
+        if let Some(buffer) = topic.get_buffer() {
+            msgs.extend(buffer.iter()
+                .filter(|msg| !topic.is_stale_at(msg, now))
+                .map(|msg| (msg.clone(), topic.name.clone(), false)));
+        }
+
+        // Sent after the buffer, so the receiver keeps it even if already seen
+        if let Some(msg) = topic.retained.as_ref().filter(|msg| !msg.is_expired_at(now)) {
+            msgs.push((msg.clone(), topic.name.clone(), true));
+        }
+    }
+
+    let Some(since) = since else {
+        return (msgs, false);
+    };
+    msgs.retain(|(msg, _, _)| msg.timestamp > since);
+    // Stable, so a retained copy still follows its buffered original
+    msgs.sort_by(|(a, _, _), (b, _, _)| a.timestamp.total_cmp(&b.timestamp));
+
+    let limit = queue.sync_response_limit.max(1);
+    if msgs.len() <= limit {
+        return (msgs, false);
+    }
+    // A page never ends inside a run of equal timestamps: the follow-up request
+    // asks for strictly newer messages and would skip the rest of the run
+    let last_timestamp = msgs[limit - 1].0.timestamp;
+    let end = msgs.iter().position(|(msg, _, _)| msg.timestamp > last_timestamp).unwrap_or(msgs.len());
+    let truncated = end < msgs.len();
+    msgs.truncate(end);
+    (msgs, truncated)
+}
+
+/// Request the messages following a truncated SYNC_RESP
+/// Pages end between timestamps, so the last timestamp received is the cursor.
+fn request_next_page(queue: &mut InnerQueue, msgs: &Array) {
+    let last_timestamp = js_sys::Reflect::get(&msgs.get(msgs.length().saturating_sub(1)), &"timestamp".into())
+        .ok()
+        .and_then(|timestamp| timestamp.as_f64());
+    let Some(last_timestamp) = last_timestamp else {
+        return;
+    };
+    let mut waiting = queue.pending_syncs.iter_mut().filter(|sync| sync.incremental).peekable();
+    if waiting.peek().is_none() {
+        return;
+    }
+    waiting.for_each(|sync| sync.extended = true);
+
+    let _ = queue.post_packet(&sync_request(&queue.client_id, None, Some(last_timestamp)));
+}
+
+/// Build a SYNC_REQ packet: [1, client_id, topic_names?, since_ts?]
+fn sync_request(client_id: &str, topic_names: Option<&[String]>, since: Option<f64>) -> Array {
+    let req = Array::new();
+    req.push(&JsValue::from(PACKET_SYNC_REQ));
+    req.push(&JsValue::from_str(client_id));
+    if topic_names.is_some() || since.is_some() {
+        let names = topic_names.map_or(JsValue::NULL, |names| {
+            names.iter().map(|name| JsValue::from_str(name)).collect::<Array>().into()
+        });
+        req.push(&names);
+    }
+    if let Some(since) = since {
+        req.push(&JsValue::from(since));
+    }
+    req
+}
+
+/// Ask other tabs for buffered and retained messages
+/// `topic_names` limits the request to those topics; `since` to messages newer
+/// than that timestamp. Resolves once no response arrived for `quiet_ms`
+/// with the number of new messages of the requested topics received meanwhile.
+pub(crate) fn request_sync(inner: &Rc<RefCell<InnerQueue>>, topic_names: Option<Vec<String>>, since: Option<f64>, quiet_ms: f64) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        if let Err(err) = start_sync(inner, topic_names.clone(), since, quiet_ms, resolve) {
+            let _ = reject.call1(&JsValue::NULL, &err);
+        }
+    })
+}
+
+fn start_sync(inner: &Rc<RefCell<InnerQueue>>, topic_names: Option<Vec<String>>, since: Option<f64>, quiet_ms: f64, resolve: Function) -> Result<(), JsValue> {
+    let mut queue = inner.borrow_mut();
+    if topic_names.as_ref().is_some_and(Vec::is_empty) || queue.transports.is_empty() {
+        resolve.call1(&JsValue::NULL, &JsValue::from(0))?;
+        return Ok(());
+    }
+
+    queue.post_packet(&sync_request(&queue.client_id, topic_names.as_deref(), since))?;
+    let id = queue.next_message_id();
+    queue.pending_syncs.push(PendingSync {
+        id,
+        topics: topic_names,
+        incremental: since.is_some(),
+        received: 0,
+        extended: false,
+    });
+    drop(queue);
+
+    schedule_sync_settle(Rc::downgrade(inner), id, quiet_ms, resolve)
+}
+
+/// Resolve a pending sync after `quiet_ms`, waiting again while responses keep arriving
+fn schedule_sync_settle(inner: Weak<RefCell<InnerQueue>>, id: u64, quiet_ms: f64, resolve: Function) -> Result<(), JsValue> {
+    let on_settled = Closure::once_into_js(move || {
+        let Some(strong) = inner.upgrade() else {
+            let _ = resolve.call1(&JsValue::NULL, &JsValue::from(0));
+            return;
+        };
+        let mut queue = strong.borrow_mut();
+        let Some(index) = queue.pending_syncs.iter().position(|sync| sync.id == id) else {
+            return;
+        };
+        if std::mem::take(&mut queue.pending_syncs[index].extended) {
+            drop(queue);
+            if schedule_sync_settle(inner.clone(), id, quiet_ms, resolve.clone()).is_ok() {
+                return;
+            }
+            queue = strong.borrow_mut();
+        }
+        let received = queue.pending_syncs.remove(index).received;
+        drop(queue);
+        let _ = resolve.call1(&JsValue::NULL, &JsValue::from(received as f64));
+    });
+    set_timeout(on_settled.unchecked_ref(), quiet_ms)?;
+    Ok(())
+}
+
+/// Messages received so far on the requested topics of each pending sync
+fn synced_counts(queue: &InnerQueue) -> Vec<u64> {
+    queue.pending_syncs.iter()
+        .map(|sync| match &sync.topics {
+            None => queue.stats.received,
+            Some(topics) => topics.iter()
+                .filter_map(|name| queue.topic_id_by_name(name))
+                .filter_map(|topic_id| queue.get_topic_by_id(topic_id))
+                .map(|topic| topic.stats.received)
+                .sum(),
+        })
+        .collect()
+}
+
+/// Broadcast a message object or binary packet of a topic to other tabs
+/// The message goes out on the topic's routed channel, or on every primary
+/// transport. With a broadcast throttle set, it is queued and flushed in a batch.
+pub(crate) fn broadcast_message(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, raw_msg: JsValue) -> Result<(), JsValue> {
+    let mut queue = inner.borrow_mut();
+    let route = queue.topic_route(topic_id);
+
+    if queue.broadcast_throttle_ms > 0.0 {
+        queue.pending_broadcasts.push((route, raw_msg));
+        if !queue.broadcast_flush_scheduled {
+            queue.broadcast_flush_scheduled = true;
+            let delay = queue.broadcast_throttle_ms;
+            drop(queue);
+            schedule_broadcast_flush(inner, delay)?;
+        }
+        return Ok(());
+    }
+
+    // Binary messages already are a complete PUB packet
+    if raw_msg.is_instance_of::<Uint8Array>() {
+        return queue.post_routed(route, &raw_msg);
+    }
+
+    // Wrap in packet [0, msg] for protocol
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_PUB));
+    packet.push(&raw_msg);
+    queue.post_routed(route, &packet)
+}
+
+/// Post a message object immediately, transferring `transfer_list` if the transport allows it
+pub(crate) fn broadcast_transfer(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, raw_msg: JsValue, transfer_list: &Array) -> Result<(), JsValue> {
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_PUB));
+    packet.push(&raw_msg);
+    let queue = inner.borrow();
+    match queue.topic_route(topic_id).and_then(|handle| queue.channel(handle)) {
+        Some(channel) => channel.post_with_transfer(&packet, transfer_list),
+        None => queue.post_packet_with_transfer(&packet, transfer_list),
+    }
+}
+
+/// Broadcast the message objects of a batch, as (topic ID, message) pairs
+/// `topic_name` is set if all messages belong to that topic, which are then sent
+/// in a single TOPIC_BATCH packet; otherwise one BATCH packet goes to each channel
+/// the messages are routed to. With a broadcast throttle set, the messages join
+/// the throttled batch instead.
+pub(crate) fn broadcast_batch(inner: &Rc<RefCell<InnerQueue>>, topic_name: Option<&str>, msgs: Vec<(u32, JsValue)>) -> Result<(), JsValue> {
+    if msgs.is_empty() {
+        return Ok(());
+    }
+
+    if inner.borrow().broadcast_throttle_ms > 0.0 {
+        for (topic_id, msg) in msgs {
+            broadcast_message(inner, topic_id, msg)?;
+        }
+        return Ok(());
+    }
+
+    let queue = inner.borrow();
+    let Some(topic_name) = topic_name else {
+        return queue.post_batch(msgs.into_iter().map(|(topic_id, msg)| (queue.topic_route(topic_id), msg)));
+    };
+
+    // TOPIC_BATCH: [3, topic_name, [msg1, msg2, ...]]
+    let route = queue.topic_route(msgs[0].0);
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_TOPIC_BATCH));
+    packet.push(&JsValue::from_str(topic_name));
+    packet.push(&msgs.into_iter().map(|(_, msg)| msg).collect::<Array>());
+    queue.post_routed(route, &packet)
+}
+
+/// Flush throttled broadcasts after `delay` milliseconds
+fn schedule_broadcast_flush(inner: &Rc<RefCell<InnerQueue>>, delay: f64) -> Result<(), JsValue> {
+    let weak = Rc::downgrade(inner);
+    let callback = Closure::once_into_js(move || {
+        if let Some(inner) = weak.upgrade() {
+            if let Ok(mut queue) = inner.try_borrow_mut() {
+                let _ = queue.flush_broadcasts();
+            }
+        }
+    });
+
+    set_timeout(callback.unchecked_ref(), delay)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_support::{buffered_topic, msg, msg_at};
+
+    fn ids(msgs: &[(Rc<Message>, String, bool)]) -> Vec<u64> {
+        msgs.iter().map(|(msg, _, _)| msg.id).collect()
+    }
+
+    #[test]
+    fn test_incremental_sync_response() {
+        let mut queue = InnerQueue { sync_response_limit: 3, ..Default::default() };
+        let prices = buffered_topic(&mut queue, "prices", 8);
+        let alerts = buffered_topic(&mut queue, "alerts", 8);
+        for (id, topic_id, timestamp) in [(1, prices, 100.0), (2, prices, 300.0), (3, alerts, 200.0), (4, alerts, 300.0), (5, alerts, 400.0)] {
+            queue.buffer_message(&msg_at(id, topic_id, timestamp));
+        }
+
+        // Without a cursor: everything, per topic, uncapped
+        let (msgs, truncated) = sync_response(&queue, None, None, 0.0);
+        assert_eq!(ids(&msgs), vec![1, 2, 3, 4, 5]);
+        assert!(!truncated);
+
+        // Strictly newer than the cursor, oldest first across topics; the page is
+        // extended past the limit rather than split between messages 2 and 4,
+        // which share a timestamp
+        let (msgs, truncated) = sync_response(&queue, None, Some(100.0), 0.0);
+        assert_eq!(ids(&msgs), vec![3, 2, 4]);
+        assert!(truncated);
+        queue.sync_response_limit = 2;
+        let (msgs, truncated) = sync_response(&queue, None, Some(100.0), 0.0);
+        assert_eq!(ids(&msgs), vec![3, 2, 4]);
+        assert!(truncated);
+
+        // A message at exactly the cursor is not sent again; the next page holds the rest
+        let (msgs, truncated) = sync_response(&queue, None, Some(300.0), 0.0);
+        assert_eq!(ids(&msgs), vec![5]);
+        assert!(!truncated);
+        let (msgs, _) = sync_response(&queue, None, Some(299.5), 0.0);
+        assert_eq!(ids(&msgs), vec![2, 4]);
+
+        // Nothing newer: an empty, complete response
+        let (msgs, truncated) = sync_response(&queue, Some(&["prices".to_string()]), Some(300.0), 0.0);
+        assert!(msgs.is_empty());
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_sync_response_skips_local_only_topics() {
+        let mut queue = InnerQueue::default();
+        let shared = buffered_topic(&mut queue, "shared", 4);
+        let frames = buffered_topic(&mut queue, "frames", 4);
+        for (id, topic_id) in [(1, shared), (2, frames)] {
+            queue.buffer_message(&msg(id, topic_id));
+        }
+        queue.get_topic_by_id_mut(frames).unwrap().local_only = true;
+
+        let (msgs, _) = sync_response(&queue, None, None, 0.0);
+        assert_eq!(ids(&msgs), vec![1]);
+        let (msgs, _) = sync_response(&queue, Some(&["frames".to_string()]), None, 0.0);
+        assert!(msgs.is_empty());
+    }
+}
@@ -1,3 +1,111 @@
 /// Error message constants
 pub const ERR_WINDOW_NOT_AVAILABLE: &str = "Window not available";
 pub const ERR_CRYPTO_NOT_AVAILABLE: &str = "Crypto not available";
+pub const ERR_RPC_TIMEOUT: &str = "RPC timeout";
+pub const ERR_QUEUE_FULL: &str = "Queue full";
+pub const ERR_QUEUE_CLOSED: &str = "Queue is closed";
+pub const ERR_SIGNAL_ABORTED: &str = "Signal is already aborted";
+pub const ERR_PEER_CLOSED: &str = "peer closed";
+pub const ERR_BUFFER_FULL: &str = "Topic buffer full";
+
+/// Hysteresis band applied below backpressure thresholds (5%)
+pub const BACKPRESSURE_HYSTERESIS: f64 = 0.05;
+
+/// Topic IDs hold the slot index in the low 20 bits and the slot generation
+/// in the remaining 12 bits, so a stale ID of a destroyed topic never resolves
+/// to the topic reusing its slot (until the generation wraps after 4096 reuses)
+pub const TOPIC_SLOT_BITS: u32 = 20;
+pub const TOPIC_SLOT_MASK: u32 = (1 << TOPIC_SLOT_BITS) - 1;
+pub const TOPIC_GENERATION_MASK: u32 = (1 << (32 - TOPIC_SLOT_BITS)) - 1;
+
+/// Subscriber IDs are built the same way from the subscriber's slot in its topic
+pub const SUBSCRIBER_SLOT_BITS: u32 = 20;
+pub const SUBSCRIBER_SLOT_MASK: u32 = (1 << SUBSCRIBER_SLOT_BITS) - 1;
+pub const SUBSCRIBER_GENERATION_MASK: u32 = (1 << (32 - SUBSCRIBER_SLOT_BITS)) - 1;
+
+/// Priority of `publish_async` messages
+/// Priorities range from 0 (lowest) to 255 (highest); queued messages of a
+/// topic are delivered highest priority first, in publish order within a level
+pub const DEFAULT_PRIORITY: u8 = 128;
+
+/// Default number of recent message IDs remembered for deduplication
+pub const DEFAULT_DEDUP_WINDOW: usize = 4096;
+
+/// Default time a sync waits without responses before resolving, in milliseconds
+pub const SYNC_SETTLE_MS: f64 = 200.0;
+
+/// Maximum random delay before answering a SYNC_REQ, in milliseconds
+/// The first tab to answer suppresses the answers of the others.
+pub const SYNC_JITTER_MS: f64 = 50.0;
+
+/// Number of presence intervals without a packet after which a peer is dropped
+pub const PRESENCE_TIMEOUT_FACTOR: f64 = 3.0;
+
+/// Time a leader election candidate waits for a lower client ID to answer, in milliseconds
+pub const ELECTION_TIMEOUT_MS: f64 = 100.0;
+
+/// Default limit for messages in a response to an incremental SYNC_REQ
+pub const DEFAULT_SYNC_RESPONSE_LIMIT: usize = 500;
+
+/// Estimated size in bytes of payloads that are neither strings nor binary data
+/// Objects use this unless the topic has a sizer callback.
+pub const OBJECT_SIZE_ESTIMATE: usize = 256;
+pub const PRIMITIVE_SIZE_ESTIMATE: usize = 8;
+
+/// Default limit for nested (re-entrant) dispatches
+pub const DEFAULT_MAX_DISPATCH_DEPTH: usize = 10;
+
+/// Default limit for messages held while the queue is paused
+pub const DEFAULT_MAX_HELD_MESSAGES: usize = 10_000;
+
+/// Default maximum length of topic names, in characters
+pub const DEFAULT_MAX_TOPIC_NAME_LEN: usize = 256;
+
+/// Default limit for messages a stream queues while no `next()` is waiting
+pub const DEFAULT_STREAM_BUFFER_LIMIT: usize = 1000;
+
+/// Default number of messages a `readable_stream` queues before it counts as full
+pub const DEFAULT_STREAM_HIGH_WATER_MARK: f64 = 16.0;
+
+/// Maximum messages delivered to an idle subscriber per idle callback
+pub const IDLE_BATCH_SIZE: usize = 50;
+
+/// Diagnostic log levels for `enable_wasm_logging`
+pub const LOG_OFF: u8 = 0;
+pub const LOG_ERROR: u8 = 1;
+pub const LOG_WARN: u8 = 2;
+pub const LOG_INFO: u8 = 3;
+pub const LOG_DEBUG: u8 = 4;
+
+/// BroadcastChannel packet types: the first element of every packet array
+/// PUB: [0, msg]
+pub const PACKET_PUB: u8 = 0;
+/// SYNC_REQ: [1, origin_id, topic_names?] (all topics without a name list)
+pub const PACKET_SYNC_REQ: u8 = 1;
+/// SYNC_RESP: [2, requester_id, [msg1, msg2, ...], truncated?]
+/// (older versions send [2, [msg1, msg2, ...]] without the requester ID)
+pub const PACKET_SYNC_RESP: u8 = 2;
+/// TOPIC_BATCH: [3, topic_name, [msg1, msg2, ...]] (batch published to one topic)
+pub const PACKET_TOPIC_BATCH: u8 = 3;
+/// PRESENCE: [4, client_id, "join" | "here" | "heartbeat" | "leave"]
+pub const PACKET_PRESENCE: u8 = 4;
+/// BATCH: [5, [msg1, msg2, ...]] (throttled broadcasts, mixed-topic batches)
+pub const PACKET_BATCH: u8 = 5;
+/// MIGRATE: [6, old_name, new_name] (topic renamed)
+pub const PACKET_MIGRATE: u8 = 6;
+/// RPC_REQUEST: [7, topic_name, correlation_id, requester_id, payload]
+pub const PACKET_RPC_REQUEST: u8 = 7;
+/// RPC_REPLY: [8, correlation_id, requester_id, ok, value, responder_id]
+pub const PACKET_RPC_REPLY: u8 = 8;
+
+/// LEADER: [9, client_id, "elect" | "claim"]
+pub const PACKET_LEADER: u8 = 9;
+/// GOODBYE: [10, client_id] (queue closed or dropped; 5 is taken by BATCH)
+pub const PACKET_GOODBYE: u8 = 10;
+
+/// Origin ID prefix of messages injected by a WebSocket bridge
+/// Bridges never forward messages carrying it, which prevents echo loops
+pub const BRIDGE_ORIGIN_PREFIX: &str = "bridge:";
+
+/// Version byte of binary PUB packets (a `Uint8Array` starting with `PACKET_PUB`)
+pub const WIRE_VERSION: u8 = 1;
@@ -0,0 +1,138 @@
+use std::collections::{HashSet, VecDeque};
+use crate::constants::DEFAULT_DEDUP_WINDOW;
+
+/// A bounded set of recently seen message IDs
+/// Remembers at most `capacity` IDs; inserting beyond that evicts the oldest,
+/// so memory stays constant in long-lived contexts.
+#[derive(Debug, Clone)]
+pub struct DedupWindow {
+    /// IDs currently remembered
+    ids: HashSet<u64>,
+    /// IDs in insertion order, oldest first
+    order: VecDeque<u64>,
+    /// Maximum number of IDs remembered
+    capacity: usize,
+}
+
+impl DedupWindow {
+    /// Create a new window remembering up to `capacity` IDs
+    pub fn new(capacity: usize) -> Self {
+        DedupWindow {
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record an ID
+    /// Returns false if the ID is already in the window (a duplicate)
+    pub fn insert(&mut self, id: u64) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if !self.ids.insert(id) {
+            return false;
+        }
+
+        self.order.push_back(id);
+        self.evict();
+        true
+    }
+
+    /// Check if an ID is in the window
+    pub fn contains(&self, id: u64) -> bool {
+        self.ids.contains(&id)
+    }
+
+    /// Get the number of IDs remembered
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Check if the window is empty
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Get the maximum number of IDs remembered
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity, evicting the oldest IDs if the window shrinks
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Forget all IDs
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.order.clear();
+    }
+
+    fn evict(&mut self) {
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl Default for DedupWindow {
+    fn default() -> Self {
+        DedupWindow::new(DEFAULT_DEDUP_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_window_suppresses_duplicates() {
+        let mut window = DedupWindow::new(3);
+        assert!(window.insert(1));
+        assert!(window.insert(2));
+        assert!(!window.insert(1));
+        assert!(!window.insert(2));
+        assert_eq!(window.len(), 2);
+    }
+
+    #[test]
+    fn test_dedup_window_evicts_oldest() {
+        let mut window = DedupWindow::new(3);
+        for id in 1..=4 {
+            assert!(window.insert(id));
+        }
+
+        // 1 was evicted, so it is accepted again; recent IDs are still duplicates
+        assert_eq!(window.len(), 3);
+        assert!(!window.contains(1));
+        assert!(!window.insert(3));
+        assert!(!window.insert(4));
+        assert!(window.insert(1));
+        assert!(!window.contains(2));
+    }
+
+    #[test]
+    fn test_dedup_window_set_capacity() {
+        let mut window = DedupWindow::new(5);
+        for id in 1..=5 {
+            window.insert(id);
+        }
+
+        window.set_capacity(2);
+        assert_eq!(window.len(), 2);
+        assert!(window.contains(4));
+        assert!(window.contains(5));
+        assert!(!window.contains(3));
+
+        // A zero-sized window disables deduplication
+        window.set_capacity(0);
+        assert!(window.is_empty());
+        assert!(window.insert(5));
+        assert!(window.insert(5));
+    }
+}
@@ -0,0 +1,322 @@
+use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::types::{DeadLetterReason, Delivery, InnerQueue, Message, PublishOptions};
+use crate::js_utils::{dead_letter_record, message_to_js_full};
+use crate::utils::set_timeout;
+use js_sys::{Function, Promise};
+
+/// Invoke the subscriber callbacks collected by `InnerQueue::dispatch_local`
+/// Must be called without holding the queue borrow: callbacks may publish,
+/// subscribe or unsubscribe on the same queue.
+pub(crate) fn deliver(inner: &Rc<RefCell<InnerQueue>>, deliveries: impl IntoIterator<Item = Delivery>) {
+    // Topics created while preparing the deliveries are announced first
+    notify_topic_events(inner);
+    schedule_echoes(inner);
+    for delivery in deliveries {
+        deliver_one(inner, delivery, None);
+    }
+}
+
+/// Like `deliver`, but collect what each invoked callback returned or threw
+/// Subscribers skipped by their filter, and idle or deferred ones, are not included.
+pub(crate) fn deliver_collecting(inner: &Rc<RefCell<InnerQueue>>, deliveries: impl IntoIterator<Item = Delivery>) -> Vec<Result<JsValue, JsValue>> {
+    notify_topic_events(inner);
+    schedule_echoes(inner);
+    let mut results = Vec::new();
+    for delivery in deliveries {
+        deliver_one(inner, delivery, Some(&mut results));
+    }
+    results
+}
+
+/// Pass topic lifecycle changes recorded under the borrow to the topic hooks
+/// Hooks are invoked after the queue borrow is released, with (topic_id, topic_name).
+pub(crate) fn notify_topic_events(inner: &Rc<RefCell<InnerQueue>>) {
+    let (hooks, events) = match inner.try_borrow_mut() {
+        Ok(mut queue) if !queue.topic_events.is_empty() => {
+            let events = std::mem::take(&mut queue.topic_events);
+            (queue.topic_hooks.clone(), events)
+        }
+        _ => return,
+    };
+
+    for (event, topic_id, name) in &events {
+        let (topic_id, name) = (JsValue::from(*topic_id), JsValue::from_str(name));
+        for (hook_id, _, hook) in hooks.iter().filter(|(_, hook_event, _)| hook_event == event) {
+            if let Err(err) = hook.call2(&JsValue::NULL, &topic_id, &name) {
+                report_error(inner, &err, None, Some(*hook_id), None);
+            }
+        }
+    }
+}
+
+/// Schedule the echo task for messages held back by echo mode
+/// With a transport, the task is a timer task like the arrival of a packet from
+/// another tab; without one, a microtask.
+fn schedule_echoes(inner: &Rc<RefCell<InnerQueue>>) {
+    let as_task = match inner.try_borrow_mut() {
+        Ok(mut queue) if !queue.echo_scheduled && !queue.pending_echoes.is_empty() => {
+            queue.echo_scheduled = true;
+            !queue.transports.is_empty()
+        }
+        _ => return,
+    };
+
+    let weak = Rc::downgrade(inner);
+    let echo = move || {
+        if let Some(inner) = weak.upgrade() {
+            deliver_echoes(&inner);
+        }
+    };
+    if as_task {
+        let echo = Closure::once_into_js(echo);
+        let _ = set_timeout(echo.unchecked_ref(), 0.0);
+    } else {
+        let echo = Closure::once(move |_value: JsValue| echo());
+        let _ = Promise::resolve(&JsValue::UNDEFINED).then(&echo);
+        echo.forget();
+    }
+}
+
+/// The echo task: dispatch this tab's held publishes in publish order
+/// Messages published by their subscribers are held for the next echo task.
+fn deliver_echoes(inner: &Rc<RefCell<InnerQueue>>) {
+    let deliveries: Vec<_> = {
+        let mut queue = inner.borrow_mut();
+        queue.echo_scheduled = false;
+        let echoes = std::mem::take(&mut queue.pending_echoes);
+        #[cfg(feature = "wasm-logging")]
+        if queue.logs_at(crate::constants::LOG_DEBUG) {
+            crate::logging::log_group(crate::constants::LOG_DEBUG, "echo", &[
+                ("messages", JsValue::from(echoes.len() as u32)),
+            ]);
+        }
+        echoes.iter().filter_map(|message| queue.dispatch_local(message)).collect()
+    };
+    deliver(inner, deliveries);
+    crate::queue::notify_backpressure(inner);
+}
+
+fn deliver_one(inner: &Rc<RefCell<InnerQueue>>, mut delivery: Delivery, mut results: Option<&mut Vec<Result<JsValue, JsValue>>>) {
+    let message = &delivery.message;
+    if delivery.depth_exceeded {
+        let error = JsValue::from_str("Max dispatch depth exceeded");
+        report_error(inner, &error, Some(message.topic_id), None, Some(message.id));
+        return;
+    }
+
+    let (depth, dead_letter_errors) = {
+        let queue = inner.borrow();
+        let depth = queue.dispatch_depth.get();
+        queue.dispatch_depth.set(depth + 1);
+        let dead_letter_errors = queue.dead_letter.as_ref()
+            .is_some_and(|config| config.accepts(message.topic_id, DeadLetterReason::Error));
+        (depth, dead_letter_errors)
+    };
+
+    // Optimization: Zero-allocation dispatch
+    // Instead of creating a JS object, we pass arguments directly to the callback.
+    // Signature: callback(payload, topic_id, timestamp, id, headers)
+    // This avoids Reflect::set/get and object creation entirely.
+    // The argument values are only created when there is a callback to pass them to.
+    let this = JsValue::NULL;
+    let mut errors = Vec::new();
+    let mut delivered = 0;
+    let mut failed = 0;
+    let args = delivery.topic_id_val.take().map(|topic_id| CallbackArgs {
+        topic_id,
+        timestamp: JsValue::from(message.timestamp),
+        // IDs use all 64 bits, so they are passed as BigInt to avoid precision loss
+        id: JsValue::from(message.id),
+        headers: message.headers_js(),
+    });
+
+    for target in &delivery.targets {
+        // Present whenever there are targets
+        let Some(args) = &args else { break };
+        if let Some(filter) = &target.filter {
+            let accepted = filter.call1(&this, &message.payload).is_ok_and(|result| result.is_truthy());
+            if !accepted {
+                continue;
+            }
+        }
+
+        // Errors are reported, logged (`wasm-logging`) and dead-lettered
+        let result = if target.raw {
+            match &delivery.msg_obj {
+                Some(obj) => target.callback.call1(&this, obj),
+                None => Ok(JsValue::UNDEFINED),
+            }
+        } else {
+            // Positional arguments are faster than creating an array or object
+            target.callback.call5(&this, &message.payload, &args.topic_id, &args.timestamp, &args.id, &args.headers)
+        };
+
+        #[cfg(feature = "wasm-logging")]
+        if let Err(err) = &result {
+            let queue = inner.borrow();
+            if queue.logs_at(crate::constants::LOG_ERROR) {
+                crate::logging::log_group(crate::constants::LOG_ERROR, "Subscriber callback threw", &[
+                    ("topic", topic_name(&queue, message.topic_id)),
+                    ("subscriber", JsValue::from(target.sub_id)),
+                    ("error", err.clone()),
+                ]);
+            }
+        }
+
+        delivered += 1;
+        if let Some(results) = results.as_deref_mut() {
+            results.push(result.clone());
+        }
+        if let Err(err) = result {
+            failed += 1;
+            report_error(inner, &err, Some(message.topic_id), Some(target.sub_id), Some(message.id));
+            if dead_letter_errors {
+                errors.push(err);
+            }
+        }
+    }
+
+    // Global subscribers run after the topic's, and need the topic name
+    if let (Some((name, subscribers)), Some(args)) = (&delivery.globals, &args) {
+        let name_val = JsValue::from_str(name);
+        for (sub_id, callback) in subscribers {
+            delivered += 1;
+            let result = callback.call5(&this, &message.payload, &name_val, &args.topic_id, &args.timestamp, &args.id);
+            if let Some(results) = results.as_deref_mut() {
+                results.push(result.clone());
+            }
+            if let Err(err) = result {
+                failed += 1;
+                report_error(inner, &err, Some(message.topic_id), Some(*sub_id), Some(message.id));
+            }
+        }
+    }
+
+    let mut queue = inner.borrow_mut();
+    queue.dispatch_depth.set(depth);
+    queue.record_stat(message.topic_id, |stats| {
+        stats.delivered += delivered;
+        stats.errors += failed;
+    });
+
+    #[cfg(feature = "wasm-logging")]
+    if let Some(started) = delivery.started {
+        crate::logging::log_group(crate::constants::LOG_DEBUG, "dispatch_local", &[
+            ("topic", topic_name(&queue, message.topic_id)),
+            ("message_id", JsValue::from(message.id)),
+            ("subscribers", JsValue::from(delivery.targets.len() as u32)),
+            ("latency_ms", JsValue::from(crate::logging::now_ms() - started)),
+        ]);
+    }
+    drop(queue);
+
+    for (dead, reason) in &delivery.dead_letters {
+        route_dead_letter(inner, dead, *reason, None);
+    }
+    for error in &errors {
+        route_dead_letter(inner, message, DeadLetterReason::Error, Some(error));
+    }
+    for (hook, lost) in &delivery.overflow {
+        notify_overflow(inner, hook, lost);
+    }
+}
+
+/// Positional callback arguments shared by all subscribers of a message
+struct CallbackArgs {
+    topic_id: JsValue,
+    timestamp: JsValue,
+    id: JsValue,
+    headers: JsValue,
+}
+
+/// Call a topic's `on_overflow` hook with a message lost to its full buffer
+pub(crate) fn notify_overflow(inner: &Rc<RefCell<InnerQueue>>, hook: &Function, lost: &Message) {
+    let Some(name) = inner.borrow().get_topic_by_id(lost.topic_id).map(|topic| topic.name.clone()) else {
+        return;
+    };
+    let result = message_to_js_full(lost, &name)
+        .and_then(|msg_obj| hook.call1(&JsValue::NULL, &msg_obj));
+    if let Err(err) = result {
+        report_error(inner, &err, Some(lost.topic_id), None, Some(lost.id));
+    }
+}
+
+/// Pass publishes rejected under the "error" overflow policy to their topics' overflow hooks
+/// The messages are recorded by `InnerQueue::check_overflow` under the borrow;
+/// callers invoke this once the borrow is released, whether or not the publish failed.
+pub(crate) fn notify_rejected(inner: &Rc<RefCell<InnerQueue>>) {
+    let rejected = match inner.try_borrow_mut() {
+        Ok(mut queue) if !queue.rejected_publishes.is_empty() => std::mem::take(&mut queue.rejected_publishes),
+        _ => return,
+    };
+    for (hook, message) in &rejected {
+        notify_overflow(inner, hook, message);
+    }
+}
+
+/// Publish a dead-letter record for `message` to the dead-letter topic (this tab only)
+fn route_dead_letter(inner: &Rc<RefCell<InnerQueue>>, message: &Message, reason: DeadLetterReason, error: Option<&JsValue>) {
+    let delivery = {
+        let mut queue = inner.borrow_mut();
+        let Some(dead_letter_topic) = queue.dead_letter.as_ref().map(|config| config.topic_id) else {
+            return;
+        };
+        let original_topic = topic_name(&queue, message.topic_id);
+        let Ok(record) = dead_letter_record(&original_topic, &message.payload, reason.as_str(), error) else {
+            return;
+        };
+        let record = queue.create_message(dead_letter_topic, record, PublishOptions::default());
+        queue.dispatch_local(&record)
+    };
+    deliver(inner, delivery);
+}
+
+/// Pass an error to the handler registered with `set_error_handler`
+/// Called with (error, topic_name, sub_id, message_id); handler errors are ignored.
+/// Arguments that do not apply (e.g. for a malformed bridge frame) are undefined.
+/// Without a handler, the error is written to `console.error` in debug mode.
+pub(crate) fn report_error(inner: &Rc<RefCell<InnerQueue>>, error: &JsValue, topic_id: Option<u32>, sub_id: Option<u32>, message_id: Option<u64>) {
+    let (handler, topic_name) = {
+        let queue = inner.borrow();
+        if queue.error_handler.is_none() && !queue.debug {
+            return;
+        }
+        let topic_name = topic_id.map_or(JsValue::UNDEFINED, |topic_id| topic_name(&queue, topic_id));
+        (queue.error_handler.clone(), topic_name)
+    };
+
+    let sub_id = sub_id.map_or(JsValue::UNDEFINED, JsValue::from);
+    let message_id = message_id.map_or(JsValue::UNDEFINED, JsValue::from);
+    match handler {
+        Some(handler) => {
+            let _ = handler.call4(&JsValue::NULL, error, &topic_name, &sub_id, &message_id);
+        }
+        None => web_sys::console::error_4(
+            &JsValue::from_str("wasm-ripple: dispatch error on topic"),
+            &topic_name,
+            &sub_id,
+            error,
+        ),
+    }
+}
+
+/// Pass received messages dropped under the borrow (e.g. for an invalid topic
+/// name) to the error handler, with their message IDs
+pub(crate) fn report_receive_errors(inner: &Rc<RefCell<InnerQueue>>) {
+    let errors = match inner.try_borrow_mut() {
+        Ok(mut queue) if !queue.receive_errors.is_empty() => std::mem::take(&mut queue.receive_errors),
+        _ => return,
+    };
+    for (error, message_id) in errors {
+        report_error(inner, &error, None, None, Some(message_id));
+    }
+}
+
+/// Name of a topic as a JS string, or undefined for an unknown ID
+fn topic_name(queue: &InnerQueue, topic_id: u32) -> JsValue {
+    queue.get_topic_by_id(topic_id)
+        .map_or(JsValue::UNDEFINED, |topic| JsValue::from_str(&topic.name))
+}
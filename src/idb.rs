@@ -0,0 +1,399 @@
+//! IndexedDB persistence of topic buffers, so selected topics survive the tab closing
+//!
+//! Each persistent topic writes to its own auto-increment object store, so keys
+//! follow write order. Records are `{id, topic, payload, timestamp, origin_id}`
+//! with the ID as a decimal string and the payload as JSON text; payloads JSON
+//! cannot represent are stored as `{..., skipped: true}` without a payload.
+//!
+//! Only messages published in this tab are written: tabs sharing a store would
+//! otherwise each write a copy of every message. Writes are collected in a
+//! pending list and flushed in one transaction per task, so `publish` never waits
+//! on IndexedDB; failures are passed to the error handler.
+
+use wasm_bindgen::prelude::*;
+use std::rc::{Rc, Weak};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use js_sys::{Array, Function, Object, Promise, Reflect, JSON};
+use web_sys::{Event, IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStore, IdbObjectStoreParameters, IdbRequest, IdbTransactionMode};
+
+use crate::types::{InnerQueue, Message};
+use crate::dispatch::report_error;
+use crate::utils::set_timeout;
+
+/// A topic's IndexedDB store and the messages waiting to be written to it
+pub struct PersistentBuffer {
+    /// Topic whose messages are persisted, for error reports
+    topic_id: u32,
+    store_name: String,
+    /// Records kept in the store; older ones are deleted after each write
+    max_entries: u32,
+    /// The open database, None until `enable` finished opening it
+    db: RefCell<Option<IdbDatabase>>,
+    /// Messages waiting to be written, with their topic names, in publish order
+    pending: RefCell<Vec<(Rc<Message>, String)>>,
+    flush_scheduled: Cell<bool>,
+    queue: Weak<RefCell<InnerQueue>>,
+    /// Reports failed write transactions
+    on_error: Closure<dyn FnMut(Event)>,
+    /// Deletes the oldest records once the store has more than `max_entries`
+    on_count: Closure<dyn FnMut(Event)>,
+    on_excess_keys: Closure<dyn FnMut(Event)>,
+}
+
+/// The global `indexedDB` factory, in windows and workers alike
+fn idb_factory() -> Result<IdbFactory, JsValue> {
+    let factory = Reflect::get(&js_sys::global(), &"indexedDB".into())?;
+    if factory.is_undefined() || factory.is_null() {
+        return Err(JsValue::from_str("IndexedDB not available"));
+    }
+    Ok(factory.unchecked_into())
+}
+
+/// Persist a topic's messages to `store_name` in the database `db_name`
+/// The topic gets a buffer of `max_entries` messages if it has none.
+/// The Promise resolves once the database is open; messages published before
+/// are written then.
+pub(crate) fn enable(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, db_name: &str, store_name: &str, max_entries: u32) -> Result<Promise, JsValue> {
+    if max_entries == 0 {
+        return Err(JsValue::from_str("max_entries must be greater than 0"));
+    }
+    let factory = idb_factory()?;
+
+    let persistent = PersistentBuffer::new(inner, topic_id, store_name, max_entries);
+    {
+        let mut queue = inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        if !topic.has_buffer() {
+            topic.enable_buffer(max_entries as usize);
+        }
+        topic.persistence = Some(persistent.clone());
+    }
+
+    let db_name = db_name.to_string();
+    Ok(Promise::new(&mut |resolve, reject| {
+        if let Err(err) = open(&factory, &db_name, None, persistent.clone(), resolve, reject.clone()) {
+            let _ = reject.call1(&JsValue::NULL, &err);
+        }
+    }))
+}
+
+/// Open the database, creating the object store if it is missing
+/// An existing database without the store is reopened with the next version,
+/// since stores can only be created during a version upgrade.
+fn open(factory: &IdbFactory, db_name: &str, version: Option<u32>, persistent: Rc<PersistentBuffer>, resolve: Function, reject: Function) -> Result<(), JsValue> {
+    let request = match version {
+        Some(version) => factory.open_with_u32(db_name, version)?,
+        None => factory.open(db_name)?,
+    };
+
+    let store_name = persistent.store_name.clone();
+    let upgrade_request = request.clone();
+    let on_upgrade = Closure::once_into_js(move |_event: Event| {
+        let Ok(db) = upgrade_request.result().map(IdbDatabase::unchecked_from_js) else {
+            return;
+        };
+        if !db.object_store_names().contains(&store_name) {
+            let params = IdbObjectStoreParameters::new();
+            params.set_auto_increment(true);
+            let _ = db.create_object_store_with_optional_parameters(&store_name, &params);
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+    let success_request = request.clone();
+    let factory = factory.clone();
+    let db_name = db_name.to_string();
+    let on_error_reject = reject.clone();
+    let on_success = Closure::once_into_js(move |_event: Event| {
+        let db = match success_request.result() {
+            Ok(db) => IdbDatabase::unchecked_from_js(db),
+            Err(err) => {
+                let _ = reject.call1(&JsValue::NULL, &err);
+                return;
+            }
+        };
+        if !db.object_store_names().contains(&persistent.store_name) {
+            let next_version = db.version() as u32 + 1;
+            db.close();
+            if let Err(err) = open(&factory, &db_name, Some(next_version), persistent, resolve, reject.clone()) {
+                let _ = reject.call1(&JsValue::NULL, &err);
+            }
+            return;
+        }
+        // Another connection upgrading the database (e.g. to add a store for
+        // another topic) waits until this one closes, so close and reopen
+        let weak = Rc::downgrade(&persistent);
+        let on_version_change = Closure::once_into_js(move |_event: Event| {
+            let Some(persistent) = weak.upgrade() else {
+                return;
+            };
+            if let Some(db) = persistent.db.borrow_mut().take() {
+                db.close();
+            }
+            let ignore = Function::new_no_args("");
+            if let Err(err) = open(&factory, &db_name, None, persistent.clone(), ignore.clone(), ignore) {
+                persistent.report(&err);
+            }
+        });
+        db.set_onversionchange(Some(on_version_change.unchecked_ref()));
+        *persistent.db.borrow_mut() = Some(db);
+        persistent.flush();
+        let _ = resolve.call0(&JsValue::NULL);
+    });
+    request.set_onsuccess(Some(on_success.unchecked_ref()));
+
+    let error_request = request.clone();
+    let on_error = Closure::once_into_js(move |_event: Event| {
+        let error = error_request.error().ok().flatten().map_or(JsValue::UNDEFINED, JsValue::from);
+        let _ = on_error_reject.call1(&JsValue::NULL, &error);
+    });
+    request.set_onerror(Some(on_error.unchecked_ref()));
+    Ok(())
+}
+
+/// The request that fired an IDB event
+fn event_request(event: &Event) -> Option<IdbRequest> {
+    event.target()?.dyn_into().ok()
+}
+
+impl PersistentBuffer {
+    fn new(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, store_name: &str, max_entries: u32) -> Rc<Self> {
+        Rc::new_cyclic(|this: &Weak<PersistentBuffer>| {
+            let weak = this.clone();
+            let on_error = Closure::new(move |event: Event| {
+                let error = event_request(&event)
+                    .and_then(|request| request.error().ok().flatten())
+                    .map_or(JsValue::UNDEFINED, JsValue::from);
+                if let Some(persistent) = weak.upgrade() {
+                    persistent.report(&error);
+                }
+            });
+            let weak = this.clone();
+            let on_count = Closure::new(move |event: Event| {
+                if let (Some(persistent), Some(request)) = (weak.upgrade(), event_request(&event)) {
+                    persistent.request_excess_keys(&request);
+                }
+            });
+            let on_excess_keys = Closure::new(move |event: Event| {
+                if let Some(request) = event_request(&event) {
+                    delete_excess(&request);
+                }
+            });
+
+            PersistentBuffer {
+                topic_id,
+                store_name: store_name.to_string(),
+                max_entries,
+                db: RefCell::new(None),
+                pending: RefCell::new(Vec::new()),
+                flush_scheduled: Cell::new(false),
+                queue: Rc::downgrade(inner),
+                on_error,
+                on_count,
+                on_excess_keys,
+            }
+        })
+    }
+
+    /// Queue a message for writing, flushing the pending list after the current task
+    /// Called under the queue borrow, so nothing here touches IndexedDB directly.
+    pub(crate) fn enqueue(self: &Rc<Self>, message: &Rc<Message>, topic_name: &str) {
+        self.pending.borrow_mut().push((message.clone(), topic_name.to_string()));
+        if self.flush_scheduled.get() {
+            return;
+        }
+        let weak = Rc::downgrade(self);
+        let on_timeout = Closure::once_into_js(move || {
+            if let Some(persistent) = weak.upgrade() {
+                persistent.flush();
+            }
+        });
+        // Without timers the next message schedules the flush again
+        let scheduled = set_timeout(on_timeout.unchecked_ref(), 0.0).is_ok();
+        self.flush_scheduled.set(scheduled);
+    }
+
+    /// Write the pending messages in one transaction, once the database is open
+    fn flush(&self) {
+        self.flush_scheduled.set(false);
+        let Some(db) = self.db.borrow().clone() else {
+            return;
+        };
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        if pending.is_empty() {
+            return;
+        }
+        if let Err(err) = self.write(&db, &pending) {
+            self.report(&err);
+        }
+    }
+
+    fn write(&self, db: &IdbDatabase, messages: &[(Rc<Message>, String)]) -> Result<(), JsValue> {
+        let transaction = db.transaction_with_str_and_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        transaction.set_onerror(Some(self.on_error.as_ref().unchecked_ref()));
+        let store = transaction.object_store(&self.store_name)?;
+        for (message, topic_name) in messages {
+            let record = record(message, topic_name)?;
+            store.add(&record)?;
+        }
+        store.count()?.set_onsuccess(Some(self.on_count.as_ref().unchecked_ref()));
+        Ok(())
+    }
+
+    /// Ask for the keys of the records beyond `max_entries`, given the answer to a count
+    fn request_excess_keys(&self, count_request: &IdbRequest) {
+        let count = count_request.result().ok().and_then(|count| count.as_f64()).unwrap_or(0.0) as u32;
+        let Some(store) = count_request.source().map(JsCast::unchecked_into::<IdbObjectStore>) else {
+            return;
+        };
+        if count <= self.max_entries {
+            return;
+        }
+        // Keys follow write order: the first keys belong to the oldest records
+        match store.get_all_keys_with_key_and_limit(&JsValue::NULL, count - self.max_entries) {
+            Ok(keys) => keys.set_onsuccess(Some(self.on_excess_keys.as_ref().unchecked_ref())),
+            Err(err) => self.report(&err),
+        }
+    }
+
+    /// Pass a failed IndexedDB operation to the queue's error handler
+    fn report(&self, error: &JsValue) {
+        if let Some(inner) = self.queue.upgrade() {
+            report_error(&inner, error, Some(self.topic_id), None, None);
+        }
+    }
+}
+
+/// Delete the records up to the last key returned by `request_excess_keys`
+fn delete_excess(keys_request: &IdbRequest) {
+    let keys: Array = keys_request.result().map(Array::unchecked_from_js).unwrap_or_default();
+    let Some(store) = keys_request.source().map(JsCast::unchecked_into::<IdbObjectStore>) else {
+        return;
+    };
+    if keys.length() == 0 {
+        return;
+    }
+    if let Ok(range) = IdbKeyRange::upper_bound(&keys.get(keys.length() - 1)) {
+        let _ = store.delete(&range);
+    }
+}
+
+impl Drop for PersistentBuffer {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.get_mut().take() {
+            db.close();
+        }
+    }
+}
+
+/// Build the stored record of a message
+/// Payloads JSON cannot represent (functions, BigInts, cycles, undefined) are skipped.
+fn record(message: &Message, topic_name: &str) -> Result<Object, JsValue> {
+    let record = Object::new();
+    Reflect::set(&record, &"id".into(), &message.id.to_string().into())?;
+    Reflect::set(&record, &"topic".into(), &topic_name.into())?;
+    Reflect::set(&record, &"timestamp".into(), &message.timestamp.into())?;
+    Reflect::set(&record, &"origin_id".into(), &message.origin_id.as_str().into())?;
+    match JSON::stringify(&message.payload).ok().and_then(|json| json.as_string()) {
+        Some(json) => Reflect::set(&record, &"payload".into(), &json.into())?,
+        None => Reflect::set(&record, &"skipped".into(), &JsValue::TRUE)?,
+    };
+    Ok(record)
+}
+
+/// Read a persistent topic's store back into its buffer
+/// Resolves with the number of messages restored. Messages already buffered or
+/// seen (e.g. loaded before) and skipped records are left out; restored IDs are
+/// marked as seen so other tabs syncing them do not deliver them again.
+pub(crate) fn load(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> Result<Promise, JsValue> {
+    let persistent = inner.borrow()
+        .get_topic_by_id(topic_id)
+        .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?
+        .persistence.clone()
+        .ok_or_else(|| JsValue::from_str("Topic has no persistent buffer"))?;
+    let db = persistent.db.borrow().clone()
+        .ok_or_else(|| JsValue::from_str("Persistent buffer is not open yet"))?;
+    let request = db.transaction_with_str(&persistent.store_name)?
+        .object_store(&persistent.store_name)?
+        .get_all()?;
+
+    let weak = Rc::downgrade(inner);
+    Ok(Promise::new(&mut |resolve, reject| {
+        let success_request = request.clone();
+        let weak = weak.clone();
+        let on_error_reject = reject.clone();
+        let on_success = Closure::once_into_js(move |_event: Event| {
+            let restored = match (weak.upgrade(), success_request.result()) {
+                (Some(inner), Ok(records)) => restore(&mut inner.borrow_mut(), topic_id, &records.unchecked_into()),
+                (None, _) => 0,
+                (_, Err(err)) => {
+                    let _ = reject.call1(&JsValue::NULL, &err);
+                    return;
+                }
+            };
+            let _ = resolve.call1(&JsValue::NULL, &JsValue::from(restored));
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+
+        let error_request = request.clone();
+        let on_error = Closure::once_into_js(move |_event: Event| {
+            let error = error_request.error().ok().flatten().map_or(JsValue::UNDEFINED, JsValue::from);
+            let _ = on_error_reject.call1(&JsValue::NULL, &error);
+        });
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    }))
+}
+
+/// Push stored records (oldest first) into a topic's buffer
+fn restore(queue: &mut InnerQueue, topic_id: u32, records: &Array) -> u32 {
+    // Messages published since the queue was created are buffered already
+    let buffered: HashSet<u64> = queue.get_topic_by_id(topic_id)
+        .and_then(|topic| topic.get_buffer())
+        .map(|buffer| buffer.iter().map(|msg| msg.id).collect())
+        .unwrap_or_default();
+    let mut messages = Vec::new();
+    for record in records.iter() {
+        let field = |name: &str| Reflect::get(&record, &name.into()).unwrap_or_default();
+        if field("skipped").is_truthy() {
+            continue;
+        }
+        let Some(id) = field("id").as_string().and_then(|id| id.parse::<u64>().ok()) else {
+            continue;
+        };
+        let Some(payload) = field("payload").as_string().and_then(|json| JSON::parse(&json).ok()) else {
+            continue;
+        };
+        let (Some(timestamp), Some(origin_id)) = (field("timestamp").as_f64(), field("origin_id").as_string()) else {
+            continue;
+        };
+        if buffered.contains(&id) || !queue.seen_ids.insert(id) {
+            continue;
+        }
+        messages.push(Rc::new(Message {
+            id,
+            topic_id,
+            payload,
+            timestamp,
+            origin_id: Rc::new(origin_id),
+            headers: None,
+            expires_at: None,
+            seq: 0,
+        }));
+    }
+
+    let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+        return 0;
+    };
+    let restored = messages.len() as u32;
+    if let Some(buffer) = topic.get_buffer_mut() {
+        for message in messages {
+            buffer.push(message);
+        }
+    }
+    if topic.max_bytes.is_some() {
+        topic.measure_buffer();
+    }
+    restored
+}
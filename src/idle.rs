@@ -0,0 +1,110 @@
+use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use js_sys::{Function, Reflect};
+
+use crate::constants::IDLE_BATCH_SIZE;
+use crate::types::Message;
+use crate::utils::{cancel_idle_callback, request_idle_callback};
+
+/// Deferred delivery state for a subscriber that runs during browser idle time
+pub struct IdleDelivery {
+    /// Messages waiting for the next idle callback
+    pub idle_queue: Rc<RefCell<VecDeque<Rc<Message>>>>,
+    /// Handle of the scheduled idle callback (0 = none scheduled)
+    pub idle_callback_id: Rc<Cell<i32>>,
+}
+
+impl IdleDelivery {
+    pub fn new() -> Self {
+        IdleDelivery {
+            idle_queue: Rc::new(RefCell::new(VecDeque::new())),
+            idle_callback_id: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Queue a message and make sure an idle callback is scheduled to deliver it
+    pub fn enqueue(&self, message: Rc<Message>, callback: &Function) {
+        self.idle_queue.borrow_mut().push_back(message);
+        if self.idle_callback_id.get() == 0 {
+            schedule_drain(&self.idle_queue, &self.idle_callback_id, callback);
+        }
+    }
+}
+
+impl Default for IdleDelivery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for IdleDelivery {
+    /// Cancel the pending idle callback and drop undelivered messages
+    fn drop(&mut self) {
+        let handle = self.idle_callback_id.replace(0);
+        if handle != 0 {
+            cancel_idle_callback(handle);
+        }
+        self.idle_queue.borrow_mut().clear();
+    }
+}
+
+/// Register an idle callback that drains the queue into `callback`
+fn schedule_drain(queue: &Rc<RefCell<VecDeque<Rc<Message>>>>, callback_id: &Rc<Cell<i32>>, callback: &Function) {
+    let queue_clone = queue.clone();
+    let id_clone = callback_id.clone();
+    let callback_clone = callback.clone();
+
+    let drain = Closure::once_into_js(move |deadline: JsValue| {
+        id_clone.set(0);
+        drain_queue(&queue_clone, &callback_clone, &deadline);
+
+        if !queue_clone.borrow().is_empty() {
+            schedule_drain(&queue_clone, &id_clone, &callback_clone);
+        }
+    });
+
+    // Errors are ignored: without a scheduler the messages stay queued
+    if let Ok(handle) = request_idle_callback(drain.unchecked_ref()) {
+        callback_id.set(handle);
+    }
+}
+
+/// Deliver up to `IDLE_BATCH_SIZE` messages while the idle deadline has time left
+/// At least one message is delivered per callback so the queue always makes progress
+fn drain_queue(queue: &Rc<RefCell<VecDeque<Rc<Message>>>>, callback: &Function, deadline: &JsValue) {
+    let this = JsValue::NULL;
+
+    for delivered in 0..IDLE_BATCH_SIZE {
+        if delivered > 0 && time_remaining(deadline) <= 0.0 {
+            break;
+        }
+
+        // Release the borrow before calling out: the callback may unsubscribe
+        let message = match queue.borrow_mut().pop_front() {
+            Some(message) => message,
+            None => break,
+        };
+
+        let _ = callback.call5(
+            &this,
+            &message.payload,
+            &JsValue::from(message.topic_id),
+            &JsValue::from(message.timestamp),
+            &JsValue::from(message.id),
+            &message.headers_js(),
+        );
+    }
+}
+
+/// Milliseconds left in an `IdleDeadline`
+/// The `setTimeout` fallback passes no deadline, which counts as unlimited
+fn time_remaining(deadline: &JsValue) -> f64 {
+    Reflect::get(deadline, &"timeRemaining".into())
+        .ok()
+        .and_then(|f| f.dyn_into::<Function>().ok())
+        .and_then(|f| f.call0(deadline).ok())
+        .and_then(|v| v.as_f64())
+        .unwrap_or(f64::INFINITY)
+}
@@ -1,52 +1,1189 @@
-use crate::types::{InnerQueue, Message};
-use wasm_bindgen::JsValue;
+use crate::types::{DeadLetterReason, Delivery, DeliveryTarget, InnerQueue, Message, OverflowPolicy, PublishOptions, QueuedPublish, SequenceGap, Stats, Transport};
+use crate::constants::{BACKPRESSURE_HYSTERESIS, ERR_BUFFER_FULL, ERR_QUEUE_CLOSED, PACKET_BATCH};
+#[cfg(feature = "wasm-logging")]
+use crate::constants::{LOG_DEBUG, LOG_WARN};
+use crate::pattern::{topic_matches, PatternSubscriber};
+use crate::js_utils::{is_retained, message_to_js, message_to_js_full, parse_js_message};
+use crate::wire::decode_message;
+use crate::ring_buffer::RingBuffer;
+use wasm_bindgen::{JsCast, JsValue};
+use js_sys::{Array, Function, Uint8Array};
 use std::rc::Rc;
 // use web_sys::console; // Removed for size optimization
 
 impl InnerQueue {
-    /// Dispatch a message to all local subscribers of its topic
-    /// 
-    /// # Arguments
-    /// * `message` - The message to dispatch (wrapped in Rc for efficient cloning)
-    /// * `_msg_js` - Deprecated/Unused. We now pass arguments directly.
-    pub fn dispatch_local(&mut self, message: &Rc<Message>, _msg_js: Option<&JsValue>) {
-        // Try to find topic by index
-        // Since message.topic_id is a u32, we can directly use it as index
-        // But we need to verify it's valid
-        let topic_idx = message.topic_id as usize;
-        
-        // Safety check: ensure topic_idx is within bounds
-        if topic_idx >= self.topics.len() {
-             return;
+    /// Compute the message ID prefix for a client: a 32-bit FNV-1a hash of its ID
+    pub fn message_id_prefix(client_id: &str) -> u64 {
+        let hash = client_id.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        });
+        (hash as u64) << 32
+    }
+
+    /// Fail with "Queue is closed" once the queue has been closed
+    pub fn check_open(&self) -> Result<(), JsValue> {
+        if self.closed {
+            return Err(JsValue::from_str(ERR_QUEUE_CLOSED));
+        }
+        Ok(())
+    }
+
+    /// Allocate the ID of the next message originating from this client
+    /// IDs never repeat within a client (until 2^32 messages) and differ
+    /// across clients unless their ID hashes collide.
+    pub fn next_message_id(&self) -> u64 {
+        let seq = self.next_message_seq.get();
+        self.next_message_seq.set(seq.wrapping_add(1));
+        self.message_id_prefix | seq as u64
+    }
+
+    /// Create a new message originating from this client
+    pub fn create_message(&mut self, topic_id: u32, payload: JsValue, options: PublishOptions) -> Rc<Message> {
+        let timestamp = js_sys::Date::now();
+        self.record_stat(topic_id, |stats| stats.published += 1);
+        let seq = match self.get_topic_by_id_mut(topic_id) {
+            Some(topic) if options.sequenced => {
+                topic.last_seq += 1;
+                topic.last_seq
+            }
+            _ => 0,
+        };
+        Rc::new(Message {
+            id: self.next_message_id(),
+            topic_id,
+            payload,
+            timestamp,
+            origin_id: self.client_id.clone(),
+            headers: options.headers,
+            expires_at: options.ttl_ms.map(|ttl_ms| timestamp + ttl_ms),
+            seq,
+        })
+    }
+
+    /// Increment a counter in the queue-wide and the topic's stats
+    pub fn record_stat(&mut self, topic_id: u32, record: impl Fn(&mut Stats)) {
+        record(&mut self.stats);
+        if let Some(topic) = self.get_topic_by_id_mut(topic_id) {
+            record(&mut topic.stats);
+        }
+    }
+
+    /// Store a message in its topic's buffer if buffering is enabled
+    /// Messages lost to a full buffer are counted in the topic's overflow count
+    /// Returns the lost messages: under `DropOldest` the displaced oldest one,
+    /// otherwise the new message, which is not buffered; plus the oldest
+    /// messages evicted to stay within the topic's byte budget
+    /// Messages from other tabs cannot be rejected, so under `Error` they are
+    /// handled as under `DropNewest`: delivered, but left out of the full buffer.
+    pub fn buffer_message(&mut self, message: &Rc<Message>) -> Vec<Rc<Message>> {
+        let own = *message.origin_id == *self.client_id;
+        let Some(topic) = self.get_topic_by_id_mut(message.topic_id).filter(|topic| topic.has_buffer()) else {
+            return Vec::new();
+        };
+        // Aged-out messages make room before the capacity applies
+        topic.evict_aged();
+        let size = topic.payload_size(&message.payload);
+        let too_large = topic.max_bytes.is_some_and(|max_bytes| size > max_bytes);
+        let full = topic.get_buffer().is_some_and(RingBuffer::is_full);
+        if too_large || (full && topic.overflow_policy != OverflowPolicy::DropOldest) {
+            topic.overflow_count += 1;
+            return vec![message.clone()];
+        }
+
+        let max_bytes = topic.max_bytes;
+        let Some(buffer) = topic.get_buffer_mut() else {
+            return Vec::new();
+        };
+        let mut lost: Vec<Rc<Message>> = buffer.push_sized(message.clone(), size).into_iter().collect();
+        let overwritten = lost.len() as u64;
+        if let Some(max_bytes) = max_bytes {
+            lost.extend(buffer.evict_to_bytes(max_bytes));
+        }
+        if own {
+            if let Some(persistence) = &topic.persistence {
+                persistence.enqueue(message, &topic.name);
+            }
+        }
+        topic.overflow_count += lost.len() as u64;
+        topic.stats.overwrites += overwritten;
+        self.stats.overwrites += overwritten;
+        lost
+    }
+
+    /// Reject publishing `payload` to a full buffer whose overflow policy is `Error`
+    /// The rejected message is counted in the topic's overflow count and, if the
+    /// topic has an overflow hook, recorded for `dispatch::notify_rejected`.
+    pub fn check_overflow(&mut self, topic_id: u32, payload: &JsValue) -> Result<(), JsValue> {
+        if !self.rejects_publishes(topic_id, 1) {
+            return Ok(());
+        }
+        let Some(topic) = self.get_topic_by_id_mut(topic_id) else {
+            return Ok(());
+        };
+        topic.overflow_count += 1;
+        if let Some(hook) = topic.overflow_hook.clone() {
+            let timestamp = js_sys::Date::now();
+            let rejected = Rc::new(Message {
+                id: self.next_message_id(),
+                topic_id,
+                payload: payload.clone(),
+                timestamp,
+                origin_id: self.client_id.clone(),
+                headers: None,
+                expires_at: None,
+                seq: 0,
+            });
+            self.rejected_publishes.push((hook, rejected));
+        }
+        Err(JsValue::from_str(ERR_BUFFER_FULL))
+    }
+
+    /// Check if a topic's buffer has no room for `count` more publishes under the overflow policy `Error`
+    pub fn rejects_publishes(&mut self, topic_id: u32, count: usize) -> bool {
+        let Some(topic) = self.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+        topic.evict_aged();
+        topic.overflow_policy == OverflowPolicy::Error
+            && topic.get_buffer().is_some_and(|buffer| buffer.len() + count > buffer.capacity())
+    }
+
+    /// Check if the dead-letter topic takes messages from `topic_id` for `reason`
+    fn dead_letters(&self, topic_id: u32, reason: DeadLetterReason) -> bool {
+        self.dead_letter.as_ref().is_some_and(|config| config.accepts(topic_id, reason))
+    }
+
+    /// Prepare a `Delivery` of `message` that routes lost messages to the
+    /// overflow dead letter and their topics' overflow hooks
+    fn report_losses(&self, message: &Rc<Message>, lost: Vec<Rc<Message>>) -> Delivery {
+        let mut dead_letters = Vec::new();
+        let mut overflow = Vec::new();
+        for msg in lost {
+            if self.dead_letters(msg.topic_id, DeadLetterReason::Overflow) {
+                dead_letters.push((msg.clone(), DeadLetterReason::Overflow));
+            }
+            if let Some(hook) = self.get_topic_by_id(msg.topic_id).and_then(|topic| topic.overflow_hook.clone()) {
+                overflow.push((hook, msg));
+            }
+        }
+        Delivery::losses(message.clone(), dead_letters, overflow)
+    }
+
+    /// Prepare delivery of a message published in this tab
+    /// In echo mode the message is held for the echo task instead, which
+    /// `dispatch::deliver` schedules, and nothing is delivered now.
+    pub fn dispatch_published(&mut self, message: &Rc<Message>) -> Option<Delivery> {
+        if self.echo {
+            self.pending_echoes.push(message.clone());
+            return None;
+        }
+        self.dispatch_local(message)
+    }
+
+    /// Prepare delivery of a message to all local subscribers of its topic
+    /// Buffers the message and updates subscriber state (skip counters, one-shot
+    /// removal, idle and replay queues) under the borrow. The returned `Delivery` must be
+    /// passed to `dispatch::deliver` after the borrow is released, so callbacks
+    /// can use the queue re-entrantly.
+    /// While the queue is paused, the message is buffered and held instead, and
+    /// the returned `Delivery` only reports the messages this lost.
+    pub fn dispatch_local(&mut self, message: &Rc<Message>) -> Option<Delivery> {
+        // Resolve the topic slot, rejecting IDs of destroyed topics
+        let topic_idx = self.topic_slot(message.topic_id)?;
+
+        // Expired messages are neither buffered nor delivered
+        if message.is_expired() {
+            return None;
+        }
+
+        if self.paused {
+            return self.hold_message(message);
+        }
+
+        // Guard against subscribers publishing in an endless loop
+        if self.dispatch_depth.get() >= self.max_dispatch_depth {
+            return Some(Delivery::depth_exceeded(message.clone()));
         }
         
-        if let Some(topic) = self.topics.get_mut(topic_idx) {
-            // Store message in buffer if buffering is enabled
-            if let Some(buffer) = topic.get_buffer_mut() {
-                buffer.push(message.clone());
-            }
-
-            // Optimization: Zero-allocation dispatch
-            // Instead of creating a JS object, we pass arguments directly to the callback.
-            // Signature: callback(payload, topic_id, timestamp, id)
-            // This avoids Reflect::set/get and object creation entirely.
-            
-            let this = JsValue::NULL;
-            let topic_id_val = JsValue::from(message.topic_id);
-            let timestamp_val = JsValue::from(message.timestamp);
-            // ID is u64, precision loss in JS Number (f64) is possible for values > 2^53
-            // But for our random usage it's fine, or we pass as BigInt if needed.
-            // For speed, let's pass as f64.
-            let id_val = JsValue::from(message.id as f64);
-
-            for sub in topic.subscribers.values() {
-                // call4 is faster than creating an array or object
-                if let Err(_) = sub.call4(&this, &message.payload, &topic_id_val, &timestamp_val, &id_val) {
-                    // console::error_1(&e); // Removed console logging for size optimization
+        let lost = self.buffer_message(message);
+        let losses = self.report_losses(message, lost);
+        let mut delivery = self.prepare_delivery(topic_idx, message, losses.dead_letters)?;
+        delivery.overflow = losses.overflow;
+        Some(delivery)
+    }
+
+    /// Prepare delivery of a message held while the queue was paused
+    /// The message was already buffered when it was held.
+    pub fn dispatch_held(&mut self, message: &Rc<Message>) -> Option<Delivery> {
+        let topic_idx = self.topic_slot(message.topic_id)?;
+        if message.is_expired() {
+            return None;
+        }
+        self.prepare_delivery(topic_idx, message, Vec::new())
+    }
+
+    /// Buffer a message published or received while paused and hold it for `resume`
+    /// Beyond `max_held_messages`, the oldest held message is dropped and, like a
+    /// message lost to a full buffer, counted in its topic's overflow count.
+    /// Returns a `Delivery` reporting the lost messages, if any.
+    fn hold_message(&mut self, message: &Rc<Message>) -> Option<Delivery> {
+        let mut lost = self.buffer_message(message);
+        if self.max_held_messages > 0 {
+            lost.extend(self.drop_held(self.max_held_messages - 1));
+            self.held_messages.push_back(message.clone());
+        }
+        if lost.is_empty() {
+            return None;
+        }
+        Some(self.report_losses(message, lost))
+    }
+
+    /// Change `max_held_messages`, dropping the oldest held messages beyond it
+    /// Returns a `Delivery` reporting the dropped messages, if any.
+    pub fn set_max_held_messages(&mut self, max: usize) -> Option<Delivery> {
+        self.max_held_messages = max;
+        let dropped = self.drop_held(max);
+        let first = dropped.first()?.clone();
+        Some(self.report_losses(&first, dropped))
+    }
+
+    /// Drop the oldest held messages until at most `keep` remain
+    /// The dropped messages are counted in their topics' overflow counts.
+    fn drop_held(&mut self, keep: usize) -> Vec<Rc<Message>> {
+        let excess = self.held_messages.len().saturating_sub(keep);
+        let dropped: Vec<Rc<Message>> = self.held_messages.drain(..excess).collect();
+        for message in &dropped {
+            if let Some(topic) = self.get_topic_by_id_mut(message.topic_id) {
+                topic.overflow_count += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Collect the subscriber callbacks for a message and update subscriber state
+    fn prepare_delivery(&mut self, topic_idx: usize, message: &Rc<Message>, mut dead_letters: Vec<(Rc<Message>, DeadLetterReason)>) -> Option<Delivery> {
+        let dead_letter_unheard = self.dead_letters(message.topic_id, DeadLetterReason::NoSubscribers)
+            && Rc::ptr_eq(&message.origin_id, &self.client_id);
+
+        let topic = self.topics.get_mut(topic_idx)?;
+        topic.total_dispatched += 1;
+        topic.last_message_time = Some(message.timestamp);
+
+        #[cfg(feature = "console-logging")]
+        if let Some(label) = &topic.log_label {
+            let header = JsValue::from_str(&format!("{} @ {}", label, message.timestamp));
+            web_sys::console::group_collapsed_1(&header);
+            web_sys::console::log_3(&JsValue::from_str(label), &JsValue::from(message.topic_id), &message.payload);
+            web_sys::console::group_end();
+        }
+
+        // Resolve matching pattern subscribers once per topic
+        let pattern_matches = topic.pattern_matches.get_or_insert_with(|| {
+            self.pattern_subscribers.iter()
+                .filter(|(_, sub)| topic_matches(&sub.pattern, &topic.name))
+                .map(|(sub_id, sub)| (*sub_id, sub.callback.clone()))
+                .collect()
+        });
+
+        let mut targets = Vec::with_capacity(topic.subscribers.len() + pattern_matches.len());
+        for (sub_id, callback) in pattern_matches.iter() {
+            targets.push(DeliveryTarget {
+                sub_id: *sub_id,
+                callback: callback.clone(),
+                raw: false,
+                filter: None,
+            });
+        }
+        let mut finished = Vec::new();
+        // Message object for raw subscribers, built on first use
+        let mut msg_obj: Option<JsValue> = None;
+
+        let topic_name = &topic.name;
+        topic.subscribers.for_each_mut(|sub_id, sub| {
+            if sub.paused {
+                return;
+            }
+
+            // Messages rejected by a key filter do not count towards skip or once.
+            // Predicate filters run at delivery, outside the borrow (see
+            // `dispatch::deliver_one`), so messages they reject still count.
+            if sub.filter.as_ref().is_some_and(|filter| !filter.accepts_key(&message.payload)) {
+                return;
+            }
+
+            if sub.skip_remaining > 0 {
+                sub.skip_remaining -= 1;
+                return;
+            }
+
+            if let Some(idle) = &sub.idle {
+                idle.enqueue(message.clone(), &sub.callback);
+            } else if let Some(deferred) = &sub.deferred {
+                deferred.borrow_mut().push_back(message.clone());
+            } else {
+                if sub.raw && msg_obj.is_none() {
+                    msg_obj = message_to_js_full(message, topic_name).ok();
+                }
+                targets.push(DeliveryTarget {
+                    sub_id,
+                    callback: sub.callback.clone(),
+                    raw: sub.raw,
+                    filter: sub.filter.as_ref().and_then(|filter| filter.predicate()).cloned(),
+                });
+            }
+
+            if sub.once {
+                finished.push(sub_id);
+            }
+        });
+
+        // Remove one-shot subscribers after iteration
+        for sub_id in finished {
+            topic.subscribers.remove(sub_id);
+        }
+
+        // Only messages published in this tab; other tabs may have listened
+        if dead_letter_unheard && topic.subscribers.is_empty() && pattern_matches.is_empty() && !topic.has_buffer() {
+            dead_letters.push((message.clone(), DeadLetterReason::NoSubscribers));
+        }
+
+        let globals = (!self.global_subscribers.is_empty()).then(|| {
+            let subscribers = self.global_subscribers.iter()
+                .map(|(sub_id, callback)| (*sub_id, callback.clone()))
+                .collect();
+            (topic.name.clone(), subscribers)
+        });
+        let topic_id_val = (!targets.is_empty() || globals.is_some()).then(|| {
+            topic.id_value.get_or_insert_with(|| JsValue::from(message.topic_id)).clone()
+        });
+
+        Some(Delivery {
+            message: message.clone(),
+            targets,
+            topic_id_val,
+            msg_obj,
+            depth_exceeded: false,
+            dead_letters,
+            overflow: Vec::new(),
+            globals,
+            #[cfg(feature = "wasm-logging")]
+            started: self.logs_at(LOG_DEBUG).then(crate::logging::now_ms),
+        })
+    }
+
+    /// Account for a publish nobody can observe, without creating the message
+    /// Returns false, changing nothing, unless the topic has no subscribers and
+    /// no buffer, nothing else listens (wildcard, global, dead-letter, other
+    /// tabs unless the topic is local-only) and the queue is not paused; the
+    /// message is then only counted.
+    pub fn publish_unobserved(&mut self, topic_id: u32) -> bool {
+        if self.paused
+            || !self.pattern_subscribers.is_empty()
+            || !self.global_subscribers.is_empty()
+            || self.dead_letters(topic_id, DeadLetterReason::NoSubscribers)
+        {
+            return false;
+        }
+        #[cfg(feature = "wasm-logging")]
+        if self.logs_at(LOG_DEBUG) {
+            return false;
+        }
+
+        let broadcasting = self.broadcasts_topic(topic_id);
+        let Some(topic) = self.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+        if !topic.subscribers.is_empty() || topic.has_buffer() || topic.log_label.is_some() {
+            return false;
+        }
+        if broadcasting {
+            return false;
+        }
+        topic.last_seq += 1;
+        topic.total_dispatched += 1;
+        topic.last_message_time = Some(js_sys::Date::now());
+        self.record_stat(topic_id, |stats| stats.published += 1);
+        true
+    }
+
+    /// Capacity-weighted average utilization of all topic buffers (0.0 - 1.0)
+    /// Returns 0.0 if no topic has buffering enabled
+    pub fn backpressure_indicator(&self) -> f64 {
+        let (len, capacity) = self.topics.iter()
+            .filter_map(|topic| topic.get_buffer())
+            .fold((0usize, 0usize), |(len, cap), buffer| {
+                (len + buffer.len(), cap + buffer.capacity())
+            });
+
+        if capacity == 0 {
+            0.0
+        } else {
+            len as f64 / capacity as f64
+        }
+    }
+
+    /// Update backpressure hook states and collect the callbacks that crossed their threshold
+    /// Rising crossings fire at `threshold`, falling crossings at `threshold - BACKPRESSURE_HYSTERESIS`
+    pub fn poll_backpressure(&mut self) -> Vec<(Function, f64)> {
+        if self.backpressure_hooks.is_empty() {
+            return Vec::new();
+        }
+
+        let utilization = self.backpressure_indicator();
+        let mut crossed = Vec::new();
+
+        for hook in self.backpressure_hooks.iter_mut() {
+            if !hook.above && utilization >= hook.threshold {
+                hook.above = true;
+                crossed.push((hook.callback.clone(), utilization));
+            } else if hook.above && utilization <= hook.threshold - BACKPRESSURE_HYSTERESIS {
+                hook.above = false;
+                crossed.push((hook.callback.clone(), utilization));
+            }
+        }
+
+        crossed
+    }
+
+    /// IDs of all topics carrying the given tag
+    pub fn topic_ids_with_tag(&self, tag: &str) -> Vec<u32> {
+        self.tag_index.get(tag).cloned().unwrap_or_default()
+    }
+
+    /// Add a tag to a topic, keeping the tag index in sync
+    /// Returns false if the topic ID is invalid
+    pub fn add_tag(&mut self, topic_id: u32, tag: &str) -> bool {
+        let Some(topic) = self.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+
+        if !topic.tags.iter().any(|t| t == tag) {
+            topic.tags.push(tag.to_string());
+            self.tag_index.entry(tag.to_string()).or_default().push(topic_id);
+        }
+        true
+    }
+
+    /// Remove a tag from a topic, keeping the tag index in sync
+    /// Returns true if the tag was present
+    pub fn remove_tag(&mut self, topic_id: u32, tag: &str) -> bool {
+        let Some(topic) = self.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+
+        let before = topic.tags.len();
+        topic.tags.retain(|t| t != tag);
+        if topic.tags.len() == before {
+            return false;
+        }
+
+        if let Some(ids) = self.tag_index.get_mut(tag) {
+            ids.retain(|&id| id != topic_id);
+            if ids.is_empty() {
+                self.tag_index.remove(tag);
+            }
+        }
+        true
+    }
+
+    /// Remove all tags from a topic
+    pub fn clear_tags(&mut self, topic_id: u32) {
+        let tags = match self.get_topic_by_id_mut(topic_id) {
+            Some(topic) => std::mem::take(&mut topic.tags),
+            None => return,
+        };
+
+        for tag in tags {
+            if let Some(ids) = self.tag_index.get_mut(&tag) {
+                ids.retain(|&id| id != topic_id);
+                if ids.is_empty() {
+                    self.tag_index.remove(&tag);
+                }
+            }
+        }
+    }
+
+    /// Pass an incoming broadcast message through all interceptors
+    /// Returns None if an interceptor returned `false` or threw
+    pub fn intercept_broadcast(&self, msg_val: JsValue) -> Option<JsValue> {
+        let mut current = msg_val;
+        for (_, interceptor) in &self.broadcast_interceptors {
+            let result = interceptor.call1(&JsValue::NULL, &current).ok()?;
+            if result.as_bool() == Some(false) {
+                return None;
+            }
+            if result.is_object() {
+                current = result;
+            }
+        }
+        Some(current)
+    }
+
+    /// Rename a topic, keeping its ID, subscribers and buffer
+    /// Returns None if `old_name` does not exist or `new_name` is taken
+    pub fn rename_topic(&mut self, old_name: &str, new_name: &str) -> Option<u32> {
+        if self.topic_index.contains_key(new_name) {
+            return None;
+        }
+
+        let slot = self.topic_index.remove(old_name)?;
+        self.topics[slot].name = new_name.to_string();
+        self.topics[slot].pattern_matches = None;
+        self.topic_index.insert(new_name.to_string(), slot);
+        Some(self.topic_handle(slot))
+    }
+
+    /// Register a wildcard subscriber and return its handle
+    /// Cached pattern matches are recomputed on the next dispatch of each topic
+    pub fn add_pattern_subscriber(&mut self, subscriber: PatternSubscriber) -> u32 {
+        let sub_id = self.next_pattern_sub_id;
+        self.next_pattern_sub_id = self.next_pattern_sub_id.wrapping_add(1);
+        self.pattern_subscribers.push((sub_id, subscriber));
+        self.invalidate_pattern_matches();
+        sub_id
+    }
+
+    /// Register a subscriber to every topic and return its handle
+    pub fn add_global_subscriber(&mut self, callback: Function) -> u32 {
+        let sub_id = self.next_global_sub_id;
+        self.next_global_sub_id = self.next_global_sub_id.wrapping_add(1);
+        self.global_subscribers.insert(sub_id, callback);
+        sub_id
+    }
+
+    /// Remove a wildcard subscriber
+    /// Returns false if the handle is unknown
+    pub fn remove_pattern_subscriber(&mut self, sub_id: u32) -> bool {
+        let before = self.pattern_subscribers.len();
+        self.pattern_subscribers.retain(|(id, _)| *id != sub_id);
+        if self.pattern_subscribers.len() == before {
+            return false;
+        }
+        self.invalidate_pattern_matches();
+        true
+    }
+
+    fn invalidate_pattern_matches(&mut self) {
+        for topic in self.topics.iter_mut() {
+            topic.pattern_matches = None;
+        }
+    }
+
+    /// Remove all topics with an ID of `len` or above
+    /// Used to roll back topics created by a failed transaction
+    pub fn truncate_topics(&mut self, len: usize) {
+        for slot in len..self.topics.len() {
+            self.clear_tags(self.topic_handle(slot));
+        }
+        for topic in self.topics.drain(len..) {
+            self.topic_index.remove(&topic.name);
+        }
+    }
+
+    /// Detach and drop all DOM event listeners
+    pub fn remove_event_listeners(&mut self) {
+        // Each listener detaches itself when dropped
+        self.event_listeners.clear();
+    }
+
+    /// Handle a message object received from another context (tab, worker)
+    /// Duplicate IDs are ignored, unknown topics are created, and messages
+    /// originating from this client are not dispatched again. Messages flagged
+    /// as retained become their topic's retained message, even if already seen.
+    pub fn receive_remote(&mut self, msg_val: &JsValue) -> Result<Option<Delivery>, JsValue> {
+        let (m, topic_name) = parse_js_message(msg_val)?;
+        Ok(self.receive_parsed(m, topic_name, is_retained(msg_val)))
+    }
+
+    /// Handle a binary PUB packet received from another context
+    /// With broadcast interceptors registered, the message is converted to its
+    /// object form first, so interceptors see the same shape for both formats.
+    pub fn receive_binary(&mut self, packet: &Uint8Array) -> Result<Option<Delivery>, JsValue> {
+        let (m, topic_name) = decode_message(packet)?;
+        if self.broadcast_interceptors.is_empty() {
+            return Ok(self.receive_parsed(m, topic_name, false));
+        }
+
+        match self.intercept_broadcast(message_to_js(&m, &topic_name)?) {
+            Some(msg_val) => self.receive_remote(&msg_val),
+            None => Ok(None),
+        }
+    }
+
+    /// Shared tail of `receive_remote` and `receive_binary`
+    fn receive_parsed(&mut self, mut m: Message, topic_name: String, retained: bool) -> Option<Delivery> {
+        let is_new = self.seen_ids.insert(m.id);
+        if !is_new && !retained {
+            return None;
+        }
+
+        // Resolve topic ID
+        m.topic_id = match self.topic_id_by_name(&topic_name) {
+            Some(topic_id) => topic_id,
+            None => {
+                // Names this tab would reject do not create topics either
+                if let Err(err) = self.validate_topic_name(&topic_name) {
+                    self.receive_errors.push((err, m.id));
+                    return None;
+                }
+                let topic_id = self.get_or_create_topic_id(&topic_name);
+                // Keep what arrives before anyone subscribes, for late subscribers to replay
+                if let Some(capacity) = self.remote_topic_buffer {
+                    if let Some(topic) = self.get_topic_by_id_mut(topic_id).filter(|topic| !topic.has_buffer()) {
+                        topic.enable_buffer(capacity);
+                    }
+                }
+                topic_id
+            }
+        };
+        let m = Rc::new(m);
+
+        if retained {
+            if let Some(topic) = self.get_topic_by_id_mut(m.topic_id) {
+                topic.retained = Some(m.clone());
+            }
+        }
+
+        if is_new && *m.origin_id != *self.client_id {
+            self.record_stat(m.topic_id, |stats| stats.received += 1);
+            self.track_sequence(&m, topic_name);
+            self.dispatch_local(&m)
+        } else {
+            None
+        }
+    }
+
+    /// Remove the queued async publish that was queued first, across all topics
+    pub fn pop_oldest_queued(&mut self) -> Option<QueuedPublish> {
+        let heap = self.priority_queues.values_mut()
+            .filter(|heap| !heap.is_empty())
+            .min_by_key(|heap| heap.iter().map(|queued| queued.seq).min())?;
+
+        let mut queued = std::mem::take(heap).into_vec();
+        let oldest = queued.iter().enumerate().min_by_key(|(_, queued)| queued.seq)?.0;
+        let removed = queued.swap_remove(oldest);
+        *heap = queued.into();
+        Some(removed)
+    }
+
+    /// Record the sequence number of a message from another client
+    /// Queues a `SequenceGap` (if a gap handler is set) when numbers were skipped.
+    /// Older or repeated numbers, e.g. from a sync response, are ignored.
+    pub fn track_sequence(&mut self, message: &Message, topic_name: String) {
+        if message.seq == 0 {
+            return;
+        }
+
+        let last_seqs = match self.remote_seqs.get_mut(message.origin_id.as_str()) {
+            Some(last_seqs) => last_seqs,
+            None => self.remote_seqs.entry(message.origin_id.to_string()).or_default(),
+        };
+        let last = last_seqs.entry(message.topic_id).or_insert(0);
+        if message.seq <= *last {
+            return;
+        }
+
+        // The first message seen from an origin sets the baseline
+        let expected = *last + 1;
+        let gap = *last > 0 && message.seq > expected;
+        *last = message.seq;
+
+        if gap && self.gap_handler.is_some() {
+            self.sequence_gaps.push(SequenceGap {
+                topic: topic_name,
+                origin: message.origin_id.to_string(),
+                expected,
+                received: message.seq,
+            });
+        }
+    }
+
+    /// Route a message received on the BroadcastChannel through the interceptors
+    /// Binary packets (see `wire`) are decoded without going through the object form.
+    pub fn receive_broadcast(&mut self, msg_val: JsValue) -> Option<Delivery> {
+        let result = match msg_val.dyn_ref::<Uint8Array>() {
+            Some(packet) => self.receive_binary(packet),
+            None => {
+                let msg_val = self.intercept_broadcast(msg_val.clone())?;
+                self.receive_remote(&msg_val)
+            }
+        };
+
+        #[cfg(feature = "wasm-logging")]
+        if let Err(err) = &result {
+            if self.logs_at(LOG_WARN) {
+                crate::logging::log_group(LOG_WARN, "Invalid broadcast message", &[
+                    ("message", msg_val),
+                    ("error", err.clone()),
+                ]);
+            }
+        }
+
+        result.ok().flatten()
+    }
+
+    /// Get a channel attached with `attach_channel` by its handle
+    pub fn channel(&self, handle: u32) -> Option<&Transport> {
+        self.channels.iter().find(|(id, _)| *id == handle).map(|(_, channel)| channel)
+    }
+
+    /// Handle of the channel a topic's messages are posted on, or None for the primary transports
+    pub fn topic_route(&self, topic_id: u32) -> Option<u32> {
+        self.get_topic_by_id(topic_id)?.route
+    }
+
+    /// Whether messages published on a topic are posted anywhere
+    /// False for local-only topics, and when the topic is neither routed to a
+    /// channel nor is there a primary transport.
+    pub fn broadcasts_topic(&self, topic_id: u32) -> bool {
+        match self.get_topic_by_id(topic_id) {
+            Some(topic) if !topic.local_only => topic.route.is_some() || !self.transports.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Post a packet on a routed channel, or with None on every primary transport
+    pub fn post_routed(&self, route: Option<u32>, packet: &JsValue) -> Result<(), JsValue> {
+        match route.and_then(|handle| self.channel(handle)) {
+            Some(channel) => channel.post(packet),
+            None => self.post_packet(packet),
+        }
+    }
+
+    /// Post message objects in BATCH packets, one per channel they are routed to
+    /// Each packet keeps its messages in the given order.
+    pub fn post_batch(&self, msgs: impl IntoIterator<Item = (Option<u32>, JsValue)>) -> Result<(), JsValue> {
+        let mut batches: Vec<(Option<u32>, Array)> = Vec::new();
+        for (route, msg) in msgs {
+            match batches.iter().find(|(batch_route, _)| *batch_route == route) {
+                Some((_, batch)) => {
+                    batch.push(&msg);
                 }
+                None => batches.push((route, Array::of1(&msg))),
             }
         }
+
+        for (route, msgs) in batches {
+            // BATCH: [5, [msg1, msg2, ...]]
+            let packet = Array::new();
+            packet.push(&JsValue::from(PACKET_BATCH));
+            packet.push(&msgs);
+            self.post_routed(route, &packet)?;
+        }
+        Ok(())
+    }
+
+    /// Post a packet on every transport
+    pub fn post_packet(&self, packet: &JsValue) -> Result<(), JsValue> {
+        for transport in &self.transports {
+            transport.post(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Post a packet answering one received from `source` (the event's `currentTarget`)
+    /// The answer goes back on the transport the packet arrived on. When that is
+    /// unknown (localStorage events), it goes on the named channel but not on ports.
+    pub fn post_reply(&self, packet: &JsValue, source: &JsValue) -> Result<(), JsValue> {
+        if let Some(transport) = self.transports.iter().find(|transport| transport.is_target(source)) {
+            return transport.post(packet);
+        }
+        for transport in self.transports.iter().filter(|transport| transport.is_channel()) {
+            transport.post(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Post a packet, transferring the objects in `transfer_list` where supported
+    /// A buffer can only be transferred once, so every other transport gets a
+    /// copy first and the last transport able to transfer receives the originals.
+    pub fn post_packet_with_transfer(&self, packet: &JsValue, transfer_list: &Array) -> Result<(), JsValue> {
+        let transferring = self.transports.iter().rposition(Transport::supports_transfer);
+        for (i, transport) in self.transports.iter().enumerate() {
+            if Some(i) != transferring {
+                transport.post(packet)?;
+            } else {
+                transport.post_with_transfer(packet, transfer_list)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close every transport and attached channel and stop listening for packets
+    pub fn close_transports(&mut self) {
+        for transport in self.transports.drain(..) {
+            transport.close();
+        }
+        for (_, channel) in self.channels.drain(..) {
+            channel.close();
+        }
+    }
+
+    /// Post all throttled broadcasts as a single batch packet per channel
+    /// Returns the number of messages flushed
+    pub fn flush_broadcasts(&mut self) -> Result<usize, JsValue> {
+        self.broadcast_flush_scheduled = false;
+        if self.pending_broadcasts.is_empty() {
+            return Ok(0);
+        }
+
+        let msgs = std::mem::take(&mut self.pending_broadcasts);
+        let count = msgs.len();
+        self.post_batch(msgs)?;
+        Ok(count)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_support::{buffered_topic, message, msg, queued};
+    use crate::types::{Subscriber, Topic, TopicEvent};
+
+    fn buffered_ids(queue: &InnerQueue, topic_id: u32) -> Vec<u64> {
+        queue.get_topic_by_id(topic_id).unwrap().get_buffer().unwrap().iter().map(|msg| msg.id).collect()
+    }
+
+    #[test]
+    fn test_backpressure_indicator() {
+        let mut queue = InnerQueue::default();
+        assert_eq!(queue.backpressure_indicator(), 0.0);
+
+        queue.topics.push(Topic::with_buffer("a".to_string(), 4));
+        queue.topics.push(Topic::with_buffer("b".to_string(), 12));
+        queue.topics.push(Topic::new("c".to_string()));
+
+        for id in 0..4 {
+            queue.topics[0].get_buffer_mut().unwrap().push(msg(id, 0));
+        }
+
+        // 4 of 16 total slots used across both buffers
+        assert_eq!(queue.backpressure_indicator(), 0.25);
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let mut queue = InnerQueue::default();
+        let a = queue.get_or_create_topic_id("a");
+        let b = queue.get_or_create_topic_id("b");
 
+        assert!(queue.add_tag(a, "ui"));
+        assert!(queue.add_tag(b, "ui"));
+        assert!(queue.add_tag(a, "ui")); // duplicate is a no-op
+        assert!(!queue.add_tag(99, "ui"));
+        assert_eq!(queue.topic_ids_with_tag("ui"), vec![a, b]);
+
+        assert!(queue.remove_tag(a, "ui"));
+        assert!(!queue.remove_tag(a, "ui"));
+        assert_eq!(queue.topic_ids_with_tag("ui"), vec![b]);
+
+        queue.clear_tags(b);
+        assert!(queue.topic_ids_with_tag("ui").is_empty());
+        assert!(queue.tag_index.is_empty());
+    }
+
+    #[test]
+    fn test_rename_topic() {
+        let mut queue = InnerQueue::default();
+        let id = queue.get_or_create_topic_id("old");
+        queue.get_or_create_topic_id("taken");
+
+        assert_eq!(queue.rename_topic("old", "taken"), None);
+        assert_eq!(queue.rename_topic("missing", "new"), None);
+        assert_eq!(queue.rename_topic("old", "new"), Some(id));
+
+        assert_eq!(queue.get_topic_by_id(id).unwrap().name, "new");
+        assert!(!queue.topic_index.contains_key("old"));
+        assert_eq!(queue.get_or_create_topic_id("new"), id);
+    }
+
+    #[test]
+    fn test_truncate_topics_rolls_back_index() {
+        let mut queue = InnerQueue::default();
+        queue.get_or_create_topic_id("kept");
+        let rolled_back = queue.get_or_create_topic_id("rolled-back");
+        queue.add_tag(rolled_back, "tx");
+
+        queue.truncate_topics(1);
+
+        assert_eq!(queue.topics.len(), 1);
+        assert!(queue.topic_index.contains_key("kept"));
+        assert!(!queue.topic_index.contains_key("rolled-back"));
+        assert!(queue.topic_ids_with_tag("tx").is_empty());
+    }
+
+    #[test]
+    fn test_removed_topic_slot_is_reused() {
+        let mut queue = InnerQueue::default();
+        let a = queue.get_or_create_topic_id("a");
+        queue.get_or_create_topic_id("b");
+        queue.add_tag(a, "ui");
+
+        assert!(queue.remove_topic(a).is_some());
+        assert!(queue.remove_topic(a).is_none());
+        assert!(!queue.has_topic_id(a));
+        assert!(queue.topic_ids_with_tag("ui").is_empty());
+
+        // The freed slot is reused under a new ID; the stale ID stays invalid
+        let c = queue.get_or_create_topic_id("c");
+        assert_eq!(queue.topics.len(), 2);
+        assert_ne!(c, a);
+        assert!(queue.get_topic_by_id(a).is_none());
+        assert_eq!(queue.get_topic_by_id(c).unwrap().name, "c");
+
+        // The destroyed name can be registered again
+        let a2 = queue.get_or_create_topic_id("a");
+        assert!(queue.has_topic_id(a2));
+        assert_ne!(a2, a);
+    }
+
+    #[test]
+    fn test_message_ids_are_unique() {
+        use std::cell::Cell;
+        use std::collections::HashSet;
+        use crate::dedup::DedupWindow;
+
+        let client = |id: &str| InnerQueue {
+            message_id_prefix: InnerQueue::message_id_prefix(id),
+            next_message_seq: Cell::new(0),
+            ..Default::default()
+        };
+        let a = client("6f1c2b9e-8d4a-4f55-9c1e-2a7b3d4e5f60");
+        let b = client("0b8e7d6c-5a4f-4e3d-8c2b-1a0f9e8d7c6b");
+        assert_ne!(a.message_id_prefix, b.message_id_prefix);
+
+        // Interleave 100k messages from two clients as a receiver would see them
+        let mut ids = HashSet::new();
+        let mut window = DedupWindow::new(200_000);
+        for _ in 0..50_000 {
+            for id in [a.next_message_id(), b.next_message_id()] {
+                assert!(ids.insert(id), "duplicate message ID {}", id);
+                assert!(window.insert(id), "message {} dropped as a duplicate", id);
+            }
+        }
+        assert_eq!(ids.len(), 100_000);
+    }
+
+    #[test]
+    fn test_pop_oldest_queued() {
+        let mut queue = InnerQueue::default();
+        assert!(queue.pop_oldest_queued().is_none());
+        queue.priority_queues.entry(1).or_default().extend([queued(255, 3), queued(0, 1)]);
+        queue.priority_queues.entry(2).or_default().extend([queued(128, 0), queued(128, 2)]);
+
+        // Oldest regardless of topic or priority
+        let seqs: Vec<u64> = std::iter::from_fn(|| queue.pop_oldest_queued()).map(|q| q.seq).collect();
+        assert_eq!(seqs, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let mut queue = InnerQueue::default();
+        let topic_id = queue.get_or_create_topic_id("stream");
+        queue.gap_handler = Some(JsValue::UNDEFINED.unchecked_into());
+        let mut receive = |seq| {
+            queue.track_sequence(&Message { seq, ..message(seq, topic_id) }, "stream".to_string());
+        };
+
+        // Joining mid-stream, repeats and late arrivals are not gaps
+        for seq in [5, 6, 6, 4, 7] {
+            receive(seq);
+        }
+        receive(10);
+        receive(11);
+        receive(13);
+
+        let gaps: Vec<(u64, u64)> = queue.sequence_gaps.iter().map(|gap| (gap.expected, gap.received)).collect();
+        assert_eq!(gaps, vec![(8, 10), (12, 13)]);
+        assert_eq!(queue.sequence_gaps[0].origin, "other-tab");
+        assert_eq!(queue.sequence_gaps[0].topic, "stream");
+    }
+
+    #[test]
+    fn test_paused_queue_holds_messages() {
+        let mut queue = InnerQueue::default();
+        let topic_id = buffered_topic(&mut queue, "paused", 8);
+        queue.paused = true;
+        queue.max_held_messages = 2;
+
+        for id in 1..=3 {
+            // Only the third message displaces a held one, and its delivery reports just that
+            let delivery = queue.dispatch_local(&msg(id, topic_id));
+            assert_eq!(delivery.is_some(), id == 3);
+            assert!(delivery.is_none_or(|delivery| delivery.targets.is_empty()));
+        }
+
+        // Every message is buffered, but only the newest ones are held
+        assert_eq!(buffered_ids(&queue, topic_id), vec![1, 2, 3]);
+        let held: Vec<u64> = queue.held_messages.iter().map(|msg| msg.id).collect();
+        assert_eq!(held, vec![2, 3]);
+        assert_eq!(queue.get_topic_by_id(topic_id).unwrap().overflow_count, 1);
+    }
+
+    #[test]
+    fn test_paused_subscriber_is_skipped() {
+        let mut queue = InnerQueue::default();
+        let topic_id = queue.get_or_create_topic_id("paused-sub");
+        let mut subscriber = Subscriber::new(JsValue::UNDEFINED.unchecked_into());
+        subscriber.once = true;
+        subscriber.paused = true;
+        let sub_id = queue.get_topic_by_id_mut(topic_id).unwrap().add_subscriber(subscriber);
+
+        let delivery = queue.dispatch_local(&msg(1, topic_id)).unwrap();
+        assert!(delivery.targets.is_empty());
+
+        // A paused `once` subscriber is not consumed
+        assert!(queue.get_topic_by_id(topic_id).unwrap().subscribers.contains_key(sub_id));
+    }
+
+    #[test]
+    fn test_skipping_subscriber() {
+        let mut queue = InnerQueue::default();
+        let topic_id = queue.get_or_create_topic_id("skip");
+        let mut subscriber = Subscriber::new(JsValue::UNDEFINED.unchecked_into());
+        subscriber.skip_remaining = 2;
+        let sub_id = queue.get_topic_by_id_mut(topic_id).unwrap().add_subscriber(subscriber);
+
+        // Each skipped message counts down; delivery afterwards is covered by the wasm tests
+        for (id, remaining) in [(1, 1), (2, 0)] {
+            assert!(queue.dispatch_local(&msg(id, topic_id)).unwrap().targets.is_empty());
+            let topic = queue.get_topic_by_id(topic_id).unwrap();
+            assert_eq!(topic.subscribers.get(sub_id).unwrap().skip_remaining, remaining);
+        }
+    }
+
+    #[test]
+    fn test_dispatch_beyond_max_depth_is_skipped() {
+        let mut queue = InnerQueue { max_dispatch_depth: 2, ..Default::default() };
+        let topic_id = buffered_topic(&mut queue, "nested", 4);
+        queue.dispatch_depth.set(2);
+
+        // Neither buffered nor delivered, only reported
+        let delivery = queue.dispatch_local(&msg(1, topic_id)).unwrap();
+        assert!(delivery.depth_exceeded);
+        assert!(delivery.targets.is_empty());
+        assert_eq!(queue.get_topic_by_id(topic_id).unwrap().get_buffer().unwrap().len(), 0);
+
+        queue.dispatch_depth.set(1);
+        assert!(!queue.dispatch_local(&msg(2, topic_id)).unwrap().depth_exceeded);
+        assert_eq!(buffered_ids(&queue, topic_id), vec![2]);
+    }
+
+    #[test]
+    fn test_stats_counters() {
+        let mut queue = InnerQueue::default();
+        let topic_id = buffered_topic(&mut queue, "counted", 1);
+        let other_id = queue.get_or_create_topic_id("other");
+
+        for id in 1..=3 {
+            queue.buffer_message(&msg(id, topic_id));
+        }
+        queue.record_stat(other_id, |stats| stats.received += 1);
+
+        assert_eq!(queue.stats.overwrites, 2);
+        assert_eq!(queue.stats.received, 1);
+        let topic = queue.get_topic_by_id_mut(topic_id).unwrap();
+        assert_eq!(topic.stats.overwrites, 2);
+        assert_eq!(topic.stats.received, 0);
+
+        // Counters survive disabling the buffer
+        topic.disable_buffer();
+        assert_eq!(topic.stats.overwrites, 2);
+    }
+
+    #[test]
+    fn test_buffer_overflow_policies() {
+        assert_eq!(OverflowPolicy::parse("drop-newest"), Some(OverflowPolicy::DropNewest));
+        assert_eq!(OverflowPolicy::parse("drop_newest"), None);
+
+        let mut queue = InnerQueue::default();
+        let newest = buffered_topic(&mut queue, "newest", 2);
+        let strict = buffered_topic(&mut queue, "strict", 2);
+        queue.get_topic_by_id_mut(newest).unwrap().overflow_policy = OverflowPolicy::DropNewest;
+        queue.get_topic_by_id_mut(strict).unwrap().overflow_policy = OverflowPolicy::Error;
+
+        // Drop-newest keeps the head of history and hands back the new message
+        for id in 1..=2 {
+            assert!(queue.buffer_message(&msg(id, newest)).is_empty());
+        }
+        assert_eq!(queue.buffer_message(&msg(3, newest))[0].id, 3);
+        assert_eq!(buffered_ids(&queue, newest), vec![1, 2]);
+        assert_eq!(queue.get_topic_by_id(newest).unwrap().overflow_count, 1);
+        assert_eq!(queue.stats.overwrites, 0);
+
+        // Error lets publishing through until the buffer is full (the rejection
+        // itself is covered by the wasm tests); other tabs' messages are dropped
+        for id in 1..=2 {
+            assert!(queue.check_overflow(strict, &JsValue::NULL).is_ok());
+            queue.buffer_message(&msg(id, strict));
+        }
+        assert!(queue.rejects_publishes(strict, 1));
+        assert!(queue.check_overflow(newest, &JsValue::NULL).is_ok());
+        assert_eq!(queue.buffer_message(&msg(3, strict))[0].id, 3);
+        assert_eq!(queue.get_topic_by_id(strict).unwrap().overflow_count, 1);
+    }
+
+    #[test]
+    fn test_topic_events() {
+        let mut queue = InnerQueue::default();
+        // Without hooks nothing is recorded
+        let early = queue.get_or_create_topic_id("early");
+        assert!(queue.topic_events.is_empty());
+
+        queue.topic_hooks.push((0, TopicEvent::Created, JsValue::UNDEFINED.unchecked_into()));
+        let a = queue.get_or_create_topic_id("a");
+        assert_eq!(queue.get_or_create_topic_id("a"), a);
+        assert_eq!(queue.topic_events, vec![(TopicEvent::Created, a, "a".to_string())]);
+
+        // Destruction is only recorded once something listens for it
+        queue.remove_topic(early);
+        assert_eq!(queue.topic_events.len(), 1);
+        queue.topic_hooks.push((1, TopicEvent::Destroyed, JsValue::UNDEFINED.unchecked_into()));
+        queue.remove_topic(a);
+        assert!(queue.remove_topic(a).is_none());
+        assert_eq!(queue.topic_events[1], (TopicEvent::Destroyed, a, "a".to_string()));
+
+        // A reused slot is announced under its new ID
+        let b = queue.get_or_create_topic_id("b");
+        assert_ne!(b, a);
+        assert_eq!(queue.topic_events[2], (TopicEvent::Created, b, "b".to_string()));
+        assert_eq!(queue.topic_events.len(), 3);
+    }
+
+    #[test]
+    fn test_default_buffer_capacity() {
+        let mut queue = InnerQueue::default();
+        let before = queue.get_or_create_topic_id("before");
+        queue.default_buffer_capacity = Some(3);
+        let reused = queue.get_or_create_topic_id("before");
+        let after = queue.get_or_create_topic_id("after");
+        let pushed = queue.push_topic("pushed");
+
+        // Only topics created afterwards get a buffer
+        assert_eq!(reused, before);
+        assert!(!queue.get_topic_by_id(before).unwrap().has_buffer());
+        assert_eq!(queue.get_topic_by_id(after).unwrap().get_buffer().unwrap().capacity(), 3);
+        assert_eq!(queue.get_topic_by_id(pushed).unwrap().get_buffer().unwrap().capacity(), 3);
+
+        // Also when reusing the slot of a destroyed topic
+        queue.remove_topic(before);
+        let recycled = queue.get_or_create_topic_id("recycled");
+        assert_eq!(queue.get_topic_by_id(recycled).unwrap().get_buffer().unwrap().capacity(), 3);
+    }
+
+    #[test]
+    fn test_topic_name_normalization() {
+        let mut queue = InnerQueue::default();
+        assert_eq!(queue.normalize_topic_name(" Orders "), " Orders ");
+
+        queue.trim_topic_names = true;
+        queue.fold_topic_name_case = true;
+        assert_eq!(queue.normalize_topic_name(" Orders/New "), "orders/new");
+        assert!(queue.validate_topic_name("orders").is_ok());
+
+        // Names are measured in characters, not bytes
+        queue.max_topic_name_len = 3;
+        assert!(queue.validate_topic_name("äöü").is_ok());
+        assert_eq!(queue.get_or_create_valid_topic_id("abc").unwrap(), queue.topic_id_by_name("abc").unwrap());
+    }
+}
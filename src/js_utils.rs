@@ -1,7 +1,9 @@
 use wasm_bindgen::prelude::*;
-use js_sys::{Function, Array};
+use js_sys::{Function, Array, ArrayBuffer, JsString, Object, Reflect, Uint8Array};
 use std::rc::Rc;
-use crate::types::Message;
+use crate::types::{Message, SequenceGap, Stats, Topic};
+use crate::constants::{OBJECT_SIZE_ESTIMATE, PRIMITIVE_SIZE_ESTIMATE};
+use web_sys::EventTarget;
 
 thread_local! {
     static MSG_FACTORY: Function = Function::new_with_args(
@@ -11,8 +13,55 @@ thread_local! {
     
     static MSG_EXTRACTOR: Function = Function::new_with_args(
         "obj",
-        "return [obj.id, obj.topic, obj.payload, obj.timestamp, obj.origin_id];"
+        "return [obj.id, obj.topic, obj.payload, obj.timestamp, obj.origin_id, obj.headers, obj.expires_at, obj.seq];"
     );
+
+    static FULL_MSG_FACTORY: Function = Function::new_with_args(
+        "id, topic, topic_id, payload, timestamp, origin_id",
+        "return {payload: payload, topic_id: topic_id, topic: topic, timestamp: timestamp, id: id, origin_id: origin_id};"
+    );
+
+    static BRIDGE_FRAME: Function = Function::new_with_args(
+        "m",
+        "return JSON.stringify({topic: m.topic, id: String(m.id), payload: m.payload, timestamp: m.timestamp, origin_id: m.origin_id});"
+    );
+
+    static SETTLE_WITH: Function = Function::new_with_args(
+        "value, callback",
+        "Promise.resolve(value).then(function(v) { callback(true, v); }, function(e) { callback(false, e); });"
+    );
+
+    static EVENT_DISPATCHER_FACTORY: Function = Function::new_with_args(
+        "target",
+        "return function(m) { target.dispatchEvent(new CustomEvent(m.topic, {detail: {payload: m.payload, id: m.id, timestamp: m.timestamp, origin_id: m.origin_id}})); };"
+    );
+
+    static ONCE_RESOLVER_FACTORY: Function = Function::new_with_args(
+        "resolve",
+        "return function(payload, topic_id, timestamp, id) { resolve({payload: payload, id: id, timestamp: timestamp, topic_id: topic_id}); };"
+    );
+}
+
+/// Create a subscriber callback that resolves a Promise with the delivered message
+/// The Promise resolves with {payload, id, timestamp, topic_id}
+pub fn once_resolver(resolve: &Function) -> Result<Function, JsValue> {
+    ONCE_RESOLVER_FACTORY.with(|factory| {
+        factory.call1(&JsValue::NULL, resolve).map(|f| f.unchecked_into())
+    })
+}
+
+/// Create a raw subscriber callback that dispatches each message on `target`
+/// as a CustomEvent named after the topic, with detail {payload, id, timestamp, origin_id}
+pub fn event_dispatcher(target: &EventTarget) -> Result<Function, JsValue> {
+    EVENT_DISPATCHER_FACTORY.with(|factory| {
+        factory.call1(&JsValue::NULL, target).map(|f| f.unchecked_into())
+    })
+}
+
+/// Call `callback(true, value)` once `value` (a Promise or plain value) resolves,
+/// or `callback(false, error)` if it rejects
+pub fn settle_with(value: &JsValue, callback: &Function) -> Result<(), JsValue> {
+    SETTLE_WITH.with(|settle| settle.call2(&JsValue::NULL, value, callback).map(|_| ()))
 }
 
 /// Convert a Message struct to a JavaScript object
@@ -20,22 +69,78 @@ thread_local! {
 pub fn message_to_js(msg: &Message, topic_name: &str) -> Result<JsValue, JsValue> {
     MSG_FACTORY.with(|factory| {
         // Use explicit type annotations to help rust-analyzer
-        let id_val: JsValue = JsValue::from(msg.id as f64);
+        let id_val: JsValue = JsValue::from(msg.id);
         let topic_val: JsValue = topic_name.into();
         let timestamp_val: JsValue = msg.timestamp.into();
         let origin_id_val: JsValue = msg.origin_id.as_str().into();
 
-        factory.call5(
+        let obj = factory.call5(
             &JsValue::NULL,
             &id_val,
             &topic_val,
             &msg.payload,
             &timestamp_val,
             &origin_id_val
-        )
+        )?;
+        set_optional_fields(&obj, msg)?;
+        Ok(obj)
+    })
+}
+
+/// Add a message's optional fields (headers, expiry, sequence number) to its JS object
+/// Fields the message does not have are omitted
+fn set_optional_fields(obj: &JsValue, msg: &Message) -> Result<(), JsValue> {
+    if let Some(headers) = &msg.headers {
+        Reflect::set(obj, &"headers".into(), headers)?;
+    }
+    if let Some(expires_at) = msg.expires_at {
+        Reflect::set(obj, &"expires_at".into(), &expires_at.into())?;
+    }
+    if msg.seq > 0 {
+        Reflect::set(obj, &"seq".into(), &(msg.seq as f64).into())?;
+    }
+    Ok(())
+}
+
+/// Convert a Message struct to the object passed to raw subscribers
+/// A superset of `message_to_js` that also carries the numeric topic ID,
+/// so it can still be parsed with `parse_js_message`
+pub fn message_to_js_full(msg: &Message, topic_name: &str) -> Result<JsValue, JsValue> {
+    FULL_MSG_FACTORY.with(|factory| {
+        let obj = factory.call6(
+            &JsValue::NULL,
+            &JsValue::from(msg.id),
+            &topic_name.into(),
+            &JsValue::from(msg.topic_id),
+            &msg.payload,
+            &msg.timestamp.into(),
+            &msg.origin_id.as_str().into(),
+        )?;
+        set_optional_fields(&obj, msg)?;
+        Ok(obj)
     })
 }
 
+/// Serialize a raw subscriber's message object to a WebSocket bridge frame
+/// The ID is sent as a string, since JSON has no BigInt.
+pub fn bridge_frame(msg_obj: &JsValue) -> Result<String, JsValue> {
+    BRIDGE_FRAME.with(|frame| frame.call1(&JsValue::NULL, msg_obj))
+        .ok()
+        .and_then(|frame| frame.as_string())
+        .ok_or_else(|| JsValue::from_str("Payload is not JSON-serializable"))
+}
+
+/// Flag a broadcast message object as a retained message
+pub fn mark_retained(msg_obj: &JsValue) -> Result<(), JsValue> {
+    Reflect::set(msg_obj, &"retained".into(), &JsValue::TRUE)?;
+    Ok(())
+}
+
+/// Check if a received message object carries the retained flag
+pub fn is_retained(msg_obj: &JsValue) -> bool {
+    Reflect::get(msg_obj, &"retained".into()).is_ok_and(|v| v.is_truthy())
+}
+
 /// Parse a JavaScript object into a Message struct
 /// Returns (Message, String) tuple where String is the topic name
 pub fn parse_js_message(val: &JsValue) -> Result<(Message, String), JsValue> {
@@ -52,14 +157,14 @@ pub fn parse_js_message(val: &JsValue) -> Result<(Message, String), JsValue> {
             }
         };
 
-        // ID can be string or number from JS
+        // ID is a BigInt from our system; numbers and strings are accepted from other senders
         let id_val = check_val(arr.get(0), "id")?;
-        let id = if let Some(n) = id_val.as_f64() {
+        let id = if id_val.is_bigint() {
+            u64::try_from(id_val).map_err(|_| JsValue::from_str("Invalid id value"))?
+        } else if let Some(n) = id_val.as_f64() {
             n as u64
         } else if let Some(s) = id_val.as_string() {
-            // Try parsing string to u64, fallback to hash if needed, or 0
-            // For now assuming it's a number if coming from our system
-            s.parse::<u64>().unwrap_or(0)
+            s.parse::<u64>().map_err(|_| JsValue::from_str("Invalid id value"))?
         } else {
             return Err(JsValue::from_str("Invalid id type"));
         };
@@ -75,6 +180,11 @@ pub fn parse_js_message(val: &JsValue) -> Result<(Message, String), JsValue> {
         let origin_id = check_val(arr.get(4), "origin_id")?.as_string()
             .ok_or_else(|| JsValue::from_str("Invalid origin_id type"))?;
 
+        let headers = arr.get(5);
+        let headers = headers.is_object().then(|| headers.unchecked_into());
+        let expires_at = arr.get(6).as_f64();
+        let seq = arr.get(7).as_f64().map_or(0, |seq| seq as u64);
+
         // Note: topic_id will be resolved by the caller using topic_name
         Ok((Message {
             id,
@@ -82,6 +192,143 @@ pub fn parse_js_message(val: &JsValue) -> Result<(Message, String), JsValue> {
             payload,
             timestamp,
             origin_id: Rc::new(origin_id),
+            headers,
+            expires_at,
+            seq,
         }, topic_name))
     })
 }
+
+/// Build a metadata snapshot object for a topic
+/// Fields that do not apply to the topic are omitted rather than set to null
+pub fn topic_metadata_to_js(id: u32, topic: &Topic) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(&obj, &"id".into(), &id.into())?;
+    Reflect::set(&obj, &"name".into(), &topic.name.as_str().into())?;
+    Reflect::set(&obj, &"subscriber_count".into(), &(topic.subscribers.len() as u32).into())?;
+    Reflect::set(&obj, &"has_buffer".into(), &topic.has_buffer().into())?;
+
+    if let Some(buffer) = topic.get_buffer() {
+        Reflect::set(&obj, &"buffer_size".into(), &(buffer.len() as u32).into())?;
+        Reflect::set(&obj, &"buffer_capacity".into(), &(buffer.capacity() as u32).into())?;
+        Reflect::set(&obj, &"overflow_count".into(), &(topic.overflow_count as f64).into())?;
+    }
+
+    Reflect::set(&obj, &"total_dispatched".into(), &(topic.total_dispatched as f64).into())?;
+
+    if topic.stream_dropped > 0 {
+        Reflect::set(&obj, &"stream_dropped".into(), &(topic.stream_dropped as f64).into())?;
+    }
+
+    if let Some(time) = topic.last_message_time {
+        Reflect::set(&obj, &"last_message_time".into(), &time.into())?;
+    }
+
+    Reflect::set(&obj, &"is_replay_subject".into(), &topic.is_replay_subject.into())?;
+
+    if !topic.tags.is_empty() {
+        let tags: Array = topic.tags.iter().map(|t| JsValue::from_str(t)).collect();
+        Reflect::set(&obj, &"tags".into(), &tags)?;
+    }
+
+    Ok(obj.into())
+}
+
+/// Build the compact topic snapshot used by topic discovery
+pub fn topic_summary_to_js(id: u32, topic: &Topic) -> Result<JsValue, JsValue> {
+    let (buffered, capacity) = topic.get_buffer()
+        .map_or((0, 0), |buffer| (buffer.len(), buffer.capacity()));
+
+    let obj = Object::new();
+    Reflect::set(&obj, &"id".into(), &id.into())?;
+    Reflect::set(&obj, &"name".into(), &topic.name.as_str().into())?;
+    Reflect::set(&obj, &"subscribers".into(), &(topic.subscribers.len() as u32).into())?;
+    Reflect::set(&obj, &"buffered".into(), &(buffered as u32).into())?;
+    Reflect::set(&obj, &"buffer_capacity".into(), &(capacity as u32).into())?;
+    Reflect::set(&obj, &"local_only".into(), &topic.local_only.into())?;
+    Reflect::set(&obj, &"broadcast_only".into(), &topic.broadcast_only.into())?;
+    Ok(obj.into())
+}
+
+/// Build a plain object from a set of message counters
+pub fn stats_to_js(stats: &Stats) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(&obj, &"published".into(), &(stats.published as f64).into())?;
+    Reflect::set(&obj, &"delivered".into(), &(stats.delivered as f64).into())?;
+    Reflect::set(&obj, &"errors".into(), &(stats.errors as f64).into())?;
+    Reflect::set(&obj, &"broadcast".into(), &(stats.broadcast as f64).into())?;
+    Reflect::set(&obj, &"received".into(), &(stats.received as f64).into())?;
+    Reflect::set(&obj, &"overwrites".into(), &(stats.overwrites as f64).into())?;
+    Reflect::set(&obj, &"rejected_subscribes".into(), &(stats.rejected_subscribes as f64).into())?;
+    Ok(obj.into())
+}
+
+/// Cheaply estimate the memory used by a payload, for byte-budgeted buffers
+/// Strings count two bytes per UTF-16 code unit and binary data its byte length.
+/// Objects are measured by `sizer` if given; a sizer that throws or returns
+/// anything but a non-negative number falls back to `OBJECT_SIZE_ESTIMATE`.
+pub fn estimate_payload_size(payload: &JsValue, sizer: Option<&Function>) -> usize {
+    if let Some(string) = payload.dyn_ref::<JsString>() {
+        return string.length() as usize * 2;
+    }
+    if let Some(buffer) = payload.dyn_ref::<ArrayBuffer>() {
+        return buffer.byte_length() as usize;
+    }
+    if ArrayBuffer::is_view(payload) {
+        // Every ArrayBufferView (typed arrays, DataView) has a byteLength
+        return payload.unchecked_ref::<Uint8Array>().byte_length() as usize;
+    }
+    if !payload.is_object() {
+        return PRIMITIVE_SIZE_ESTIMATE;
+    }
+    sizer.and_then(|sizer| sizer.call1(&JsValue::NULL, payload).ok())
+        .and_then(|size| size.as_f64())
+        .filter(|size| size.is_finite() && *size >= 0.0)
+        .map_or(OBJECT_SIZE_ESTIMATE, |size| size as usize)
+}
+
+/// Build the object passed to the gap handler
+pub fn sequence_gap_to_js(gap: &SequenceGap) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(&obj, &"topic".into(), &gap.topic.as_str().into())?;
+    Reflect::set(&obj, &"origin".into(), &gap.origin.as_str().into())?;
+    Reflect::set(&obj, &"expected".into(), &(gap.expected as f64).into())?;
+    Reflect::set(&obj, &"received".into(), &(gap.received as f64).into())?;
+    Ok(obj.into())
+}
+
+/// Build the record published to the dead-letter topic
+/// {original_topic, payload, reason, timestamp}, plus `error` for callback errors
+pub fn dead_letter_record(original_topic: &JsValue, payload: &JsValue, reason: &str, error: Option<&JsValue>) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(&obj, &"original_topic".into(), original_topic)?;
+    Reflect::set(&obj, &"payload".into(), payload)?;
+    Reflect::set(&obj, &"reason".into(), &reason.into())?;
+    Reflect::set(&obj, &"timestamp".into(), &js_sys::Date::now().into())?;
+    if let Some(error) = error {
+        Reflect::set(&obj, &"error".into(), error)?;
+    }
+    Ok(obj.into())
+}
+
+/// Convert a DOM event into a clonable message payload
+/// Returns {type, target, timeStamp} instead of the Event itself
+pub fn event_to_js(event: &web_sys::Event) -> Result<JsValue, JsValue> {
+    let obj = Object::new();
+    Reflect::set(&obj, &"type".into(), &event.type_().into())?;
+    let target: JsValue = event.target().map_or(JsValue::NULL, JsValue::from);
+    Reflect::set(&obj, &"target".into(), &target)?;
+    Reflect::set(&obj, &"timeStamp".into(), &event.time_stamp().into())?;
+    Ok(obj.into())
+}
+
+/// Read an optional field from a config object
+/// Returns None if the field is missing, undefined or null
+pub fn config_field(config: &JsValue, key: &str) -> Result<Option<JsValue>, JsValue> {
+    let value = Reflect::get(config, &JsValue::from_str(key))?;
+    if value.is_undefined() || value.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(value))
+    }
+}
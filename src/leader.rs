@@ -0,0 +1,190 @@
+//! Leader election: one tab on the channel owns singleton work
+//!
+//! LEADER packets are `[9, client_id, kind]`. Candidates announce themselves with
+//! `elect`; a candidate with a lower client ID answers with its own `elect`, and
+//! a candidate that hears no lower ID within `ELECTION_TIMEOUT_MS` takes over and
+//! sends `claim`. The lowest client ID therefore always wins, and a leader that
+//! sees a claim from a lower ID steps down. When the leader leaves (see
+//! `presence`), the remaining candidates elect a new one.
+
+use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+use js_sys::{Array, Function, Promise};
+
+use crate::types::{Election, InnerQueue};
+use crate::constants::{ELECTION_TIMEOUT_MS, PACKET_LEADER};
+use crate::dispatch::report_error;
+use crate::presence::PeerChange;
+use crate::utils::set_timeout;
+
+const ELECT: &str = "elect";
+const CLAIM: &str = "claim";
+
+/// Build a LEADER packet: [9, client_id, kind]
+fn packet(client_id: &str, kind: &str) -> Array {
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_LEADER));
+    packet.push(&JsValue::from_str(client_id));
+    packet.push(&JsValue::from_str(kind));
+    packet
+}
+
+/// Become a candidate and resolve with whether this tab won the election
+/// A candidate stays one: it takes part in every later re-election.
+pub(crate) fn elect(inner: &Rc<RefCell<InnerQueue>>) -> Promise {
+    Promise::new(&mut |resolve, _reject| {
+        let mut queue = inner.borrow_mut();
+        queue.leader_candidate = true;
+        if queue.is_leader() {
+            drop(queue);
+            let _ = resolve.call1(&JsValue::NULL, &JsValue::TRUE);
+            return;
+        }
+        match queue.election.as_mut() {
+            Some(election) => election.resolvers.push(resolve),
+            None => {
+                drop(queue);
+                start_election(inner, Some(resolve));
+            }
+        }
+    })
+}
+
+/// Announce this candidate and decide once `ELECTION_TIMEOUT_MS` has passed
+fn start_election(inner: &Rc<RefCell<InnerQueue>>, resolve: Option<Function>) {
+    let mut queue = inner.borrow_mut();
+    let _ = queue.post_packet(&packet(&queue.client_id, ELECT));
+    queue.election = Some(Election { resolvers: resolve.into_iter().collect(), lost: false });
+    drop(queue);
+
+    let weak = Rc::downgrade(inner);
+    let on_timeout = Closure::once_into_js(move || {
+        if let Some(inner) = weak.upgrade() {
+            finish_election(&inner);
+        }
+    });
+    if set_timeout(on_timeout.unchecked_ref(), ELECTION_TIMEOUT_MS).is_err() {
+        // Without timers there is nobody to wait for
+        finish_election(inner);
+    }
+}
+
+/// Take over unless a lower client ID answered or already leads
+fn finish_election(inner: &Rc<RefCell<InnerQueue>>) {
+    let (resolvers, won) = {
+        let mut queue = inner.borrow_mut();
+        let Some(election) = queue.election.take() else {
+            return;
+        };
+        let lower_leader = queue.leader_id.as_ref().is_some_and(|leader| *leader < *queue.client_id);
+        let won = queue.leader_candidate && !election.lost && !lower_leader;
+        if won {
+            queue.leader_id = Some(queue.client_id.to_string());
+            let _ = queue.post_packet(&packet(&queue.client_id, CLAIM));
+        }
+        (election.resolvers, won)
+    };
+
+    if won {
+        notify(inner, true);
+    }
+    for resolve in resolvers {
+        let _ = resolve.call1(&JsValue::NULL, &JsValue::from(won));
+    }
+}
+
+/// What to do after a LEADER packet, once the queue borrow is released
+pub(crate) enum LeaderTask {
+    /// Run an election without a waiting Promise
+    Elect,
+    /// Tell the leadership change handler this tab lost leadership
+    SteppedDown,
+}
+
+/// Handle a LEADER packet from another client
+pub(crate) fn receive(queue: &mut InnerQueue, arr: &Array) -> Option<LeaderTask> {
+    let peer_id = arr.get(1).as_string()?;
+    if peer_id == *queue.client_id {
+        return None;
+    }
+    let lower = peer_id < *queue.client_id;
+
+    match arr.get(2).as_string()?.as_str() {
+        ELECT if lower => {
+            if let Some(election) = queue.election.as_mut() {
+                election.lost = true;
+            }
+            None
+        }
+        // Bully the higher candidate out of the election
+        ELECT if queue.leader_candidate => {
+            let kind = if queue.is_leader() { CLAIM } else { ELECT };
+            let _ = queue.post_packet(&packet(&queue.client_id, kind));
+            None
+        }
+        CLAIM if lower || !queue.leader_candidate => {
+            let was_leader = queue.is_leader();
+            queue.leader_id = Some(peer_id);
+            if let Some(election) = queue.election.as_mut() {
+                election.lost = true;
+            }
+            was_leader.then_some(LeaderTask::SteppedDown)
+        }
+        // A higher client ID claims leadership: contest it
+        CLAIM if queue.is_leader() => {
+            let _ = queue.post_packet(&packet(&queue.client_id, CLAIM));
+            None
+        }
+        CLAIM => {
+            // A known leader with a lower client ID contests the claim itself
+            if queue.leader_id.as_ref().is_some_and(|leader| *leader < *queue.client_id) {
+                return None;
+            }
+            queue.leader_id = Some(peer_id);
+            queue.election.is_none().then_some(LeaderTask::Elect)
+        }
+        _ => None,
+    }
+}
+
+/// Carry out a task returned by `receive`
+pub(crate) fn run_task(inner: &Rc<RefCell<InnerQueue>>, task: LeaderTask) {
+    match task {
+        LeaderTask::Elect => start_election(inner, None),
+        LeaderTask::SteppedDown => notify(inner, false),
+    }
+}
+
+/// Re-elect when the current leader left or stopped sending heartbeats
+pub(crate) fn handle_peer_changes(inner: &Rc<RefCell<InnerQueue>>, changes: &[PeerChange]) {
+    let reelect = {
+        let mut queue = inner.borrow_mut();
+        let leader_left = changes.iter()
+            .any(|change| !change.joined && queue.leader_id.as_deref() == Some(change.peer_id.as_str()));
+        if leader_left {
+            queue.leader_id = None;
+        }
+        leader_left && queue.leader_candidate && queue.election.is_none()
+    };
+    if reelect {
+        start_election(inner, None);
+    }
+}
+
+/// Call the leadership change handler
+fn notify(inner: &Rc<RefCell<InnerQueue>>, is_leader: bool) {
+    let Some(handler) = inner.borrow().leadership_handler.clone() else {
+        return;
+    };
+    if let Err(err) = handler.call1(&JsValue::NULL, &JsValue::from(is_leader)) {
+        report_error(inner, &err, None, None, None);
+    }
+}
+
+impl InnerQueue {
+    /// Whether this client won the last election
+    pub fn is_leader(&self) -> bool {
+        self.leader_id.as_deref() == Some(self.client_id.as_str())
+    }
+}
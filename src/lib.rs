@@ -1,17 +1,47 @@
 #![allow(clippy::await_holding_refcell_ref)]
 #![allow(dead_code)]
 
+mod bridge;
 mod constants;
 mod ring_buffer;
+mod dedup;
 mod types;
 mod js_utils;
+mod leader;
 mod utils;
 mod inner_queue;
 mod queue;
+mod channel;
+mod dispatch;
+mod idb;
+mod idle;
+#[cfg(feature = "wasm-logging")]
+mod logging;
+mod namespace;
+mod pattern;
+mod presence;
+mod rpc;
+mod schedule;
+mod scope;
+mod snapshot;
+mod storage;
+mod stream;
+mod subscribers;
+mod transaction;
+mod typescript;
+mod wire;
+#[cfg(feature = "threads")]
+mod thread_safe;
 
 // Re-export the main MessageQueue type and its dependencies
+pub use namespace::NamespacedQueue;
 pub use queue::MessageQueue;
-pub use types::{Message, Topic, InnerQueue};
+pub use scope::ScopedQueue;
+pub use stream::TopicStream;
+pub use transaction::TransactionQueue;
+pub use types::{DropPolicy, Message, Topic, InnerQueue};
+#[cfg(feature = "threads")]
+pub use thread_safe::{ThreadSafeInnerQueue, ThreadSafeMessage, ThreadSafeMessageQueue};
 
 
 
@@ -42,6 +72,9 @@ mod tests {
                 payload: JsValue::from_str("test payload"),
                 timestamp: 12345.0,
                 origin_id: Rc::new("test-origin".to_string()),
+                headers: None,
+                expires_at: None,
+                seq: 0,
             };
 
             assert_eq!(msg.id, 12345);
@@ -54,7 +87,7 @@ mod tests {
     #[test]
     fn test_topic_new() {
         let topic = Topic::new("test".to_string());
-        assert_eq!(topic.next_id, 0);
+        assert_eq!(topic.subscribers.len(), 0);
         assert!(topic.subscribers.is_empty());
         assert_eq!(topic.name, "test");
     }
@@ -72,13 +105,14 @@ mod tests {
         let queue = InnerQueue {
             topics: Vec::new(),
             topic_index: HashMap::new(),
-            channel: None,
+            transports: Vec::new(),
             client_id: Rc::new("test-client".to_string()),
+            ..Default::default()
         };
 
         assert!(queue.topics.is_empty());
         assert!(queue.topic_index.is_empty());
-        assert!(queue.channel.is_none());
+        assert!(queue.transports.is_empty());
         assert_eq!(*queue.client_id, "test-client");
     }
 }
@@ -0,0 +1,72 @@
+use wasm_bindgen::{JsCast, JsValue};
+use js_sys::{Function, Reflect};
+use web_sys::console;
+
+use crate::constants::{LOG_DEBUG, LOG_ERROR, LOG_INFO, LOG_WARN};
+use crate::types::InnerQueue;
+
+impl InnerQueue {
+    /// Check if messages at `level` pass the configured log level
+    pub fn logs_at(&self, level: u8) -> bool {
+        self.log_level.get() >= level
+    }
+}
+
+/// Log a collapsible console group with one line per field
+pub fn log_group(level: u8, title: &str, fields: &[(&str, JsValue)]) {
+    let tag = match level {
+        LOG_ERROR => "error",
+        LOG_WARN => "warn",
+        LOG_INFO => "info",
+        LOG_DEBUG => "debug",
+        _ => "log",
+    };
+
+    console::group_collapsed_1(&JsValue::from_str(&format!("[wasm-ripple:{}] {}", tag, title)));
+    for (name, value) in fields {
+        let name = JsValue::from_str(name);
+        match level {
+            LOG_ERROR => console::error_2(&name, value),
+            LOG_WARN => console::warn_2(&name, value),
+            _ => console::log_2(&name, value),
+        }
+    }
+    console::group_end();
+}
+
+/// High-resolution timestamp for latency measurements
+/// Uses `performance.now()` when available, `Date.now()` otherwise
+pub fn now_ms() -> f64 {
+    let global = js_sys::global();
+    Reflect::get(&global, &"performance".into())
+        .ok()
+        .filter(|performance| performance.is_object())
+        .and_then(|performance| {
+            let now = Reflect::get(&performance, &"now".into()).ok()?;
+            now.dyn_into::<Function>().ok()?.call0(&performance).ok()
+        })
+        .and_then(|now| now.as_f64())
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::LOG_OFF;
+
+    #[test]
+    fn test_log_level_filters_by_severity() {
+        let queue = InnerQueue::default();
+        assert!(!queue.logs_at(LOG_ERROR));
+
+        queue.log_level.set(LOG_WARN);
+        assert!(queue.logs_at(LOG_ERROR));
+        assert!(queue.logs_at(LOG_WARN));
+        assert!(!queue.logs_at(LOG_INFO));
+
+        queue.log_level.set(LOG_DEBUG);
+        assert!(queue.logs_at(LOG_DEBUG));
+        queue.log_level.set(LOG_OFF);
+        assert!(!queue.logs_at(LOG_ERROR));
+    }
+}
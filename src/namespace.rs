@@ -0,0 +1,152 @@
+use wasm_bindgen::JsValue;
+use js_sys::{Array, Function};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::scope::ScopedQueue;
+use crate::types::InnerQueue;
+
+/// A view of a `MessageQueue` that scopes all topic names under a prefix
+/// Topic names are stored as `prefix.name`, so modules using different
+/// namespaces cannot collide. This is a Rust-side convenience and is not
+/// exported to JavaScript; it is a `ScopedQueue` with a dot-terminated prefix
+/// and the same checks.
+pub struct NamespacedQueue {
+    /// Scope over `prefix.`, which enforces topic ownership
+    scope: ScopedQueue,
+    /// Prefix prepended to every topic name, without the dot
+    prefix: String,
+}
+
+impl NamespacedQueue {
+    pub(crate) fn new(inner: Rc<RefCell<InnerQueue>>, prefix: &str) -> Self {
+        NamespacedQueue {
+            scope: ScopedQueue::new(inner, &format!("{}.", prefix)),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Get the namespace prefix
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get the full topic name for a name local to this namespace
+    pub fn full_name(&self, local_name: &str) -> String {
+        format!("{}.{}", self.prefix, local_name)
+    }
+
+    /// Create a topic in this namespace
+    /// Returns false if it already exists
+    pub fn create_topic(&self, local_name: &str) -> Result<bool, JsValue> {
+        self.scope.create_topic(local_name)
+    }
+
+    /// Register a topic in this namespace and get its ID
+    pub fn register_topic(&self, local_name: &str) -> Result<u32, JsValue> {
+        self.scope.register_topic(local_name)
+    }
+
+    /// Look up the ID of an existing topic by its local name
+    pub fn get_namespaced_topic_id(&self, local_name: &str) -> Option<u32> {
+        self.scope.get_topic_id(local_name)
+    }
+
+    /// Get the local name of a topic of this namespace
+    pub fn get_topic_name(&self, topic_id: u32) -> Option<String> {
+        self.scope.get_topic_name(topic_id)
+    }
+
+    /// Destroy a topic of this namespace
+    /// Returns false if the ID does not refer to a live topic of this namespace
+    pub fn destroy_topic(&self, topic_id: u32) -> bool {
+        self.scope.destroy_topic(topic_id)
+    }
+
+    /// Describe the topics of this namespace, named without the prefix
+    pub fn list_topics(&self) -> Result<Array, JsValue> {
+        self.scope.list_topics()
+    }
+
+    /// Subscribe to a topic of this namespace
+    /// Callback signature: (payload, topic_id, timestamp, message_id, headers)
+    pub fn subscribe(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        self.scope.subscribe(topic_id, callback)
+    }
+
+    /// Subscribe to a topic of this namespace for a single message
+    pub fn subscribe_once(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        self.scope.subscribe_once(topic_id, callback)
+    }
+
+    /// Subscribe to a topic of this namespace with a predicate called with (payload)
+    pub fn subscribe_filtered(&self, topic_id: u32, filter: Function, callback: Function) -> Result<u32, JsValue> {
+        self.scope.subscribe_filtered(topic_id, filter, callback)
+    }
+
+    /// Unsubscribe from a topic of this namespace
+    pub fn unsubscribe(&self, topic_id: u32, sub_id: u32) -> bool {
+        self.scope.unsubscribe(topic_id, sub_id)
+    }
+
+    /// Publish to a topic of this namespace
+    pub fn publish(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        self.scope.publish(topic_id, payload)
+    }
+
+    /// Publish to a topic of this namespace by its local name
+    pub fn publish_by_name(&self, local_name: &str, payload: JsValue) -> Result<(), JsValue> {
+        self.scope.publish_by_name(local_name, payload)
+    }
+
+    /// Enable message buffering for a topic of this namespace (default capacity: 100)
+    pub fn enable_topic_buffer(&self, topic_id: u32, capacity: Option<usize>) -> Result<(), JsValue> {
+        self.scope.enable_topic_buffer(topic_id, capacity)
+    }
+
+    /// Disable message buffering for a topic of this namespace
+    pub fn disable_topic_buffer(&self, topic_id: u32) -> Result<(), JsValue> {
+        self.scope.disable_topic_buffer(topic_id)
+    }
+
+    /// Get the number of buffered messages, or -1 without a buffer
+    pub fn get_buffer_size(&self, topic_id: u32) -> i32 {
+        self.scope.get_buffer_size(topic_id)
+    }
+
+    /// Clear the buffer of a topic of this namespace
+    /// Returns the number of messages cleared
+    pub fn clear_buffer(&self, topic_id: u32) -> usize {
+        self.scope.clear_buffer(topic_id)
+    }
+
+    /// Get the buffered messages of a topic of this namespace, oldest first
+    pub fn get_buffered_messages(&self, topic_id: u32) -> Result<Array, JsValue> {
+        self.scope.get_buffered_messages(topic_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaces_do_not_collide() {
+        let inner = Rc::new(RefCell::new(InnerQueue::default()));
+        let editor = NamespacedQueue::new(inner.clone(), "editor");
+        let chat = NamespacedQueue::new(inner.clone(), "chat");
+
+        let editor_id = editor.register_topic("events").unwrap();
+        let chat_id = chat.register_topic("events").unwrap();
+        assert_ne!(editor_id, chat_id);
+
+        assert_eq!(editor.get_namespaced_topic_id("events"), Some(editor_id));
+        assert_eq!(chat.get_namespaced_topic_id("events"), Some(chat_id));
+        assert_eq!(editor.get_namespaced_topic_id("missing"), None);
+        assert_eq!(editor.get_topic_name(editor_id).as_deref(), Some("events"));
+        assert_eq!(editor.get_topic_name(chat_id), None);
+        assert!(!chat.create_topic("events").unwrap());
+        assert!(inner.borrow().topic_index.contains_key("editor.events"));
+        assert_eq!(editor.full_name("events"), "editor.events");
+    }
+}
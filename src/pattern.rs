@@ -0,0 +1,84 @@
+use js_sys::Function;
+
+/// Separator between the segments of hierarchical topic names
+pub const SEGMENT_SEPARATOR: char = '/';
+
+/// A subscriber registered for all topics matching a wildcard pattern
+pub struct PatternSubscriber {
+    /// MQTT-style pattern (`+` matches one segment, `#` the remaining segments)
+    pub pattern: String,
+    /// Callback function invoked for each delivered message
+    pub callback: Function,
+}
+
+/// Check that a pattern is well-formed
+/// `#` may only appear as the last segment, and wildcards must fill a whole segment
+pub fn validate_pattern(pattern: &str) -> Result<(), &'static str> {
+    let segments: Vec<&str> = pattern.split(SEGMENT_SEPARATOR).collect();
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.len() > 1 && (segment.contains('+') || segment.contains('#')) {
+            return Err("Wildcards must occupy a whole segment");
+        }
+        if *segment == "#" && i != segments.len() - 1 {
+            return Err("# must be the last segment");
+        }
+    }
+    Ok(())
+}
+
+/// Match a topic name against an MQTT-style pattern
+/// `+` matches exactly one segment and `#` matches zero or more trailing segments
+pub fn topic_matches(pattern: &str, topic_name: &str) -> bool {
+    let mut pattern_segments = pattern.split(SEGMENT_SEPARATOR);
+    let mut name_segments = topic_name.split(SEGMENT_SEPARATOR);
+
+    loop {
+        match (pattern_segments.next(), name_segments.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(p), Some(n)) if p == n => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_matches_exact() {
+        assert!(topic_matches("sensor/kitchen/temp", "sensor/kitchen/temp"));
+        assert!(!topic_matches("sensor/kitchen/temp", "sensor/kitchen"));
+        assert!(!topic_matches("sensor/kitchen", "sensor/kitchen/temp"));
+    }
+
+    #[test]
+    fn test_topic_matches_single_level() {
+        assert!(topic_matches("sensor/+/temp", "sensor/kitchen/temp"));
+        assert!(topic_matches("sensor/+/temp", "sensor/livingroom/temp"));
+        assert!(!topic_matches("sensor/+/temp", "sensor/kitchen/humidity"));
+        assert!(!topic_matches("sensor/+/temp", "sensor/a/b/temp"));
+        assert!(topic_matches("+", "events"));
+        assert!(!topic_matches("+", "a/b"));
+    }
+
+    #[test]
+    fn test_topic_matches_multi_level() {
+        assert!(topic_matches("sensor/#", "sensor/kitchen/temp"));
+        assert!(topic_matches("sensor/#", "sensor"));
+        assert!(topic_matches("#", "anything/at/all"));
+        assert!(topic_matches("sensor/+/#", "sensor/kitchen/temp/max"));
+        assert!(!topic_matches("sensor/#", "actuator/kitchen"));
+    }
+
+    #[test]
+    fn test_validate_pattern() {
+        assert!(validate_pattern("sensor/+/temp").is_ok());
+        assert!(validate_pattern("sensor/#").is_ok());
+        assert!(validate_pattern("sensor/#/temp").is_err());
+        assert!(validate_pattern("sensor/kit+chen").is_err());
+        assert!(validate_pattern("sensor/##").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Presence: which other clients are connected to the channel
+//!
+//! PRESENCE packets are `[4, client_id, kind]`, where kind is `join` (sent when a
+//! transport is attached), `here` (reply to a join), `heartbeat` (sent every
+//! presence interval) or `leave` (sent when the queue closes).
+//!
+//! Closing queues also send a GOODBYE packet, `[10, client_id]`, which needs no
+//! presence tracking: requests the closed peer was expected to answer fail right away.
+
+use wasm_bindgen::prelude::*;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use js_sys::Array;
+
+use crate::types::{InnerQueue, PendingRequest, PresenceTimer};
+use crate::constants::{ERR_PEER_CLOSED, PACKET_GOODBYE, PACKET_PRESENCE, PRESENCE_TIMEOUT_FACTOR};
+use crate::dispatch::report_error;
+use crate::leader;
+use crate::rpc;
+use crate::utils::{clear_interval, set_interval};
+
+pub(crate) const JOIN: &str = "join";
+pub(crate) const HERE: &str = "here";
+pub(crate) const HEARTBEAT: &str = "heartbeat";
+pub(crate) const LEAVE: &str = "leave";
+
+/// A peer that appeared or went away, reported to the `on_peer_change` handler
+pub(crate) struct PeerChange {
+    pub peer_id: String,
+    pub joined: bool,
+}
+
+/// Build a PRESENCE packet: [4, client_id, kind]
+pub(crate) fn packet(client_id: &str, kind: &str) -> Array {
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_PRESENCE));
+    packet.push(&JsValue::from_str(client_id));
+    packet.push(&JsValue::from_str(kind));
+    packet
+}
+
+/// Handle a PRESENCE packet from another client, answering joins
+pub(crate) fn receive(queue: &mut InnerQueue, arr: &Array) -> Option<PeerChange> {
+    let peer_id = arr.get(1).as_string()?;
+    if peer_id == *queue.client_id {
+        return None;
+    }
+
+    match arr.get(2).as_string()?.as_str() {
+        LEAVE => queue.peers.remove(&peer_id).map(|_| PeerChange { peer_id, joined: false }),
+        kind @ (JOIN | HERE | HEARTBEAT) => {
+            if kind == JOIN {
+                let _ = queue.post_packet(&packet(&queue.client_id, HERE));
+            }
+            let joined = queue.peers.insert(peer_id.clone(), js_sys::Date::now()).is_none();
+            joined.then_some(PeerChange { peer_id, joined })
+        }
+        _ => None,
+    }
+}
+
+/// Call the `on_peer_change` handler for each change
+/// A leader that left is replaced first.
+pub(crate) fn notify(inner: &Rc<RefCell<InnerQueue>>, changes: Vec<PeerChange>) {
+    if changes.is_empty() {
+        return;
+    }
+    leader::handle_peer_changes(inner, &changes);
+    let Some(handler) = inner.borrow().peer_change_handler.clone() else {
+        return;
+    };
+    for change in changes {
+        let kind = if change.joined { "joined" } else { "left" };
+        if let Err(err) = handler.call2(&JsValue::NULL, &JsValue::from_str(&change.peer_id), &JsValue::from_str(kind)) {
+            report_error(inner, &err, None, None, None);
+        }
+    }
+}
+
+/// Build a GOODBYE packet: [10, client_id]
+pub(crate) fn goodbye_packet(client_id: &str) -> Array {
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_GOODBYE));
+    packet.push(&JsValue::from_str(client_id));
+    packet
+}
+
+/// Tell the other tabs this queue is going away, with both a GOODBYE and a presence leave
+/// Posting fails silently on transports that are already closed.
+pub(crate) fn announce_close(queue: &InnerQueue) {
+    let _ = queue.post_packet(&goodbye_packet(&queue.client_id));
+    let _ = queue.post_packet(&packet(&queue.client_id, LEAVE));
+}
+
+/// Handle a GOODBYE packet from another client
+/// Returns the peer ID and the requests it was expected to answer.
+pub(crate) fn receive_goodbye(queue: &mut InnerQueue, arr: &Array) -> Option<(String, Vec<PendingRequest>)> {
+    let peer_id = arr.get(1).as_string()?;
+    if peer_id == *queue.client_id {
+        return None;
+    }
+    let orphaned = rpc::forget_peer(queue, &peer_id);
+    Some((peer_id, orphaned))
+}
+
+/// Reject the requests a closed peer left unanswered and call the `on_peer_closed` handler
+pub(crate) fn notify_closed(inner: &Rc<RefCell<InnerQueue>>, peer_id: &str, orphaned: Vec<PendingRequest>) {
+    rpc::reject_pending(orphaned, ERR_PEER_CLOSED);
+    let Some(handler) = inner.borrow().peer_closed_handler.clone() else {
+        return;
+    };
+    if let Err(err) = handler.call1(&JsValue::NULL, &JsValue::from_str(peer_id)) {
+        report_error(inner, &err, None, None, None);
+    }
+}
+
+/// Start sending heartbeats every `interval_ms` and expiring silent peers
+/// A peer is dropped after `PRESENCE_TIMEOUT_FACTOR` intervals without a packet.
+pub(crate) fn enable(inner: &Rc<RefCell<InnerQueue>>, interval_ms: f64) -> Result<(), JsValue> {
+    if !(interval_ms.is_finite() && interval_ms > 0.0) {
+        return Err(JsValue::from_str("Presence interval must be a positive number"));
+    }
+    disable(&mut inner.borrow_mut());
+
+    let weak = Rc::downgrade(inner);
+    let callback = Closure::<dyn FnMut()>::new(move || tick(&weak, interval_ms));
+    let timer = set_interval(callback.as_ref().unchecked_ref(), interval_ms)?;
+
+    let mut queue = inner.borrow_mut();
+    let _ = queue.post_packet(&packet(&queue.client_id, HEARTBEAT));
+    queue.presence = Some(PresenceTimer { timer, callback });
+    Ok(())
+}
+
+/// Stop sending heartbeats; known peers are kept until they leave
+pub(crate) fn disable(queue: &mut InnerQueue) {
+    if let Some(presence) = queue.presence.take() {
+        clear_interval(presence.timer);
+    }
+}
+
+fn tick(inner: &Weak<RefCell<InnerQueue>>, interval_ms: f64) {
+    let Some(inner) = inner.upgrade() else {
+        return;
+    };
+    let changes = {
+        let Ok(mut queue) = inner.try_borrow_mut() else {
+            return;
+        };
+        let _ = queue.post_packet(&packet(&queue.client_id, HEARTBEAT));
+        queue.expire_peers(js_sys::Date::now(), interval_ms * PRESENCE_TIMEOUT_FACTOR)
+    };
+    notify(&inner, changes);
+}
+
+impl InnerQueue {
+    /// Remove peers not heard from within `timeout_ms` of `now`
+    pub(crate) fn expire_peers(&mut self, now: f64, timeout_ms: f64) -> Vec<PeerChange> {
+        let mut expired: Vec<String> = self.peers.iter()
+            .filter(|(_, last_seen)| now - **last_seen > timeout_ms)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+        expired.sort();
+        for peer_id in &expired {
+            self.peers.remove(peer_id);
+        }
+        expired.into_iter().map(|peer_id| PeerChange { peer_id, joined: false }).collect()
+    }
+
+    /// IDs of the known peers, sorted
+    pub fn peer_ids(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self.peers.keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence_expires_silent_peers() {
+        let mut queue = InnerQueue::default();
+        queue.peers.insert("tab-b".to_string(), 1_000.0);
+        queue.peers.insert("tab-a".to_string(), 2_500.0);
+        queue.peers.insert("tab-c".to_string(), 1_500.0);
+        assert_eq!(queue.peer_ids(), vec!["tab-a", "tab-b", "tab-c"]);
+
+        // Exactly at the timeout a peer is kept
+        let expired = queue.expire_peers(4_000.0, 2_500.0);
+        let expired: Vec<(&str, bool)> = expired.iter().map(|change| (change.peer_id.as_str(), change.joined)).collect();
+        assert_eq!(expired, vec![("tab-b", false)]);
+        assert_eq!(queue.peer_ids(), vec!["tab-a", "tab-c"]);
+    }
+}
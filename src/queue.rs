@@ -1,531 +1,3982 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::collections::HashMap;
-use web_sys::{BroadcastChannel, MessageEvent};
-use js_sys::{Promise, Function, Array};
+use std::rc::{Rc, Weak};
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use web_sys::{AbortSignal, BroadcastChannel, Event, EventTarget, MessageEvent, MessagePort, ReadableStream, ServiceWorker, WebSocket};
+use js_sys::{Promise, Function, Array, Int32Array, Uint8Array, Uint32Array};
 
-use crate::types::{InnerQueue, Message};
-use crate::utils::generate_uuid;
-use crate::js_utils::parse_js_message;
+use crate::types::{AsyncMode, BackpressureHook, CoalescedFlush, DeadLetterConfig, Delivery, DropPolicy, EventListener, InnerQueue, Message, OverflowPolicy, PublishOptions, QueuedPublish, Stats, Subscriber, SubscriberFilter, Topic, TopicEvent, Transport, WireFormat};
+use crate::dispatch::{deliver, deliver_collecting, notify_overflow, notify_rejected, notify_topic_events, report_error, report_receive_errors};
+use crate::utils::{clear_timeout, generate_uuid, set_timeout};
+use crate::js_utils;
+use crate::idle::IdleDelivery;
+use crate::channel::{broadcast_batch, broadcast_message, broadcast_transfer, handle_channel_message, request_sync};
+use crate::constants::{DEFAULT_MAX_TOPIC_NAME_LEN, DEFAULT_PRIORITY, DEFAULT_STREAM_BUFFER_LIMIT, DEFAULT_STREAM_HIGH_WATER_MARK, ERR_BUFFER_FULL, ERR_QUEUE_FULL, ERR_SIGNAL_ABORTED, LOG_DEBUG, PACKET_MIGRATE, PACKET_SYNC_REQ, SYNC_SETTLE_MS};
+use crate::namespace::NamespacedQueue;
+use crate::scope::ScopedQueue;
+use crate::stream::{ReadableSource, TopicStream};
+use crate::rpc;
+use crate::schedule;
+use crate::snapshot;
+use crate::pattern::{validate_pattern, PatternSubscriber};
+use crate::transaction::{TransactionLog, TransactionQueue};
+use crate::wire;
+use crate::bridge;
+use crate::idb;
+use crate::leader;
+use crate::presence;
+use crate::storage::StorageTransport;
 
 /// A WebAssembly-based message queue with support for:
 /// - Topic-based pub/sub messaging
-/// - Cross-tab communication via BroadcastChannel
+/// - Cross-tab communication via BroadcastChannel, and MessagePort for workers
 /// - Synchronous and asynchronous publishing
 /// - Zero-copy message passing (using JsValue)
 #[wasm_bindgen]
 pub struct MessageQueue {
     /// Internal queue state (wrapped in `Rc<RefCell>` for shared mutable access)
     inner: Rc<RefCell<InnerQueue>>,
-    /// Closure for broadcast channel event handler
-    _closure: Option<Closure<dyn FnMut(MessageEvent)>>,
+    /// Handler for packets arriving on any transport, created with the first transport
+    on_message: OnceCell<Closure<dyn FnMut(MessageEvent)>>,
 }
 
 #[wasm_bindgen]
 impl MessageQueue {
     #[wasm_bindgen(constructor)]
     pub fn new(channel_name: Option<String>) -> Result<MessageQueue, JsValue> {
-        let client_id = generate_uuid()?;
+        Self::open(OpenOptions::channel(channel_name))
+    }
 
-        let channel = if let Some(name) = channel_name {
-            Some(BroadcastChannel::new(&name).map_err(|_| {
-                JsValue::from_str("Failed to create BroadcastChannel")
-            })?)
-        } else {
-            None
+    /// Create a queue from an options object
+    /// Options are applied before the queue connects, so they take effect for
+    /// the initial SYNC_REQ. Options (all optional):
+    /// - `channel`: BroadcastChannel name (default: no channel)
+    /// - `clientId`: client ID to use instead of a generated UUID
+    /// - `syncOnConnect`: request other tabs' buffered messages on connect (default: true)
+    /// - `dedupCapacity`: number of remembered message IDs, as with `set_dedup_window`
+    /// - `wireFormat`: "json" (default) or "binary", as with `set_wire_format`
+    /// - `debug`: log dispatch errors to the console, as with `set_debug` (default: false)
+    /// - `defaultBufferCapacity`: give every new topic a buffer of this capacity
+    /// - `maxTopicNameLength`: longest accepted topic name in characters (default: 256)
+    /// - `trimTopicNames`: trim whitespace around topic names (default: false)
+    /// - `foldTopicNameCase`: lowercase topic names (default: false)
+    /// - `signal`: AbortSignal closing the queue when aborted
+    /// @param options - Options object, or undefined for the defaults
+    #[wasm_bindgen]
+    pub fn with_options(#[wasm_bindgen(unchecked_param_type = "QueueOptions | undefined")] options: JsValue) -> Result<MessageQueue, JsValue> {
+        let mut open = OpenOptions::channel(None);
+        if options.is_undefined() || options.is_null() {
+            return Self::open(open);
+        }
+        if !options.is_object() {
+            return Err(JsValue::from_str("Options must be an object"));
+        }
+
+        let string = |key: &str| -> Result<Option<String>, JsValue> {
+            js_utils::config_field(&options, key)?
+                .map(|v| v.as_string().ok_or_else(|| JsValue::from_str(&format!("{} must be a string", key))))
+                .transpose()
+        };
+        let boolean = |key: &str| -> Result<Option<bool>, JsValue> {
+            js_utils::config_field(&options, key)?
+                .map(|v| v.as_bool().ok_or_else(|| JsValue::from_str(&format!("{} must be a boolean", key))))
+                .transpose()
+        };
+        let count = |key: &str, min: f64| -> Result<Option<usize>, JsValue> {
+            js_utils::config_field(&options, key)?
+                .map(|v| v.as_f64()
+                    .filter(|n| n.fract() == 0.0 && *n >= min)
+                    .map(|n| n as usize)
+                    .ok_or_else(|| JsValue::from_str(&format!("{} must be an integer of at least {}", key, min))))
+                .transpose()
         };
 
-        let inner = Rc::new(RefCell::new(InnerQueue {
-            topics: Vec::new(),
-            topic_index: HashMap::new(),
-            channel: channel.clone(),
-            client_id: Rc::new(client_id.clone()),
-            seen_ids: std::collections::HashSet::new(),
-        }));
+        open.channel_name = string("channel")?;
+        open.client_id = string("clientId")?;
+        if open.client_id.as_deref() == Some("") {
+            return Err(JsValue::from_str("clientId must not be empty"));
+        }
+        open.sync_on_connect = boolean("syncOnConnect")?.unwrap_or(true);
+        open.dedup_capacity = count("dedupCapacity", 0.0)?;
+        if let Some(format) = string("wireFormat")? {
+            open.wire_format = parse_wire_format(&format)?;
+        }
+        open.debug = boolean("debug")?.unwrap_or(false);
+        open.default_buffer_capacity = count("defaultBufferCapacity", 1.0)?;
+        open.max_topic_name_len = count("maxTopicNameLength", 1.0)?.unwrap_or(DEFAULT_MAX_TOPIC_NAME_LEN);
+        open.trim_topic_names = boolean("trimTopicNames")?.unwrap_or(false);
+        open.fold_topic_name_case = boolean("foldTopicNameCase")?.unwrap_or(false);
+        let signal = js_utils::config_field(&options, "signal")?
+            .map(|v| v.dyn_into::<AbortSignal>().map_err(|_| JsValue::from_str("signal must be an AbortSignal")))
+            .transpose()?;
+        if signal.as_ref().is_some_and(AbortSignal::aborted) {
+            return Err(JsValue::from_str(ERR_SIGNAL_ABORTED));
+        }
 
-        // Setup BroadcastChannel listener if it exists
-        let inner_clone = inner.clone();
-        let closure = if channel.is_some() {
-            let cb = Closure::wrap(Box::new(move |event: MessageEvent| {
-                let data = event.data();
-                let mut queue = inner_clone.borrow_mut();
-
-                // Helper to process a message
-                let mut process_msg = |queue: &mut InnerQueue, msg_val: JsValue| {
-                    if let Ok((mut m, topic_name)) = parse_js_message(&msg_val) {
-                        if !queue.seen_ids.contains(&m.id) {
-                            queue.seen_ids.insert(m.id);
-                            
-                            // Resolve topic ID
-                            let topic_id = queue.get_or_create_topic_id(&topic_name) as u32;
-                            m.topic_id = topic_id;
-                            
-                            if *m.origin_id != *queue.client_id {
-                                 queue.dispatch_local(&Rc::new(m), None);
-                            }
-                        }
-                    }
-                };
+        let mq = Self::open(open)?;
+        if let Some(signal) = signal {
+            let weak = Rc::downgrade(&mq.inner);
+            let listener = abort_listener(signal, move || when_unborrowed(weak.clone(), close_queue))?;
+            mq.inner.borrow_mut().event_listeners.push(listener);
+        }
+        Ok(mq)
+    }
 
-                if data.is_array() {
-                    let arr = Array::from(&data);
-                    if let Some(type_val) = arr.get(0).as_f64() {
-                        match type_val as u8 {
-                            0 => { // PUB: [0, msg]
-                                process_msg(&mut queue, arr.get(1));
-                            },
-                            1 => { // SYNC_REQ: [1, origin_id]
-                                let origin_id = arr.get(1).as_string().unwrap_or_default();
-                                if origin_id != *queue.client_id {
-                                    // Send all buffered messages
-                                    let all_msgs = Array::new();
-                                    for topic in &queue.topics {
-                                        if let Some(buffer) = topic.get_buffer() {
-                                            for msg in buffer.iter() {
-                                                if let Ok(msg_js) = crate::js_utils::message_to_js(msg, &topic.name) {
-                                                    all_msgs.push(&msg_js);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    
-                                    if all_msgs.length() > 0 {
-                                        if let Some(ref ch) = queue.channel {
-                                            let resp = Array::new();
-                                            resp.push(&JsValue::from(2)); // SYNC_RESP
-                                            resp.push(&all_msgs);
-                                            let _ = ch.post_message(&resp);
-                                        }
-                                    }
-                                }
-                            },
-                            2 => { // SYNC_RESP: [2, [msg1, msg2...]]
-                                let msgs = Array::from(&arr.get(1));
-                                for i in 0..msgs.length() {
-                                    process_msg(&mut queue, msgs.get(i));
-                                }
-                            },
-                            _ => {}
-                        }
-                    }
-                } else if data.is_object() {
-                    // Fallback for backward compatibility
-                    process_msg(&mut queue, data);
-                }
-            }) as Box<dyn FnMut(MessageEvent)>);
-
-            if let Some(ref c) = inner.borrow().channel {
-                c.set_onmessage(Some(cb.as_ref().unchecked_ref()));
-                
-                // Send SYNC_REQ
-                // [1, client_id]
-                let req = Array::new();
-                req.push(&JsValue::from(1));
-                req.push(&JsValue::from(inner.borrow().client_id.as_str()));
-                let _ = c.post_message(&req);
-            }
-            Some(cb)
-        } else {
-            None
-        };
+    /// Create a queue that talks to the other end of a MessagePort (e.g. a Worker)
+    /// instead of a BroadcastChannel. More ports can be added with `attach_port`.
+    /// @param port - One end of a MessageChannel
+    #[wasm_bindgen]
+    pub fn with_port(port: MessagePort) -> Result<MessageQueue, JsValue> {
+        let mq = Self::open(OpenOptions::channel(None))?;
+        mq.attach_port(port)?;
+        Ok(mq)
+    }
 
-        Ok(MessageQueue {
-            inner,
-            _closure: closure,
-        })
+    /// Get the name of the channel this queue was created or last reconnected with
+    /// The name is kept after `disconnect`; use `is_connected` to check whether
+    /// the channel is open.
+    /// @returns The channel name, or undefined for a queue without a channel
+    #[wasm_bindgen]
+    pub fn channel_name(&self) -> Option<String> {
+        self.inner.borrow().channel_name.clone()
+    }
+
+    /// Check if the queue is exchanging messages with other tabs over its channel
+    /// MessagePorts attached with `attach_port` do not count.
+    #[wasm_bindgen]
+    pub fn is_connected(&self) -> bool {
+        self.inner.borrow().transports.iter().any(Transport::is_channel)
     }
 
-    pub fn create_topic(&self, topic_name: &str) -> bool {
+    /// Close the channel to other tabs, keeping topics, subscribers and buffers
+    /// Throttled broadcasts are posted first, and other tabs are told this
+    /// queue left, as with `close`. Attached MessagePorts stay connected.
+    #[wasm_bindgen]
+    pub fn disconnect(&self) {
         let mut queue = self.inner.borrow_mut();
-        if queue.topic_index.contains_key(topic_name) {
-            false
-        } else {
-            queue.get_or_create_topic_id(topic_name);
-            true
+        let _ = queue.flush_broadcasts();
+        let (channels, ports): (Vec<_>, Vec<_>) = std::mem::take(&mut queue.transports)
+            .into_iter()
+            .partition(Transport::is_channel);
+        queue.transports = ports;
+
+        let goodbye = presence::goodbye_packet(&queue.client_id);
+        let leave = presence::packet(&queue.client_id, presence::LEAVE);
+        for channel in channels {
+            let _ = channel.post(&goodbye);
+            let _ = channel.post(&leave);
+            channel.close();
         }
     }
-    
-    /// Register a topic and get its ID (handle) for fast publishing
-    /// Returns the topic ID that can be used with publish_by_id
-    pub fn register_topic(&self, topic_name: &str) -> u32 {
-        self.inner.borrow_mut().get_or_create_topic_id(topic_name) as u32
+
+    /// Close the current channel and open a new one
+    /// The message handler is installed on the new channel, which is announced
+    /// to other tabs, and the SYNC handshake runs again unless the queue was
+    /// created with `syncOnConnect: false`. On failure the current channel stays open.
+    /// @param channel_name - Optional, name of the new channel (default: the current one)
+    #[wasm_bindgen]
+    pub fn reconnect(&self, channel_name: Option<String>) -> Result<(), JsValue> {
+        let (name, force_storage) = {
+            let queue = self.inner.borrow();
+            queue.check_open()?;
+            let name = channel_name.or_else(|| queue.channel_name.clone())
+                .ok_or_else(|| JsValue::from_str("No channel name"))?;
+            (name, queue.force_storage)
+        };
+        let transport = open_channel(&name, force_storage)?;
+
+        self.disconnect();
+        self.inner.borrow_mut().channel_name = Some(name);
+        self.attach_transport(transport);
+        Ok(())
     }
 
-    pub fn destroy_topic(&self, topic_id: u32) -> bool {
+    /// Open an additional named channel for topics routed to it with `route_topic`
+    /// Packets arriving on the channel are handled like those on the primary
+    /// channel, sharing its duplicate detection. Only routed topics post on it;
+    /// sync and presence stay on the primary channel.
+    /// @param name - Name of the BroadcastChannel
+    /// @returns Handle of the channel, for `route_topic`
+    #[wasm_bindgen]
+    pub fn attach_channel(&self, name: &str) -> Result<u32, JsValue> {
+        self.inner.borrow().check_open()?;
+        let channel = open_channel(name, self.inner.borrow().force_storage)?;
+        channel.set_onmessage(Some(self.message_handler().as_ref().unchecked_ref()));
+
         let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.topics.get_mut(topic_id as usize) {
-            topic.subscribers.clear();
-            topic.disable_buffer();
-            return true;
-        }
-        false
+        let handle = queue.next_channel_handle;
+        queue.next_channel_handle += 1;
+        queue.channels.push((handle, channel));
+        Ok(handle)
     }
 
-    /// Subscribe to a topic using its ID
-    /// Callback signature: (payload, topic_id, timestamp, message_id)
-    pub fn subscribe(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+    /// Post a topic's messages on a channel opened with `attach_channel`
+    /// Instead of the primary channel and ports, the topic's publishes, batches
+    /// and throttled broadcasts go out on that channel only.
+    /// @param topic_id - ID of the topic
+    /// @param channel_handle - Handle returned by `attach_channel`, or 0 for the primary channel
+    #[wasm_bindgen]
+    pub fn route_topic(&self, topic_id: u32, channel_handle: u32) -> Result<(), JsValue> {
         let mut queue = self.inner.borrow_mut();
-        
-        if let Some(topic) = queue.topics.get_mut(topic_id as usize) {
-            let sub_id = topic.next_id;
-            topic.subscribers.insert(sub_id, callback);
-            topic.next_id = topic.next_id.wrapping_add(1);
-            Ok(sub_id)
-        } else {
-            Err(JsValue::from_str("Invalid topic ID"))
+        if channel_handle != 0 && queue.channel(channel_handle).is_none() {
+            return Err(JsValue::from_str("Invalid channel handle"));
         }
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.route = (channel_handle != 0).then_some(channel_handle);
+        Ok(())
     }
 
-    /// Unsubscribe from a topic using its ID
-    pub fn unsubscribe(&self, topic_id: u32, sub_id: u32) -> bool {
-        let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.topics.get_mut(topic_id as usize) {
-            topic.subscribers.remove(&sub_id).is_some()
-        } else {
-            false
-        }
+    /// Exchange messages over an additional MessagePort
+    /// Published messages are posted on every attached port (and the
+    /// BroadcastChannel, if any); messages arriving on several of them are
+    /// delivered once. Messages are not relayed from one port to another.
+    /// @param port - One end of a MessageChannel
+    #[wasm_bindgen]
+    pub fn attach_port(&self, port: MessagePort) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        self.attach_transport(Transport::Port(port));
+        Ok(())
     }
-    
-    /// Publish using a topic ID (handle)
-    /// This is O(1) and avoids string hashing/copying - significantly faster for high frequency
-    pub fn publish(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
-        // Step 1: Create message and dispatch locally
-        let (msg_js, has_channel) = {
-            let mut queue = self.inner.borrow_mut();
-            
-            // Verify topic ID exists
-            if topic_id as usize >= queue.topics.len() {
-                return Err(JsValue::from_str("Invalid topic ID"));
-            }
 
-            // Use a simple counter for ID or generate UUID if needed?
-            // For now, let's use a random u64 which is faster than string UUID
-            // Or better: use a simple counter for local messages
-            let id = (js_sys::Math::random() * 1e16) as u64;
+    /// Create a queue from a declarative configuration object
+    /// Config fields (all optional):
+    /// - `channel`: BroadcastChannel name
+    /// - `transport`: "broadcast" (default; falls back to localStorage where
+    ///   BroadcastChannel is unavailable) or "storage" to always use localStorage
+    /// - `client_id`: client ID to use instead of a generated UUID
+    /// - `topics`: array of `{name, buffer_capacity?, tags?}` to register
+    /// - `error_handler`: function passed to `set_error_handler`
+    /// - `dedup_window`: number passed to `set_dedup_window`
+    /// @param config - Configuration object
+    #[wasm_bindgen]
+    pub fn from_js_config(#[wasm_bindgen(unchecked_param_type = "QueueConfig")] config: JsValue) -> Result<MessageQueue, JsValue> {
+        if !config.is_object() {
+            return Err(JsValue::from_str("Config must be an object"));
+        }
 
-            let message = Message {
-                id,
-                topic_id,
-                payload,
-                timestamp: js_sys::Date::now(),
-                origin_id: queue.client_id.clone(),
-            };
-            
-            let rc_msg = Rc::new(message);
-
-            // Dispatch locally
-            // No JS object creation needed here for local dispatch!
-            queue.dispatch_local(&rc_msg, None);
-
-            // Check if we have a channel
-            let has_channel = queue.channel.is_some();
-            
-            // Only create JS object if we really need to broadcast
-            let msg_js = if has_channel {
-                // For broadcast we still need the old object format or a new one?
-                // Let's stick to the old format for now for compatibility with other tabs
-                // But we need the topic name
-                let topic_name = &queue.topics[topic_id as usize].name;
-                let raw_msg = crate::js_utils::message_to_js(&rc_msg, topic_name)?;
-                
-                // Wrap in packet [0, msg] for protocol
-                let packet = Array::new();
-                packet.push(&JsValue::from(0));
-                packet.push(&raw_msg);
-                packet.into()
-            } else {
-                JsValue::NULL
-            };
+        // Reject options this queue does not implement instead of silently ignoring them
+        for field in ["middleware", "sync_timeout_ms"] {
+            if js_utils::config_field(&config, field)?.is_some() {
+                return Err(JsValue::from_str(&format!("Unsupported config field: {}", field)));
+            }
+        }
 
-            (msg_js, has_channel)
+        let channel = js_utils::config_field(&config, "channel")?
+            .map(|v| v.as_string().ok_or_else(|| JsValue::from_str("channel must be a string")))
+            .transpose()?;
+        let force_storage = match js_utils::config_field(&config, "transport")? {
+            None => false,
+            Some(v) => match v.as_string().as_deref() {
+                Some("broadcast") => false,
+                Some("storage") => true,
+                _ => return Err(JsValue::from_str("transport must be \"broadcast\" or \"storage\"")),
+            },
         };
+        let client_id = js_utils::config_field(&config, "client_id")?
+            .map(|v| v.as_string().ok_or_else(|| JsValue::from_str("client_id must be a string")))
+            .transpose()?;
+        let error_handler = js_utils::config_field(&config, "error_handler")?
+            .map(|v| v.dyn_into::<Function>().map_err(|_| JsValue::from_str("error_handler must be a function")))
+            .transpose()?;
+        let dedup_window = js_utils::config_field(&config, "dedup_window")?
+            .map(|v| v.as_f64()
+                .filter(|n| *n >= 0.0)
+                .map(|n| n as usize)
+                .ok_or_else(|| JsValue::from_str("dedup_window must be a non-negative number")))
+            .transpose()?;
+        let topics = js_utils::config_field(&config, "topics")?
+            .map(|v| v.dyn_into::<Array>().map_err(|_| JsValue::from_str("topics must be an array")))
+            .transpose()?;
 
-        // Step 2: Broadcast if channel exists
-        if has_channel {
-            let queue = self.inner.borrow();
-            if let Some(ref channel) = queue.channel {
-                channel.post_message(&msg_js).map_err(|_| {
-                    JsValue::from_str("Failed to broadcast message")
-                })?;
+        let mq = Self::open(OpenOptions { client_id, force_storage, ..OpenOptions::channel(channel) })?;
+        {
+            let mut queue = mq.inner.borrow_mut();
+            queue.error_handler = error_handler;
+            if let Some(size) = dedup_window {
+                queue.seen_ids.set_capacity(size);
+            }
+
+            for (i, topic_config) in topics.iter().flat_map(|topics| topics.iter()).enumerate() {
+                configure_topic(&mut queue, i, &topic_config)?;
             }
         }
 
-        Ok(())
+        Ok(mq)
     }
 
-    /// Publish a message asynchronously using Promise/microtask
-    /// This returns immediately and delivers the message in the next microtask
-    /// Useful for non-blocking operations and better browser responsiveness
-    pub fn publish_async(&self, topic_id: u32, payload: JsValue) -> Result<Promise, JsValue> {
-        // Clone necessary data for the async closure
-        let inner = self.inner.clone();
-        let payload_clone = payload.clone();
-
-        // Create a Promise that resolves in a microtask
-        let promise = Promise::new(&mut |resolve, reject| {
-            // Schedule in a microtask using Promise.resolve().then()
-            let resolve_clone = resolve.clone();
-            let reject_clone = reject.clone();
-
-            // Clone again for the inner closure
-            let inner2 = inner.clone();
-            let payload_clone2 = payload_clone.clone();
-
-            // Create the closure that will run in the microtask
-            let closure = Closure::once(move |_value: JsValue| {
-                let mut queue = match inner2.try_borrow_mut() {
-                    Ok(q) => q,
-                    Err(_) => {
-                        let err_msg = JsValue::from_str("Failed to borrow queue");
-                        let _ = reject_clone.call1(&JsValue::NULL, &err_msg);
-                        return;
-                    }
-                };
+    /// Create a topic
+    /// Names are normalized and checked as with `register_topic`.
+    /// @param topic_name - Name of the topic
+    /// @returns false if the topic already exists
+    pub fn create_topic(&self, topic_name: &str) -> Result<bool, JsValue> {
+        let created = {
+            let mut queue = self.inner.borrow_mut();
+            queue.check_open()?;
+            let topic_name = queue.normalize_topic_name(topic_name);
+            if queue.topic_index.contains_key(topic_name.as_ref()) {
+                false
+            } else {
+                queue.validate_topic_name(&topic_name)?;
+                queue.get_or_create_topic_id(&topic_name);
+                true
+            }
+        };
+        notify_topic_events(&self.inner);
+        Ok(created)
+    }
+    
+    /// Register a topic and get its ID (handle) for fast publishing
+    /// Returns the topic ID that can be used with publish_by_id
+    /// The name is normalized as configured with `trimTopicNames` and
+    /// `foldTopicNameCase`. New topics need a non-empty name of at most
+    /// `maxTopicNameLength` characters (default 256) that passes the validator
+    /// set with `set_topic_name_validator`.
+    pub fn register_topic(&self, topic_name: &str) -> Result<u32, JsValue> {
+        let topic_id = {
+            let mut queue = self.inner.borrow_mut();
+            queue.check_open()?;
+            let topic_name = queue.normalize_topic_name(topic_name);
+            queue.get_or_create_valid_topic_id(&topic_name)?
+        };
+        notify_topic_events(&self.inner);
+        Ok(topic_id)
+    }
 
-                // Generate u64 ID
-                let id = (js_sys::Math::random() * 1e16) as u64;
-                
-                // Verify topic ID exists
-                if (topic_id as usize) >= queue.topics.len() {
-                     let err_msg = JsValue::from_str("Invalid topic ID");
-                     let _ = reject_clone.call1(&JsValue::NULL, &err_msg);
-                     return;
-                }
+    /// Destroy a topic by ID
+    /// Frees the topic's slot and name for reuse. The old ID becomes invalid and
+    /// never refers to a topic created later, even if it reuses the slot.
+    /// @returns false if the ID does not refer to a live topic
+    pub fn destroy_topic(&self, topic_id: u32) -> bool {
+        destroy_topic(&self.inner, topic_id)
+    }
 
-                // Create the message
-                let message = Message {
-                    id,
-                    topic_id,
-                    payload: payload_clone2,
-                    timestamp: js_sys::Date::now(),
-                    origin_id: queue.client_id.clone(),
-                };
-                
-                let rc_msg = Rc::new(message);
-                
-                // Dispatch locally
-                queue.dispatch_local(&rc_msg, None);
-
-                // Resolve the promise
-                let _ = resolve_clone.call0(&JsValue::NULL);
-            });
+    /// Rename a topic atomically, keeping its ID, subscribers, buffer and tags
+    /// Other tabs on the same channel are notified and apply the rename as well.
+    /// @param old_name - Current name of the topic
+    /// @param new_name - New name (must be a valid topic name that does not exist yet)
+    /// @returns The (unchanged) topic ID
+    #[wasm_bindgen]
+    pub fn migrate_topic(&self, old_name: &str, new_name: &str) -> Result<u32, JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        queue.check_open()?;
+        let old_name = queue.normalize_topic_name(old_name).into_owned();
+        let new_name = queue.normalize_topic_name(new_name).into_owned();
+        if queue.topic_index.contains_key(&new_name) {
+            return Err(JsValue::from_str("Topic already exists"));
+        }
+        queue.validate_topic_name(&new_name)?;
+        let topic_id = queue.rename_topic(&old_name, &new_name)
+            .ok_or_else(|| JsValue::from_str("Topic not found"))?;
 
-            // Use Promise.resolve().then() to create a microtask
-            let microtask_promise = Promise::resolve(&JsValue::UNDEFINED);
-            
-            // Pass the function to then() and forget the closure to keep it alive
-            let _ = microtask_promise.then(&closure);
-            
-            // IMPORTANT: We must forget the closure so it's not dropped before execution
-            closure.forget();
-        });
+        // MIGRATE: [6, old_name, new_name]
+        let packet = Array::new();
+        packet.push(&JsValue::from(PACKET_MIGRATE));
+        packet.push(&JsValue::from_str(&old_name));
+        packet.push(&JsValue::from_str(&new_name));
+        queue.post_packet(&packet)?;
 
-        Ok(promise)
+        Ok(topic_id)
     }
 
-    pub fn get_client_id(&self) -> String {
-        self.inner.borrow().client_id.as_ref().clone()
-    }
+    /// Create a replay subject: a buffered topic that replays its last
+    /// `buffer_size` messages to every new subscriber
+    /// @param name - Name of the topic
+    /// @param buffer_size - Number of messages to retain for replay
+    /// @returns The topic ID
+    #[wasm_bindgen]
+    pub fn create_replay_subject(&self, name: &str, buffer_size: usize) -> Result<u32, JsValue> {
+        if buffer_size == 0 {
+            return Err(JsValue::from_str("Buffer capacity must be greater than 0"));
+        }
 
-    /// Get the number of topics
-    pub fn topic_count(&self) -> usize {
-        self.inner.borrow().topics.len()
+        let topic_id = {
+            let mut queue = self.inner.borrow_mut();
+            let name = queue.normalize_topic_name(name);
+            let topic_id = queue.get_or_create_valid_topic_id(&name)?;
+            if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+                topic.enable_buffer(buffer_size);
+                topic.is_replay_subject = true;
+            }
+            topic_id
+        };
+        notify_topic_events(&self.inner);
+        Ok(topic_id)
     }
 
-    /// Get the number of subscribers for a specific topic
-    pub fn subscriber_count(&self, topic_id: u32) -> usize {
+    /// Check if a topic is a replay subject
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn is_replay_subject(&self, topic_id: u32) -> bool {
         self.inner.borrow()
-            .get_topic_by_id(topic_id as usize)
-            .map_or(0, |topic| topic.subscribers.len())
+            .get_topic_by_id(topic_id)
+            .is_some_and(|topic| topic.is_replay_subject)
     }
 
-    /// Check if a topic exists
-    pub fn has_topic(&self, topic_id: u32) -> bool {
-        self.has_topic_id(topic_id)
+    /// Get all metadata of a topic in a single call
+    /// Optional fields (buffer stats, last_message_time, tags) are absent when not applicable
+    /// @param topic_id - ID of the topic
+    /// @returns Object with {id, name, subscriber_count, has_buffer, buffer_size, buffer_capacity,
+    /// overflow_count, total_dispatched, stream_dropped, last_message_time, is_replay_subject, tags}
+    #[wasm_bindgen]
+    pub fn get_topic_metadata(&self, topic_id: u32) -> Result<JsValue, JsValue> {
+        let queue = self.inner.borrow();
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        js_utils::topic_metadata_to_js(topic_id, topic)
     }
 
-    /// Unsubscribe all subscribers from a topic
-    pub fn unsubscribe_all(&self, topic_id: u32) -> usize {
-        let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.get_topic_by_id_mut(topic_id as usize) {
-            let count = topic.subscribers.len();
-            topic.subscribers.clear();
-            count
-        } else {
-            0
-        }
+    /// List every topic with a few basic stats
+    /// The objects are snapshots; destroyed topics are not included.
+    /// @returns Array of {id, name, subscribers, buffered, buffer_capacity, local_only, broadcast_only}, in ID slot order
+    #[wasm_bindgen]
+    pub fn list_topics(&self) -> Result<Array, JsValue> {
+        let queue = self.inner.borrow();
+        queue.live_topics()
+            .map(|(id, topic)| js_utils::topic_summary_to_js(id, topic))
+            .collect()
     }
 
-    /// Enable message buffering for a specific topic
-    /// Messages will be cached in a ring buffer for later retrieval
+    /// Get the `list_topics` entry of a single topic
     /// @param topic_id - ID of the topic
-    /// @param capacity - Maximum number of messages to buffer (default: 100)
+    /// @returns {id, name, subscribers, buffered, buffer_capacity, local_only, broadcast_only}, or undefined for invalid IDs
     #[wasm_bindgen]
-    pub fn enable_topic_buffer(&self, topic_id: u32, capacity: Option<usize>) -> Result<(), JsValue> {
-        let cap = capacity.unwrap_or(100);
-        if cap == 0 {
-            return Err(JsValue::from_str("Buffer capacity must be greater than 0"));
+    pub fn topic_info(&self, topic_id: u32) -> Result<JsValue, JsValue> {
+        let queue = self.inner.borrow();
+        match queue.get_topic_by_id(topic_id) {
+            Some(topic) => js_utils::topic_summary_to_js(topic_id, topic),
+            None => Ok(JsValue::UNDEFINED),
         }
+    }
 
+    /// Keep a topic's messages in this tab even when the queue has a channel
+    /// Its publishes are neither posted nor offered to other tabs' sync requests.
+    /// Clears `set_topic_broadcast_only`.
+    /// @param topic_id - ID of the topic
+    /// @param local - true to stop broadcasting, false to broadcast again
+    #[wasm_bindgen]
+    pub fn set_topic_local_only(&self, topic_id: u32, local: bool) -> Result<(), JsValue> {
         let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.get_topic_by_id_mut(topic_id as usize) {
-            topic.enable_buffer(cap);
-            Ok(())
-        } else {
-            Err(JsValue::from_str("Invalid topic ID"))
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.local_only = local;
+        if local {
+            topic.broadcast_only = false;
         }
+        Ok(())
     }
 
-    /// Disable message buffering for a specific topic
-    /// Clears all buffered messages
+    /// Forward a topic's publishes to the channel without dispatching them in this tab
+    /// Local subscribers, buffers and wildcard subscribers do not see them; messages
+    /// received from other tabs are dispatched as usual. Clears `set_topic_local_only`.
     /// @param topic_id - ID of the topic
+    /// @param enabled - Optional, false to dispatch locally again (default: true)
     #[wasm_bindgen]
-    pub fn disable_topic_buffer(&self, topic_id: u32) -> Result<(), JsValue> {
+    pub fn set_topic_broadcast_only(&self, topic_id: u32, enabled: Option<bool>) -> Result<(), JsValue> {
+        let enabled = enabled.unwrap_or(true);
         let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.get_topic_by_id_mut(topic_id as usize) {
-            topic.disable_buffer();
-            Ok(())
-        } else {
-            Err(JsValue::from_str("Invalid topic ID"))
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.broadcast_only = enabled;
+        if enabled {
+            topic.local_only = false;
         }
+        Ok(())
     }
 
-    /// Get the current size of the message buffer for a topic
-    /// @param topic_id - ID of the topic
-    /// @returns Number of messages currently buffered, or -1 if buffering is not enabled
+    /// List the topics whose name starts with a prefix
+    /// @param prefix - Name prefix ("" matches every topic)
+    /// @returns Array of {id, name, subscribers, buffered, buffer_capacity, local_only, broadcast_only}, sorted by name
     #[wasm_bindgen]
-    pub fn get_buffer_size(&self, topic_id: u32) -> i32 {
+    pub fn find_topics(&self, prefix: &str) -> Result<Array, JsValue> {
         let queue = self.inner.borrow();
-        if let Some(topic) = queue.get_topic_by_id(topic_id as usize) {
-            topic.get_buffer()
-                .map(|b| b.len() as i32)
-                .unwrap_or(-1)
-        } else {
-            -1
-        }
+        let mut matches: Vec<(&String, usize)> = queue.topic_index.iter()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, &slot)| (name, slot))
+            .collect();
+        matches.sort_unstable();
+
+        matches.into_iter()
+            .map(|(_, slot)| js_utils::topic_summary_to_js(queue.topic_handle(slot), &queue.topics[slot]))
+            .collect()
     }
 
-    /// Get the buffer capacity for a topic
+    /// Get the message counters of the whole queue
+    /// @returns {published, delivered, errors, broadcast, received, overwrites, rejected_subscribes}
+    #[wasm_bindgen]
+    pub fn get_stats(&self) -> Result<JsValue, JsValue> {
+        js_utils::stats_to_js(&self.inner.borrow().stats)
+    }
+
+    /// Get the message counters of a single topic
+    /// `buffer_bytes` (see `get_buffer_bytes`) is only present for buffered topics.
     /// @param topic_id - ID of the topic
-    /// @returns Maximum buffer capacity, or 0 if buffering is not enabled
+    /// @returns {published, delivered, errors, broadcast, received, overwrites, rejected_subscribes, buffer_bytes}
     #[wasm_bindgen]
-    pub fn get_buffer_capacity(&self, topic_id: u32) -> usize {
+    pub fn get_topic_stats(&self, topic_id: u32) -> Result<JsValue, JsValue> {
         let queue = self.inner.borrow();
-        if let Some(topic) = queue.get_topic_by_id(topic_id as usize) {
-            topic.get_buffer()
-                .map(|b| b.capacity())
-                .unwrap_or(0)
-        } else {
-            0
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        let stats = js_utils::stats_to_js(&topic.stats)?;
+        if let Some(buffer) = topic.get_buffer() {
+            js_sys::Reflect::set(&stats, &"buffer_bytes".into(), &(buffer.bytes() as f64).into())?;
         }
+        Ok(stats)
     }
 
-    /// Clear all buffered messages for a topic
-    /// @param topic_id - ID of the topic
-    /// @returns Number of messages cleared
+    /// Reset the queue-wide and all per-topic message counters to zero
     #[wasm_bindgen]
-    pub fn clear_buffer(&self, topic_id: u32) -> usize {
+    pub fn reset_stats(&self) {
         let mut queue = self.inner.borrow_mut();
-        if let Some(topic) = queue.get_topic_by_id_mut(topic_id as usize) {
-            if let Some(buffer) = topic.get_buffer_mut() {
-                let count = buffer.len();
-                buffer.clear();
-                count
-            } else {
-                0
-            }
-        } else {
-            0
+        queue.stats = Stats::default();
+        for topic in queue.topics.iter_mut() {
+            topic.stats = Stats::default();
         }
     }
 
-    /// Check if a topic has buffering enabled
+    /// Add a tag to a topic (no-op if already present)
     /// @param topic_id - ID of the topic
+    /// @param tag - Tag to add
     #[wasm_bindgen]
-    pub fn has_buffer(&self, topic_id: u32) -> bool {
-        let queue = self.inner.borrow();
-        if let Some(topic) = queue.get_topic_by_id(topic_id as usize) {
-            topic.has_buffer()
+    pub fn add_topic_tag(&self, topic_id: u32, tag: &str) -> Result<(), JsValue> {
+        if self.inner.borrow_mut().add_tag(topic_id, tag) {
+            Ok(())
         } else {
-            false
+            Err(JsValue::from_str("Invalid topic ID"))
         }
     }
 
-    /// Get buffered messages for a topic as a JavaScript array
+    /// Remove a tag from a topic
     /// @param topic_id - ID of the topic
-    /// @returns Array of buffered messages (oldest first), or empty array if no buffer
+    /// @param tag - Tag to remove
+    /// @returns true if the tag was present
     #[wasm_bindgen]
-    pub fn get_buffered_messages(&self, topic_id: u32) -> Result<js_sys::Array, JsValue> {
-        let queue = self.inner.borrow();
-        if let Some(topic) = queue.get_topic_by_id(topic_id as usize) {
-            if let Some(buffer) = topic.get_buffer() {
-                let messages = buffer.to_vec();
-                let array = js_sys::Array::new();
-                for msg in messages {
-                    let msg_js = crate::js_utils::message_to_js(&msg, &topic.name)?;
-                    array.push(&msg_js);
-                }
-                Ok(array)
-            } else {
-                Ok(js_sys::Array::new())
-            }
-        } else {
-            Ok(js_sys::Array::new())
-        }
+    pub fn remove_topic_tag(&self, topic_id: u32, tag: &str) -> bool {
+        self.inner.borrow_mut().remove_tag(topic_id, tag)
     }
 
-    pub fn close(&mut self) -> Result<(), JsValue> {
-        let mut queue = self.inner.borrow_mut();
-        if let Some(channel) = &queue.channel {
-            channel.close();
-            channel.set_onmessage(None);
-        }
-        queue.channel = None;
-        queue.topics.clear();
-        queue.topic_index.clear();
+    /// Check if a topic has a tag
+    /// @param topic_id - ID of the topic
+    /// @param tag - Tag to look for
+    #[wasm_bindgen]
+    pub fn has_topic_tag(&self, topic_id: u32, tag: &str) -> bool {
+        self.inner.borrow()
+            .get_topic_by_id(topic_id)
+            .is_some_and(|topic| topic.tags.iter().any(|t| t == tag))
+    }
 
-        // Clear the closure - it will be properly dropped here
-        self._closure.take();
+    /// Get all tags of a topic
+    /// @param topic_id - ID of the topic
+    /// @returns Array of tag strings, or empty array for invalid IDs
+    #[wasm_bindgen]
+    pub fn get_topic_tags(&self, topic_id: u32) -> Array {
+        self.inner.borrow()
+            .get_topic_by_id(topic_id)
+            .map(|topic| topic.tags.iter().map(|t| JsValue::from_str(t)).collect())
+            .unwrap_or_default()
+    }
 
-        Ok(())
+    /// Find all topics carrying a tag
+    /// @param tag - Tag to look for
+    /// @returns IDs of matching topics
+    #[wasm_bindgen]
+    pub fn find_topics_by_tag(&self, tag: &str) -> Uint32Array {
+        let ids = self.inner.borrow().topic_ids_with_tag(tag);
+        Uint32Array::from(&ids[..])
     }
 
-    /// Publish multiple messages by ID (handle) efficiently
-    /// This is the fastest way to publish multiple messages
-    pub fn publish_batch_by_id(&self, topic_id: u32, payloads: js_sys::Array) -> Result<(), JsValue> {
-        // No need to lookup name anymore, just use ID
-        // Verify ID exists once
-        if !self.has_topic_id(topic_id) {
-             return Err(JsValue::from_str("Invalid topic ID"));
-        }
+    /// Destroy all topics carrying a tag
+    /// @param tag - Tag to look for
+    /// @returns Number of topics destroyed
+    #[wasm_bindgen]
+    pub fn destroy_topics_by_tag(&self, tag: &str) -> u32 {
+        let ids = self.inner.borrow().topic_ids_with_tag(tag);
+        ids.into_iter()
+            .filter(|&topic_id| self.destroy_topic(topic_id))
+            .count() as u32
+    }
 
-        for payload in payloads.iter() {
-            self.publish(topic_id, payload)?;
+    /// Create a handle that only sees the topics whose names start with `prefix`
+    /// The scope shares this queue's state and channel. Names passed to the
+    /// scope are prefixed verbatim (`scope('editor/').register_topic('save')`
+    /// creates "editor/save"), and names it returns have the prefix removed.
+    /// Closing the scope destroys only its topics; closing the queue closes
+    /// every scope too.
+    /// @param prefix - Non-empty name prefix, e.g. "editor/"
+    #[wasm_bindgen]
+    pub fn scope(&self, prefix: &str) -> Result<ScopedQueue, JsValue> {
+        self.inner.borrow().check_open()?;
+        if prefix.is_empty() {
+            return Err(JsValue::from_str("Scope prefix must not be empty"));
         }
-        Ok(())
+        Ok(ScopedQueue::new(self.inner.clone(), prefix))
     }
 
-    fn has_topic_id(&self, topic_id: u32) -> bool {
-        let queue = self.inner.borrow();
-        (topic_id as usize) < queue.topics.len()
+    /// Subscribe to a topic using its ID
+    /// Callback signature: (payload, topic_id, timestamp, message_id, headers)
+    /// `headers` is undefined for messages published without headers
+    /// Replay subjects deliver their buffered messages to the new subscriber first
+    /// A topic's subscribers are called in the order they subscribed (wildcard
+    /// subscribers of `subscribe_pattern` come first), unless subscribed with
+    /// `subscribe_with_order`. IDs of removed subscribers are never valid again,
+    /// even though their slots are reused.
+    #[wasm_bindgen(skip_typescript)]
+    pub fn subscribe(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        subscribe_callback(&self.inner, topic_id, callback)
     }
-}
 
-// ============================================================================
-// Additional MessageQueue Methods (Not exported to JavaScript)
-// ============================================================================
+    /// Subscribe with an explicit position in the topic's dispatch order
+    /// Subscribers with a lower order are called first; subscribers with the
+    /// same order are called in the order they subscribed. `subscribe` and the
+    /// other variants use order 0, so e.g. a validating subscriber at -1 runs
+    /// before them and a rendering one at 1 after them.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @param order - Dispatch order
+    /// @returns Subscriber ID
+    #[wasm_bindgen]
+    pub fn subscribe_with_order(&self, topic_id: u32, callback: Function, order: i32) -> Result<u32, JsValue> {
+        subscribe_ordered(&self.inner, topic_id, Subscriber::new(callback), order)
+    }
 
-impl MessageQueue {
-    /// Publish multiple messages efficiently
+    /// Subscribe until a signal is aborted, like `addEventListener`'s `signal` option
+    /// The subscriber is removed when the signal fires; unsubscribing it before
+    /// that detaches it from the signal.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @param signal - AbortSignal ending the subscription
+    /// @returns Subscriber ID
+    #[wasm_bindgen(skip_typescript)]
+    pub fn subscribe_with_signal(&self, topic_id: u32, callback: Function, signal: AbortSignal) -> Result<u32, JsValue> {
+        if signal.aborted() {
+            return Err(JsValue::from_str(ERR_SIGNAL_ABORTED));
+        }
+        let sub_id = subscribe_callback(&self.inner, topic_id, callback)?;
+
+        let weak = Rc::downgrade(&self.inner);
+        let listener = abort_listener(signal, move || when_unborrowed(weak.clone(), move |inner| {
+            // Dropped after the borrow is released, detaching this listener
+            let removed = inner.borrow_mut().get_topic_by_id_mut(topic_id)
+                .and_then(|topic| topic.subscribers.remove(sub_id));
+            drop(removed);
+        }))?;
+        let mut queue = self.inner.borrow_mut();
+        if let Some(subscriber) = queue.get_topic_by_id_mut(topic_id).and_then(|topic| topic.subscribers.get_mut(sub_id)) {
+            subscriber.abort_listener = Some(listener);
+        }
+        Ok(sub_id)
+    }
+
+    /// Subscribe unless the same callback is already subscribed to the topic
+    /// Callbacks are compared by reference (`Object.is`), so an inline arrow
+    /// function created on every call is a new callback each time.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @returns ID of the new subscriber, or of the existing one
+    #[wasm_bindgen(skip_typescript)]
+    pub fn subscribe_unique(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        match self.find_subscription(topic_id, &callback) {
+            Some(sub_id) => Ok(sub_id),
+            None => subscribe_callback(&self.inner, topic_id, callback),
+        }
+    }
+
+    /// Find the subscriber a callback was subscribed to a topic as
+    /// Callbacks are compared by reference (`Object.is`).
+    /// @param topic_id - ID of the topic
+    /// @param callback - The subscribed function
+    /// @returns ID of the first matching subscriber, or undefined
+    #[wasm_bindgen]
+    pub fn find_subscription(&self, topic_id: u32, callback: &Function) -> Option<u32> {
+        self.inner.borrow().get_topic_by_id(topic_id)?.find_subscriber(callback)
+    }
+
+    /// Limit the number of subscribers of a topic
+    /// Subscribing beyond the limit throws "Subscriber limit reached" and is counted
+    /// in the `rejected_subscribes` stat. Existing subscribers are kept when the
+    /// limit is lowered below their number.
+    /// @param topic_id - ID of the topic
+    /// @param limit - Maximum number of subscribers, or undefined for no limit
+    #[wasm_bindgen]
+    pub fn set_max_subscribers(&self, topic_id: u32, limit: Option<usize>) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.max_subscribers = limit;
+        Ok(())
+    }
+
+    /// Change a subscriber's dispatch order (see `subscribe_with_order`)
+    /// The subscriber is called after the subscribers already at the new order.
+    /// @param topic_id - ID of the topic
+    /// @param sub_id - ID of the subscriber
+    /// @param order - New dispatch order
+    #[wasm_bindgen]
+    pub fn set_subscriber_order(&self, topic_id: u32, sub_id: u32, order: i32) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        if topic.subscribers.set_order(sub_id, order) {
+            Ok(())
+        } else {
+            Err(JsValue::from_str("Invalid subscriber ID"))
+        }
+    }
+
+    /// Subscribe to a topic with options
+    /// Options (all optional):
+    /// - `replay_retained`: immediately invoke the callback with the topic's retained message
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @param options - Options object
+    /// @returns Subscriber ID
+    #[wasm_bindgen(skip_typescript)]
+    pub fn subscribe_with_options(&self, topic_id: u32, callback: Function, options: JsValue) -> Result<u32, JsValue> {
+        let replay_retained = if options.is_object() {
+            js_utils::config_field(&options, "replay_retained")?
+                .is_some_and(|v| v.is_truthy())
+        } else {
+            false
+        };
+
+        let sub_id = subscribe_callback(&self.inner, topic_id, callback.clone())?;
+        if replay_retained {
+            let retained = self.inner.borrow()
+                .get_topic_by_id(topic_id)
+                .and_then(|topic| topic.retained.clone())
+                .filter(|msg| !msg.is_expired());
+            if let Some(msg) = retained {
+                deliver_to(&self.inner, &callback, topic_id, sub_id, &msg);
+            }
+        }
+        Ok(sub_id)
+    }
+
+    /// Subscribe with a predicate deciding which messages are delivered
+    /// The callback is only invoked for messages whose payload makes `filter`
+    /// return a truthy value; this applies to replayed and cross-tab messages too.
+    /// @param topic_id - ID of the topic
+    /// @param filter - Function called with (payload)
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    #[wasm_bindgen]
+    pub fn subscribe_filtered(&self, topic_id: u32, filter: Function, callback: Function) -> Result<u32, JsValue> {
+        subscribe_filtered(&self.inner, topic_id, filter, callback)
+    }
+
+    /// Subscribe to messages whose payload has `payload[key] === expected`
+    /// The comparison runs in WASM, so rejected messages never enter user code.
+    /// @param topic_id - ID of the topic
+    /// @param key - Payload property to compare
+    /// @param expected - Value the property must strictly equal
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    #[wasm_bindgen]
+    pub fn subscribe_filtered_key(&self, topic_id: u32, key: &str, expected: JsValue, callback: Function) -> Result<u32, JsValue> {
+        let mut subscriber = Subscriber::new(callback);
+        subscriber.filter = Some(SubscriberFilter::KeyEquals { key: JsValue::from_str(key), expected });
+        subscribe_with_replay_subject(&self.inner, topic_id, subscriber)
+    }
+
+    /// Subscribe and receive the topic's buffered messages before live ones
+    /// Buffered messages are delivered oldest first; messages published while the
+    /// replay runs (e.g. by the callback itself) are delivered after it, in order.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @param limit - Replay at most this many of the most recent messages (default: all)
+    /// @returns {sub_id, replayed} where `replayed` is the number of buffered messages delivered
+    #[wasm_bindgen]
+    pub fn subscribe_with_replay(&self, topic_id: u32, callback: Function, limit: Option<usize>) -> Result<JsValue, JsValue> {
+        let deferred = Rc::new(RefCell::new(VecDeque::new()));
+        let mut subscriber = Subscriber::new(callback.clone());
+        subscriber.deferred = Some(deferred.clone());
+        let sub_id = self.add_subscriber(topic_id, subscriber)?;
+
+        let messages = live_buffered_messages(&self.inner, topic_id);
+        let skip = limit.map_or(0, |limit| messages.len().saturating_sub(limit));
+
+        // Invoked directly, so replayed messages are not buffered again
+        let mut replayed = 0u32;
+        for msg in &messages[skip..] {
+            deliver_to(&self.inner, &callback, topic_id, sub_id, msg);
+            replayed += 1;
+        }
+
+        finish_deferred(&self.inner, topic_id, sub_id, &callback, &deferred, |_| true);
+
+        let result = js_sys::Object::new();
+        js_sys::Reflect::set(&result, &"sub_id".into(), &sub_id.into())?;
+        js_sys::Reflect::set(&result, &"replayed".into(), &replayed.into())?;
+        Ok(result.into())
+    }
+
+    /// Subscribe to a topic, skipping the first `n` messages before delivering
+    /// After `n` messages have been skipped the subscriber behaves like a regular one
+    /// @param topic_id - ID of the topic
+    /// @param n - Number of messages to skip (0 behaves like `subscribe`)
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    #[wasm_bindgen]
+    pub fn subscribe_skip_n(&self, topic_id: u32, n: u32, callback: Function) -> Result<u32, JsValue> {
+        let mut subscriber = Subscriber::new(callback);
+        subscriber.skip_remaining = n;
+        self.add_subscriber(topic_id, subscriber)
+    }
+
+    /// Subscribe with a callback that receives a single message object
+    /// The object is {payload, topic_id, topic, timestamp, id, origin_id, headers?}.
+    /// Slower than `subscribe` (one object per message), but easier to destructure.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (message)
+    #[wasm_bindgen]
+    pub fn subscribe_raw(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        let mut subscriber = Subscriber::new(callback);
+        subscriber.raw = true;
+        self.add_subscriber(topic_id, subscriber)
+    }
+
+    /// Subscribe for delivery during browser idle time
+    /// Messages are queued and delivered from `requestIdleCallback` (or `setTimeout(0)`
+    /// where unavailable), a bounded batch per callback. Suited to non-urgent work
+    /// such as analytics. Undelivered messages are dropped on unsubscribe.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    #[wasm_bindgen]
+    pub fn subscribe_on_idle(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        let mut subscriber = Subscriber::new(callback);
+        subscriber.idle = Some(IdleDelivery::new());
+        self.add_subscriber(topic_id, subscriber)
+    }
+
+    /// Subscribe for a single message
+    /// The subscriber is removed after its first delivery.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @returns Subscriber ID, usable with `unsubscribe` before delivery
+    #[wasm_bindgen]
+    pub fn subscribe_once(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        subscribe_once(&self.inner, topic_id, callback)
+    }
+
+    /// Wait for the payload of the next message published on a topic
+    /// Rejects with "Topic destroyed" or "Queue closed" if that happens first.
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn next_message(&self, topic_id: u32) -> Promise {
+        next_message_promise(&self.inner, topic_id, None)
+    }
+
+    /// Like `next_message`, but rejects with "Timeout" if no message arrives in time
+    /// @param topic_id - ID of the topic
+    /// @param ms - Timeout in milliseconds
+    #[wasm_bindgen]
+    pub fn next_message_timeout(&self, topic_id: u32, ms: f64) -> Promise {
+        next_message_promise(&self.inner, topic_id, Some(ms))
+    }
+
+    /// Wait for the next message on a topic
+    /// Resolves with {payload, id, timestamp, topic_id} and removes the subscriber after delivery.
+    /// Rejects with "Topic destroyed" if the topic is destroyed before a message arrives.
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn subscribe_once_async(&self, topic_id: u32) -> Promise {
+        let inner = self.inner.clone();
+
+        Promise::new(&mut |resolve, reject| {
+            let result = js_utils::once_resolver(&resolve).and_then(|callback| {
+                let mut subscriber = Subscriber::new(callback);
+                subscriber.once = true;
+                subscriber.on_destroy = Some(reject.clone());
+                add_subscriber(&inner, topic_id, subscriber)
+            });
+
+            if let Err(err) = result {
+                let _ = reject.call1(&JsValue::NULL, &err);
+            }
+        })
+    }
+
+    /// Unsubscribe from a topic using its ID
+    pub fn unsubscribe(&self, topic_id: u32, sub_id: u32) -> bool {
+        let mut queue = self.inner.borrow_mut();
+        if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+            topic.subscribers.remove(sub_id).is_some()
+        } else {
+            false
+        }
+    }
+
+    /// Unsubscribe a callback from a topic without knowing its subscriber ID
+    /// Callbacks are compared by reference (`Object.is`). A callback subscribed
+    /// several times is removed once per call, first subscription first.
+    /// @param topic_id - ID of the topic
+    /// @param callback - The subscribed function
+    /// @returns true if a subscriber was removed
+    #[wasm_bindgen]
+    pub fn unsubscribe_callback(&self, topic_id: u32, callback: &Function) -> bool {
+        let mut queue = self.inner.borrow_mut();
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+        match topic.find_subscriber(callback) {
+            Some(sub_id) => topic.subscribers.remove(sub_id).is_some(),
+            None => false,
+        }
+    }
+
+    /// Unsubscribe every subscription of a callback, on all topics
+    /// Callbacks are compared by reference (`Object.is`).
+    /// @param callback - The subscribed function
+    /// @returns Number of subscribers removed
+    #[wasm_bindgen]
+    pub fn unsubscribe_callback_all_topics(&self, callback: &Function) -> u32 {
+        let mut queue = self.inner.borrow_mut();
+        let mut removed = 0;
+        for topic in queue.topics.iter_mut().filter(|topic| topic.alive) {
+            while let Some(sub_id) = topic.find_subscriber(callback) {
+                topic.subscribers.remove(sub_id);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Stop delivering messages to one subscriber without unsubscribing it
+    /// Other subscribers of the topic keep receiving messages. Skip counts and
+    /// `once` subscriptions are not consumed while paused.
+    /// @param topic_id - ID of the topic
+    /// @param sub_id - ID of the subscriber
+    /// @returns true if the subscriber exists and was not already paused
+    #[wasm_bindgen]
+    pub fn pause_subscriber(&self, topic_id: u32, sub_id: u32) -> bool {
+        let mut queue = self.inner.borrow_mut();
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            return false;
+        };
+        let newest = topic.get_buffer().and_then(|buffer| buffer.peek_back()).cloned();
+        match topic.subscribers.get_mut(sub_id) {
+            Some(sub) if !sub.paused => {
+                sub.paused = true;
+                sub.paused_after = newest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Resume a paused subscriber
+    /// With `replay_missed`, buffered messages that arrived while it was paused
+    /// are delivered to this subscriber only, oldest first, before live ones.
+    /// Messages already evicted from the buffer (or on unbuffered topics) are lost.
+    /// @param topic_id - ID of the topic
+    /// @param sub_id - ID of the subscriber
+    /// @param replay_missed - Replay missed messages from the topic's buffer
+    /// @returns Number of replayed messages
+    #[wasm_bindgen]
+    pub fn resume_subscriber(&self, topic_id: u32, sub_id: u32, replay_missed: bool) -> Result<u32, JsValue> {
+        let (callback, filter, deferred, missed) = {
+            let mut queue = self.inner.borrow_mut();
+            let topic = queue.get_topic_by_id_mut(topic_id)
+                .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+            if replay_missed {
+                topic.purge_expired();
+            }
+            let buffered = topic.get_buffer().map(|buffer| buffer.to_vec()).unwrap_or_default();
+            let sub = topic.subscribers.get_mut(sub_id)
+                .ok_or_else(|| JsValue::from_str("Invalid subscriber ID"))?;
+            if !sub.paused {
+                return Ok(0);
+            }
+            sub.paused = false;
+            let paused_after = sub.paused_after.take();
+            if !replay_missed {
+                return Ok(0);
+            }
+
+            // Without the marker in the buffer, every buffered message is newer
+            let start = paused_after
+                .and_then(|marker| buffered.iter().position(|msg| Rc::ptr_eq(msg, &marker)))
+                .map_or(0, |pos| pos + 1);
+            let deferred = Rc::new(RefCell::new(VecDeque::new()));
+            sub.deferred = Some(deferred.clone());
+            (sub.callback.clone(), sub.filter.clone(), deferred, buffered[start..].to_vec())
+        };
+
+        let accepts = |msg: &Message| filter.as_ref().is_none_or(|filter| filter.accepts(&msg.payload));
+        let mut replayed = 0u32;
+        for msg in &missed {
+            if accepts(msg) {
+                deliver_to(&self.inner, &callback, topic_id, sub_id, msg);
+                replayed += 1;
+            }
+        }
+
+        finish_deferred(&self.inner, topic_id, sub_id, &callback, &deferred, accepts);
+        Ok(replayed)
+    }
+
+    /// Subscribe to all topics whose name matches an MQTT-style pattern
+    /// Topic names are split into segments on `/`; `+` matches exactly one
+    /// segment and `#` (last segment only) matches any number of trailing ones.
+    /// Topics created later are covered automatically.
+    /// @param pattern - Pattern such as `sensor/+/temp` or `sensor/#`
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @returns Pattern subscriber ID, for use with `unsubscribe_pattern`
+    #[wasm_bindgen]
+    pub fn subscribe_pattern(&self, pattern: &str, callback: Function) -> Result<u32, JsValue> {
+        self.inner.borrow().check_open()?;
+        validate_pattern(pattern).map_err(JsValue::from_str)?;
+        Ok(self.inner.borrow_mut().add_pattern_subscriber(PatternSubscriber {
+            pattern: pattern.to_string(),
+            callback,
+        }))
+    }
+
+    /// Remove a subscriber registered with `subscribe_pattern`
+    /// @param sub_id - ID returned by `subscribe_pattern`
+    /// @returns false if no such pattern subscriber exists
+    #[wasm_bindgen]
+    pub fn unsubscribe_pattern(&self, sub_id: u32) -> bool {
+        self.inner.borrow_mut().remove_pattern_subscriber(sub_id)
+    }
+
+    /// Call a function whenever a topic is created
+    /// Covers every way of creating a topic, including topics first seen in a
+    /// message or sync from another tab. Hooks run after the queue is updated,
+    /// so they may use the queue; errors they throw are reported (see `set_error_handler`).
+    /// @param callback - Function called with (topic_id, topic_name)
+    /// @returns Hook handle, for use with `remove_topic_hook`
+    #[wasm_bindgen]
+    pub fn on_topic_created(&self, callback: Function) -> u32 {
+        self.add_topic_hook(TopicEvent::Created, callback)
+    }
+
+    /// Call a function whenever a topic is destroyed
+    /// @param callback - Function called with (topic_id, topic_name); the ID is no longer valid
+    /// @returns Hook handle, for use with `remove_topic_hook`
+    #[wasm_bindgen]
+    pub fn on_topic_destroyed(&self, callback: Function) -> u32 {
+        self.add_topic_hook(TopicEvent::Destroyed, callback)
+    }
+
+    /// Remove a hook registered with `on_topic_created` or `on_topic_destroyed`
+    /// @param handle - Handle returned when registering the hook
+    /// @returns false if no such hook exists
+    #[wasm_bindgen]
+    pub fn remove_topic_hook(&self, handle: u32) -> bool {
+        let mut queue = self.inner.borrow_mut();
+        let before = queue.topic_hooks.len();
+        queue.topic_hooks.retain(|(id, _, _)| *id != handle);
+        queue.topic_hooks.len() != before
+    }
+
+    fn add_topic_hook(&self, event: TopicEvent, callback: Function) -> u32 {
+        let mut queue = self.inner.borrow_mut();
+        let handle = queue.next_topic_hook_id;
+        queue.next_topic_hook_id = queue.next_topic_hook_id.wrapping_add(1);
+        queue.topic_hooks.push((handle, event, callback));
+        handle
+    }
+
+    /// Consume a topic as an async iterator: `for await (const msg of mq.stream(topicId))`
+    /// Each value is a message object ({payload, topic_id, topic, timestamp, id, origin_id}).
+    /// Messages arriving while no `next()` is waiting are queued; beyond `buffer_limit`
+    /// the oldest queued message is dropped. The stream ends, and its pending
+    /// `next()` calls resolve with {done: true}, when `return()` is called (e.g. by
+    /// `break`), the topic is destroyed or the queue is closed.
+    /// @param topic_id - ID of the topic
+    /// @param buffer_limit - Maximum number of queued messages (default: 1000)
+    /// @returns A `TopicStream`
+    #[wasm_bindgen]
+    pub fn stream(&self, topic_id: u32, buffer_limit: Option<usize>) -> Result<JsValue, JsValue> {
+        TopicStream::open(&self.inner, topic_id, buffer_limit.unwrap_or(DEFAULT_STREAM_BUFFER_LIMIT))
+    }
+
+    /// Read a topic as a `ReadableStream` of message objects, e.g. to `pipeTo` a `WritableStream`
+    /// Once `high_water_mark` messages are queued in the stream, new messages are not
+    /// enqueued: with a topic buffer they are enqueued from the buffer when the reader
+    /// catches up (unless evicted meanwhile), otherwise they are dropped and counted in
+    /// the `stream_dropped` of `get_topic_metadata`. Cancelling the stream unsubscribes;
+    /// destroying the topic or closing the queue closes it.
+    /// @param topic_id - ID of the topic
+    /// @param high_water_mark - Messages queued in the stream before it is full (default: 16)
+    #[wasm_bindgen]
+    pub fn readable_stream(&self, topic_id: u32, high_water_mark: Option<f64>) -> Result<ReadableStream, JsValue> {
+        let high_water_mark = high_water_mark.unwrap_or(DEFAULT_STREAM_HIGH_WATER_MARK);
+        if high_water_mark.is_nan() || high_water_mark < 0.0 {
+            return Err(JsValue::from_str("high_water_mark must be a non-negative number"));
+        }
+        ReadableSource::open(&self.inner, topic_id, high_water_mark)
+    }
+
+    /// Get an EventTarget re-emitting the topic's messages, for `addEventListener` consumers
+    /// Each message is dispatched as a CustomEvent whose type is the topic name and whose
+    /// detail is {payload, id, timestamp, origin_id}. The target is created and subscribed
+    /// on the first call and reused afterwards; it stops emitting when the topic is
+    /// destroyed or the queue closed.
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn as_event_target(&self, topic_id: u32) -> Result<EventTarget, JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        let target = match &topic.event_target {
+            Some((target, sub_id)) if topic.subscribers.contains_key(*sub_id) => return Ok(target.clone()),
+            // Its subscriber was removed, e.g. by `unsubscribe_all`
+            Some((target, _)) => target.clone(),
+            None => EventTarget::new()?,
+        };
+
+        let mut subscriber = Subscriber::new(js_utils::event_dispatcher(&target)?);
+        subscriber.raw = true;
+        let sub_id = topic.add_subscriber(subscriber);
+        topic.event_target = Some((target.clone(), sub_id));
+        Ok(target)
+    }
+
+    /// Subscribe to every message on every topic, e.g. for logging or devtools
+    /// Covers topics created later and messages from other tabs. Global
+    /// subscribers run after the topic's own and are not part of buffer replay.
+    /// @param callback - Function called with (payload, topic_name, topic_id, timestamp, message_id)
+    /// @returns Global subscriber ID, for use with `unsubscribe_all_listener`
+    #[wasm_bindgen]
+    pub fn subscribe_all(&self, callback: Function) -> u32 {
+        self.inner.borrow_mut().add_global_subscriber(callback)
+    }
+
+    /// Remove a subscriber registered with `subscribe_all`
+    /// @param sub_id - ID returned by `subscribe_all`
+    /// @returns false if no such global subscriber exists
+    #[wasm_bindgen]
+    pub fn unsubscribe_all_listener(&self, sub_id: u32) -> bool {
+        self.inner.borrow_mut().global_subscribers.remove(&sub_id).is_some()
+    }
+
+    /// Publish using a topic ID (handle)
+    /// This is O(1) and avoids string hashing/copying - significantly faster for high frequency
+    /// Publishing to a topic without subscribers, buffer or channel creates no message at all.
+    #[wasm_bindgen(skip_typescript)]
+    pub fn publish(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        publish_message(&self.inner, topic_id, payload)
+    }
+
+    /// Publish a message, transferring `transfer_list` to other contexts instead of copying
+    /// Local subscribers in this tab receive the payload first, then the message is
+    /// posted, bypassing the broadcast throttle. Transports that cannot transfer
+    /// (BroadcastChannel) copy the payload as usual and nothing is detached. Once a
+    /// buffer has been transferred it is detached (`byteLength` 0) in this tab, so
+    /// buffered copies of the message and later replays see an empty buffer.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param transfer_list - Transferable objects (e.g. ArrayBuffers) inside the payload
+    #[wasm_bindgen]
+    pub fn publish_transfer(&self, topic_id: u32, payload: JsValue, transfer_list: Array) -> Result<(), JsValue> {
+        let published = publish_locked(&mut self.inner.borrow_mut(), topic_id, payload, PublishOptions::default());
+        notify_rejected(&self.inner);
+        let published = published?;
+        let topic_name = self.inner.borrow().get_topic_by_id(topic_id).map(|topic| topic.name.clone()).unwrap_or_default();
+
+        // Deliver before posting: the transfer detaches the payload's buffers
+        deliver(&self.inner, published.delivery);
+
+        let broadcast = match published.raw_msg {
+            Some(raw_msg) => {
+                // Binary packets copy the payload, so transfers need the object format
+                let raw_msg = if raw_msg.is_instance_of::<Uint8Array>() {
+                    js_utils::message_to_js(&published.message, &topic_name)?
+                } else {
+                    raw_msg
+                };
+                broadcast_transfer(&self.inner, topic_id, raw_msg, &transfer_list)
+            }
+            None => Ok(()),
+        };
+
+        notify_backpressure(&self.inner);
+        broadcast
+    }
+
+    /// Publish a message with headers (metadata kept apart from the payload)
+    /// Subscribers receive the headers as a fifth callback argument; they are
+    /// also broadcast to other tabs and kept with buffered messages.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param headers - Plain object of header values, or undefined/null for none
+    #[wasm_bindgen]
+    pub fn publish_with_headers(&self, topic_id: u32, payload: JsValue, headers: JsValue) -> Result<(), JsValue> {
+        let headers = if headers.is_undefined() || headers.is_null() {
+            None
+        } else if headers.is_object() {
+            Some(headers.unchecked_into::<js_sys::Object>())
+        } else {
+            return Err(JsValue::from_str("Headers must be an object"));
+        };
+
+        let options = PublishOptions { headers, ..Default::default() };
+        let published = publish_locked(&mut self.inner.borrow_mut(), topic_id, payload, options);
+        notify_rejected(&self.inner);
+        finish_publish(&self.inner, published?)
+    }
+
+    /// Publish a message that expires after `ttl_ms` milliseconds
+    /// Expired messages are dropped from buffers when the buffer is next read,
+    /// are not replayed to new subscribers, and are discarded by other tabs that
+    /// receive them too late.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param ttl_ms - Time to live in milliseconds (must be positive)
+    #[wasm_bindgen]
+    pub fn publish_with_ttl(&self, topic_id: u32, payload: JsValue, ttl_ms: f64) -> Result<(), JsValue> {
+        if ttl_ms.is_nan() || ttl_ms <= 0.0 {
+            return Err(JsValue::from_str("TTL must be a positive number"));
+        }
+
+        let options = PublishOptions { ttl_ms: Some(ttl_ms), ..Default::default() };
+        let published = publish_locked(&mut self.inner.borrow_mut(), topic_id, payload, options);
+        notify_rejected(&self.inner);
+        finish_publish(&self.inner, published?)
+    }
+
+    /// Publish to a topic by name
+    /// Resolves the name on every call; prefer `publish` with a registered ID on hot paths.
+    /// Unknown topics are created unless disabled with `set_auto_create_topics(false)`.
+    /// @param topic_name - Name of the topic
+    /// @param payload - Message payload
+    #[wasm_bindgen]
+    pub fn publish_by_name(&self, topic_name: &str, payload: JsValue) -> Result<(), JsValue> {
+        let topic_id = {
+            let mut queue = self.inner.borrow_mut();
+            queue.check_open()?;
+            let topic_name = queue.normalize_topic_name(topic_name);
+            match queue.topic_id_by_name(&topic_name) {
+                Some(topic_id) => topic_id,
+                None if queue.auto_create_topics => queue.get_or_create_valid_topic_id(&topic_name)?,
+                None => return Err(JsValue::from_str(&format!("Unknown topic: {}", topic_name))),
+            }
+        };
+        notify_topic_events(&self.inner);
+        publish_message(&self.inner, topic_id, payload)
+    }
+
+    /// Publish a message and wait for local subscribers to finish handling it
+    /// Promises (and other thenables) returned by subscribers are awaited, other
+    /// return values count as fulfilled and throws as rejected. Broadcasting to
+    /// other tabs is not awaited. Idle and deferred subscribers, and messages
+    /// held while paused, are not waited for.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param timeout_ms - Reject with "Timeout" if handlers have not settled in time (default: wait forever)
+    /// @returns Promise resolving with {fulfilled, rejected} subscriber counts
+    #[wasm_bindgen]
+    pub fn publish_and_wait(&self, topic_id: u32, payload: JsValue, timeout_ms: Option<f64>) -> Result<Promise, JsValue> {
+        let published = publish_locked(&mut self.inner.borrow_mut(), topic_id, payload, PublishOptions::default());
+        notify_rejected(&self.inner);
+        let published = published?;
+        let broadcast = match published.raw_msg {
+            Some(raw_msg) => broadcast_message(&self.inner, topic_id, raw_msg),
+            None => Ok(()),
+        };
+        let results = deliver_collecting(&self.inner, published.delivery);
+        notify_backpressure(&self.inner);
+        broadcast?;
+        Ok(settle_results(results, timeout_ms))
+    }
+
+    /// Publish a message and retain it as the topic's current value
+    /// The retained message replaces any previous one, is broadcast with a
+    /// `retained` flag, and is delivered to subscribers registered with
+    /// `subscribe_with_options(..., { replay_retained: true })`.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    #[wasm_bindgen]
+    pub fn publish_retained(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        let published = {
+            let mut queue = self.inner.borrow_mut();
+            publish_locked(&mut queue, topic_id, payload, PublishOptions::default()).and_then(|published| {
+                if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+                    topic.retained = Some(published.message.clone());
+                }
+                if let Some(raw_msg) = &published.raw_msg {
+                    js_utils::mark_retained(raw_msg)?;
+                }
+                Ok(published)
+            })
+        };
+        notify_rejected(&self.inner);
+        finish_publish(&self.inner, published?)
+    }
+
+    /// Publish a message after a delay
+    /// The message is created (ID, timestamp) when the delay has elapsed, not now.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param delay_ms - Delay in milliseconds
+    /// @returns Schedule handle for `cancel_scheduled`
+    #[wasm_bindgen]
+    pub fn publish_delayed(&self, topic_id: u32, payload: JsValue, delay_ms: f64) -> Result<u32, JsValue> {
+        schedule::publish_delayed(&self.inner, topic_id, payload, delay_ms)
+    }
+
+    /// Publish a message produced by `payload_factory` every `interval_ms`
+    /// Useful for heartbeats. Stops when cancelled, when the topic is destroyed
+    /// or when the queue closes.
+    /// @param topic_id - ID of the topic
+    /// @param payload_factory - Function called with no arguments, returning the payload
+    /// @param interval_ms - Interval in milliseconds
+    /// @returns Schedule handle for `cancel_scheduled`
+    #[wasm_bindgen]
+    pub fn publish_interval(&self, topic_id: u32, payload_factory: Function, interval_ms: f64) -> Result<u32, JsValue> {
+        schedule::publish_interval(&self.inner, topic_id, payload_factory, interval_ms)
+    }
+
+    /// Cancel a delayed or repeating publish
+    /// @param handle - Handle returned by `publish_delayed` or `publish_interval`
+    /// @returns true if it was cancelled before firing (always true for active intervals)
+    #[wasm_bindgen]
+    pub fn cancel_scheduled(&self, handle: u32) -> bool {
+        schedule::cancel(&self.inner, handle)
+    }
+
+    /// Remove the retained message of a topic
+    /// @param topic_id - ID of the topic
+    /// @returns true if a message was retained
+    #[wasm_bindgen]
+    pub fn clear_retained(&self, topic_id: u32) -> bool {
+        self.inner.borrow_mut()
+            .get_topic_by_id_mut(topic_id)
+            .and_then(|topic| topic.retained.take())
+            .is_some()
+    }
+
+    /// Send a request on a topic and wait for the reply
+    /// Answered by the handler registered with `respond` in this tab, or else
+    /// by the first tab on the channel that has one. Rejects with "RPC timeout"
+    /// if no reply arrives in time, or "Queue closed" if the queue closes first.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Request payload passed to the handler
+    /// @param timeout_ms - Time to wait for a reply in milliseconds
+    #[wasm_bindgen]
+    pub fn request(&self, topic_id: u32, payload: JsValue, timeout_ms: f64) -> Promise {
+        rpc::request(&self.inner, topic_id, payload, timeout_ms)
+    }
+
+    /// Answer requests made with `request` on a topic
+    /// The handler's return value (or the value its returned Promise resolves
+    /// to) is sent back to the requester; a thrown error or rejection rejects it.
+    /// Replaces any handler previously registered for the topic.
+    /// @param topic_id - ID of the topic
+    /// @param handler - Function called with (payload)
+    #[wasm_bindgen]
+    pub fn respond(&self, topic_id: u32, handler: Function) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.responder = Some(handler);
+        Ok(())
+    }
+
+    /// Choose whether `publish_by_name` creates unknown topics (default) or fails
+    /// @param enabled - true to create topics on first publish
+    #[wasm_bindgen]
+    pub fn set_auto_create_topics(&self, enabled: bool) {
+        self.inner.borrow_mut().auto_create_topics = enabled;
+    }
+
+    /// Set an application-specific check for the names of new topics
+    /// The validator is called with each name about to be created, after the
+    /// built-in checks; a falsy return value or a throw rejects the name. Topics
+    /// announced by other tabs with a rejected name are dropped and reported to
+    /// the error handler. The validator must not use the queue.
+    /// @param callback - (name) => boolean, or undefined to remove the validator
+    #[wasm_bindgen]
+    pub fn set_topic_name_validator(&self, callback: Option<Function>) {
+        self.inner.borrow_mut().topic_name_validator = callback;
+    }
+
+    /// Set the longest accepted name of new topics
+    /// @param max - Maximum length in characters (default: 256)
+    #[wasm_bindgen]
+    pub fn set_max_topic_name_length(&self, max: usize) -> Result<(), JsValue> {
+        if max == 0 {
+            return Err(JsValue::from_str("Maximum topic name length must be greater than 0"));
+        }
+        self.inner.borrow_mut().max_topic_name_len = max;
+        Ok(())
+    }
+
+    /// Store a message in the topic buffer without notifying subscribers or broadcasting
+    /// Useful for bulk-loading history that is replayed on demand later.
+    /// Has no effect if the topic has no buffer.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    #[wasm_bindgen]
+    pub fn publish_silent(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        if !queue.has_topic_id(topic_id) {
+            return Err(JsValue::from_str("Invalid topic ID"));
+        }
+
+        if let Err(err) = queue.check_overflow(topic_id, &payload) {
+            drop(queue);
+            notify_rejected(&self.inner);
+            return Err(err);
+        }
+
+        let rc_msg = queue.create_message(topic_id, payload, PublishOptions::default());
+        let lost = queue.buffer_message(&rc_msg);
+        let hook = queue.get_topic_by_id(topic_id).and_then(|topic| topic.overflow_hook.clone());
+        drop(queue);
+
+        if let Some(hook) = hook {
+            for msg in &lost {
+                notify_overflow(&self.inner, &hook, msg);
+            }
+        }
+        notify_backpressure(&self.inner);
+        Ok(())
+    }
+
+    /// Alias of `publish_silent`: update the topic buffer only
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    #[wasm_bindgen]
+    pub fn update_buffer_only(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        self.publish_silent(topic_id, payload)
+    }
+
+    /// Register a subscriber and publish a message in one atomic step
+    /// Both operations run while holding a single mutable borrow of the queue,
+    /// so no other message can be published between them. The new subscriber
+    /// receives the published message as its first delivery.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param callback - Function called with (payload, topic_id, timestamp, message_id)
+    /// @returns The subscriber ID
+    #[wasm_bindgen]
+    pub fn atomic_publish_subscribe(&self, topic_id: u32, payload: JsValue, callback: Function) -> Result<u32, JsValue> {
+        let (sub_id, published) = {
+            let mut queue = self.inner.borrow_mut();
+            let sub_id = queue.get_topic_by_id_mut(topic_id)
+                .map(|topic| topic.add_subscriber(Subscriber::new(callback)))
+                .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+            (sub_id, publish_locked(&mut queue, topic_id, payload, PublishOptions::default()))
+        };
+
+        notify_rejected(&self.inner);
+        finish_publish(&self.inner, published?)?;
+        Ok(sub_id)
+    }
+
+    /// Run several operations atomically
+    /// `callback` receives a `TransactionQueue` proxy whose `create_topic` and
+    /// `publish` calls are recorded. Topic names are normalized and validated as
+    /// they are recorded, and checked again before anything is applied. When the
+    /// callback returns they are applied
+    /// under a single mutable borrow, all or nothing: every queued publish is
+    /// checked first, and if one targets an invalid topic or a buffer without
+    /// room for it (overflow policy "error"), created topics are rolled back and
+    /// nothing is published. If the callback throws, all recorded operations are
+    /// discarded. A failed broadcast is returned once every publish is delivered.
+    /// @param callback - Function called with (proxy)
+    /// @returns The callback's return value
+    #[wasm_bindgen]
+    pub fn transaction(&self, callback: Function) -> Result<JsValue, JsValue> {
+        let log = Rc::new(RefCell::new(TransactionLog {
+            base_len: self.inner.borrow().topics.len(),
+            ..Default::default()
+        }));
+        let proxy = TransactionQueue::new(self.inner.clone(), log.clone());
+
+        let result = callback.call1(&JsValue::NULL, &JsValue::from(proxy));
+        log.borrow_mut().finished = true;
+        let result = result?;
+
+        let log = std::mem::take(&mut *log.borrow_mut());
+        let publishes = {
+            let mut queue = self.inner.borrow_mut();
+            queue.check_open()?;
+            if queue.topics.len() != log.base_len {
+                return Err(JsValue::from_str("Queue modified during transaction"));
+            }
+
+            // Names created outside the transaction would shift the provisional IDs
+            if log.new_topics.iter().any(|name| queue.topic_index.contains_key(name)) {
+                return Err(JsValue::from_str("Queue modified during transaction"));
+            }
+            // Names were validated when recorded, but the rules may have changed since
+            for name in &log.new_topics {
+                queue.validate_topic_name(name)?;
+            }
+            let created: Vec<u32> = log.new_topics.iter()
+                .map(|name| queue.push_topic(name))
+                .collect();
+
+            let invalid = log.publishes.iter()
+                .any(|(topic_id, _)| !queue.has_topic_id(*topic_id));
+            if invalid {
+                queue.truncate_topics(log.base_len);
+                return Err(JsValue::from_str("Invalid topic ID"));
+            }
+            let mut counts: HashMap<u32, usize> = HashMap::new();
+            for (topic_id, _) in &log.publishes {
+                *counts.entry(*topic_id).or_default() += 1;
+            }
+            if counts.into_iter().any(|(topic_id, count)| queue.rejects_publishes(topic_id, count)) {
+                queue.truncate_topics(log.base_len);
+                return Err(JsValue::from_str(ERR_BUFFER_FULL));
+            }
+            // Topics rolled back above were never announced
+            for (topic_id, name) in created.into_iter().zip(&log.new_topics) {
+                queue.record_topic_event(TopicEvent::Created, topic_id, name);
+            }
+
+            log.publishes.into_iter()
+                .map(|(topic_id, payload)| publish_locked(&mut queue, topic_id, payload, PublishOptions::default()))
+                .collect::<Result<Vec<_>, JsValue>>()
+        };
+
+        notify_topic_events(&self.inner);
+        notify_rejected(&self.inner);
+        publishes?.into_iter()
+            .map(|published| finish_publish(&self.inner, published))
+            .fold(Ok(()), Result::and)?;
+        Ok(result)
+    }
+
+    /// Publish a DOM event as a message
+    /// The payload is {type, target, timeStamp}; the Event itself is not clonable
+    /// @param topic_id - ID of the topic
+    /// @param event - The DOM event (or CustomEvent)
+    #[wasm_bindgen]
+    pub fn publish_event(&self, topic_id: u32, event: Event) -> Result<(), JsValue> {
+        let payload = js_utils::event_to_js(&event)?;
+        self.publish(topic_id, payload)
+    }
+
+    /// Create (or reuse) a topic that receives every `event_type` event fired on `target`
+    /// The listener is removed when the queue is closed.
+    /// @param name - Name of the topic
+    /// @param target - Event target to listen on
+    /// @param event_type - Event type, e.g. "click"
+    /// @returns The topic ID
+    #[wasm_bindgen]
+    pub fn create_event_listener_topic(&self, name: &str, target: EventTarget, event_type: &str) -> Result<u32, JsValue> {
+        let topic_id = self.register_topic(name)?;
+
+        // Hold a weak reference so the listener does not keep the queue alive
+        let weak = Rc::downgrade(&self.inner);
+        let closure = Closure::wrap(Box::new(move |event: Event| {
+            if let Some(inner) = weak.upgrade() {
+                if let Ok(payload) = js_utils::event_to_js(&event) {
+                    let _ = publish_message(&inner, topic_id, payload);
+                }
+            }
+        }) as Box<dyn FnMut(Event)>);
+
+        target.add_event_listener_with_callback(event_type, closure.as_ref().unchecked_ref())?;
+
+        self.inner.borrow_mut().event_listeners.push(EventListener {
+            target,
+            event_type: event_type.to_string(),
+            closure,
+        });
+        Ok(topic_id)
+    }
+
+    /// Publish the same payload to every topic carrying a tag
+    /// Every topic is checked first: if the queue is closed or any topic's
+    /// buffer rejects the message (overflow policy "error"), nothing is published.
+    /// @param tag - Tag selecting the target topics
+    /// @param payload - Message payload
+    /// @returns Number of topics published to
+    #[wasm_bindgen]
+    pub fn publish_to_tagged(&self, tag: &str, payload: JsValue) -> Result<u32, JsValue> {
+        let checked = {
+            let mut queue = self.inner.borrow_mut();
+            queue.check_open()?;
+            let ids = queue.topic_ids_with_tag(tag);
+            ids.iter()
+                .try_for_each(|&topic_id| queue.check_overflow(topic_id, &payload))
+                .map(|()| ids)
+        };
+        notify_rejected(&self.inner);
+        let ids = checked?;
+        for &topic_id in &ids {
+            self.publish(topic_id, payload.clone())?;
+        }
+        Ok(ids.len() as u32)
+    }
+
+    /// Publish a message asynchronously using Promise/microtask
+    /// This returns immediately and delivers the message in the next microtask,
+    /// then broadcasts it to other tabs like `publish`; a failed broadcast rejects.
+    /// Useful for non-blocking operations and better browser responsiveness
+    /// Uses the default priority (128); see `publish_with_priority`
+    /// In coalesced mode (see `set_async_mode`) the priority does not apply.
+    pub fn publish_async(&self, topic_id: u32, payload: JsValue) -> Result<Promise, JsValue> {
+        self.inner.borrow().check_open()?;
+        if self.inner.borrow().async_mode == AsyncMode::Coalesced {
+            return enqueue_coalesced(&self.inner, topic_id, payload);
+        }
+        self.publish_with_priority(topic_id, payload, DEFAULT_PRIORITY)
+    }
+
+    /// Choose how `publish_async` schedules delivery
+    /// - `"immediate"` (default): every call gets its own Promise and is queued
+    ///   by priority, with a microtask per topic
+    /// - `"coalesced"`: calls made in the same tick are delivered together, in
+    ///   call order across topics, by a single microtask that broadcasts them as
+    ///   one packet. They all return the same Promise, which resolves after the
+    ///   flush or rejects with its first error (other messages are still
+    ///   delivered). Unknown topic IDs throw right away. Calls made by subscribers
+    ///   during a flush are delivered by the next one.
+    ///
+    /// `publish_with_priority` is not affected.
+    /// @param mode - "immediate" or "coalesced"
+    #[wasm_bindgen]
+    pub fn set_async_mode(&self, mode: &str) -> Result<(), JsValue> {
+        let mode = match mode {
+            "immediate" => AsyncMode::Immediate,
+            "coalesced" => AsyncMode::Coalesced,
+            _ => return Err(JsValue::from_str("Unknown async mode")),
+        };
+        self.inner.borrow_mut().async_mode = mode;
+        Ok(())
+    }
+
+    /// Dispatch this tab's publishes the way other tabs receive them
+    /// When enabled, `publish` and the other publish methods broadcast right away
+    /// but no longer call local subscribers inline: a later task dispatches them,
+    /// in publish order, so this tab runs the same code path as every other tab.
+    /// Without a channel the dispatch is deferred to a microtask instead.
+    /// `publish_async` Promises resolve before that dispatch.
+    /// @param enabled - true to enable echo mode, false (default) to dispatch inline
+    #[wasm_bindgen]
+    pub fn set_echo(&self, enabled: bool) {
+        self.inner.borrow_mut().echo = enabled;
+    }
+
+    /// Check if echo mode is enabled (see `set_echo`)
+    #[wasm_bindgen]
+    pub fn is_echo(&self) -> bool {
+        self.inner.borrow().echo
+    }
+
+    /// Publish a message asynchronously with a delivery priority
+    /// Messages queued on a topic before its next microtask are delivered
+    /// highest priority first, and in publish order within a priority.
+    /// @param topic_id - ID of the topic
+    /// @param payload - Message payload
+    /// @param priority - 0 (lowest) to 255 (highest); `publish_async` uses 128
+    /// @returns Promise resolving once the message has been delivered
+    #[wasm_bindgen]
+    pub fn publish_with_priority(&self, topic_id: u32, payload: JsValue, priority: u8) -> Result<Promise, JsValue> {
+        self.inner.borrow().check_open()?;
+        Ok(enqueue_publish(&self.inner, topic_id, payload, priority))
+    }
+
+    /// Number of `publish_async` calls scheduled but not yet delivered
+    #[wasm_bindgen]
+    pub fn get_pending_async_publishes(&self) -> usize {
+        self.inner.borrow().pending_async.get()
+    }
+
+    /// Number of async publishes waiting for delivery
+    /// Same as `get_pending_async_publishes`; use it for custom flow control.
+    #[wasm_bindgen]
+    pub fn pending_count(&self) -> usize {
+        self.get_pending_async_publishes()
+    }
+
+    /// Limit the number of pending async publishes
+    /// Once `max` are pending, further `publish_async` / `publish_with_priority`
+    /// calls are handled by the drop policy (see `set_drop_policy`).
+    /// @param max - Maximum pending publishes (0 = unlimited, the default)
+    #[wasm_bindgen]
+    pub fn set_max_pending(&self, max: usize) {
+        self.inner.borrow_mut().max_pending = max;
+    }
+
+    /// Choose what happens to async publishes beyond the pending limit
+    /// @param policy - `DropPolicy.Reject` (default), `DropOldest` or `DropNewest`
+    #[wasm_bindgen]
+    pub fn set_drop_policy(&self, policy: DropPolicy) {
+        self.inner.borrow_mut().drop_policy = policy;
+    }
+
+    /// Number of async publishes dropped by the drop policy so far
+    #[wasm_bindgen]
+    pub fn get_dropped_count(&self) -> f64 {
+        self.inner.borrow().dropped_async as f64
+    }
+
+    /// Wait until every pending `publish_async` call has been delivered
+    /// Useful in test teardown. Resolves immediately if nothing is pending.
+    #[wasm_bindgen]
+    pub fn wait_for_pending_async(&self) -> Promise {
+        self.drain()
+    }
+
+    /// Resolve once all pending async publishes have been dispatched
+    /// Resolves immediately if nothing is pending.
+    #[wasm_bindgen]
+    pub fn drain(&self) -> Promise {
+        let pending = self.inner.borrow().pending_async.clone();
+        Promise::new(&mut |resolve, _reject| {
+            poll_pending_async(pending.clone(), resolve);
+        })
+    }
+
+    /// Create an independent queue from this one's configuration
+    /// The clone has the same topics (names, IDs and buffer configuration) and
+    /// channel name, but no subscribers, a fresh client ID and an empty dedup set.
+    /// @param copy_buffers - Also copy buffered messages (default: false)
+    #[wasm_bindgen]
+    pub fn clone_message_queue(&self, copy_buffers: Option<bool>) -> Result<MessageQueue, JsValue> {
+        let copy_buffers = copy_buffers.unwrap_or(false);
+        let channel_name = self.inner.borrow().channel_name.clone();
+        let clone = MessageQueue::new(channel_name)?;
+
+        {
+            let source = self.inner.borrow();
+            let mut target = clone.inner.borrow_mut();
+
+            // Copy the slot layout (including tombstones) so topic IDs stay valid
+            target.topic_index = source.topic_index.clone();
+            target.free_slots = source.free_slots.clone();
+            for topic in &source.topics {
+                let mut new_topic = Topic::new(topic.name.clone());
+                new_topic.generation = topic.generation;
+                new_topic.alive = topic.alive;
+                new_topic.is_replay_subject = topic.is_replay_subject;
+                new_topic.retained = topic.retained.clone();
+
+                if let Some(buffer) = topic.get_buffer() {
+                    new_topic.enable_buffer(buffer.capacity());
+                    if copy_buffers {
+                        if let Some(new_buffer) = new_topic.get_buffer_mut() {
+                            for msg in buffer.iter() {
+                                new_buffer.push(msg.clone());
+                            }
+                        }
+                    }
+                }
+                target.topics.push(new_topic);
+            }
+        }
+
+        Ok(clone)
+    }
+
+    /// Register an interceptor for messages arriving over the BroadcastChannel
+    /// Interceptors run in registration order before a message is routed.
+    /// Returning `false` discards the message, returning an object replaces it
+    /// (e.g. after decryption), any other return value keeps it unchanged.
+    /// @param interceptor - Function called with (message)
+    /// @returns Handle for `remove_broadcast_interceptor`
+    #[wasm_bindgen]
+    pub fn intercept_broadcast(&self, interceptor: Function) -> u32 {
+        let mut queue = self.inner.borrow_mut();
+        let handle = queue.next_interceptor_id;
+        queue.next_interceptor_id = handle.wrapping_add(1);
+        queue.broadcast_interceptors.push((handle, interceptor));
+        handle
+    }
+
+    /// Remove a broadcast interceptor
+    /// @param handle - Handle returned by `intercept_broadcast`
+    /// @returns true if the interceptor was registered
+    #[wasm_bindgen]
+    pub fn remove_broadcast_interceptor(&self, handle: u32) -> bool {
+        let mut queue = self.inner.borrow_mut();
+        let before = queue.broadcast_interceptors.len();
+        queue.broadcast_interceptors.retain(|(id, _)| *id != handle);
+        queue.broadcast_interceptors.len() != before
+    }
+
+    /// Forward every message on a topic to a ServiceWorker via `postMessage`
+    /// Messages are sent as {id, topic, payload, timestamp, origin_id} objects.
+    /// @param sw - The ServiceWorker to forward to
+    /// @param topic_id - ID of the topic
+    /// @returns Subscriber ID, usable with `unsubscribe` to stop forwarding
+    #[wasm_bindgen]
+    pub fn forward_to_service_worker(&self, sw: ServiceWorker, topic_id: u32) -> Result<u32, JsValue> {
+        let post: Function = js_sys::Reflect::get(&sw, &"postMessage".into())?.dyn_into()?;
+        let mut subscriber = Subscriber::new(post.bind0(&sw));
+        subscriber.raw = true;
+        self.add_subscriber(topic_id, subscriber)
+    }
+
+    /// Dispatch a message received from a ServiceWorker locally
+    /// Accepts the message object format sent by `forward_to_service_worker`
+    /// (or a `[0, msg]` publish packet). Duplicates and this client's own messages are ignored.
+    /// @param sw_message - The `data` of the ServiceWorker message event
+    #[wasm_bindgen]
+    pub fn receive_from_service_worker(&self, sw_message: JsValue) -> Result<(), JsValue> {
+        let msg_val = if sw_message.is_array() {
+            let packet = Array::from(&sw_message);
+            if packet.get(0).as_f64() != Some(0.0) {
+                return Err(JsValue::from_str("Unsupported packet type"));
+            }
+            packet.get(1)
+        } else {
+            sw_message
+        };
+
+        let delivery = self.inner.borrow_mut().receive_remote(&msg_val)?;
+        deliver(&self.inner, delivery);
+        report_receive_errors(&self.inner);
+        notify_gaps(&self.inner);
+        notify_backpressure(&self.inner);
+        Ok(())
+    }
+
+    /// Current nesting depth of message dispatch
+    /// Greater than 0 while subscriber callbacks are running
+    #[wasm_bindgen]
+    pub fn get_dispatch_queue_depth(&self) -> usize {
+        self.inner.borrow().dispatch_depth.get()
+    }
+
+    /// Limit how deeply dispatches may nest (subscribers publishing re-entrantly)
+    /// Dispatches beyond the limit are skipped and reported to the error handler
+    /// with "Max dispatch depth exceeded".
+    /// @param depth - Maximum nesting depth (default: 10)
+    #[wasm_bindgen]
+    pub fn set_max_dispatch_depth(&self, depth: usize) {
+        self.inner.borrow_mut().max_dispatch_depth = depth;
+    }
+
+    /// Set how many recent message IDs are remembered to drop duplicates
+    /// received from other contexts. Older IDs are forgotten first.
+    /// @param size - Number of IDs to remember (default: 4096, 0 disables deduplication)
+    #[wasm_bindgen]
+    pub fn set_dedup_window(&self, size: usize) {
+        self.inner.borrow_mut().seen_ids.set_capacity(size);
+    }
+
+    /// Register a handler for errors raised during dispatch
+    /// Subscriber callbacks that throw (including during buffer replay) are
+    /// reported here; an error thrown by the handler itself is ignored.
+    /// @param callback - Function called with (error, topic_name, sub_id, message_id)
+    #[wasm_bindgen(skip_typescript)]
+    pub fn set_error_handler(&self, callback: Function) {
+        self.inner.borrow_mut().error_handler = Some(callback);
+    }
+
+    /// Write dispatch errors to `console.error` while no error handler is set
+    /// Off by default, so errors thrown by subscribers are otherwise ignored.
+    /// @param enabled - Whether to log errors to the console
+    #[wasm_bindgen]
+    pub fn set_debug(&self, enabled: bool) {
+        self.inner.borrow_mut().debug = enabled;
+    }
+
+    /// Register a handler for gaps in the messages received from other clients
+    /// Messages published with `publish` (and variants that broadcast) carry a
+    /// per-topic sequence number; when one arrives out of sequence, the handler is
+    /// called with `{topic, origin, expected, received}`.
+    /// @param callback - Function called with the gap object
+    #[wasm_bindgen]
+    pub fn on_gap(&self, callback: Function) {
+        self.inner.borrow_mut().gap_handler = Some(callback);
+    }
+
+    /// Route undeliverable and failed messages to a dead-letter topic
+    /// Its subscribers receive records `{original_topic, payload, reason, timestamp}`
+    /// (plus `error` for callback errors), where `reason` is one of:
+    /// - `"error"`: a subscriber callback threw
+    /// - `"no_subscribers"`: published in this tab to a topic with no subscribers and no buffer
+    /// - `"overflow"`: displaced from a full ring buffer
+    ///
+    /// Messages of the dead-letter topic itself are never dead-lettered.
+    /// @param topic_id - ID of the dead-letter topic
+    /// @param options - Optional `{errors, no_subscribers, overflow}` booleans (each default: true)
+    #[wasm_bindgen(skip_typescript)]
+    pub fn set_dead_letter_topic(&self, topic_id: u32, options: JsValue) -> Result<(), JsValue> {
+        let enabled = |key: &str| -> Result<bool, JsValue> {
+            if !options.is_object() {
+                return Ok(true);
+            }
+            Ok(js_utils::config_field(&options, key)?.is_none_or(|v| v.is_truthy()))
+        };
+        let config = DeadLetterConfig {
+            topic_id,
+            on_error: enabled("errors")?,
+            on_no_subscribers: enabled("no_subscribers")?,
+            on_overflow: enabled("overflow")?,
+        };
+
+        let mut queue = self.inner.borrow_mut();
+        if !queue.has_topic_id(topic_id) {
+            return Err(JsValue::from_str("Invalid topic ID"));
+        }
+        queue.dead_letter = Some(config);
+        Ok(())
+    }
+
+    /// Stop routing messages to the dead-letter topic
+    #[wasm_bindgen]
+    pub fn clear_dead_letter_topic(&self) {
+        self.inner.borrow_mut().dead_letter = None;
+    }
+
+    /// Fetch the buffered and retained messages of other tabs
+    /// Performs the handshake a queue sends when it connects, e.g. for queues
+    /// created with `syncOnConnect: false` once their topics and buffers are set
+    /// up. Messages of topics this tab does not know create the topic (buffered
+    /// if a default buffer is set, see `set_default_buffer`). Messages already
+    /// seen are skipped.
+    /// @param quiet_ms - Resolve once no response arrived for this long (default: 200)
+    /// @returns Promise resolving with the number of new messages received
+    #[wasm_bindgen]
+    pub fn request_sync(&self, quiet_ms: Option<f64>) -> Promise {
+        let quiet_ms = quiet_ms.unwrap_or(SYNC_SETTLE_MS);
+        if !quiet_ms.is_finite() || quiet_ms < 0.0 {
+            return Promise::reject(&JsValue::from_str("quiet_ms must be a non-negative finite number"));
+        }
+        request_sync(&self.inner, None, None, quiet_ms)
+    }
+
+    /// Fetch the buffered and retained messages of specific topics from other tabs
+    /// Only tabs holding messages of the named topics answer, and only with those
+    /// messages. Messages already seen are skipped; the rest are delivered to
+    /// subscribers oldest first per topic.
+    /// @param names - Names of the topics to sync
+    /// @returns Promise resolving with the number of new messages received
+    #[wasm_bindgen]
+    pub fn sync_topics(&self, names: Array) -> Promise {
+        match names.iter().map(|name| name.as_string()).collect::<Option<Vec<String>>>() {
+            Some(names) => request_sync(&self.inner, Some(names), None, SYNC_SETTLE_MS),
+            None => Promise::reject(&JsValue::from_str("Topic names must be strings")),
+        }
+    }
+
+    /// Fetch the messages other tabs received after a timestamp
+    /// Use it after restoring persisted state instead of a full sync. Responses
+    /// are oldest first and capped (see `set_sync_response_limit`); the rest is
+    /// requested automatically until nothing new arrives.
+    /// @param timestamp - Only messages with a later timestamp are sent
+    /// @returns Promise resolving with the number of new messages received
+    #[wasm_bindgen]
+    pub fn request_sync_since(&self, timestamp: f64) -> Promise {
+        if !timestamp.is_finite() {
+            return Promise::reject(&JsValue::from_str("Invalid timestamp"));
+        }
+        request_sync(&self.inner, None, Some(timestamp), SYNC_SETTLE_MS)
+    }
+
+    /// Send presence heartbeats and drop peers that stop sending them
+    /// Peers are always tracked through the join and leave announcements; with
+    /// presence enabled, a peer that crashed or was frozen is also dropped after
+    /// three intervals without a packet. Enable it in every tab with the same interval.
+    /// @param interval_ms - Heartbeat interval in milliseconds
+    #[wasm_bindgen]
+    pub fn enable_presence(&self, interval_ms: f64) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        presence::enable(&self.inner, interval_ms)
+    }
+
+    /// Stop sending presence heartbeats and expiring peers
+    #[wasm_bindgen]
+    pub fn disable_presence(&self) {
+        presence::disable(&mut self.inner.borrow_mut());
+    }
+
+    /// Client IDs of the other tabs and workers on the channel, sorted
+    #[wasm_bindgen]
+    pub fn get_peers(&self) -> Array {
+        self.inner.borrow().peer_ids().iter().map(|peer_id| JsValue::from_str(peer_id)).collect()
+    }
+
+    /// Set the function called when a peer joins or leaves
+    /// @param callback - Called with (peer_id, "joined" | "left"), or null to remove
+    #[wasm_bindgen]
+    pub fn on_peer_change(&self, callback: Option<Function>) {
+        self.inner.borrow_mut().peer_change_handler = callback;
+    }
+
+    /// Set the function called when another tab closes its queue
+    /// Requests that tab was expected to answer (it answered the last request on
+    /// the topic) are rejected with "peer closed" instead of timing out.
+    /// @param callback - Called with the closed tab's client ID, or null to remove
+    #[wasm_bindgen]
+    pub fn on_peer_closed(&self, callback: Option<Function>) {
+        self.inner.borrow_mut().peer_closed_handler = callback;
+    }
+
+    /// Take part in electing one leader among the tabs on the channel
+    /// The candidate with the lowest client ID wins. This tab stays a candidate
+    /// afterwards: if the leader closes (or stops sending presence heartbeats),
+    /// a new leader is elected automatically and `on_leadership_change` reports it.
+    /// @returns Promise resolving with whether this tab is the leader, rejected if the queue is closed
+    #[wasm_bindgen]
+    pub fn elect_leader(&self) -> Promise {
+        if let Err(err) = self.inner.borrow().check_open() {
+            return Promise::reject(&err);
+        }
+        leader::elect(&self.inner)
+    }
+
+    /// Whether this tab is the elected leader
+    #[wasm_bindgen]
+    pub fn is_leader(&self) -> bool {
+        self.inner.borrow().is_leader()
+    }
+
+    /// Set the function called when this tab gains or loses leadership
+    /// @param callback - Called with true or false, or null to remove
+    #[wasm_bindgen]
+    pub fn on_leadership_change(&self, callback: Option<Function>) {
+        self.inner.borrow_mut().leadership_handler = callback;
+    }
+
+    /// Answer every sync request right away (for debugging)
+    /// By default each tab waits a random 0-50 ms before answering a sync request
+    /// and stays silent if another tab answers first, so a new tab receives one
+    /// response instead of one per open tab.
+    /// @param enabled - Whether to always respond
+    #[wasm_bindgen]
+    pub fn set_sync_respond_always(&self, enabled: bool) {
+        self.inner.borrow_mut().sync_respond_always = enabled;
+    }
+
+    /// Limit the number of messages this tab sends in reply to `request_sync_since`
+    /// Larger histories are sent in pages the requester asks for one by one.
+    /// @param max - Maximum messages per response (default: 500)
+    #[wasm_bindgen]
+    pub fn set_sync_response_limit(&self, max: usize) -> Result<(), JsValue> {
+        if max == 0 {
+            return Err(JsValue::from_str("Sync response limit must be at least 1"));
+        }
+        self.inner.borrow_mut().sync_response_limit = max;
+        Ok(())
+    }
+
+    /// Forward messages of selected topics to a server over a WebSocket
+    /// Each message is sent as a JSON text frame `{topic, id, payload, timestamp,
+    /// origin_id}`. Inbound text frames in the same format are published locally
+    /// (and with `rebroadcast`, to other tabs); binary frames are reported to the
+    /// error handler. Messages that came from a bridge are never forwarded, so
+    /// nothing echoes back. The bridge detaches itself when the socket closes or fails.
+    /// @param ws - Open (or opening) WebSocket
+    /// @param topics - IDs of the topics to forward
+    /// @param rebroadcast - Also send inbound messages to other tabs (default: false)
+    #[wasm_bindgen]
+    pub fn bridge_websocket(&self, ws: WebSocket, topics: Array, rebroadcast: Option<bool>) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        let topic_ids = topics.iter()
+            .map(|id| id.as_f64().map(|id| id as u32).ok_or_else(|| JsValue::from_str("Invalid topic ID")))
+            .collect::<Result<Vec<u32>, JsValue>>()?;
+        bridge::attach(&self.inner, ws, topic_ids, rebroadcast.unwrap_or(false))
+    }
+
+    /// Stop the WebSocket bridge; the socket itself is left open
+    /// @returns Number of messages forwarded to the server (0 without a bridge)
+    #[wasm_bindgen]
+    pub fn unbridge(&self) -> u32 {
+        bridge::detach(&self.inner)
+    }
+
+    /// Choose how published messages are encoded for other tabs
+    /// `"binary"` sends messages with string, `ArrayBuffer`, typed array or empty
+    /// payloads as a single `Uint8Array`, which is much cheaper to clone than an
+    /// object; other messages (and messages with headers) still use `"json"`.
+    /// Receivers understand both formats regardless of this setting.
+    /// @param format - "json" (default) or "binary"
+    #[wasm_bindgen]
+    pub fn set_wire_format(&self, format: &str) -> Result<(), JsValue> {
+        self.inner.borrow_mut().wire_format = parse_wire_format(format)?;
+        Ok(())
+    }
+
+    /// Throttle outgoing BroadcastChannel traffic
+    /// When `min_interval_ms` > 0, published messages are collected and posted
+    /// as a single batch packet at most once per interval. 0 disables throttling
+    /// and immediately flushes anything pending.
+    /// @param min_interval_ms - Minimum interval between broadcasts in milliseconds
+    #[wasm_bindgen]
+    pub fn set_broadcast_throttle(&self, min_interval_ms: f64) -> Result<(), JsValue> {
+        if min_interval_ms.is_nan() || min_interval_ms < 0.0 {
+            return Err(JsValue::from_str("Throttle interval must be a non-negative number"));
+        }
+
+        let mut queue = self.inner.borrow_mut();
+        queue.broadcast_throttle_ms = min_interval_ms;
+        if min_interval_ms == 0.0 {
+            queue.flush_broadcasts()?;
+        }
+        Ok(())
+    }
+
+    /// Immediately post all broadcasts held back by the throttle
+    /// @returns Number of messages flushed
+    #[wasm_bindgen]
+    pub fn flush_broadcast_queue(&self) -> Result<usize, JsValue> {
+        self.inner.borrow_mut().flush_broadcasts()
+    }
+
+    pub fn get_client_id(&self) -> String {
+        self.inner.borrow().client_id.as_ref().clone()
+    }
+
+    /// Get the number of topics
+    pub fn topic_count(&self) -> usize {
+        self.inner.borrow().topic_index.len()
+    }
+
+    /// Get the number of subscribers for a specific topic
+    pub fn subscriber_count(&self, topic_id: u32) -> usize {
+        self.inner.borrow()
+            .get_topic_by_id(topic_id)
+            .map_or(0, |topic| topic.subscribers.len())
+    }
+
+    /// Check if a topic exists
+    pub fn has_topic(&self, topic_id: u32) -> bool {
+        self.has_topic_id(topic_id)
+    }
+
+    /// Look up the ID of an existing topic without creating it
+    /// @param name - Name of the topic
+    /// @returns The topic ID, or undefined if no such topic exists
+    #[wasm_bindgen]
+    pub fn get_topic_id(&self, name: &str) -> Option<u32> {
+        self.inner.borrow().topic_id_by_name(name)
+    }
+
+    /// Get the name of a topic
+    /// @param topic_id - ID of the topic
+    /// @returns The topic name, or undefined for an invalid ID
+    #[wasm_bindgen]
+    pub fn get_topic_name(&self, topic_id: u32) -> Option<String> {
+        self.inner.borrow().get_topic_by_id(topic_id).map(|topic| topic.name.clone())
+    }
+
+    /// Get the callback function registered for a subscriber
+    /// Useful to assert registrations in tests or to track down leaking subscribers
+    /// @param topic_id - ID of the topic
+    /// @param sub_id - ID of the subscriber
+    #[wasm_bindgen]
+    pub fn get_subscriber_callback(&self, topic_id: u32, sub_id: u32) -> Result<Function, JsValue> {
+        let queue = self.inner.borrow();
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.subscribers.get(sub_id)
+            .map(|sub| sub.callback.clone())
+            .ok_or_else(|| JsValue::from_str("Invalid subscriber ID"))
+    }
+
+    /// Check if a subscriber has a message filter
+    /// @param topic_id - ID of the topic
+    /// @param sub_id - ID of the subscriber
+    #[wasm_bindgen]
+    pub fn subscriber_has_filter(&self, topic_id: u32, sub_id: u32) -> Result<bool, JsValue> {
+        let queue = self.inner.borrow();
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.subscribers.get(sub_id)
+            .map(|sub| sub.has_filter())
+            .ok_or_else(|| JsValue::from_str("Invalid subscriber ID"))
+    }
+
+    /// Unsubscribe all subscribers from a topic
+    pub fn unsubscribe_all(&self, topic_id: u32) -> usize {
+        let mut queue = self.inner.borrow_mut();
+        if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+            let count = topic.subscribers.len();
+            topic.subscribers.clear();
+            count
+        } else {
+            0
+        }
+    }
+
+    /// Enable message buffering for a specific topic
+    /// Messages will be cached in a ring buffer for later retrieval
+    /// @param topic_id - ID of the topic
+    /// @param capacity - Maximum number of messages to buffer (default: 100)
+    /// The buffer overwrites its oldest message when full.
+    #[wasm_bindgen]
+    pub fn enable_topic_buffer(&self, topic_id: u32, capacity: Option<usize>) -> Result<(), JsValue> {
+        enable_buffer_with(&self.inner, topic_id, capacity, OverflowPolicy::DropOldest)
+    }
+
+    /// Give topics created later a buffer automatically
+    /// Topics created because a message arrived from another tab (or a sync
+    /// response) get a buffer of `capacity`, so subscribers attaching later can
+    /// replay what arrived before them, e.g. with `subscribe_with_replay`. With
+    /// `all_topics`, topics the application creates get one too. Existing topics
+    /// are unaffected. Replaces any previous setting, including `defaultBufferCapacity`.
+    /// @param capacity - Buffer capacity, or 0 to stop buffering new topics
+    /// @param all_topics - Also buffer topics created by this tab (default: false)
+    #[wasm_bindgen]
+    pub fn set_default_buffer(&self, capacity: usize, all_topics: Option<bool>) {
+        let capacity = Some(capacity).filter(|&capacity| capacity > 0);
+        let mut queue = self.inner.borrow_mut();
+        queue.remote_topic_buffer = capacity;
+        queue.default_buffer_capacity = capacity.filter(|_| all_topics.unwrap_or(false));
+    }
+
+    /// Enable message buffering for a topic with a policy for full buffers
+    /// - `"drop-oldest"`: overwrite the oldest buffered message
+    /// - `"drop-newest"`: skip buffering the new message; it is still delivered
+    /// - `"error"`: fail `publish` with "Topic buffer full"; nothing is delivered
+    ///
+    /// Messages lost under any policy are counted in the `overflow_count` of `get_topic_metadata`.
+    /// @param topic_id - ID of the topic
+    /// @param capacity - Maximum number of messages to buffer
+    /// @param policy - "drop-oldest", "drop-newest" or "error"
+    #[wasm_bindgen]
+    pub fn enable_topic_buffer_with_policy(&self, topic_id: u32, capacity: usize, policy: &str) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        let policy = OverflowPolicy::parse(policy)
+            .ok_or_else(|| JsValue::from_str("Unknown overflow policy"))?;
+        enable_buffer_with(&self.inner, topic_id, Some(capacity), policy)
+    }
+
+    /// Set the function called with each message a topic's full buffer loses
+    /// Under "drop-oldest" that is the overwritten message, under "drop-newest"
+    /// and for publishes rejected under "error" the new one (reported before the
+    /// publish throws). Messages from other tabs are never rejected: under "error"
+    /// they are delivered but left out of the full buffer, and reported as under "drop-newest".
+    /// @param topic_id - ID of the topic
+    /// @param callback - Called with the message object ({topic, payload, ...}), or null to remove
+    #[wasm_bindgen]
+    pub fn on_overflow(&self, topic_id: u32, callback: Option<Function>) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.overflow_hook = callback;
+        Ok(())
+    }
+
+    /// Limit how long a topic's buffer keeps messages, in addition to its capacity
+    /// Messages older than `max_age_ms` (by timestamp) are evicted on every push
+    /// and are never returned by reads or sent to syncing tabs.
+    /// @param topic_id - ID of the topic
+    /// @param max_age_ms - Maximum message age in milliseconds, or null to keep messages until displaced
+    #[wasm_bindgen]
+    pub fn set_buffer_retention(&self, topic_id: u32, max_age_ms: Option<f64>) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        if max_age_ms.is_some_and(|max_age| !(max_age.is_finite() && max_age > 0.0)) {
+            return Err(JsValue::from_str("Retention must be a positive number"));
+        }
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.retention_ms = max_age_ms;
+        topic.purge_expired();
+        drop(queue);
+
+        notify_backpressure(&self.inner);
+        Ok(())
+    }
+
+    /// Limit a topic's buffer to an estimated payload size, in addition to its capacity
+    /// On every push the oldest messages are evicted until the buffer is within
+    /// budget; a message larger than the whole budget is delivered but not buffered.
+    /// Strings count two bytes per character and binary data its byte length.
+    /// Objects count 256 bytes unless `sizer` returns their size; a sizer that
+    /// throws or returns anything but a non-negative number falls back to 256.
+    /// Evicted messages are counted in `overflow_count` and passed to `on_overflow`.
+    /// @param topic_id - ID of the topic
+    /// @param max_bytes - Byte budget, or null to remove it
+    /// @param sizer - Optional function (payload) => bytes for object payloads
+    #[wasm_bindgen]
+    pub fn set_buffer_max_bytes(&self, topic_id: u32, max_bytes: Option<usize>, sizer: Option<Function>) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        if max_bytes == Some(0) {
+            return Err(JsValue::from_str("Byte budget must be greater than 0"));
+        }
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.max_bytes = max_bytes;
+        topic.sizer = sizer;
+        let evicted = topic.measure_buffer();
+        let hook = topic.overflow_hook.clone();
+        drop(queue);
+
+        if let Some(hook) = hook {
+            for msg in &evicted {
+                notify_overflow(&self.inner, &hook, msg);
+            }
+        }
+        notify_backpressure(&self.inner);
+        Ok(())
+    }
+
+    /// Get the estimated size of a topic's buffered payloads
+    /// Payloads are only measured while the topic has a byte budget.
+    /// @param topic_id - ID of the topic
+    /// @returns Estimated bytes, or -1 if buffering is not enabled
+    #[wasm_bindgen]
+    pub fn get_buffer_bytes(&self, topic_id: u32) -> f64 {
+        let mut queue = self.inner.borrow_mut();
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            return -1.0;
+        };
+        topic.purge_expired();
+        topic.get_buffer().map_or(-1.0, |buffer| buffer.bytes() as f64)
+    }
+
+    /// Copy buffered messages from one topic to another (oldest first)
+    /// The destination buffer is grown to the source capacity if it is smaller.
+    /// Copies are re-addressed to the destination topic; the source buffer is unchanged.
+    /// @param from_id - ID of the source topic
+    /// @param to_id - ID of the destination topic
+    /// @returns Number of messages copied
+    #[wasm_bindgen]
+    pub fn clone_topic_buffer(&self, from_id: u32, to_id: u32) -> Result<usize, JsValue> {
+        self.inner.borrow().check_open()?;
+        if from_id == to_id {
+            return Err(JsValue::from_str("Source and destination topics must differ"));
+        }
+
+        let mut queue = self.inner.borrow_mut();
+        let (Some(from), Some(to)) = (queue.topic_slot(from_id), queue.topic_slot(to_id)) else {
+            return Err(JsValue::from_str("Invalid topic ID"));
+        };
+
+        let (messages, min_capacity) = match queue.topics[from].get_buffer() {
+            Some(buffer) => (buffer.to_vec(), buffer.capacity()),
+            None => return Err(JsValue::from_str("Source topic has no buffer")),
+        };
+
+        let target = &mut queue.topics[to];
+        let needs_grow = match target.get_buffer() {
+            Some(buffer) => buffer.capacity() < min_capacity,
+            None => return Err(JsValue::from_str("Destination topic has no buffer")),
+        };
+        if needs_grow {
+            target.resize_buffer(min_capacity);
+        }
+
+        let Some(buffer) = target.get_buffer_mut() else {
+            return Ok(0);
+        };
+        let mut overflow = 0;
+        for msg in &messages {
+            let copy = Rc::new(Message { topic_id: to_id, ..(**msg).clone() });
+            if buffer.push(copy).is_some() {
+                overflow += 1;
+            }
+        }
+        target.overflow_count += overflow;
+        // Copies are sized for the destination's byte budget
+        if target.max_bytes.is_some() {
+            target.measure_buffer();
+        }
+
+        Ok(messages.len())
+    }
+
+    /// Enable message buffering for multiple topics in a single call
+    /// @param topic_ids - IDs of the topics
+    /// @param capacity - Maximum number of messages to buffer per topic
+    /// @returns Array with 1 (enabled) or 0 (invalid topic ID or zero capacity) per entry
+    #[wasm_bindgen]
+    pub fn batch_enable_topic_buffer(&self, topic_ids: Uint32Array, capacity: usize) -> Uint32Array {
+        let mut queue = self.inner.borrow_mut();
+        let results: Vec<u32> = topic_ids.to_vec().into_iter()
+            .map(|topic_id| {
+                match queue.get_topic_by_id_mut(topic_id) {
+                    Some(topic) if capacity > 0 => {
+                        topic.enable_buffer(capacity);
+                        1
+                    }
+                    _ => 0,
+                }
+            })
+            .collect();
+
+        Uint32Array::from(&results[..])
+    }
+
+    /// Get the current buffer sizes of multiple topics in a single call
+    /// @param topic_ids - IDs of the topics
+    /// @returns Array of buffer sizes, -1 for topics without a buffer or invalid IDs
+    #[wasm_bindgen]
+    pub fn batch_get_buffer_sizes(&self, topic_ids: Uint32Array) -> Int32Array {
+        let queue = self.inner.borrow();
+        let sizes: Vec<i32> = topic_ids.to_vec().into_iter()
+            .map(|topic_id| {
+                queue.get_topic_by_id(topic_id)
+                    .and_then(|topic| topic.get_buffer())
+                    .map_or(-1, |b| b.len() as i32)
+            })
+            .collect();
+
+        Int32Array::from(&sizes[..])
+    }
+
+    /// Disable message buffering for a specific topic
+    /// Clears all buffered messages
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn disable_topic_buffer(&self, topic_id: u32) -> Result<(), JsValue> {
+        disable_topic_buffer(&self.inner, topic_id)
+    }
+
+    /// Get the current size of the message buffer for a topic
+    /// @param topic_id - ID of the topic
+    /// @returns Number of messages currently buffered, or -1 if buffering is not enabled
+    #[wasm_bindgen]
+    pub fn get_buffer_size(&self, topic_id: u32) -> i32 {
+        buffer_size(&self.inner, topic_id)
+    }
+
+    /// Get the buffer capacity for a topic
+    /// @param topic_id - ID of the topic
+    /// @returns Maximum buffer capacity, or 0 if buffering is not enabled
+    #[wasm_bindgen]
+    pub fn get_buffer_capacity(&self, topic_id: u32) -> usize {
+        let queue = self.inner.borrow();
+        if let Some(topic) = queue.get_topic_by_id(topic_id) {
+            topic.get_buffer()
+                .map(|b| b.capacity())
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    /// Change a topic's buffer capacity without losing messages
+    /// When shrinking, the newest messages that fit are kept.
+    /// @param topic_id - ID of the topic
+    /// @param new_capacity - New maximum number of messages (must be > 0)
+    #[wasm_bindgen]
+    pub fn set_topic_buffer_capacity(&self, topic_id: u32, new_capacity: usize) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        self.resize_topic_buffer(topic_id, new_capacity).map(|_| ())
+    }
+
+    /// Resize a topic's buffer in place, keeping the newest messages that fit
+    /// @param topic_id - ID of the topic
+    /// @param capacity - New maximum number of messages (must be > 0)
+    /// @returns Number of oldest messages dropped to fit the new capacity
+    #[wasm_bindgen]
+    pub fn resize_topic_buffer(&self, topic_id: u32, capacity: usize) -> Result<usize, JsValue> {
+        self.inner.borrow().check_open()?;
+        if capacity == 0 {
+            return Err(JsValue::from_str("Buffer capacity must be greater than 0"));
+        }
+
+        let mut queue = self.inner.borrow_mut();
+        let topic = queue.get_topic_by_id_mut(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        topic.resize_buffer(capacity)
+            .ok_or_else(|| JsValue::from_str("Topic has no buffer, use enable_topic_buffer instead"))
+    }
+
+    /// Get a topic's buffer capacity (companion to `set_topic_buffer_capacity`)
+    /// @param topic_id - ID of the topic
+    /// @returns Maximum buffer capacity, or 0 if buffering is not enabled
+    #[wasm_bindgen]
+    pub fn get_topic_buffer_capacity(&self, topic_id: u32) -> usize {
+        self.get_buffer_capacity(topic_id)
+    }
+
+    /// Clear all buffered messages for a topic
+    /// @param topic_id - ID of the topic
+    /// @returns Number of messages cleared
+    #[wasm_bindgen]
+    pub fn clear_buffer(&self, topic_id: u32) -> usize {
+        clear_buffer(&self.inner, topic_id)
+    }
+
+    /// Drop the expired messages from a topic's buffer now
+    /// Also drops messages older than the topic's buffer retention.
+    /// Buffers are otherwise purged lazily whenever they are read.
+    /// @param topic_id - ID of the topic
+    /// @returns Number of messages dropped
+    #[wasm_bindgen]
+    pub fn purge_expired(&self, topic_id: u32) -> usize {
+        self.inner.borrow_mut()
+            .get_topic_by_id_mut(topic_id)
+            .map_or(0, |topic| topic.purge_expired())
+    }
+
+    /// Get the sequence number of the last message published on a topic
+    /// Only counts messages published by this queue with `publish` (and variants
+    /// that broadcast). Useful to checkpoint ordered streams.
+    /// @param topic_id - ID of the topic
+    /// @returns Last sequence number, or 0 if none was published (or the topic is unknown)
+    #[wasm_bindgen]
+    pub fn get_last_seq(&self, topic_id: u32) -> f64 {
+        self.inner.borrow()
+            .get_topic_by_id(topic_id)
+            .map_or(0.0, |topic| topic.last_seq as f64)
+    }
+
+    /// Check if a topic has buffering enabled
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn has_buffer(&self, topic_id: u32) -> bool {
+        let queue = self.inner.borrow();
+        if let Some(topic) = queue.get_topic_by_id(topic_id) {
+            topic.has_buffer()
+        } else {
+            false
+        }
+    }
+
+    /// Log every message dispatched on a topic to the browser console
+    /// Has no effect unless the crate is built with the `console-logging` feature
+    /// @param topic_id - ID of the topic
+    /// @param label - Label shown in the console output
+    #[wasm_bindgen]
+    pub fn enable_topic_logging(&self, topic_id: u32, label: &str) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+            topic.log_label = Some(label.to_string());
+            Ok(())
+        } else {
+            Err(JsValue::from_str("Invalid topic ID"))
+        }
+    }
+
+    /// Set the level of structured diagnostics written to the browser console
+    /// 0 = off, 1 = errors, 2 = warnings, 3 = info (topic lifecycle, BroadcastChannel
+    /// packets), 4 = debug (every dispatch with subscriber count and latency).
+    /// Has no effect unless the crate is built with the `wasm-logging` feature
+    /// @param level - Log level (0-4)
+    #[wasm_bindgen]
+    pub fn enable_wasm_logging(&self, level: u8) -> Result<(), JsValue> {
+        if level > LOG_DEBUG {
+            return Err(JsValue::from_str("Log level must be between 0 and 4"));
+        }
+        self.inner.borrow().log_level.set(level);
+        Ok(())
+    }
+
+    /// Stop logging messages dispatched on a topic
+    /// @param topic_id - ID of the topic
+    #[wasm_bindgen]
+    pub fn disable_topic_logging(&self, topic_id: u32) -> Result<(), JsValue> {
+        let mut queue = self.inner.borrow_mut();
+        if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+            topic.log_label = None;
+            Ok(())
+        } else {
+            Err(JsValue::from_str("Invalid topic ID"))
+        }
+    }
+
+    /// Get buffered messages for a topic as a JavaScript array
+    /// @param topic_id - ID of the topic
+    /// @returns Array of buffered messages (oldest first), or empty array if no buffer
+    #[wasm_bindgen(skip_typescript)]
+    pub fn get_buffered_messages(&self, topic_id: u32) -> Result<js_sys::Array, JsValue> {
+        buffered_messages_to_js(&self.inner, topic_id)
+    }
+
+    /// Get the buffered messages of a topic timestamped between `from_ts` and `to_ts`
+    /// Both ends are inclusive; messages are filtered without leaving wasm.
+    /// @param topic_id - ID of the topic
+    /// @param from_ts - Earliest timestamp (ms since epoch)
+    /// @param to_ts - Latest timestamp (ms since epoch)
+    /// @param limit - Maximum number of messages to return (default: all)
+    /// @returns Array of messages (oldest first), or empty array if no buffer
+    #[wasm_bindgen]
+    pub fn get_buffered_range(&self, topic_id: u32, from_ts: f64, to_ts: f64, limit: Option<usize>) -> Result<js_sys::Array, JsValue> {
+        let array = js_sys::Array::new();
+        let mut queue = self.inner.borrow_mut();
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            return Ok(array);
+        };
+        topic.purge_expired();
+        if let Some(buffer) = topic.get_buffer() {
+            for msg in buffer.iter_range(from_ts, to_ts).take(limit.unwrap_or(usize::MAX)) {
+                array.push(&crate::js_utils::message_to_js(msg, &topic.name)?);
+            }
+        }
+        Ok(array)
+    }
+
+    /// Get the buffered messages of a topic whose payload passes a predicate
+    /// The predicate runs inside the scan, so rejected messages never cross into JS
+    /// as message objects. Errors thrown by the predicate are returned.
+    /// @param topic_id - ID of the topic
+    /// @param predicate - Called with each payload; truthy results are kept
+    /// @param limit - Maximum number of messages to return (default: all)
+    /// @returns Array of messages (oldest first), or empty array if no buffer
+    #[wasm_bindgen]
+    pub fn get_buffered_where(&self, topic_id: u32, predicate: Function, limit: Option<usize>) -> Result<js_sys::Array, JsValue> {
+        let array = js_sys::Array::new();
+        // Snapshot the buffer so the predicate runs without the queue borrow
+        let (name, messages) = {
+            let mut queue = self.inner.borrow_mut();
+            let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+                return Ok(array);
+            };
+            topic.purge_expired();
+            let messages = topic.get_buffer().map(|buffer| buffer.to_vec()).unwrap_or_default();
+            (topic.name.clone(), messages)
+        };
+
+        let limit = limit.unwrap_or(usize::MAX);
+        for msg in &messages {
+            if array.length() as usize >= limit {
+                break;
+            }
+            if predicate.call1(&JsValue::NULL, &msg.payload)?.is_truthy() {
+                array.push(&crate::js_utils::message_to_js(msg, &name)?);
+            }
+        }
+        Ok(array)
+    }
+
+    /// Remove and return the oldest buffered messages of a topic
+    /// Expired messages are dropped first and never returned.
+    /// @param topic_id - ID of the topic
+    /// @param max - Maximum number of messages to remove (default: all)
+    /// @returns Array of removed messages (oldest first), or empty array if no buffer
+    #[wasm_bindgen]
+    pub fn drain_buffer(&self, topic_id: u32, max: Option<usize>) -> Result<js_sys::Array, JsValue> {
+        self.inner.borrow().check_open()?;
+        let array = js_sys::Array::new();
+        let mut queue = self.inner.borrow_mut();
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            return Ok(array);
+        };
+        topic.purge_expired();
+        let name = topic.name.clone();
+        if let Some(buffer) = topic.get_buffer_mut() {
+            for _ in 0..max.unwrap_or(usize::MAX) {
+                let Some(msg) = buffer.pop() else { break };
+                array.push(&crate::js_utils::message_to_js(&msg, &name)?);
+            }
+        }
+        drop(queue);
+
+        notify_backpressure(&self.inner);
+        Ok(array)
+    }
+
+    /// Pass the oldest buffered messages of a topic to a callback, removing each one it accepts
+    /// A message is only removed once the callback returned without throwing.
+    /// A throwing callback stops consumption: its error is reported (see
+    /// `set_error_handler`) and that message and the rest stay buffered.
+    /// @param topic_id - ID of the topic
+    /// @param callback - Called with each message object ({topic, payload, ...})
+    /// @param max - Maximum number of messages to consume (default: all)
+    /// @returns Number of messages consumed, 0 if the topic has no buffer
+    #[wasm_bindgen]
+    pub fn consume_buffer(&self, topic_id: u32, callback: Function, max: Option<usize>) -> Result<usize, JsValue> {
+        self.inner.borrow().check_open()?;
+        let name = {
+            let mut queue = self.inner.borrow_mut();
+            let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+                return Ok(0);
+            };
+            topic.purge_expired();
+            topic.name.clone()
+        };
+
+        let mut consumed = 0;
+        while consumed < max.unwrap_or(usize::MAX) {
+            // The borrow is released while the callback runs, so it may use the queue
+            let Some(msg) = self.inner.borrow()
+                .get_topic_by_id(topic_id)
+                .and_then(|topic| topic.get_buffer()?.peek().cloned()) else {
+                break;
+            };
+            let msg_js = crate::js_utils::message_to_js(&msg, &name)?;
+            if let Err(err) = callback.call1(&JsValue::NULL, &msg_js) {
+                report_error(&self.inner, &err, Some(topic_id), None, Some(msg.id));
+                break;
+            }
+
+            // The callback may have drained or cleared the buffer itself
+            let mut queue = self.inner.borrow_mut();
+            if let Some(buffer) = queue.get_topic_by_id_mut(topic_id).and_then(|topic| topic.get_buffer_mut()) {
+                if buffer.peek().is_some_and(|front| Rc::ptr_eq(front, &msg)) {
+                    buffer.pop();
+                }
+            }
+            consumed += 1;
+        }
+
+        notify_backpressure(&self.inner);
+        Ok(consumed)
+    }
+
+    /// Keep only the buffered messages of a topic that pass a predicate
+    /// Useful to drop completed work from history so replay does not resurrect it.
+    /// If the predicate throws, its error is returned and nothing is removed.
+    /// @param topic_id - ID of the topic
+    /// @param predicate - Called with (payload, id, timestamp); falsy results are removed
+    /// @returns Number of messages removed
+    #[wasm_bindgen]
+    pub fn buffer_retain(&self, topic_id: u32, predicate: Function) -> Result<usize, JsValue> {
+        self.inner.borrow().check_open()?;
+        // Snapshot the buffer so the predicate runs without the queue borrow
+        let messages = self.inner.borrow().get_topic_by_id(topic_id)
+            .and_then(|topic| topic.get_buffer())
+            .map(|buffer| buffer.to_vec())
+            .unwrap_or_default();
+
+        let mut rejected = Vec::new();
+        for msg in messages {
+            let id = JsValue::from(msg.id);
+            if !predicate.call3(&JsValue::NULL, &msg.payload, &id, &msg.timestamp.into())?.is_truthy() {
+                rejected.push(msg);
+            }
+        }
+        if rejected.is_empty() {
+            return Ok(0);
+        }
+
+        let removed = {
+            let mut queue = self.inner.borrow_mut();
+            queue.get_topic_by_id_mut(topic_id)
+                .and_then(|topic| topic.get_buffer_mut())
+                .map(|buffer| buffer.retain(|msg| !rejected.iter().any(|r| Rc::ptr_eq(r, msg))))
+                .unwrap_or(0)
+        };
+        notify_backpressure(&self.inner);
+        Ok(removed)
+    }
+
+    /// Remove a single buffered message of a topic by its ID
+    /// @param topic_id - ID of the topic
+    /// @param message_id - The message's `id` (a BigInt)
+    /// @returns true if the message was buffered and is now removed
+    #[wasm_bindgen]
+    pub fn buffer_remove(&self, topic_id: u32, message_id: u64) -> bool {
+        let removed = self.inner.borrow_mut().get_topic_by_id_mut(topic_id)
+            .and_then(|topic| topic.get_buffer_mut())
+            .is_some_and(|buffer| buffer.remove_by_id(message_id));
+        if removed {
+            notify_backpressure(&self.inner);
+        }
+        removed
+    }
+
+    /// Snapshot the buffers of all buffered topics, e.g. to persist them across reloads
+    /// The result is structured-cloneable, so it can be stored in IndexedDB as is.
+    /// Messages whose payload cannot be cloned are marked `skipped: true` and have no payload.
+    /// @returns {version, topics: [{name, capacity, messages}]}
+    #[wasm_bindgen]
+    pub fn export_state(&self) -> Result<JsValue, JsValue> {
+        snapshot::export(&mut self.inner.borrow_mut())
+    }
+
+    /// Restore buffers from a snapshot made by `export_state`
+    /// Topics are created as needed; their buffers are replaced with the recorded
+    /// capacity and messages, keeping IDs and timestamps. Restored IDs are marked
+    /// as seen, so a later sync with other tabs does not deliver them again.
+    /// Skipped and expired messages are not restored. Topic names are normalized
+    /// and validated like `register_topic`; an invalid name rejects the snapshot.
+    /// @param state - Object returned by `export_state`
+    /// @returns Number of messages restored
+    #[wasm_bindgen]
+    pub fn import_state(&self, state: JsValue) -> Result<u32, JsValue> {
+        self.inner.borrow().check_open()?;
+        let restored = snapshot::import(&mut self.inner.borrow_mut(), &state)?;
+        notify_topic_events(&self.inner);
+        notify_backpressure(&self.inner);
+        Ok(restored)
+    }
+
+    /// Write a topic's messages to IndexedDB so they survive the tab closing
+    /// Each message published in this tab and buffered on the topic is written
+    /// to `store_name` (one store per topic) shortly after `publish` returns;
+    /// the store keeps the newest `max_entries`. Write failures are passed to the
+    /// error handler and never fail `publish`. Payloads that cannot be
+    /// JSON-serialized are stored as a marker and skipped by `load_persistent_buffer`.
+    /// The topic gets a buffer of `max_entries` messages if it has none.
+    /// @param topic_id - ID of the topic
+    /// @param db_name - IndexedDB database name
+    /// @param store_name - Object store name, created if missing
+    /// @param max_entries - Maximum number of stored messages
+    /// @returns Promise resolving once the database is open
+    #[wasm_bindgen]
+    pub fn enable_persistent_buffer(&self, topic_id: u32, db_name: &str, store_name: &str, max_entries: u32) -> Result<Promise, JsValue> {
+        self.inner.borrow().check_open()?;
+        idb::enable(&self.inner, topic_id, db_name, store_name, max_entries)
+    }
+
+    /// Reload a persistent topic's stored messages into its buffer, oldest first
+    /// Call once the Promise of `enable_persistent_buffer` resolved, typically
+    /// right after creating the queue. Restored IDs are marked as seen, so a
+    /// later sync with other tabs does not deliver them again.
+    /// @param topic_id - ID of the topic
+    /// @returns Promise resolving with the number of messages restored
+    #[wasm_bindgen]
+    pub fn load_persistent_buffer(&self, topic_id: u32) -> Result<Promise, JsValue> {
+        self.inner.borrow().check_open()?;
+        idb::load(&self.inner, topic_id)
+    }
+
+    /// Get the overall buffer utilization of the queue (0.0 - 1.0)
+    /// Computed as the capacity-weighted average fill level of all topic buffers
+    /// @returns Utilization ratio, or 0.0 if no buffers are enabled
+    #[wasm_bindgen]
+    pub fn get_backpressure_indicator(&self) -> f64 {
+        self.inner.borrow().backpressure_indicator()
+    }
+
+    /// Register a callback fired when the backpressure indicator crosses a threshold
+    /// The callback receives the current utilization and fires in both directions,
+    /// with a 5% hysteresis band below the threshold to avoid flapping
+    /// @param threshold - Utilization level between 0.0 and 1.0
+    /// @param callback - Function called with (utilization)
+    #[wasm_bindgen]
+    pub fn on_backpressure_threshold(&self, threshold: f64, callback: Function) -> Result<(), JsValue> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(JsValue::from_str("Threshold must be between 0.0 and 1.0"));
+        }
+
+        let mut queue = self.inner.borrow_mut();
+        let above = queue.backpressure_indicator() >= threshold;
+        queue.backpressure_hooks.push(BackpressureHook {
+            threshold,
+            callback,
+            above,
+        });
+        Ok(())
+    }
+
+    /// Stop invoking subscriber callbacks without removing any subscriptions
+    /// Messages published locally or received from other tabs while paused are
+    /// still buffered and broadcast, and are held for delivery on `resume`.
+    #[wasm_bindgen]
+    pub fn pause(&self) {
+        self.inner.borrow_mut().paused = true;
+    }
+
+    /// Resume dispatch, first delivering the held messages in order
+    /// If a callback pauses the queue again, the remaining messages stay held.
+    /// @returns Number of held messages delivered
+    #[wasm_bindgen]
+    pub fn resume(&self) -> u32 {
+        self.inner.borrow_mut().paused = false;
+
+        let mut flushed = 0;
+        loop {
+            let delivery = {
+                let mut queue = self.inner.borrow_mut();
+                if queue.paused {
+                    break;
+                }
+                let Some(message) = queue.held_messages.pop_front() else {
+                    break;
+                };
+                queue.dispatch_held(&message)
+            };
+            deliver(&self.inner, delivery);
+            flushed += 1;
+        }
+
+        notify_backpressure(&self.inner);
+        flushed
+    }
+
+    /// Check if the queue is paused
+    #[wasm_bindgen]
+    pub fn is_paused(&self) -> bool {
+        self.inner.borrow().paused
+    }
+
+    /// Check if `close` has been called
+    #[wasm_bindgen]
+    pub fn is_closed(&self) -> bool {
+        self.inner.borrow().closed
+    }
+
+    /// Set how many messages are held while paused
+    /// Beyond the limit the oldest held message is dropped (it stays buffered),
+    /// counted in its topic's `overflow_count`, dead-lettered with reason "overflow"
+    /// and passed to `on_overflow`, as are the messages dropped by lowering the limit.
+    /// @param max - Maximum held messages (default: 10000, 0 holds none)
+    #[wasm_bindgen]
+    pub fn set_max_held_messages(&self, max: usize) {
+        let delivery = self.inner.borrow_mut().set_max_held_messages(max);
+        deliver(&self.inner, delivery);
+    }
+
+    /// Close the queue: leave all channels and drop every topic and subscriber
+    /// Afterwards publishing, subscribing, creating topics, changing buffers and
+    /// connecting again (`reconnect`, `attach_channel`, `attach_port`, presence,
+    /// leader election, bridges) fail with "Queue is closed". Closing again does nothing.
+    pub fn close(&mut self) -> Result<(), JsValue> {
+        close_queue(&self.inner);
+
+        // Clear the closure - it will be properly dropped here
+        self.on_message.take();
+
+        Ok(())
+    }
+
+    /// Publish multiple messages by ID (handle) efficiently
+    /// Same as `publish_batch_js`, broadcasting all messages in one packet
+    /// With `priorities` (one 0-255 value per payload), messages are published
+    /// highest priority first, keeping their order within a priority.
+    pub fn publish_batch_by_id(&self, topic_id: u32, payloads: js_sys::Array, priorities: Option<Uint8Array>) -> Result<(), JsValue> {
+        self.inner.borrow().check_open()?;
+        // No need to lookup name anymore, just use ID
+        // Verify ID exists once
+        if !self.has_topic_id(topic_id) {
+             return Err(JsValue::from_str("Invalid topic ID"));
+        }
+
+        let mut payloads: Vec<JsValue> = payloads.iter().collect();
+        if let Some(priorities) = priorities {
+            let priorities = priorities.to_vec();
+            if priorities.len() != payloads.len() {
+                return Err(JsValue::from_str("Expected one priority per payload"));
+            }
+            // Stable sort, so equal priorities keep their order
+            let mut ordered: Vec<(u8, JsValue)> = priorities.into_iter().zip(payloads).collect();
+            ordered.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+            payloads = ordered.into_iter().map(|(_, payload)| payload).collect();
+        }
+
+        publish_topic_batch(&self.inner, topic_id, payloads)
+    }
+
+    /// Publish many messages to one topic in a single pass
+    /// Messages are dispatched locally under one borrow and broadcast to other
+    /// tabs in a single packet, instead of one `postMessage` per message.
+    /// This is the fastest way to publish multiple messages.
+    /// @param topic_id - ID of the topic
+    /// @param payloads - Array of message payloads, published in order
+    #[wasm_bindgen]
+    pub fn publish_batch_js(&self, topic_id: u32, payloads: js_sys::Array) -> Result<(), JsValue> {
+        publish_topic_batch(&self.inner, topic_id, payloads.iter())
+    }
+
+    /// Publish many messages to several topics in a single pass
+    /// Like `publish_batch_js`, with one broadcast packet for the whole batch.
+    /// @param pairs - Array of `[topic_id, payload]` pairs, published in order
+    #[wasm_bindgen]
+    pub fn publish_batch_pairs(&self, pairs: js_sys::Array) -> Result<(), JsValue> {
+        let messages = pairs.iter()
+            .map(|pair| {
+                let pair = pair.dyn_into::<Array>()
+                    .map_err(|_| JsValue::from_str("Expected [topic_id, payload] pairs"))?;
+                let topic_id = pair.get(0).as_f64()
+                    .ok_or_else(|| JsValue::from_str("Expected [topic_id, payload] pairs"))?;
+                Ok((topic_id as u32, pair.get(1)))
+            })
+            .collect::<Result<Vec<_>, JsValue>>()?;
+        publish_mixed_batch(&self.inner, messages)
+    }
+
+    fn add_subscriber(&self, topic_id: u32, subscriber: Subscriber) -> Result<u32, JsValue> {
+        add_subscriber(&self.inner, topic_id, subscriber)
+    }
+
+    fn has_topic_id(&self, topic_id: u32) -> bool {
+        self.inner.borrow().has_topic_id(topic_id)
+    }
+}
+
+// ============================================================================
+// Additional MessageQueue Methods (Not exported to JavaScript)
+// ============================================================================
+
+/// Settings a queue is created with, applied before its transport sends anything
+struct OpenOptions {
+    channel_name: Option<String>,
+    /// Client ID to use instead of a generated UUID
+    client_id: Option<String>,
+    /// Use localStorage even if BroadcastChannel exists
+    force_storage: bool,
+    sync_on_connect: bool,
+    dedup_capacity: Option<usize>,
+    wire_format: WireFormat,
+    debug: bool,
+    default_buffer_capacity: Option<usize>,
+    max_topic_name_len: usize,
+    trim_topic_names: bool,
+    fold_topic_name_case: bool,
+}
+
+impl OpenOptions {
+    fn channel(channel_name: Option<String>) -> Self {
+        OpenOptions {
+            channel_name,
+            client_id: None,
+            force_storage: false,
+            sync_on_connect: true,
+            dedup_capacity: None,
+            wire_format: WireFormat::default(),
+            debug: false,
+            default_buffer_capacity: None,
+            max_topic_name_len: DEFAULT_MAX_TOPIC_NAME_LEN,
+            trim_topic_names: false,
+            fold_topic_name_case: false,
+        }
+    }
+}
+
+impl MessageQueue {
+    /// Create a queue
+    fn open(options: OpenOptions) -> Result<MessageQueue, JsValue> {
+        let OpenOptions { channel_name, client_id, force_storage, .. } = options;
+        let client_id = match client_id {
+            Some(id) => id,
+            None => generate_uuid()?,
+        };
+
+        let transport = match channel_name {
+            Some(ref name) => Some(open_channel(name, force_storage)?),
+            None => None,
+        };
+
+        let inner = Rc::new(RefCell::new(InnerQueue {
+            topics: Vec::new(),
+            topic_index: HashMap::new(),
+            channel_name,
+            force_storage,
+            message_id_prefix: InnerQueue::message_id_prefix(&client_id),
+            client_id: Rc::new(client_id),
+            sync_on_connect: options.sync_on_connect,
+            wire_format: options.wire_format,
+            debug: options.debug,
+            default_buffer_capacity: options.default_buffer_capacity,
+            max_topic_name_len: options.max_topic_name_len,
+            trim_topic_names: options.trim_topic_names,
+            fold_topic_name_case: options.fold_topic_name_case,
+            ..Default::default()
+        }));
+        if let Some(size) = options.dedup_capacity {
+            inner.borrow_mut().seen_ids.set_capacity(size);
+        }
+
+        let mq = MessageQueue {
+            inner,
+            on_message: OnceCell::new(),
+        };
+        if let Some(transport) = transport {
+            mq.attach_transport(transport);
+        }
+        Ok(mq)
+    }
+
+    /// Start exchanging packets over a transport
+    /// Sends a SYNC_REQ so the other side replies with its buffered messages,
+    /// unless the queue was created with `syncOnConnect: false`.
+    fn attach_transport(&self, transport: Transport) {
+        transport.set_onmessage(Some(self.message_handler().as_ref().unchecked_ref()));
+
+        // SYNC_REQ: [1, client_id]
+        if self.inner.borrow().sync_on_connect {
+            let req = Array::new();
+            req.push(&JsValue::from(PACKET_SYNC_REQ));
+            req.push(&JsValue::from(self.inner.borrow().client_id.as_str()));
+            let _ = transport.post(&req);
+        }
+        let _ = transport.post(&presence::packet(&self.inner.borrow().client_id, presence::JOIN));
+
+        self.inner.borrow_mut().transports.push(transport);
+    }
+
+    /// The handler installed on every transport and channel, created on first use
+    fn message_handler(&self) -> &Closure<dyn FnMut(MessageEvent)> {
+        self.on_message.get_or_init(|| {
+            let inner = self.inner.clone();
+            Closure::wrap(Box::new(move |event: MessageEvent| {
+                // localStorage passes a plain {data} object without a currentTarget
+                let source = js_sys::Reflect::get(&event, &"currentTarget".into()).unwrap_or(JsValue::UNDEFINED);
+                handle_channel_message(&inner, event.data(), source);
+            }) as Box<dyn FnMut(MessageEvent)>)
+        })
+    }
+
+    /// Create a view of this queue whose topic names are scoped under `prefix`
+    /// Topics are named `prefix.name`. The namespace shares the queue's topics,
+    /// subscribers and channel. See `scope` for the JavaScript counterpart.
+    pub fn create_namespace(&self, prefix: &str) -> NamespacedQueue {
+        NamespacedQueue::new(self.inner.clone(), prefix)
+    }
+
+    /// Publish multiple messages efficiently
     /// Note: Not exported to JavaScript due to wasm-bindgen limitations with tuple vectors.
-    /// Use multiple publish() calls instead from JS code.
+    /// Use `publish_batch_pairs` instead from JS code.
     pub fn publish_batch(&self, messages: Vec<(u32, JsValue)>) -> Result<(), JsValue> {
-        for (topic_id, payload) in messages {
-            self.publish(topic_id, payload)?;
+        publish_mixed_batch(&self.inner, messages)
+    }
+}
+
+/// Open the named channel to other tabs
+/// Falls back to localStorage where BroadcastChannel is missing.
+fn open_channel(name: &str, force_storage: bool) -> Result<Transport, JsValue> {
+    if force_storage {
+        return Ok(Transport::Storage(Rc::new(StorageTransport::new(name)?)));
+    }
+    match BroadcastChannel::new(name) {
+        Ok(channel) => Ok(Transport::Broadcast(channel)),
+        Err(_) => Ok(Transport::Storage(Rc::new(StorageTransport::new(name).map_err(|_| {
+            JsValue::from_str("Failed to create BroadcastChannel")
+        })?))),
+    }
+}
+
+/// Parse the name of a wire format, as accepted by `set_wire_format`
+fn parse_wire_format(format: &str) -> Result<WireFormat, JsValue> {
+    match format {
+        "json" => Ok(WireFormat::Json),
+        "binary" => Ok(WireFormat::Binary),
+        _ => Err(JsValue::from_str("Unknown wire format")),
+    }
+}
+
+/// Destroy a topic, rejecting the pending promises of its subscribers
+/// @returns false if the ID does not refer to a live topic
+pub(crate) fn destroy_topic(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> bool {
+    let topic = match inner.borrow_mut().remove_topic(topic_id) {
+        Some(topic) => topic,
+        None => return false,
+    };
+    notify_topic_events(inner);
+
+    #[cfg(feature = "wasm-logging")]
+    {
+        let queue = inner.borrow();
+        if queue.logs_at(crate::constants::LOG_INFO) {
+            crate::logging::log_group(crate::constants::LOG_INFO, "Topic destroyed", &[
+                ("name", JsValue::from_str(&topic.name)),
+                ("id", JsValue::from(topic_id)),
+            ]);
+        }
+    }
+
+    let rejecters: Vec<Function> = topic.subscribers.into_values()
+        .filter_map(|sub| sub.on_destroy)
+        .collect();
+
+    // Notify pending subscribers after releasing the borrow
+    let reason = JsValue::from_str("Topic destroyed");
+    for reject in rejecters {
+        let _ = reject.call1(&JsValue::NULL, &reason);
+    }
+    true
+}
+
+/// Enable or resize a topic's buffer and set its overflow policy
+/// @param capacity - Maximum number of messages to buffer (default: 100)
+pub(crate) fn enable_buffer_with(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, capacity: Option<usize>, policy: OverflowPolicy) -> Result<(), JsValue> {
+    let cap = capacity.unwrap_or(100);
+    if cap == 0 {
+        return Err(JsValue::from_str("Buffer capacity must be greater than 0"));
+    }
+
+    let mut queue = inner.borrow_mut();
+    queue.check_open()?;
+    if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+        // Re-enabling resizes in place rather than discarding the buffer
+        if topic.resize_buffer(cap).is_none() {
+            topic.enable_buffer(cap);
         }
+        topic.overflow_policy = policy;
+        Ok(())
+    } else {
+        Err(JsValue::from_str("Invalid topic ID"))
+    }
+}
+
+/// Remove a topic's buffer and the messages in it
+pub(crate) fn disable_topic_buffer(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> Result<(), JsValue> {
+    let mut queue = inner.borrow_mut();
+    queue.check_open()?;
+    if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+        topic.disable_buffer();
         Ok(())
+    } else {
+        Err(JsValue::from_str("Invalid topic ID"))
+    }
+}
+
+/// Number of unexpired messages in a topic's buffer, or -1 without a buffer
+pub(crate) fn buffer_size(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> i32 {
+    let mut queue = inner.borrow_mut();
+    if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+        topic.purge_expired();
+        topic.get_buffer()
+            .map(|b| b.len() as i32)
+            .unwrap_or(-1)
+    } else {
+        -1
+    }
+}
+
+/// Empty a topic's buffer
+/// @returns Number of messages cleared
+pub(crate) fn clear_buffer(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> usize {
+    let mut queue = inner.borrow_mut();
+    if let Some(buffer) = queue.get_topic_by_id_mut(topic_id).and_then(|topic| topic.get_buffer_mut()) {
+        let count = buffer.len();
+        buffer.clear();
+        count
+    } else {
+        0
+    }
+}
+
+/// A topic's unexpired buffered messages as JavaScript objects, oldest first
+pub(crate) fn buffered_messages_to_js(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> Result<js_sys::Array, JsValue> {
+    let mut queue = inner.borrow_mut();
+    let array = js_sys::Array::new();
+    if let Some(topic) = queue.get_topic_by_id_mut(topic_id) {
+        topic.purge_expired();
+        if let Some(buffer) = topic.get_buffer() {
+            for msg in buffer.to_vec() {
+                array.push(&crate::js_utils::message_to_js(&msg, &topic.name)?);
+            }
+        }
+    }
+    Ok(array)
+}
+
+/// Create a message, dispatch it locally and broadcast it if a channel exists
+pub(crate) fn publish_message(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+    // Fast path: nobody would see the message, so it is only counted
+    if inner.borrow_mut().publish_unobserved(topic_id) {
+        return Ok(());
+    }
+
+    // Step 1: Create message and prepare local dispatch
+    let published = publish_locked(&mut inner.borrow_mut(), topic_id, payload, PublishOptions::default());
+    notify_rejected(inner);
+
+    // Step 2: Broadcast if channel exists and invoke subscribers
+    finish_publish(inner, published?)
+}
+
+/// A publish prepared under the queue borrow, completed by `finish_publish`
+struct PendingPublish {
+    /// The published message
+    message: Rc<Message>,
+    /// Local subscriber callbacks to invoke
+    delivery: Option<Delivery>,
+    /// Message object to broadcast, if the queue has a transport
+    raw_msg: Option<JsValue>,
+}
+
+/// Publish a batch to one topic: one borrow, one topic lookup, one broadcast packet
+fn publish_topic_batch(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payloads: impl IntoIterator<Item = JsValue>) -> Result<(), JsValue> {
+    let mut deliveries = Vec::new();
+    let mut msgs = Vec::new();
+    let (topic_name, result) = {
+        let mut queue = inner.borrow_mut();
+        queue.check_open()?;
+        let topic_name = queue.get_topic_by_id(topic_id)
+            .map(|topic| topic.name.clone())
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+
+        let mut result = Ok(());
+        for payload in payloads {
+            match publish_locked(&mut queue, topic_id, payload, PublishOptions::default()) {
+                Ok(published) => {
+                    deliveries.extend(published.delivery);
+                    if let Some(raw_msg) = published.raw_msg {
+                        msgs.push((topic_id, raw_msg));
+                    }
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        (topic_name, result)
+    };
+
+    finish_batch(inner, Some(&topic_name), msgs, deliveries)?;
+    result
+}
+
+/// Publish a batch spanning several topics with one broadcast packet
+/// All topic IDs are checked before anything is published.
+fn publish_mixed_batch(inner: &Rc<RefCell<InnerQueue>>, messages: Vec<(u32, JsValue)>) -> Result<(), JsValue> {
+    let mut deliveries = Vec::new();
+    let mut msgs = Vec::new();
+    let result = {
+        let mut queue = inner.borrow_mut();
+        queue.check_open()?;
+        if messages.iter().any(|(topic_id, _)| !queue.has_topic_id(*topic_id)) {
+            return Err(JsValue::from_str("Invalid topic ID"));
+        }
+
+        let mut result = Ok(());
+        for (topic_id, payload) in messages {
+            match publish_locked(&mut queue, topic_id, payload, PublishOptions::default()) {
+                Ok(published) => {
+                    deliveries.extend(published.delivery);
+                    if let Some(raw_msg) = published.raw_msg {
+                        msgs.push((topic_id, raw_msg));
+                    }
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        result
+    };
+
+    finish_batch(inner, None, msgs, deliveries)?;
+    result
+}
+
+/// Broadcast a batch, then invoke its subscribers (see `finish_publish`)
+/// A publish of the batch rejected by a full buffer is reported first.
+fn finish_batch(inner: &Rc<RefCell<InnerQueue>>, topic_name: Option<&str>, msgs: Vec<(u32, JsValue)>, deliveries: Vec<Delivery>) -> Result<(), JsValue> {
+    notify_rejected(inner);
+    let broadcast = broadcast_batch(inner, topic_name, msgs);
+    deliver(inner, deliveries);
+    notify_backpressure(inner);
+    broadcast
+}
+
+/// Create a message and prepare its local dispatch while the caller holds the queue borrow
+fn publish_locked(queue: &mut InnerQueue, topic_id: u32, payload: JsValue, options: PublishOptions) -> Result<PendingPublish, JsValue> {
+    queue.check_open()?;
+    // Verify topic ID exists
+    if !queue.has_topic_id(topic_id) {
+        return Err(JsValue::from_str("Invalid topic ID"));
+    }
+    queue.check_overflow(topic_id, &payload)?;
+
+    // Broadcast messages carry a sequence number so receivers can detect gaps
+    let options = PublishOptions { sequenced: true, ..options };
+    let rc_msg = queue.create_message(topic_id, payload, options);
+
+    let broadcast_only = match queue.get_topic_by_id(topic_id) {
+        Some(topic) => topic.broadcast_only,
+        None => return Err(JsValue::from_str("Invalid topic ID")),
+    };
+
+    // Dispatch locally
+    // No JS object creation needed here for local dispatch!
+    let delivery = if broadcast_only { None } else { queue.dispatch_published(&rc_msg) };
+
+    // Only create JS object if we really need to broadcast
+    if !queue.broadcasts_topic(topic_id) {
+        return Ok(PendingPublish { message: rc_msg, delivery, raw_msg: None });
+    }
+
+    // Stick to the object format for compatibility with other tabs
+    let topic_name = match queue.get_topic_by_id(topic_id) {
+        Some(topic) => &topic.name,
+        None => return Err(JsValue::from_str("Invalid topic ID")),
+    };
+    let raw_msg = wire::message_to_wire(&rc_msg, topic_name, queue.wire_format)?;
+    queue.record_stat(topic_id, |stats| stats.broadcast += 1);
+    // A relayed echo of our own message is then dropped like any duplicate
+    queue.seen_ids.insert(rc_msg.id);
+    Ok(PendingPublish { message: rc_msg, delivery, raw_msg: Some(raw_msg) })
+}
+
+/// Broadcast a prepared publish, invoke local subscribers and notify backpressure hooks
+/// Must be called after the queue borrow is released.
+fn finish_publish(inner: &Rc<RefCell<InnerQueue>>, published: PendingPublish) -> Result<(), JsValue> {
+    // Broadcast before delivering, so publishes made by subscribers reach
+    // other tabs after the message that triggered them
+    let broadcast = match published.raw_msg {
+        Some(raw_msg) => broadcast_message(inner, published.message.topic_id, raw_msg),
+        None => Ok(()),
+    };
+
+    deliver(inner, published.delivery);
+    notify_backpressure(inner);
+
+    broadcast
+}
+
+/// Subscribe a callback to a topic, replaying the buffer first for replay subjects
+pub(crate) fn subscribe_callback(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+    subscribe_with_replay_subject(inner, topic_id, Subscriber::new(callback))
+}
+
+/// Subscribe a callback that only sees messages for which `filter` returns a truthy value
+pub(crate) fn subscribe_filtered(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, filter: Function, callback: Function) -> Result<u32, JsValue> {
+    let mut subscriber = Subscriber::new(callback);
+    subscriber.filter = Some(SubscriberFilter::Predicate(filter));
+    subscribe_with_replay_subject(inner, topic_id, subscriber)
+}
+
+/// Subscribe a callback that is removed after its first delivery
+pub(crate) fn subscribe_once(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+    let mut subscriber = Subscriber::new(callback);
+    subscriber.once = true;
+    add_subscriber(inner, topic_id, subscriber)
+}
+
+/// Register a subscriber, replaying the buffer first for replay subjects
+/// The subscriber's filter applies to the replayed messages too
+fn subscribe_with_replay_subject(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, subscriber: Subscriber) -> Result<u32, JsValue> {
+    subscribe_ordered(inner, topic_id, subscriber, 0)
+}
+
+/// Like `subscribe_with_replay_subject`, with a dispatch order for the subscriber
+fn subscribe_ordered(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, subscriber: Subscriber, order: i32) -> Result<u32, JsValue> {
+    let callback = subscriber.callback.clone();
+    let filter = subscriber.filter.clone();
+    let sub_id = add_subscriber_ordered(inner, topic_id, subscriber, order)?;
+    let is_replay_subject = inner.borrow()
+        .get_topic_by_id(topic_id)
+        .is_some_and(|topic| topic.is_replay_subject);
+    if is_replay_subject {
+        replay_buffer(inner, topic_id, sub_id, &callback, filter.as_ref());
+    }
+    Ok(sub_id)
+}
+
+/// Close a queue, as `close` does, leaving its message handler to the MessageQueue
+fn close_queue(inner: &Rc<RefCell<InnerQueue>>) {
+    if inner.borrow().closed {
+        return;
+    }
+    bridge::detach(inner);
+    let mut queue = inner.borrow_mut();
+    queue.closed = true;
+    presence::announce_close(&queue);
+    presence::disable(&mut queue);
+    queue.peers.clear();
+    queue.peer_change_handler = None;
+    queue.leader_id = None;
+    queue.leader_candidate = false;
+    queue.leadership_handler = None;
+    queue.peer_closed_handler = None;
+    queue.rpc_responders.clear();
+    let elections: Vec<Function> = queue.election.take().map(|election| election.resolvers).unwrap_or_default();
+    queue.close_transports();
+
+    // Pending `next_message` and `request` promises are rejected once the borrow is released
+    let rejecters: Vec<Function> = queue.topics.drain(..)
+        .flat_map(|topic| topic.subscribers.into_values())
+        .filter_map(|sub| sub.on_destroy)
+        .collect();
+    let pending_requests: Vec<_> = queue.pending_requests.drain().map(|(_, request)| request).collect();
+    let scheduled: Vec<_> = queue.scheduled_publishes.drain().map(|(_, scheduled)| scheduled).collect();
+    queue.topic_index.clear();
+    queue.free_slots.clear();
+    queue.tag_index.clear();
+    queue.backpressure_hooks.clear();
+    queue.broadcast_interceptors.clear();
+    queue.pattern_subscribers.clear();
+    queue.global_subscribers.clear();
+    queue.topic_hooks.clear();
+    queue.topic_events.clear();
+    queue.pending_broadcasts.clear();
+    queue.pending_echoes.clear();
+    queue.dead_letter = None;
+    queue.paused = false;
+    queue.held_messages.clear();
+    queue.gap_handler = None;
+    queue.remote_seqs.clear();
+    for (_, answer) in queue.pending_sync_answers.drain() {
+        clear_timeout(answer.timeout);
+    }
+    queue.remove_event_listeners();
+
+    drop(queue);
+
+    let reason = JsValue::from_str("Queue closed");
+    for reject in rejecters {
+        let _ = reject.call1(&JsValue::NULL, &reason);
+    }
+    rpc::reject_pending(pending_requests, "Queue closed");
+    for resolve in elections {
+        let _ = resolve.call1(&JsValue::NULL, &JsValue::FALSE);
+    }
+    schedule::cancel_all(scheduled);
+}
+
+/// Listen for a signal's `abort` event
+/// The returned listener detaches itself when dropped.
+fn abort_listener(signal: AbortSignal, mut on_abort: impl FnMut() + 'static) -> Result<EventListener, JsValue> {
+    let closure = Closure::wrap(Box::new(move |_: Event| on_abort()) as Box<dyn FnMut(Event)>);
+    signal.add_event_listener_with_callback("abort", closure.as_ref().unchecked_ref())?;
+    Ok(EventListener {
+        target: signal.into(),
+        event_type: "abort".to_string(),
+        closure,
+    })
+}
+
+/// Run `task` on the queue once nothing borrows it
+/// Abort events fire synchronously, so a signal aborted from a callback that
+/// runs under the borrow (e.g. a filter) finds the queue borrowed; the task is
+/// then retried in a new task. Nothing runs once the queue is dropped.
+fn when_unborrowed(weak: Weak<RefCell<InnerQueue>>, task: impl FnOnce(&Rc<RefCell<InnerQueue>>) + 'static) {
+    let Some(inner) = weak.upgrade() else {
+        return;
+    };
+    if inner.try_borrow_mut().is_ok() {
+        task(&inner);
+        return;
+    }
+    let retry = Closure::once_into_js(move || when_unborrowed(weak, task));
+    let _ = set_timeout(retry.unchecked_ref(), 0.0);
+}
+
+/// Subscribe to a topic, enforcing the open state and its subscriber limit
+/// Every subscribe goes through here, so rejected subscribes are counted.
+pub(crate) fn add_subscriber(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, subscriber: Subscriber) -> Result<u32, JsValue> {
+    add_subscriber_ordered(inner, topic_id, subscriber, 0)
+}
+
+fn add_subscriber_ordered(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, subscriber: Subscriber, order: i32) -> Result<u32, JsValue> {
+    let mut queue = inner.borrow_mut();
+    queue.check_open()?;
+    let topic = queue.get_topic_by_id_mut(topic_id)
+        .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+    if topic.max_subscribers.is_some_and(|limit| topic.subscribers.len() >= limit) {
+        queue.record_stat(topic_id, |stats| stats.rejected_subscribes += 1);
+        return Err(JsValue::from_str("Subscriber limit reached"));
+    }
+    Ok(topic.add_subscriber_ordered(subscriber, order))
+}
+
+/// Promise resolving with the payload of the next message on a topic
+/// With a timeout, the subscriber is removed and the promise rejected when it expires.
+fn next_message_promise(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, timeout_ms: Option<f64>) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        // `resolve` takes the payload (the first callback argument) as the value
+        let mut subscriber = Subscriber::new(resolve);
+        subscriber.once = true;
+        subscriber.on_destroy = Some(reject.clone());
+
+        let sub_id = match add_subscriber(inner, topic_id, subscriber) {
+            Ok(sub_id) => sub_id,
+            Err(err) => {
+                let _ = reject.call1(&JsValue::NULL, &err);
+                return;
+            }
+        };
+
+        if let Some(ms) = timeout_ms {
+            let weak = Rc::downgrade(inner);
+            let reject_timeout = reject.clone();
+            let on_timeout = Closure::once_into_js(move || {
+                let removed = weak.upgrade().is_some_and(|inner| {
+                    inner.try_borrow_mut().is_ok_and(|mut queue| {
+                        queue.get_topic_by_id_mut(topic_id)
+                            .is_some_and(|topic| topic.subscribers.remove(sub_id).is_some())
+                    })
+                });
+                // Not removed: the message already arrived or the topic is gone
+                if removed {
+                    let _ = reject_timeout.call1(&JsValue::NULL, &JsValue::from_str("Timeout"));
+                }
+            });
+
+            if let Err(err) = set_timeout(on_timeout.unchecked_ref(), ms) {
+                let _ = reject.call1(&JsValue::NULL, &err);
+            }
+        }
+    })
+}
+
+/// Promise resolving with {fulfilled, rejected} once every subscriber result has settled
+/// With a timeout, the promise is rejected with "Timeout" if that takes longer.
+fn settle_results(results: Vec<Result<JsValue, JsValue>>, timeout_ms: Option<f64>) -> Promise {
+    let promises: Array = results.iter()
+        .map(|result| match result {
+            Ok(value) => Promise::resolve(value),
+            Err(err) => Promise::reject(err),
+        })
+        .collect();
+    let settled = Promise::all_settled(&promises);
+
+    Promise::new(&mut |resolve, reject| {
+        let timer = match timeout_ms {
+            Some(ms) => {
+                let reject_timeout = reject.clone();
+                let on_timeout = Closure::once_into_js(move || {
+                    let _ = reject_timeout.call1(&JsValue::NULL, &JsValue::from_str("Timeout"));
+                });
+                match set_timeout(on_timeout.unchecked_ref(), ms) {
+                    Ok(timer) => Some(timer),
+                    Err(err) => {
+                        let _ = reject.call1(&JsValue::NULL, &err);
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let closure = Closure::once(move |outcomes: JsValue| {
+            if let Some(timer) = timer {
+                clear_timeout(timer);
+            }
+            let outcomes = Array::from(&outcomes);
+            let fulfilled = outcomes.iter()
+                .filter(|outcome| {
+                    js_sys::Reflect::get(outcome, &"status".into())
+                        .is_ok_and(|status| status.as_string().as_deref() == Some("fulfilled"))
+                })
+                .count() as u32;
+            let counts = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&counts, &"fulfilled".into(), &fulfilled.into());
+            let _ = js_sys::Reflect::set(&counts, &"rejected".into(), &(outcomes.length() - fulfilled).into());
+            let _ = resolve.call1(&JsValue::NULL, &counts);
+        });
+        let _ = settled.then(&closure);
+        closure.forget();
+    })
+}
+
+/// Deliver a topic's buffered messages (oldest first) to a single callback
+fn replay_buffer(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, sub_id: u32, callback: &Function, filter: Option<&SubscriberFilter>) {
+    let messages = live_buffered_messages(inner, topic_id);
+
+    // Invoke after releasing the borrow so callbacks may use the queue
+    for msg in messages {
+        if filter.is_none_or(|filter| filter.accepts(&msg.payload)) {
+            deliver_to(inner, callback, topic_id, sub_id, &msg);
+        }
+    }
+}
+
+/// Deliver live messages queued for a subscriber during a replay, then switch
+/// it back to live delivery. The queue is checked and the flag cleared under one
+/// borrow, so no message published in between can be lost.
+fn finish_deferred(
+    inner: &Rc<RefCell<InnerQueue>>,
+    topic_id: u32,
+    sub_id: u32,
+    callback: &Function,
+    deferred: &RefCell<VecDeque<Rc<Message>>>,
+    accepts: impl Fn(&Message) -> bool,
+) {
+    loop {
+        let next = {
+            let mut queue = inner.borrow_mut();
+            let next = deferred.borrow_mut().pop_front();
+            if next.is_none() {
+                if let Some(sub) = queue.get_topic_by_id_mut(topic_id)
+                    .and_then(|topic| topic.subscribers.get_mut(sub_id))
+                {
+                    sub.deferred = None;
+                }
+            }
+            next
+        };
+        match next {
+            Some(msg) if accepts(&msg) => deliver_to(inner, callback, topic_id, sub_id, &msg),
+            Some(_) => {}
+            None => break,
+        }
+    }
+}
+
+/// A topic's buffered messages (oldest first), dropping expired ones first
+fn live_buffered_messages(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) -> Vec<Rc<Message>> {
+    let mut queue = inner.borrow_mut();
+    let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+        return Vec::new();
+    };
+    topic.purge_expired();
+    topic.get_buffer().map(|buffer| buffer.to_vec()).unwrap_or_default()
+}
+
+/// Invoke a single subscriber callback with a stored message, reporting errors
+fn deliver_to(inner: &Rc<RefCell<InnerQueue>>, callback: &Function, topic_id: u32, sub_id: u32, msg: &Message) {
+    let result = callback.call5(
+        &JsValue::NULL,
+        &msg.payload,
+        &JsValue::from(topic_id),
+        &JsValue::from(msg.timestamp),
+        &JsValue::from(msg.id),
+        &msg.headers_js(),
+    );
+    if let Err(err) = result {
+        report_error(inner, &err, Some(topic_id), Some(sub_id), Some(msg.id));
+    }
+}
+
+/// Register and configure one entry of the `topics` config array
+fn configure_topic(queue: &mut InnerQueue, index: usize, config: &JsValue) -> Result<(), JsValue> {
+    let field_error = |field: &str, expected: &str| {
+        JsValue::from_str(&format!("topics[{}].{} must be {}", index, field, expected))
+    };
+
+    if js_utils::config_field(config, "rate_limit")?.is_some() {
+        return Err(JsValue::from_str(&format!("topics[{}].rate_limit is not supported", index)));
+    }
+
+    let name = js_utils::config_field(config, "name")?
+        .and_then(|v| v.as_string())
+        .ok_or_else(|| field_error("name", "a string"))?;
+    let capacity = js_utils::config_field(config, "buffer_capacity")?
+        .map(|v| match v.as_f64() {
+            Some(n) if n >= 1.0 && n.fract() == 0.0 => Ok(n as usize),
+            _ => Err(field_error("buffer_capacity", "a positive integer")),
+        })
+        .transpose()?;
+    let tags = js_utils::config_field(config, "tags")?
+        .map(|v| {
+            let tags = v.dyn_into::<Array>().map_err(|_| field_error("tags", "an array of strings"))?;
+            tags.iter()
+                .map(|tag| tag.as_string().ok_or_else(|| field_error("tags", "an array of strings")))
+                .collect::<Result<Vec<String>, JsValue>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let topic_id = queue.get_or_create_valid_topic_id(&name)?;
+    if let (Some(capacity), Some(topic)) = (capacity, queue.get_topic_by_id_mut(topic_id)) {
+        topic.enable_buffer(capacity);
+    }
+    for tag in tags {
+        queue.add_tag(topic_id, &tag);
+    }
+    Ok(())
+}
+
+/// Queue an asynchronous publish on its topic's priority queue
+/// The first message queued on a topic schedules a microtask that drains it.
+/// Beyond the pending limit, the queue's drop policy applies.
+fn enqueue_publish(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue, priority: u8) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        let mut queue = inner.borrow_mut();
+        let mut dropped = None;
+        if queue.max_pending > 0 && queue.pending_async.get() >= queue.max_pending {
+            match queue.drop_policy {
+                DropPolicy::Reject => {
+                    drop(queue);
+                    let _ = reject.call1(&JsValue::NULL, &JsValue::from_str(ERR_QUEUE_FULL));
+                    return;
+                }
+                DropPolicy::DropNewest => {
+                    queue.dropped_async += 1;
+                    drop(queue);
+                    let _ = resolve.call0(&JsValue::NULL);
+                    return;
+                }
+                DropPolicy::DropOldest => {
+                    dropped = queue.pop_oldest_queued();
+                    if dropped.is_some() {
+                        queue.dropped_async += 1;
+                        queue.pending_async.set(queue.pending_async.get() - 1);
+                    }
+                }
+            }
+        }
+
+        let seq = queue.next_queued_seq;
+        queue.next_queued_seq += 1;
+        let needs_drain = !queue.priority_queues.contains_key(&topic_id);
+        queue.priority_queues.entry(topic_id).or_default()
+            .push(QueuedPublish { priority, seq, payload: payload.clone(), resolve, reject });
+        queue.pending_async.set(queue.pending_async.get() + 1);
+        drop(queue);
+
+        if let Some(dropped) = dropped {
+            let _ = dropped.resolve.call0(&JsValue::NULL);
+        }
+
+        if needs_drain {
+            let inner = inner.clone();
+            let closure = Closure::once(move |_value: JsValue| {
+                drain_priority_queue(&inner, topic_id);
+            });
+
+            // Use Promise.resolve().then() to create a microtask
+            let _ = Promise::resolve(&JsValue::UNDEFINED).then(&closure);
+            closure.forget();
+        }
+    })
+}
+
+/// Queue a `publish_async` call for the coalesced flush of this tick
+/// The first call of a tick schedules the flush microtask.
+fn enqueue_coalesced(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue) -> Result<Promise, JsValue> {
+    let mut queue = inner.borrow_mut();
+    if !queue.has_topic_id(topic_id) {
+        return Err(JsValue::from_str("Invalid topic ID"));
+    }
+
+    let mut dropped = None;
+    if queue.max_pending > 0 && queue.pending_async.get() >= queue.max_pending {
+        match queue.drop_policy {
+            DropPolicy::Reject => return Ok(Promise::reject(&JsValue::from_str(ERR_QUEUE_FULL))),
+            DropPolicy::DropNewest => {
+                queue.dropped_async += 1;
+                return Ok(Promise::resolve(&JsValue::UNDEFINED));
+            }
+            DropPolicy::DropOldest => {
+                let removed = match queue.coalesced.as_mut().and_then(|flush| flush.publishes.pop_front()) {
+                    Some(_) => true,
+                    None => {
+                        dropped = queue.pop_oldest_queued();
+                        dropped.is_some()
+                    }
+                };
+                if removed {
+                    queue.dropped_async += 1;
+                    queue.pending_async.set(queue.pending_async.get() - 1);
+                }
+            }
+        }
+    }
+
+    let needs_flush = queue.coalesced.is_none();
+    let flush = queue.coalesced.get_or_insert_with(CoalescedFlush::new);
+    flush.publishes.push_back((topic_id, payload));
+    let promise = flush.promise.clone();
+    queue.pending_async.set(queue.pending_async.get() + 1);
+    drop(queue);
+
+    if let Some(dropped) = dropped {
+        let _ = dropped.resolve.call0(&JsValue::NULL);
+    }
+
+    if needs_flush {
+        let inner = inner.clone();
+        let closure = Closure::once(move |_value: JsValue| {
+            flush_coalesced(&inner);
+        });
+        let _ = Promise::resolve(&JsValue::UNDEFINED).then(&closure);
+        closure.forget();
+    }
+    Ok(promise)
+}
+
+/// Deliver the publishes collected for a coalesced flush, then broadcast them as one packet
+/// The flush is taken off the queue first, so publishes made by subscribers start the next one.
+fn flush_coalesced(inner: &Rc<RefCell<InnerQueue>>) {
+    let Some(flush) = inner.borrow_mut().coalesced.take() else {
+        return;
+    };
+    let count = flush.publishes.len();
+
+    let mut deliveries = Vec::new();
+    let mut msgs = Vec::new();
+    let mut result = Ok(());
+    {
+        let mut queue = inner.borrow_mut();
+        for (topic_id, payload) in flush.publishes {
+            match publish_locked(&mut queue, topic_id, payload, PublishOptions::default()) {
+                Ok(published) => {
+                    deliveries.extend(published.delivery);
+                    if let Some(raw_msg) = published.raw_msg {
+                        msgs.push((topic_id, raw_msg));
+                    }
+                }
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    notify_rejected(inner);
+    deliver(inner, deliveries);
+    notify_backpressure(inner);
+    let result = result.and(broadcast_batch(inner, None, msgs));
+    let pending = inner.borrow().pending_async.clone();
+    pending.set(pending.get().saturating_sub(count));
+
+    let _ = match result {
+        Ok(()) => flush.resolve.call0(&JsValue::NULL),
+        Err(err) => flush.reject.call1(&JsValue::NULL, &err),
+    };
+}
+
+/// Deliver a topic's queued publishes in priority order
+/// Messages queued by subscribers during the drain join the same queue.
+fn drain_priority_queue(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32) {
+    let pending = inner.borrow().pending_async.clone();
+    loop {
+        let next = {
+            let mut queue = inner.borrow_mut();
+            let next = queue.priority_queues.get_mut(&topic_id).and_then(|heap| heap.pop());
+            if next.is_none() {
+                // Let the next publish schedule a new drain
+                queue.priority_queues.remove(&topic_id);
+            }
+            next
+        };
+        let Some(queued) = next else { break };
+
+        let result = dispatch_async(inner, topic_id, queued.payload);
+        pending.set(pending.get().saturating_sub(1));
+
+        // Settle the promise
+        let _ = match result {
+            Ok(()) => queued.resolve.call0(&JsValue::NULL),
+            Err(err) => queued.reject.call1(&JsValue::NULL, &err),
+        };
+    }
+}
+
+/// Deliver a message scheduled by `publish_async`, then broadcast it
+/// A failed post is returned, rejecting the publish; local delivery has happened by then.
+fn dispatch_async(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+    let mut queue = inner.try_borrow_mut()
+        .map_err(|_| JsValue::from_str("Failed to borrow queue"))?;
+    let published = publish_locked(&mut queue, topic_id, payload, PublishOptions::default());
+    drop(queue);
+    notify_rejected(inner);
+    let published = published?;
+
+    deliver(inner, published.delivery);
+    notify_backpressure(inner);
+    match published.raw_msg {
+        Some(raw_msg) => broadcast_message(inner, published.message.topic_id, raw_msg),
+        None => Ok(()),
+    }
+}
+
+/// Resolve once the pending async publish counter reaches zero
+/// Re-checks in chained microtasks, which run after already scheduled deliveries
+fn poll_pending_async(pending: Rc<Cell<usize>>, resolve: Function) {
+    if pending.get() == 0 {
+        let _ = resolve.call0(&JsValue::NULL);
+        return;
+    }
+
+    let closure = Closure::once(move |_value: JsValue| {
+        poll_pending_async(pending, resolve);
+    });
+    let _ = Promise::resolve(&JsValue::UNDEFINED).then(&closure);
+    closure.forget();
+}
+
+/// Pass sequence gaps detected while receiving to the gap handler
+/// The handler is invoked after the queue borrow is released
+pub(crate) fn notify_gaps(inner: &Rc<RefCell<InnerQueue>>) {
+    let (handler, gaps) = match inner.try_borrow_mut() {
+        Ok(mut queue) if !queue.sequence_gaps.is_empty() => {
+            let gaps = std::mem::take(&mut queue.sequence_gaps);
+            match queue.gap_handler.clone() {
+                Some(handler) => (handler, gaps),
+                None => return,
+            }
+        }
+        _ => return,
+    };
+
+    for gap in &gaps {
+        if let Ok(gap_js) = js_utils::sequence_gap_to_js(gap) {
+            let _ = handler.call1(&JsValue::NULL, &gap_js);
+        }
+    }
+}
+
+/// Fire any backpressure callbacks whose threshold was crossed
+/// Callbacks are invoked after the queue borrow is released
+pub(crate) fn notify_backpressure(inner: &Rc<RefCell<InnerQueue>>) {
+    let crossed = match inner.try_borrow_mut() {
+        Ok(mut queue) => queue.poll_backpressure(),
+        Err(_) => return,
+    };
+
+    for (callback, utilization) in crossed {
+        let _ = callback.call1(&JsValue::NULL, &JsValue::from(utilization));
     }
 }
 
@@ -541,15 +3992,15 @@ impl Drop for MessageQueue {
     fn drop(&mut self) {
         // Close the broadcast channel and clear resources
         if let Ok(mut queue) = self.inner.try_borrow_mut() {
-            if let Some(channel) = &queue.channel {
-                channel.close();
-                channel.set_onmessage(None);
-            }
-            queue.channel = None;
+            // After `close()` there are no transports left to post on
+            presence::announce_close(&queue);
+            presence::disable(&mut queue);
+            queue.close_transports();
             queue.topics.clear();
+            queue.remove_event_listeners();
         }
 
-        // The closure will be automatically dropped when _closure is dropped
+        // The message handler is dropped together with `on_message`
         // No need to explicitly call forget() as Drop handles cleanup
     }
 }
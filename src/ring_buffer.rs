@@ -7,6 +7,10 @@ use crate::types::Message;
 pub struct RingBuffer {
     /// The underlying buffer
     buffer: Vec<Option<Rc<Message>>>,
+    /// Estimated payload size of each slot in bytes (0 unless sized by the caller)
+    sizes: Vec<usize>,
+    /// Sum of the estimated sizes of the buffered messages
+    bytes: usize,
     /// Maximum capacity
     capacity: usize,
     /// Current number of elements
@@ -22,6 +26,8 @@ impl RingBuffer {
     pub fn new(capacity: usize) -> Self {
         RingBuffer {
             buffer: vec![None; capacity],
+            sizes: vec![0; capacity],
+            bytes: 0,
             capacity,
             size: 0,
             front: 0,
@@ -49,9 +55,20 @@ impl RingBuffer {
         self.capacity
     }
 
+    /// Get the estimated size of the buffered payloads in bytes
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     /// Push a message into the buffer
     /// Returns the oldest message if the buffer was full (overwrites)
     pub fn push(&mut self, msg: Rc<Message>) -> Option<Rc<Message>> {
+        self.push_sized(msg, 0)
+    }
+
+    /// Push a message with an estimated payload size in bytes
+    /// Returns the oldest message if the buffer was full (overwrites)
+    pub fn push_sized(&mut self, msg: Rc<Message>, size: usize) -> Option<Rc<Message>> {
         if self.capacity == 0 {
             return Some(msg); // Reject if capacity is 0
         }
@@ -59,6 +76,7 @@ impl RingBuffer {
         let displaced = if self.is_full() {
             // Buffer is full, remove and return the front element
             let old = self.buffer[self.front].take();
+            self.bytes -= self.sizes[self.front];
             self.front = (self.front + 1) % self.capacity;
             old
         } else {
@@ -67,9 +85,11 @@ impl RingBuffer {
 
         // Insert the new message
         self.buffer[self.rear] = Some(msg);
+        self.sizes[self.rear] = size;
+        self.bytes += size;
         self.rear = (self.rear + 1) % self.capacity;
 
-        if !displaced.is_some() {
+        if displaced.is_none() {
             self.size += 1;
         }
 
@@ -83,12 +103,48 @@ impl RingBuffer {
         }
 
         let msg = self.buffer[self.front].take();
+        self.bytes -= self.sizes[self.front];
         self.front = (self.front + 1) % self.capacity;
         self.size -= 1;
 
         msg
     }
 
+    /// Pop the oldest messages until the estimated size is at most `max_bytes`
+    /// Returns the popped messages, oldest first
+    pub fn evict_to_bytes(&mut self, max_bytes: usize) -> Vec<Rc<Message>> {
+        let mut evicted = Vec::new();
+        while self.bytes > max_bytes {
+            match self.pop() {
+                Some(msg) => evicted.push(msg),
+                None => break,
+            }
+        }
+        evicted
+    }
+
+    /// Re-estimate the size of every buffered message
+    pub fn measure(&mut self, mut size_of: impl FnMut(&Message) -> usize) {
+        self.bytes = 0;
+        for offset in 0..self.size {
+            let idx = (self.front + offset) % self.capacity;
+            let size = self.buffer[idx].as_ref().map_or(0, |msg| size_of(msg));
+            self.sizes[idx] = size;
+            self.bytes += size;
+        }
+    }
+
+    /// Pop messages from the front while they are older than `cutoff`
+    /// Stops at the first message that is not; returns the number popped
+    pub fn evict_older_than(&mut self, cutoff: f64) -> usize {
+        let mut evicted = 0;
+        while self.peek().is_some_and(|msg| msg.timestamp < cutoff) {
+            self.pop();
+            evicted += 1;
+        }
+        evicted
+    }
+
     /// Peek at the oldest message without removing it
     pub fn peek(&self) -> Option<&Rc<Message>> {
         if self.is_empty() {
@@ -115,11 +171,81 @@ impl RingBuffer {
         for item in self.buffer.iter_mut() {
             *item = None;
         }
+        self.sizes.fill(0);
+        self.bytes = 0;
         self.size = 0;
         self.front = 0;
         self.rear = 0;
     }
 
+    /// Change the capacity, keeping as many of the newest messages as fit
+    /// Returns the number of (oldest) messages dropped
+    pub fn resize(&mut self, new_capacity: usize) -> usize {
+        let mut entries = self.entries();
+        let dropped = entries.len().saturating_sub(new_capacity);
+        entries.drain(..dropped);
+
+        *self = RingBuffer::new(new_capacity);
+        for (msg, size) in entries {
+            self.push_sized(msg, size);
+        }
+
+        dropped
+    }
+
+    /// Keep only the messages for which `keep` returns true, preserving order
+    /// Returns the number of messages removed
+    pub fn retain(&mut self, mut keep: impl FnMut(&Rc<Message>) -> bool) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+
+        // Compact in place: kept messages slide towards the front
+        let mut kept = 0;
+        for offset in 0..self.size {
+            let idx = (self.front + offset) % self.capacity;
+            let size = std::mem::take(&mut self.sizes[idx]);
+            match self.buffer[idx].take() {
+                Some(msg) if keep(&msg) => {
+                    let dst = (self.front + kept) % self.capacity;
+                    self.buffer[dst] = Some(msg);
+                    self.sizes[dst] = size;
+                    kept += 1;
+                }
+                _ => self.bytes -= size,
+            }
+        }
+
+        let removed = self.size - kept;
+        self.size = kept;
+        self.rear = (self.front + kept) % self.capacity;
+        removed
+    }
+
+    /// Remove the message with the given ID, preserving the order of the rest
+    /// Returns true if a message was removed
+    pub fn remove_by_id(&mut self, id: u64) -> bool {
+        let Some(position) = self.iter().position(|msg| msg.id == id) else {
+            return false;
+        };
+
+        // Shift the newer messages back by one slot
+        let mut idx = (self.front + position) % self.capacity;
+        self.bytes -= self.sizes[idx];
+        for _ in position + 1..self.size {
+            let next = (idx + 1) % self.capacity;
+            self.buffer[idx] = self.buffer[next].take();
+            self.sizes[idx] = self.sizes[next];
+            idx = next;
+        }
+        self.buffer[idx] = None;
+        self.sizes[idx] = 0;
+
+        self.size -= 1;
+        self.rear = idx;
+        true
+    }
+
     /// Get all messages as a vector (oldest first)
     pub fn to_vec(&self) -> Vec<Rc<Message>> {
         let mut result = Vec::with_capacity(self.size);
@@ -135,6 +261,21 @@ impl RingBuffer {
         result
     }
 
+    /// All messages with their estimated sizes (oldest first)
+    fn entries(&self) -> Vec<(Rc<Message>, usize)> {
+        (0..self.size)
+            .map(|offset| (self.front + offset) % self.capacity)
+            .filter_map(|idx| self.buffer[idx].clone().map(|msg| (msg, self.sizes[idx])))
+            .collect()
+    }
+
+    /// Iterate over the messages timestamped within `from..=to`, oldest first
+    /// Messages from other tabs can be out of timestamp order, so the whole
+    /// buffer is scanned rather than stopping at the first later message.
+    pub fn iter_range(&self, from: f64, to: f64) -> impl Iterator<Item = &Rc<Message>> {
+        self.iter().filter(move |msg| from <= msg.timestamp && msg.timestamp <= to)
+    }
+
     /// Iterate over all messages from oldest to newest
     pub fn iter(&self) -> RingBufferIter<'_> {
         RingBufferIter {
@@ -172,6 +313,21 @@ impl<'a> Iterator for RingBufferIter<'a> {
     }
 }
 
+// JsValue is only a stub on non-wasm targets, so Debug output shows message IDs only
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for RingBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ids: Vec<u64> = self.iter().map(|msg| msg.id).collect();
+        f.debug_struct("RingBuffer")
+            .field("capacity", &self.capacity)
+            .field("size", &self.size)
+            .field("front", &self.front)
+            .field("rear", &self.rear)
+            .field("ids", &ids)
+            .finish()
+    }
+}
+
 impl Default for RingBuffer {
     fn default() -> Self {
         Self::new(100) // Default capacity of 100 messages
@@ -181,6 +337,19 @@ impl Default for RingBuffer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::test_support;
+
+    fn msg(id: u64) -> Rc<Message> {
+        msg_at(id, id as f64)
+    }
+
+    fn msg_at(id: u64, timestamp: f64) -> Rc<Message> {
+        test_support::msg_at(id, 0, timestamp)
+    }
+
+    fn ids(rb: &RingBuffer) -> Vec<u64> {
+        rb.iter().map(|m| m.id).collect()
+    }
 
     #[test]
     fn test_ring_buffer_new() {
@@ -193,206 +362,340 @@ mod tests {
 
     #[test]
     fn test_ring_buffer_push_pop() {
-        // Skip this test on non-WASM targets since JsValue requires WASM
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // Test skipped on non-WASM targets
-        }
+        let mut rb = RingBuffer::new(3);
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsValue;
-
-            let mut rb = RingBuffer::new(3);
-
-            let msg1 = Message {
-                id: "1".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::from_str("data1"),
-                timestamp: 1.0,
-                origin_id: Rc::new("client1".to_string()),
-            };
-
-            let msg2 = Message {
-                id: "2".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::from_str("data2"),
-                timestamp: 2.0,
-                origin_id: Rc::new("client1".to_string()),
-            };
-
-            // Push two messages
-            assert!(rb.push(Rc::new(msg1.clone())).is_none());
-            assert!(rb.push(Rc::new(msg2.clone())).is_none());
-
-            assert_eq!(rb.len(), 2);
-
-            // Pop one message
-            let popped = rb.pop().unwrap();
-            assert_eq!(popped.id, "1");
-            assert_eq!(rb.len(), 1);
-
-            // Peek at the next message
-            let peeked = rb.peek().unwrap();
-            assert_eq!(peeked.id, "2");
-        }
+        // Push two messages
+        assert!(rb.push(msg(1)).is_none());
+        assert!(rb.push(msg(2)).is_none());
+
+        assert_eq!(rb.len(), 2);
+
+        // Pop one message
+        let popped = rb.pop().unwrap();
+        assert_eq!(popped.id, 1);
+        assert_eq!(rb.len(), 1);
+
+        // Peek at the next message
+        let peeked = rb.peek().unwrap();
+        assert_eq!(peeked.id, 2);
     }
 
     #[test]
     fn test_ring_buffer_overflow() {
-        // Skip this test on non-WASM targets since JsValue requires WASM
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // Test skipped on non-WASM targets
-        }
+        let mut rb = RingBuffer::new(2);
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsValue;
-
-            let mut rb = RingBuffer::new(2);
-
-            let msg1 = Message {
-                id: "1".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 1.0,
-                origin_id: Rc::new("client1".to_string()),
-            };
-
-            let msg2 = Message {
-                id: "2".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 2.0,
-                origin_id: Rc::new("client1".to_string()),
-            };
-
-            let msg3 = Message {
-                id: "3".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 3.0,
-                origin_id: Rc::new("client1".to_string()),
-            };
-
-            rb.push(Rc::new(msg1));
-            rb.push(Rc::new(msg2));
-
-            // This should overflow and return msg1
-            let displaced = rb.push(Rc::new(msg3));
-            assert!(displaced.is_some());
-            assert_eq!(displaced.unwrap().id, "1");
-
-            assert_eq!(rb.len(), 2);
-            assert_eq!(rb.peek().unwrap().id, "2");
-            assert_eq!(rb.peek_back().unwrap().id, "3");
-        }
+        rb.push(msg(1));
+        rb.push(msg(2));
+
+        // This should overflow and return msg1
+        let displaced = rb.push(msg(3));
+        assert!(displaced.is_some());
+        assert_eq!(displaced.unwrap().id, 1);
+
+        assert_eq!(rb.len(), 2);
+        assert_eq!(rb.peek().unwrap().id, 2);
+        assert_eq!(rb.peek_back().unwrap().id, 3);
     }
 
     #[test]
     fn test_ring_buffer_peek() {
-        // Skip this test on non-WASM targets since JsValue requires WASM
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // Test skipped on non-WASM targets
-        }
+        let mut rb = RingBuffer::new(3);
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsValue;
-
-            let mut rb = RingBuffer::new(3);
-
-            let msg1 = Message {
-                id: "1".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 1.0,
-                origin_id: "client1".to_string(),
-            };
-
-            let msg2 = Message {
-                id: "2".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 2.0,
-                origin_id: "client1".to_string(),
-            };
-
-            rb.push(msg1);
-            rb.push(msg2);
-
-            // Peek front (oldest)
-            let front = rb.peek().unwrap();
-            assert_eq!(front.id, "1");
-
-            // Peek back (newest)
-            let back = rb.peek_back().unwrap();
-            assert_eq!(back.id, "2");
-        }
+        rb.push(msg(1));
+        rb.push(msg(2));
+
+        // Peek front (oldest)
+        let front = rb.peek().unwrap();
+        assert_eq!(front.id, 1);
+
+        // Peek back (newest)
+        let back = rb.peek_back().unwrap();
+        assert_eq!(back.id, 2);
     }
 
     #[test]
     fn test_ring_buffer_clear() {
-        // Skip this test on non-WASM targets since JsValue requires WASM
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // Test skipped on non-WASM targets
+        let mut rb = RingBuffer::new(3);
+
+        rb.push(msg(1));
+        assert_eq!(rb.len(), 1);
+
+        rb.clear();
+        assert_eq!(rb.len(), 0);
+        assert!(rb.is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer_to_vec() {
+        let mut rb = RingBuffer::new(3);
+
+        for i in 1..=3 {
+            rb.push(msg(i));
         }
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsValue;
+        let vec = rb.to_vec();
+        assert_eq!(vec.len(), 3);
+        assert_eq!(vec[0].id, 1);
+        assert_eq!(vec[1].id, 2);
+        assert_eq!(vec[2].id, 3);
+        assert_eq!(ids(&rb), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ring_buffer_resize() {
+        let mut rb = RingBuffer::new(3);
+        for i in 1..=4 {
+            rb.push(msg(i));
+        }
 
-            let mut rb = RingBuffer::new(3);
+        // Growing keeps every message in order
+        assert_eq!(rb.resize(5), 0);
+        assert_eq!(rb.capacity(), 5);
+        assert_eq!(ids(&rb), vec![2, 3, 4]);
 
-            let msg = Message {
-                id: "1".to_string(),
-                topic: "test".to_string(),
-                payload: JsValue::UNDEFINED,
-                timestamp: 1.0,
-                origin_id: "client1".to_string(),
-            };
+        rb.push(msg(5));
+        rb.push(msg(6));
+        assert!(rb.is_full());
 
-            rb.push(msg);
-            assert_eq!(rb.len(), 1);
+        // Shrinking keeps the newest messages
+        assert_eq!(rb.resize(2), 3);
+        assert_eq!(rb.capacity(), 2);
+        assert_eq!(ids(&rb), vec![5, 6]);
+        assert_eq!(rb.push(msg(7)).unwrap().id, 5);
+    }
 
-            rb.clear();
-            assert_eq!(rb.len(), 0);
-            assert!(rb.is_empty());
+    #[test]
+    fn test_ring_buffer_resize_wrapped() {
+        // Front and rear both wrapped past the start of the storage
+        let mut rb = RingBuffer::new(4);
+        for i in 1..=6 {
+            rb.push(msg(i));
+        }
+        rb.pop();
+        assert_eq!(rb.front, 3);
+        assert_eq!(ids(&rb), vec![4, 5, 6]);
+
+        assert_eq!(rb.resize(6), 0);
+        assert_eq!(ids(&rb), vec![4, 5, 6]);
+        for i in 7..=9 {
+            assert!(rb.push(msg(i)).is_none());
+        }
+        assert_eq!(rb.push(msg(10)).unwrap().id, 4);
+        assert_eq!(ids(&rb), vec![5, 6, 7, 8, 9, 10]);
+
+        // Shrinking a wrapped buffer drops from the front
+        rb.pop();
+        assert!(rb.front > 0);
+        assert_eq!(rb.resize(2), 3);
+        assert_eq!(ids(&rb), vec![9, 10]);
+        assert_eq!(rb.pop().unwrap().id, 9);
+        assert_eq!(rb.pop().unwrap().id, 10);
+        assert!(rb.pop().is_none());
+
+        // Resizing to the same capacity keeps everything
+        let mut rb = RingBuffer::new(3);
+        for i in 1..=5 {
+            rb.push(msg(i));
         }
+        assert_eq!(rb.resize(3), 0);
+        assert_eq!(ids(&rb), vec![3, 4, 5]);
     }
 
     #[test]
-    fn test_ring_buffer_to_vec() {
-        // Skip this test on non-WASM targets since JsValue requires WASM
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            // Test skipped on non-WASM targets
+    fn test_ring_buffer_retain() {
+        let mut rb = RingBuffer::new(4);
+        for i in 1..=6 {
+            rb.push(msg(i));
         }
 
-        #[cfg(target_arch = "wasm32")]
-        {
-            use wasm_bindgen::JsValue;
+        assert_eq!(rb.retain(|m| m.id % 2 == 0), 2);
+        assert_eq!(ids(&rb), vec![4, 6]);
+        assert_eq!(rb.capacity(), 4);
+
+        // Freed slots are reusable without displacing kept messages
+        assert!(rb.push(msg(7)).is_none());
+        assert!(rb.push(msg(8)).is_none());
+        assert_eq!(ids(&rb), vec![4, 6, 7, 8]);
+        assert_eq!(rb.retain(|_| true), 0);
+    }
+
+    #[test]
+    fn test_ring_buffer_retain_wrapped() {
+        let mut rb = RingBuffer::new(4);
+        for i in 1..=7 {
+            rb.push_sized(msg(i), i as usize);
+        }
+        // Storage is [5, 6, 7, 4] with the front at the last slot
+        assert_eq!(rb.front, 3);
+        assert_eq!(rb.bytes(), 4 + 5 + 6 + 7);
+
+        assert_eq!(rb.retain(|m| m.id != 4 && m.id != 6), 2);
+        assert_eq!(ids(&rb), vec![5, 7]);
+        assert_eq!(rb.len(), 2);
+        assert_eq!(rb.bytes(), 5 + 7);
+        assert_eq!((rb.front, rb.rear), (3, 1));
+
+        for i in 8..=9 {
+            assert!(rb.push(msg(i)).is_none());
+        }
+        assert_eq!(rb.push(msg(10)).unwrap().id, 5);
+        assert_eq!(ids(&rb), vec![7, 8, 9, 10]);
 
-            let mut rb = RingBuffer::new(3);
+        assert_eq!(rb.retain(|_| false), 4);
+        assert!(rb.is_empty());
+        assert_eq!(rb.bytes(), 0);
+        assert!(rb.push(msg(11)).is_none());
+        assert_eq!(ids(&rb), vec![11]);
+    }
 
-            for i in 1..=3 {
-                rb.push(Message {
-                    id: i.to_string(),
-                    topic: "test".to_string(),
-                    payload: JsValue::UNDEFINED,
-                    timestamp: i as f64,
-                    origin_id: "client1".to_string(),
-                });
+    #[test]
+    fn test_ring_buffer_remove_by_id() {
+        let filled = || {
+            let mut rb = RingBuffer::new(5);
+            for i in 1..=4 {
+                rb.push_sized(msg(i), 10);
             }
+            rb
+        };
+
+        // Front
+        let mut rb = filled();
+        assert!(rb.remove_by_id(1));
+        assert_eq!(ids(&rb), vec![2, 3, 4]);
+        assert_eq!(rb.peek().unwrap().id, 2);
+
+        // Rear
+        let mut rb = filled();
+        assert!(rb.remove_by_id(4));
+        assert_eq!(ids(&rb), vec![1, 2, 3]);
+        assert_eq!(rb.peek_back().unwrap().id, 3);
+
+        // Middle
+        let mut rb = filled();
+        assert!(rb.remove_by_id(2));
+        assert_eq!(ids(&rb), vec![1, 3, 4]);
+        assert_eq!(rb.bytes(), 30);
+        assert!(!rb.remove_by_id(2));
+        assert!(!rb.remove_by_id(99));
+
+        // Across the wrap point: storage is [6, 7, 3, 4, 5] with the front at 2
+        let mut rb = RingBuffer::new(5);
+        for i in 1..=7 {
+            rb.push(msg(i));
+        }
+        assert_eq!(rb.front, 2);
+        assert!(rb.remove_by_id(4));
+        assert_eq!(ids(&rb), vec![3, 5, 6, 7]);
+        assert_eq!(rb.peek_back().unwrap().id, 7);
+        assert!(rb.push(msg(8)).is_none());
+        assert_eq!(rb.push(msg(9)).unwrap().id, 3);
+        assert_eq!(ids(&rb), vec![5, 6, 7, 8, 9]);
+
+        // Down to empty
+        let mut rb = RingBuffer::new(1);
+        rb.push(msg(1));
+        assert!(rb.remove_by_id(1));
+        assert!(rb.is_empty());
+        assert!(rb.peek().is_none());
+        assert!(rb.push(msg(2)).is_none());
+        assert_eq!(ids(&rb), vec![2]);
+    }
+
+    #[test]
+    fn test_ring_buffer_evict_older_than() {
+        let mut rb = RingBuffer::new(3);
+        for i in 1..=4 {
+            rb.push(msg(i));
+        }
+
+        assert_eq!(rb.evict_older_than(2.0), 0);
+        assert_eq!(rb.evict_older_than(4.0), 2);
+        assert_eq!(ids(&rb), vec![4]);
+
+        // Wrapped slots are reused after eviction
+        rb.push(msg(5));
+        rb.push(msg(6));
+        assert!(rb.is_full());
+        assert_eq!(rb.evict_older_than(10.0), 3);
+        assert!(rb.is_empty());
+        assert!(rb.push(msg(7)).is_none());
+    }
+
+    #[test]
+    fn test_ring_buffer_bytes() {
+        let mut rb = RingBuffer::new(3);
+        for i in 1..=3 {
+            rb.push_sized(msg(i), i as usize * 10);
+        }
+        assert_eq!(rb.bytes(), 60);
+
+        // Displaced, popped and filtered messages give their bytes back
+        assert_eq!(rb.push_sized(msg(4), 5).unwrap().id, 1);
+        assert_eq!(rb.bytes(), 55);
+        assert_eq!(rb.retain(|m| m.id != 3), 1);
+        assert_eq!(rb.bytes(), 25);
+        assert_eq!(rb.resize(1), 1);
+        assert_eq!(rb.bytes(), 5);
+
+        // Eviction pops from the front until the budget is met
+        rb.resize(4);
+        rb.push_sized(msg(5), 50);
+        rb.push_sized(msg(6), 10);
+        let evicted: Vec<u64> = rb.evict_to_bytes(20).iter().map(|m| m.id).collect();
+        assert_eq!(evicted, vec![4, 5]);
+        assert_eq!(ids(&rb), vec![6]);
+        assert_eq!(rb.bytes(), 10);
+
+        rb.measure(|m| m.id as usize);
+        assert_eq!(rb.bytes(), 6);
+        rb.clear();
+        assert_eq!(rb.bytes(), 0);
+    }
+
+    #[test]
+    fn test_ring_buffer_iter_range() {
+        let mut rb = RingBuffer::new(4);
+        for i in 1..=3 {
+            rb.push(msg(i));
+        }
+        let range = |rb: &RingBuffer, from, to| rb.iter_range(from, to).map(|m| m.id).collect::<Vec<_>>();
+
+        // Both ends are inclusive
+        assert_eq!(range(&rb, 2.0, 3.0), vec![2, 3]);
+        assert_eq!(range(&rb, 1.5, 2.5), vec![2]);
+        assert!(range(&rb, 4.0, 9.0).is_empty());
+        assert!(range(&rb, 3.0, 1.0).is_empty());
 
-            let vec = rb.to_vec();
-            assert_eq!(vec.len(), 3);
-            assert_eq!(vec[0].id, "1");
-            assert_eq!(vec[1].id, "2");
-            assert_eq!(vec[2].id, "3");
+        // After wrapping around, the range still runs oldest first
+        for i in 4..=6 {
+            rb.push(msg(i));
         }
+        assert_eq!(ids(&rb), vec![3, 4, 5, 6]);
+        assert_eq!(range(&rb, 0.0, 100.0), vec![3, 4, 5, 6]);
+        assert_eq!(range(&rb, 4.0, 5.0), vec![4, 5]);
+        assert_eq!(range(&rb, 6.0, 6.0), vec![6]);
+
+        // A late message with an older timestamp is still found
+        rb.push(msg_at(7, 3.5));
+        assert_eq!(range(&rb, 3.0, 3.9), vec![7]);
+        assert_eq!(range(&rb, 3.0, 4.5), vec![4, 7]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_ring_buffer_debug() {
+        let mut rb = RingBuffer::new(2);
+        for i in 1..=3 {
+            rb.push(msg(i));
+        }
+
+        assert_eq!(
+            format!("{:?}", rb),
+            "RingBuffer { capacity: 2, size: 2, front: 1, rear: 1, ids: [2, 3] }"
+        );
+        assert_eq!(
+            format!("{:?}", rb.peek().unwrap()),
+            "Message { id: 2, topic_id: 0, timestamp: 2.0 }"
+        );
     }
 }
@@ -0,0 +1,246 @@
+use wasm_bindgen::prelude::*;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use js_sys::{Array, Function, Promise};
+
+use crate::types::{InnerQueue, PendingRequest};
+use crate::constants::{ERR_RPC_TIMEOUT, PACKET_RPC_REPLY, PACKET_RPC_REQUEST};
+use crate::js_utils::settle_with;
+use crate::utils::{clear_timeout, set_timeout};
+
+/// RPC work found while handling a BroadcastChannel packet
+/// Run with `run_task` once the queue borrow has been released.
+pub(crate) enum RpcTask {
+    /// Invoke a local responder for a request from another tab
+    Respond { handler: Function, payload: JsValue, correlation_id: u64, requester_id: String },
+    /// Settle one of this client's pending requests
+    Settle { correlation_id: u64, ok: bool, value: JsValue, responder_id: Option<String> },
+}
+
+/// Send a request on a topic and return a Promise for the reply
+/// A responder in this tab answers directly; otherwise the request is broadcast
+/// and the first reply from another tab settles the Promise.
+pub(crate) fn request(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue, timeout_ms: f64) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        if let Err(err) = start_request(inner, topic_id, &payload, timeout_ms, resolve, reject.clone()) {
+            let _ = reject.call1(&JsValue::NULL, &err);
+        }
+    })
+}
+
+fn start_request(
+    inner: &Rc<RefCell<InnerQueue>>,
+    topic_id: u32,
+    payload: &JsValue,
+    timeout_ms: f64,
+    resolve: Function,
+    reject: Function,
+) -> Result<(), JsValue> {
+    let (correlation_id, handler) = {
+        let mut queue = inner.borrow_mut();
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        let handler = topic.responder.clone();
+        let topic_name = topic.name.clone();
+        if handler.is_none() && queue.transports.is_empty() {
+            return Err(JsValue::from_str("No responder for topic"));
+        }
+
+        let correlation_id = queue.next_message_id();
+        let timeout = schedule_timeout(Rc::downgrade(inner), correlation_id, timeout_ms)?;
+        let target = if handler.is_none() { queue.rpc_responders.get(&topic_name).cloned() } else { None };
+        queue.pending_requests.insert(correlation_id, PendingRequest {
+            resolve,
+            reject,
+            timeout,
+            topic_name: topic_name.clone(),
+            target,
+        });
+
+        if handler.is_none() {
+            // REQUEST: [7, topic_name, correlation_id, requester_id, payload]
+            let packet = Array::new();
+            packet.push(&JsValue::from(PACKET_RPC_REQUEST));
+            packet.push(&JsValue::from_str(&topic_name));
+            packet.push(&JsValue::from(correlation_id));
+            packet.push(&JsValue::from_str(&queue.client_id));
+            packet.push(payload);
+            if let Err(err) = queue.post_packet(&packet) {
+                if let Some(pending) = queue.pending_requests.remove(&correlation_id) {
+                    clear_timeout(pending.timeout);
+                }
+                return Err(err);
+            }
+        }
+        (correlation_id, handler)
+    };
+
+    if let Some(handler) = handler {
+        invoke_responder(inner, &handler, payload, correlation_id, None);
+    }
+    Ok(())
+}
+
+/// Reject a pending request with `ERR_RPC_TIMEOUT` after `timeout_ms`
+fn schedule_timeout(inner: Weak<RefCell<InnerQueue>>, correlation_id: u64, timeout_ms: f64) -> Result<i32, JsValue> {
+    let on_timeout = Closure::once_into_js(move || {
+        if let Some(inner) = inner.upgrade() {
+            settle(&inner, correlation_id, false, JsValue::from_str(ERR_RPC_TIMEOUT), None);
+        }
+    });
+    set_timeout(on_timeout.unchecked_ref(), timeout_ms)
+}
+
+/// Call a responder and send its (possibly asynchronous) result to the requester
+/// `requester_id` is None for requests made in this tab.
+fn invoke_responder(
+    inner: &Rc<RefCell<InnerQueue>>,
+    handler: &Function,
+    payload: &JsValue,
+    correlation_id: u64,
+    requester_id: Option<String>,
+) {
+    let weak = Rc::downgrade(inner);
+    let reply = move |ok: bool, value: JsValue| {
+        if let Some(inner) = weak.upgrade() {
+            send_reply(&inner, correlation_id, requester_id.as_deref(), ok, value);
+        }
+    };
+
+    match handler.call1(&JsValue::NULL, payload) {
+        Ok(result) => {
+            let callback = Closure::once_into_js(reply);
+            if let Err(err) = settle_with(&result, callback.unchecked_ref()) {
+                // Cannot happen for well-formed values, but never leave the requester hanging
+                let _ = callback.unchecked_ref::<Function>().call2(&JsValue::NULL, &JsValue::FALSE, &err);
+            }
+        }
+        Err(err) => reply(false, err),
+    }
+}
+
+fn send_reply(inner: &Rc<RefCell<InnerQueue>>, correlation_id: u64, requester_id: Option<&str>, ok: bool, value: JsValue) {
+    let Some(requester_id) = requester_id else {
+        settle(inner, correlation_id, ok, value, None);
+        return;
+    };
+
+    // REPLY: [8, correlation_id, requester_id, ok, value, responder_id]
+    let queue = inner.borrow();
+    let packet = Array::new();
+    packet.push(&JsValue::from(PACKET_RPC_REPLY));
+    packet.push(&JsValue::from(correlation_id));
+    packet.push(&JsValue::from_str(requester_id));
+    packet.push(&JsValue::from(ok));
+    packet.push(&value);
+    packet.push(&JsValue::from_str(&queue.client_id));
+    let _ = queue.post_packet(&packet);
+}
+
+/// Resolve or reject a pending request; later replies for it are ignored
+/// `responder_id` identifies the tab that answered, if it was another one.
+fn settle(inner: &Rc<RefCell<InnerQueue>>, correlation_id: u64, ok: bool, value: JsValue, responder_id: Option<String>) {
+    let pending = {
+        let mut queue = inner.borrow_mut();
+        let Some(pending) = queue.pending_requests.remove(&correlation_id) else {
+            return;
+        };
+        if let Some(responder_id) = responder_id {
+            queue.rpc_responders.insert(pending.topic_name.clone(), responder_id);
+        }
+        pending
+    };
+
+    clear_timeout(pending.timeout);
+    let settle = if ok { &pending.resolve } else { &pending.reject };
+    let _ = settle.call1(&JsValue::NULL, &value);
+}
+
+/// Reject requests that can no longer be answered, e.g. because the queue closed
+pub(crate) fn reject_pending(pending: impl IntoIterator<Item = PendingRequest>, reason: &str) {
+    let reason = JsValue::from_str(reason);
+    for request in pending {
+        clear_timeout(request.timeout);
+        let _ = request.reject.call1(&JsValue::NULL, &reason);
+    }
+}
+
+/// Forget a client that closed
+/// Returns the pending requests it was expected to answer, for `reject_pending`.
+pub(crate) fn forget_peer(queue: &mut InnerQueue, peer_id: &str) -> Vec<PendingRequest> {
+    queue.rpc_responders.retain(|_, responder_id| responder_id != peer_id);
+    let orphaned: Vec<u64> = queue.pending_requests.iter()
+        .filter(|(_, request)| request.target.as_deref() == Some(peer_id))
+        .map(|(&correlation_id, _)| correlation_id)
+        .collect();
+    orphaned.iter().filter_map(|correlation_id| queue.pending_requests.remove(correlation_id)).collect()
+}
+
+/// Parse a REQUEST packet from another tab
+/// Returns a task if this tab has a responder for the topic
+pub(crate) fn receive_request(queue: &InnerQueue, packet: &Array) -> Option<RpcTask> {
+    let topic_name = packet.get(1).as_string()?;
+    let correlation_id = u64::try_from(packet.get(2)).ok()?;
+    let requester_id = packet.get(3).as_string()?;
+    if requester_id == *queue.client_id {
+        return None;
+    }
+
+    let handler = queue.get_topic(&topic_name)?.responder.clone()?;
+    Some(RpcTask::Respond { handler, payload: packet.get(4), correlation_id, requester_id })
+}
+
+/// Parse a REPLY packet, keeping only replies to this client's requests
+pub(crate) fn receive_reply(queue: &InnerQueue, packet: &Array) -> Option<RpcTask> {
+    let correlation_id = u64::try_from(packet.get(1)).ok()?;
+    if packet.get(2).as_string()? != *queue.client_id {
+        return None;
+    }
+
+    Some(RpcTask::Settle {
+        correlation_id,
+        ok: packet.get(3).is_truthy(),
+        value: packet.get(4),
+        responder_id: packet.get(5).as_string(),
+    })
+}
+
+/// Run a task returned by `receive_request` / `receive_reply`
+/// Must be called without holding the queue borrow.
+pub(crate) fn run_task(inner: &Rc<RefCell<InnerQueue>>, task: RpcTask) {
+    match task {
+        RpcTask::Respond { handler, payload, correlation_id, requester_id } => {
+            invoke_responder(inner, &handler, &payload, correlation_id, Some(requester_id));
+        }
+        RpcTask::Settle { correlation_id, ok, value, responder_id } => settle(inner, correlation_id, ok, value, responder_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forget_closed_peer() {
+        let mut queue = InnerQueue::default();
+        let request = |target: Option<&str>| PendingRequest {
+            resolve: JsValue::UNDEFINED.unchecked_into(),
+            reject: JsValue::UNDEFINED.unchecked_into(),
+            timeout: 0,
+            topic_name: "rpc".to_string(),
+            target: target.map(str::to_string),
+        };
+        queue.pending_requests.insert(1, request(Some("tab-b")));
+        queue.pending_requests.insert(2, request(None));
+        queue.pending_requests.insert(3, request(Some("tab-c")));
+        queue.rpc_responders.insert("rpc".to_string(), "tab-b".to_string());
+
+        let orphaned = forget_peer(&mut queue, "tab-b");
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].target.as_deref(), Some("tab-b"));
+        assert!(queue.rpc_responders.is_empty());
+        let mut remaining: Vec<u64> = queue.pending_requests.keys().copied().collect();
+        remaining.sort();
+        assert_eq!(remaining, vec![2, 3]);
+    }
+}
@@ -0,0 +1,100 @@
+use wasm_bindgen::prelude::*;
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use js_sys::Function;
+
+use crate::types::{InnerQueue, ScheduledPublish};
+use crate::queue::publish_message;
+use crate::utils::{clear_interval, clear_timeout, set_interval, set_timeout};
+
+/// Publish `payload` once after `delay_ms`
+/// The message (ID, timestamp) is created when the timer fires.
+/// Returns a handle for `cancel`.
+pub(crate) fn publish_delayed(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload: JsValue, delay_ms: f64) -> Result<u32, JsValue> {
+    if !delay_ms.is_finite() || delay_ms < 0.0 {
+        return Err(JsValue::from_str("Delay must be a non-negative number"));
+    }
+
+    schedule(inner, topic_id, delay_ms, false, move |weak, handle| {
+        let Some(inner) = weak.upgrade() else { return };
+        // Keep the closure alive until this call returns
+        let _scheduled = inner.borrow_mut().scheduled_publishes.remove(&handle);
+        let _ = publish_message(&inner, topic_id, payload.clone());
+    })
+}
+
+/// Publish the result of calling `payload_factory` every `interval_ms`
+/// The interval stops if the topic is destroyed. Returns a handle for `cancel`.
+pub(crate) fn publish_interval(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, payload_factory: Function, interval_ms: f64) -> Result<u32, JsValue> {
+    if !interval_ms.is_finite() || interval_ms <= 0.0 {
+        return Err(JsValue::from_str("Interval must be a positive number"));
+    }
+
+    schedule(inner, topic_id, interval_ms, true, move |weak, handle| {
+        let Some(inner) = weak.upgrade() else { return };
+        if !inner.borrow().has_topic_id(topic_id) {
+            cancel(&inner, handle);
+            return;
+        }
+        // A throwing factory skips this tick only
+        if let Ok(payload) = payload_factory.call0(&JsValue::NULL) {
+            let _ = publish_message(&inner, topic_id, payload);
+        }
+    })
+}
+
+/// Register a timer whose callback receives the queue and its own handle
+/// The callback holds the queue weakly, so pending timers never keep it alive.
+fn schedule(
+    inner: &Rc<RefCell<InnerQueue>>,
+    topic_id: u32,
+    ms: f64,
+    repeating: bool,
+    mut on_fire: impl FnMut(&Weak<RefCell<InnerQueue>>, u32) + 'static,
+) -> Result<u32, JsValue> {
+    let mut queue = inner.borrow_mut();
+    if !queue.has_topic_id(topic_id) {
+        return Err(JsValue::from_str("Invalid topic ID"));
+    }
+
+    let handle = queue.next_schedule_id;
+    let weak = Rc::downgrade(inner);
+    let callback = Closure::<dyn FnMut()>::new(move || on_fire(&weak, handle));
+    let timer = if repeating {
+        set_interval(callback.as_ref().unchecked_ref(), ms)?
+    } else {
+        set_timeout(callback.as_ref().unchecked_ref(), ms)?
+    };
+
+    queue.next_schedule_id = queue.next_schedule_id.wrapping_add(1);
+    queue.scheduled_publishes.insert(handle, ScheduledPublish { timer, repeating, callback });
+    Ok(handle)
+}
+
+/// Cancel a scheduled publish before it fires
+/// Returns false if the handle is unknown or the publish already fired.
+pub(crate) fn cancel(inner: &Rc<RefCell<InnerQueue>>, handle: u32) -> bool {
+    let scheduled = inner.borrow_mut().scheduled_publishes.remove(&handle);
+    match scheduled {
+        Some(scheduled) => {
+            stop_timer(&scheduled);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Cancel every scheduled publish, e.g. because the queue closed
+pub(crate) fn cancel_all(scheduled: impl IntoIterator<Item = ScheduledPublish>) {
+    for scheduled in scheduled {
+        stop_timer(&scheduled);
+    }
+}
+
+fn stop_timer(scheduled: &ScheduledPublish) {
+    if scheduled.repeating {
+        clear_interval(scheduled.timer);
+    } else {
+        clear_timeout(scheduled.timer);
+    }
+}
@@ -0,0 +1,326 @@
+use wasm_bindgen::prelude::*;
+use js_sys::{Array, Function, Reflect};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::constants::ERR_QUEUE_CLOSED;
+use crate::dispatch::notify_topic_events;
+use crate::js_utils;
+use crate::queue::{
+    buffer_size, buffered_messages_to_js, clear_buffer, destroy_topic, disable_topic_buffer,
+    enable_buffer_with, publish_message, subscribe_callback, subscribe_filtered, subscribe_once,
+};
+use crate::types::OverflowPolicy;
+use crate::types::InnerQueue;
+
+/// A handle to a `MessageQueue` that only sees topics under a name prefix
+/// Created with `MessageQueue::scope`. Scopes share the queue's state and
+/// channel; topic names carry the full prefixed name, also on the wire, so
+/// other tabs using the same scope see the same topics.
+#[wasm_bindgen]
+pub struct ScopedQueue {
+    /// Shared queue state
+    inner: Rc<RefCell<InnerQueue>>,
+    /// Prefix of every topic name in this scope, used verbatim (e.g. "editor/")
+    prefix: String,
+    /// Set by `close`; the parent queue stays open
+    closed: Cell<bool>,
+}
+
+impl ScopedQueue {
+    pub(crate) fn new(inner: Rc<RefCell<InnerQueue>>, prefix: &str) -> Self {
+        ScopedQueue {
+            inner,
+            prefix: prefix.to_string(),
+            closed: Cell::new(false),
+        }
+    }
+
+    /// Fail if this scope or its queue is closed
+    fn check_open(&self, queue: &InnerQueue) -> Result<(), JsValue> {
+        if self.closed.get() {
+            return Err(JsValue::from_str(ERR_QUEUE_CLOSED));
+        }
+        queue.check_open()
+    }
+
+    /// Full name of a topic local to this scope, normalized as the queue is configured
+    fn full_name(&self, queue: &InnerQueue, local_name: &str) -> Result<String, JsValue> {
+        let local_name = queue.normalize_topic_name(local_name);
+        if local_name.is_empty() {
+            return Err(JsValue::from_str("Topic name must not be empty"));
+        }
+        Ok(format!("{}{}", self.prefix, local_name))
+    }
+
+    /// Check whether a topic name lies inside this scope
+    fn owns(&self, name: &str) -> bool {
+        name.starts_with(self.prefix.as_str())
+    }
+
+    /// Ensure a topic ID refers to a topic inside this scope
+    fn check_owned(&self, topic_id: u32) -> Result<(), JsValue> {
+        let queue = self.inner.borrow();
+        self.check_open(&queue)?;
+        let topic = queue.get_topic_by_id(topic_id)
+            .ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        if self.owns(&topic.name) {
+            Ok(())
+        } else {
+            Err(JsValue::from_str("Topic does not belong to this scope"))
+        }
+    }
+
+    /// `list_topics` entry of a topic, named without the prefix
+    fn summary(&self, name: &str, summary: JsValue) -> Result<JsValue, JsValue> {
+        Reflect::set(&summary, &"name".into(), &name[self.prefix.len()..].into())?;
+        Ok(summary)
+    }
+}
+
+#[wasm_bindgen]
+impl ScopedQueue {
+    /// Get the prefix of this scope's topic names
+    #[wasm_bindgen(getter)]
+    pub fn prefix(&self) -> String {
+        self.prefix.clone()
+    }
+
+    /// Register a topic in this scope and get its ID
+    /// The name is checked and normalized as with `MessageQueue::register_topic`;
+    /// the length limit applies to the prefixed name.
+    /// @param topic_name - Name of the topic, without the prefix
+    pub fn register_topic(&self, topic_name: &str) -> Result<u32, JsValue> {
+        let topic_id = {
+            let mut queue = self.inner.borrow_mut();
+            self.check_open(&queue)?;
+            let full_name = self.full_name(&queue, topic_name)?;
+            queue.get_or_create_valid_topic_id(&full_name)?
+        };
+        notify_topic_events(&self.inner);
+        Ok(topic_id)
+    }
+
+    /// Create a topic in this scope
+    /// @param topic_name - Name of the topic, without the prefix
+    /// @returns false if the topic already exists
+    pub fn create_topic(&self, topic_name: &str) -> Result<bool, JsValue> {
+        let created = {
+            let mut queue = self.inner.borrow_mut();
+            self.check_open(&queue)?;
+            let full_name = self.full_name(&queue, topic_name)?;
+            if queue.topic_index.contains_key(&full_name) {
+                false
+            } else {
+                queue.get_or_create_valid_topic_id(&full_name)?;
+                true
+            }
+        };
+        notify_topic_events(&self.inner);
+        Ok(created)
+    }
+
+    /// Look up the ID of an existing topic of this scope
+    /// @param topic_name - Name of the topic, without the prefix
+    /// @returns The topic ID, or undefined
+    pub fn get_topic_id(&self, topic_name: &str) -> Option<u32> {
+        let queue = self.inner.borrow();
+        let full_name = self.full_name(&queue, topic_name).ok()?;
+        queue.topic_id_by_name(&full_name)
+    }
+
+    /// Get the name of a topic of this scope, without the prefix
+    /// @returns The name, or undefined for IDs outside this scope
+    pub fn get_topic_name(&self, topic_id: u32) -> Option<String> {
+        let queue = self.inner.borrow();
+        let topic = queue.get_topic_by_id(topic_id).filter(|topic| self.owns(&topic.name))?;
+        Some(topic.name[self.prefix.len()..].to_string())
+    }
+
+    /// Publish to a topic of this scope
+    pub fn publish(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        self.check_owned(topic_id)?;
+        publish_message(&self.inner, topic_id, payload)
+    }
+
+    /// Publish to a topic of this scope by name
+    /// Unknown topics are created unless the queue has `set_auto_create_topics(false)`.
+    /// @param topic_name - Name of the topic, without the prefix
+    /// @param payload - Message payload
+    pub fn publish_by_name(&self, topic_name: &str, payload: JsValue) -> Result<(), JsValue> {
+        let topic_id = {
+            let mut queue = self.inner.borrow_mut();
+            self.check_open(&queue)?;
+            let full_name = self.full_name(&queue, topic_name)?;
+            match queue.topic_id_by_name(&full_name) {
+                Some(topic_id) => topic_id,
+                None if queue.auto_create_topics => queue.get_or_create_valid_topic_id(&full_name)?,
+                None => return Err(JsValue::from_str(&format!("Unknown topic: {}", topic_name))),
+            }
+        };
+        notify_topic_events(&self.inner);
+        publish_message(&self.inner, topic_id, payload)
+    }
+
+    /// Subscribe to a topic of this scope
+    /// Callback signature: (payload, topic_id, timestamp, message_id, headers)
+    /// @returns Subscriber ID
+    pub fn subscribe(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        self.check_owned(topic_id)?;
+        subscribe_callback(&self.inner, topic_id, callback)
+    }
+
+    /// Subscribe to a topic of this scope for a single message
+    /// @returns Subscriber ID, usable with `unsubscribe` before delivery
+    pub fn subscribe_once(&self, topic_id: u32, callback: Function) -> Result<u32, JsValue> {
+        self.check_owned(topic_id)?;
+        subscribe_once(&self.inner, topic_id, callback)
+    }
+
+    /// Subscribe to a topic of this scope with a predicate, as `MessageQueue::subscribe_filtered` does
+    /// @param filter - Function called with (payload)
+    /// @returns Subscriber ID
+    pub fn subscribe_filtered(&self, topic_id: u32, filter: Function, callback: Function) -> Result<u32, JsValue> {
+        self.check_owned(topic_id)?;
+        subscribe_filtered(&self.inner, topic_id, filter, callback)
+    }
+
+    /// Unsubscribe from a topic of this scope
+    pub fn unsubscribe(&self, topic_id: u32, sub_id: u32) -> bool {
+        if self.check_owned(topic_id).is_err() {
+            return false;
+        }
+        let mut queue = self.inner.borrow_mut();
+        queue.get_topic_by_id_mut(topic_id)
+            .is_some_and(|topic| topic.subscribers.remove(sub_id).is_some())
+    }
+
+    /// Enable message buffering for a topic of this scope
+    /// The buffer overwrites its oldest message when full.
+    /// @param capacity - Maximum number of messages to buffer (default: 100)
+    pub fn enable_topic_buffer(&self, topic_id: u32, capacity: Option<usize>) -> Result<(), JsValue> {
+        self.check_owned(topic_id)?;
+        enable_buffer_with(&self.inner, topic_id, capacity, OverflowPolicy::DropOldest)
+    }
+
+    /// Disable message buffering for a topic of this scope, clearing its messages
+    pub fn disable_topic_buffer(&self, topic_id: u32) -> Result<(), JsValue> {
+        self.check_owned(topic_id)?;
+        disable_topic_buffer(&self.inner, topic_id)
+    }
+
+    /// Get the number of messages buffered for a topic of this scope
+    /// @returns The count, or -1 if buffering is not enabled or the topic is outside this scope
+    pub fn get_buffer_size(&self, topic_id: u32) -> i32 {
+        if self.check_owned(topic_id).is_err() {
+            return -1;
+        }
+        buffer_size(&self.inner, topic_id)
+    }
+
+    /// Clear the buffered messages of a topic of this scope
+    /// @returns Number of messages cleared
+    pub fn clear_buffer(&self, topic_id: u32) -> usize {
+        if self.check_owned(topic_id).is_err() {
+            return 0;
+        }
+        clear_buffer(&self.inner, topic_id)
+    }
+
+    /// Get the buffered messages of a topic of this scope, oldest first
+    /// Messages carry the full prefixed topic name, as they do on the wire.
+    /// @returns Array of messages, empty if the topic has no buffer
+    pub fn get_buffered_messages(&self, topic_id: u32) -> Result<Array, JsValue> {
+        self.check_owned(topic_id)?;
+        buffered_messages_to_js(&self.inner, topic_id)
+    }
+
+    /// Destroy a topic of this scope
+    /// @returns false if the ID does not refer to a live topic of this scope
+    pub fn destroy_topic(&self, topic_id: u32) -> bool {
+        self.check_owned(topic_id).is_ok() && destroy_topic(&self.inner, topic_id)
+    }
+
+    /// Describe the topics of this scope, as `MessageQueue::list_topics` does
+    /// Names are given without the prefix.
+    /// @returns Array of {id, name, subscribers, buffered, buffer_capacity, local_only, broadcast_only}
+    pub fn list_topics(&self) -> Result<Array, JsValue> {
+        let queue = self.inner.borrow();
+        queue.live_topics()
+            .filter(|(_, topic)| self.owns(&topic.name))
+            .map(|(id, topic)| self.summary(&topic.name, js_utils::topic_summary_to_js(id, topic)?))
+            .collect()
+    }
+
+    /// Get the `list_topics` entry of a single topic of this scope
+    /// @returns The entry, or undefined for IDs outside this scope
+    pub fn topic_info(&self, topic_id: u32) -> Result<JsValue, JsValue> {
+        let queue = self.inner.borrow();
+        match queue.get_topic_by_id(topic_id).filter(|topic| self.owns(&topic.name)) {
+            Some(topic) => self.summary(&topic.name, js_utils::topic_summary_to_js(topic_id, topic)?),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Destroy this scope's topics and stop using the scope
+    /// The queue and its other scopes are unaffected. Closing again does nothing.
+    pub fn close(&self) {
+        if self.closed.replace(true) {
+            return;
+        }
+        let ids: Vec<u32> = self.inner.borrow().live_topics()
+            .filter(|(_, topic)| self.owns(&topic.name))
+            .map(|(id, _)| id)
+            .collect();
+        for topic_id in ids {
+            destroy_topic(&self.inner, topic_id);
+        }
+    }
+
+    /// Check whether this scope or its queue has been closed
+    pub fn is_closed(&self) -> bool {
+        self.closed.get() || self.inner.borrow().closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_do_not_collide() {
+        let inner = Rc::new(RefCell::new(InnerQueue::default()));
+        let editor = ScopedQueue::new(inner.clone(), "editor/");
+        let chat = ScopedQueue::new(inner.clone(), "chat/");
+
+        let editor_id = editor.register_topic("events").unwrap();
+        let chat_id = chat.register_topic("events").unwrap();
+        assert_ne!(editor_id, chat_id);
+        assert!(inner.borrow().topic_index.contains_key("editor/events"));
+
+        assert_eq!(editor.get_topic_id("events"), Some(editor_id));
+        assert_eq!(editor.get_topic_name(editor_id).as_deref(), Some("events"));
+        assert_eq!(editor.get_topic_name(chat_id), None);
+        assert!(!chat.create_topic("events").unwrap());
+        assert!(chat.check_owned(chat_id).is_ok());
+    }
+
+    #[test]
+    fn test_scopes_manage_their_own_buffers() {
+        let inner = Rc::new(RefCell::new(InnerQueue::default()));
+        let editor = ScopedQueue::new(inner.clone(), "editor.");
+        let chat = ScopedQueue::new(inner.clone(), "chat.");
+        let editor_id = editor.register_topic("events").unwrap();
+        let chat_id = chat.register_topic("events").unwrap();
+
+        assert_eq!(editor.get_buffer_size(editor_id), -1);
+        assert!(editor.enable_topic_buffer(editor_id, Some(2)).is_ok());
+        assert_eq!(editor.get_buffer_size(editor_id), 0);
+        assert_eq!(inner.borrow().get_topic_by_id(editor_id).unwrap().get_buffer().unwrap().capacity(), 2);
+        assert!(chat.enable_topic_buffer(chat_id, None).is_ok());
+        assert_eq!(inner.borrow().get_topic_by_id(chat_id).unwrap().get_buffer().unwrap().capacity(), 100);
+
+        assert!(editor.disable_topic_buffer(editor_id).is_ok());
+        assert_eq!(editor.get_buffer_size(editor_id), -1);
+    }
+}
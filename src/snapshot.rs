@@ -0,0 +1,118 @@
+//! Snapshots of topic buffers, for persisting history across page reloads
+//!
+//! `export_state` produces `{version: 1, topics: [{name, capacity, messages}]}`,
+//! where each message has the fields of `get_buffered_messages`. Payloads that
+//! cannot be structured-cloned are left out: their message carries
+//! `skipped: true` and no payload, and is not restored by `import_state`.
+
+use wasm_bindgen::prelude::*;
+use js_sys::{Array, Function, Object, Reflect};
+use std::rc::Rc;
+
+use crate::types::{InnerQueue, Message};
+use crate::js_utils::{message_to_js, parse_js_message};
+
+const STATE_VERSION: u32 = 1;
+
+/// The global `structuredClone`, if the environment has one
+fn structured_clone() -> Option<Function> {
+    Reflect::get(&js_sys::global(), &"structuredClone".into()).ok()
+        .and_then(|clone| clone.dyn_into().ok())
+}
+
+/// Snapshot the buffer of every buffered topic
+/// Without `structuredClone`, every payload is assumed to be cloneable.
+pub(crate) fn export(queue: &mut InnerQueue) -> Result<JsValue, JsValue> {
+    let clone = structured_clone();
+    let topics = Array::new();
+    for topic in queue.topics.iter_mut().filter(|topic| topic.alive && topic.has_buffer()) {
+        topic.purge_expired();
+        let Some(buffer) = topic.get_buffer() else {
+            continue;
+        };
+
+        let messages = Array::new();
+        for msg in buffer.iter() {
+            let record = message_to_js(msg, &topic.name)?;
+            let cloneable = clone.as_ref().is_none_or(|clone| clone.call1(&JsValue::NULL, &msg.payload).is_ok());
+            if !cloneable {
+                Reflect::delete_property(record.unchecked_ref::<Object>(), &"payload".into())?;
+                Reflect::set(&record, &"skipped".into(), &JsValue::TRUE)?;
+            }
+            messages.push(&record);
+        }
+
+        let entry = Object::new();
+        Reflect::set(&entry, &"name".into(), &topic.name.as_str().into())?;
+        Reflect::set(&entry, &"capacity".into(), &(buffer.capacity() as f64).into())?;
+        Reflect::set(&entry, &"messages".into(), &messages)?;
+        topics.push(&entry);
+    }
+
+    let state = Object::new();
+    Reflect::set(&state, &"version".into(), &STATE_VERSION.into())?;
+    Reflect::set(&state, &"topics".into(), &topics)?;
+    Ok(state.into())
+}
+
+/// Restore the buffers of a snapshot made by `export`
+/// Topics are created as needed and their buffers replaced. Topic names are
+/// normalized, and a name that fails validation rejects the whole snapshot. Restored message IDs
+/// are remembered as seen, so syncing with other tabs does not deliver them again.
+/// Returns the number of messages restored.
+pub(crate) fn import(queue: &mut InnerQueue, state: &JsValue) -> Result<u32, JsValue> {
+    let invalid = || JsValue::from_str("Invalid state");
+    if Reflect::get(state, &"version".into())?.as_f64() != Some(STATE_VERSION as f64) {
+        return Err(JsValue::from_str("Unsupported state version"));
+    }
+    let topics: Array = Reflect::get(state, &"topics".into())?.dyn_into().map_err(|_| invalid())?;
+
+    // Validate everything before touching any buffer
+    let mut restored_topics = Vec::new();
+    for entry in topics.iter() {
+        let name = Reflect::get(&entry, &"name".into())?.as_string().ok_or_else(invalid)?;
+        let name = queue.normalize_topic_name(&name).into_owned();
+        if queue.topic_id_by_name(&name).is_none() {
+            queue.validate_topic_name(&name)?;
+        }
+        let capacity = Reflect::get(&entry, &"capacity".into())?.as_f64()
+            .filter(|capacity| capacity.fract() == 0.0 && *capacity >= 1.0)
+            .ok_or_else(invalid)? as usize;
+        let records: Array = Reflect::get(&entry, &"messages".into())?.dyn_into().map_err(|_| invalid())?;
+
+        let mut messages = Vec::new();
+        for record in records.iter() {
+            if Reflect::get(&record, &"skipped".into())?.is_truthy() {
+                continue;
+            }
+            let (msg, _) = parse_js_message(&record)?;
+            if !msg.is_expired() {
+                messages.push(msg);
+            }
+        }
+        restored_topics.push((name, capacity, messages));
+    }
+
+    let mut restored = 0;
+    for (name, capacity, messages) in restored_topics {
+        let topic_id = queue.get_or_create_valid_topic_id(&name)?;
+        for msg in &messages {
+            queue.seen_ids.insert(msg.id);
+        }
+        let Some(topic) = queue.get_topic_by_id_mut(topic_id) else {
+            continue;
+        };
+        topic.enable_buffer(capacity);
+        if let Some(buffer) = topic.get_buffer_mut() {
+            for msg in messages {
+                buffer.push(Rc::new(Message { topic_id, ..msg }));
+            }
+            restored += buffer.len() as u32;
+        }
+        // Restored payloads are sized for the topic's byte budget
+        if topic.max_bytes.is_some() {
+            topic.measure_buffer();
+        }
+    }
+    Ok(restored)
+}
@@ -0,0 +1,133 @@
+//! localStorage transport for browsers without BroadcastChannel
+//!
+//! Packets are JSON-encoded and written to `wasm-ripple:<channel>:<counter>`.
+//! Other tabs of the same origin receive a `storage` event carrying the value;
+//! the writing tab itself never sees its own writes.
+
+use wasm_bindgen::prelude::*;
+use js_sys::{Function, Object, Reflect};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use web_sys::{Storage, StorageEvent, Window};
+
+thread_local! {
+    // Message IDs are BigInts and binary packets are Uint8Arrays, neither of which JSON supports
+    static ENCODE_PACKET: Function = Function::new_with_args(
+        "packet",
+        "return JSON.stringify(packet, function(key, value) { \
+            if (typeof value === 'bigint') return {__bigint: value.toString()}; \
+            if (value instanceof Uint8Array) return {__bytes: Array.from(value)}; \
+            return value; \
+        });"
+    );
+
+    static DECODE_PACKET: Function = Function::new_with_args(
+        "text",
+        "return JSON.parse(text, function(key, value) { \
+            if (value && typeof value === 'object') { \
+                if (typeof value.__bigint === 'string') return BigInt(value.__bigint); \
+                if (Array.isArray(value.__bytes)) return new Uint8Array(value.__bytes); \
+            } \
+            return value; \
+        });"
+    );
+}
+
+/// Packets exchanged through `storage` events
+pub struct StorageTransport {
+    window: Window,
+    storage: Storage,
+    /// `wasm-ripple:<channel>:`
+    prefix: String,
+    /// Counter appended to the prefix for each written packet
+    counter: Cell<u64>,
+    /// Handler called with `{data: packet}`, like a MessageEvent
+    handler: Rc<RefCell<Option<Function>>>,
+    listener: Closure<dyn FnMut(StorageEvent)>,
+}
+
+impl StorageTransport {
+    /// Start listening for packets written by other tabs on `channel_name`
+    pub fn new(channel_name: &str) -> Result<Self, JsValue> {
+        let window = web_sys::window().ok_or_else(|| JsValue::from_str("localStorage not available"))?;
+        let storage = window.local_storage()
+            .ok()
+            .flatten()
+            .ok_or_else(|| JsValue::from_str("localStorage not available"))?;
+        let prefix = format!("wasm-ripple:{}:", channel_name);
+        let handler: Rc<RefCell<Option<Function>>> = Rc::default();
+
+        let listener = {
+            let prefix = prefix.clone();
+            let storage = storage.clone();
+            let handler = handler.clone();
+            Closure::wrap(Box::new(move |event: StorageEvent| {
+                let Some(key) = event.key().filter(|key| key.starts_with(&prefix)) else {
+                    return;
+                };
+                // Removals fire events too, without a new value
+                let Some(text) = event.new_value() else {
+                    return;
+                };
+                let _ = storage.remove_item(&key);
+
+                let handler = handler.borrow().clone();
+                if let (Some(handler), Ok(packet)) = (handler, decode_packet(&text)) {
+                    let event = Object::new();
+                    if Reflect::set(&event, &"data".into(), &packet).is_ok() {
+                        let _ = handler.call1(&JsValue::NULL, &event);
+                    }
+                }
+            }) as Box<dyn FnMut(StorageEvent)>)
+        };
+        window.add_event_listener_with_callback("storage", listener.as_ref().unchecked_ref())?;
+
+        Ok(StorageTransport {
+            window,
+            storage,
+            prefix,
+            counter: Cell::new(0),
+            handler,
+            listener,
+        })
+    }
+
+    /// Write a packet for the other tabs
+    /// The key is removed again right away: the storage event has already been
+    /// queued with the value, and nothing piles up when no other tab listens.
+    pub fn post(&self, packet: &JsValue) -> Result<(), JsValue> {
+        let text = encode_packet(packet)?;
+        let counter = self.counter.get();
+        self.counter.set(counter.wrapping_add(1));
+        let key = format!("{}{}", self.prefix, counter);
+
+        self.storage.set_item(&key, &text)
+            .map_err(|_| JsValue::from_str("Failed to write to localStorage"))?;
+        let _ = self.storage.remove_item(&key);
+        Ok(())
+    }
+
+    /// Set the function receiving `{data: packet}` for every packet from another tab
+    pub fn set_onmessage(&self, handler: Option<&Function>) {
+        *self.handler.borrow_mut() = handler.cloned();
+    }
+
+    /// Stop listening for storage events
+    pub fn close(&self) {
+        self.set_onmessage(None);
+        let _ = self.window.remove_event_listener_with_callback("storage", self.listener.as_ref().unchecked_ref());
+    }
+}
+
+/// Serialize a packet to JSON
+fn encode_packet(packet: &JsValue) -> Result<String, JsValue> {
+    ENCODE_PACKET.with(|encode| encode.call1(&JsValue::NULL, packet))
+        .ok()
+        .and_then(|text| text.as_string())
+        .ok_or_else(|| JsValue::from_str("Payload is not JSON-serializable"))
+}
+
+/// Parse a packet written by `encode_packet`
+fn decode_packet(text: &str) -> Result<JsValue, JsValue> {
+    DECODE_PACKET.with(|decode| decode.call1(&JsValue::NULL, &JsValue::from_str(text)))
+}
@@ -0,0 +1,385 @@
+//! Consuming a topic as a stream: an async iterator for
+//! `for await (const msg of mq.stream(topicId))`, and a `ReadableStream` for piping.
+//!
+//! A stream subscribes to its topic with a raw subscriber, so each value is a
+//! message object ({payload, topic_id, topic, timestamp, id, origin_id}).
+//! The callbacks registered with the queue only hold a weak reference to the
+//! stream state, which is owned by the JS `TopicStream` object (or, for a
+//! `ReadableStream`, by the queue): ending the stream unsubscribes and releases
+//! the callbacks.
+
+use wasm_bindgen::prelude::*;
+use js_sys::{Function, Object, Promise, Reflect, Symbol};
+use web_sys::{QueuingStrategy, ReadableStream, ReadableStreamDefaultController};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+
+use crate::types::{InnerQueue, Subscriber};
+use crate::js_utils::message_to_js_full;
+use crate::queue::add_subscriber;
+use crate::utils::set_timeout;
+
+type Callback = Closure<dyn FnMut(JsValue)>;
+
+/// State shared by a `TopicStream` and its callbacks
+struct StreamState {
+    /// Queue the stream is subscribed to
+    queue: Weak<RefCell<InnerQueue>>,
+    topic_id: u32,
+    /// ID of the internal subscriber, until the stream ends
+    sub_id: Cell<Option<u32>>,
+    /// Messages received while no `next()` was waiting, oldest first
+    pending: RefCell<VecDeque<JsValue>>,
+    /// Resolvers of `next()` calls waiting for a message, oldest first
+    waiting: RefCell<VecDeque<Function>>,
+    /// Maximum length of `pending`; the oldest message is dropped beyond it
+    buffer_limit: usize,
+    /// Subscriber callback, released when the stream ends
+    on_message: RefCell<Option<Callback>>,
+    /// Called by the queue when the topic is destroyed or the queue closed
+    on_destroy: Callback,
+    done: Cell<bool>,
+}
+
+impl StreamState {
+    fn receive(&self, msg: JsValue) {
+        if self.done.get() {
+            return;
+        }
+        let resolve = self.waiting.borrow_mut().pop_front();
+        match resolve {
+            Some(resolve) => {
+                let _ = resolve.call1(&JsValue::NULL, &step(&msg, false));
+            }
+            None => {
+                let mut pending = self.pending.borrow_mut();
+                pending.push_back(msg);
+                if pending.len() > self.buffer_limit {
+                    pending.pop_front();
+                }
+            }
+        }
+    }
+
+    /// End the stream: unsubscribe and resolve waiting `next()` calls with `{done: true}`
+    fn finish(&self) {
+        if self.done.replace(true) {
+            return;
+        }
+        let unsubscribed = self.unsubscribe();
+        self.pending.borrow_mut().clear();
+
+        let waiting = std::mem::take(&mut *self.waiting.borrow_mut());
+        let end = step(&JsValue::UNDEFINED, true);
+        for resolve in waiting {
+            let _ = resolve.call1(&JsValue::NULL, &end);
+        }
+
+        // The queue no longer calls the subscriber, so its closure can go
+        if unsubscribed {
+            self.on_message.borrow_mut().take();
+        }
+    }
+
+    /// Remove the internal subscriber, if it is still registered
+    /// Returns false if the queue is in use and the subscriber could not be removed.
+    fn unsubscribe(&self) -> bool {
+        let Some(sub_id) = self.sub_id.take() else {
+            return true;
+        };
+        let Some(queue) = self.queue.upgrade() else {
+            return true;
+        };
+        let Ok(mut queue) = queue.try_borrow_mut() else {
+            self.sub_id.set(Some(sub_id));
+            return false;
+        };
+        if let Some(topic) = queue.get_topic_by_id_mut(self.topic_id) {
+            topic.subscribers.remove(sub_id);
+        }
+        true
+    }
+}
+
+impl Drop for StreamState {
+    fn drop(&mut self) {
+        // Freed without `return()`: make sure the queue stops calling the closures
+        self.unsubscribe();
+    }
+}
+
+/// An iterator result object: `{value, done}`
+fn step(value: &JsValue, done: bool) -> JsValue {
+    let result = Object::new();
+    let _ = Reflect::set(&result, &"value".into(), value);
+    let _ = Reflect::set(&result, &"done".into(), &done.into());
+    result.into()
+}
+
+/// Async iterator over the messages of a topic, created by `MessageQueue::stream`
+/// Implements the async iterator protocol, so it can be used with `for await`.
+/// Breaking out of the loop calls `return()`, which unsubscribes.
+#[wasm_bindgen]
+pub struct TopicStream {
+    state: Rc<StreamState>,
+}
+
+impl TopicStream {
+    /// Subscribe a new stream to a topic
+    /// Returns the stream object with `[Symbol.asyncIterator]` set.
+    pub(crate) fn open(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, buffer_limit: usize) -> Result<JsValue, JsValue> {
+        let mut subscriber = None;
+        let state = Rc::new_cyclic(|weak: &Weak<StreamState>| {
+            let on_message_state = weak.clone();
+            let on_message = Closure::new(move |msg: JsValue| {
+                if let Some(state) = on_message_state.upgrade() {
+                    state.receive(msg);
+                }
+            });
+            let on_destroy_state = weak.clone();
+            let on_destroy = Closure::new(move |_reason: JsValue| {
+                if let Some(state) = on_destroy_state.upgrade() {
+                    state.finish();
+                }
+            });
+            let mut sub = Subscriber::new(on_message.as_ref().unchecked_ref::<Function>().clone());
+            sub.raw = true;
+            sub.on_destroy = Some(on_destroy.as_ref().unchecked_ref::<Function>().clone());
+            subscriber = Some(sub);
+
+            StreamState {
+                queue: Rc::downgrade(inner),
+                topic_id,
+                sub_id: Cell::new(None),
+                pending: RefCell::new(VecDeque::new()),
+                waiting: RefCell::new(VecDeque::new()),
+                buffer_limit,
+                on_message: RefCell::new(Some(on_message)),
+                on_destroy,
+                done: Cell::new(false),
+            }
+        });
+
+        let subscriber = subscriber.ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        let sub_id = add_subscriber(inner, topic_id, subscriber)?;
+        state.sub_id.set(Some(sub_id));
+
+        let stream = JsValue::from(TopicStream { state });
+        Reflect::set(&stream, &Symbol::async_iterator(), &Function::new_no_args("return this"))?;
+        Ok(stream)
+    }
+}
+
+#[wasm_bindgen]
+impl TopicStream {
+    /// Get the next message
+    /// @returns Promise resolving with {value: message, done: false}, or {done: true} once the stream ended
+    pub fn next(&self) -> Promise {
+        let state = &self.state;
+        if let Some(msg) = state.pending.borrow_mut().pop_front() {
+            return Promise::resolve(&step(&msg, false));
+        }
+        if state.done.get() {
+            return Promise::resolve(&step(&JsValue::UNDEFINED, true));
+        }
+        Promise::new(&mut |resolve, _reject| {
+            state.waiting.borrow_mut().push_back(resolve);
+        })
+    }
+
+    /// End the stream: unsubscribe and resolve pending `next()` calls with {done: true}
+    /// Called automatically when a `for await` loop exits early.
+    /// @returns Promise resolving with {done: true}
+    #[wasm_bindgen(js_name = "return")]
+    pub fn end(&self) -> Promise {
+        self.state.finish();
+        Promise::resolve(&step(&JsValue::UNDEFINED, true))
+    }
+
+    /// Number of messages received but not yet taken with `next()`
+    pub fn pending_count(&self) -> usize {
+        self.state.pending.borrow().len()
+    }
+}
+
+/// Underlying source of a `ReadableStream` created by `MessageQueue::readable_stream`
+/// Owned by the queue's `readable_sources` until the stream is cancelled, its
+/// topic destroyed or the queue closed; its callbacks only hold weak references.
+pub struct ReadableSource {
+    /// Handle in `InnerQueue::readable_sources`
+    id: u32,
+    queue: Weak<RefCell<InnerQueue>>,
+    topic_id: u32,
+    /// ID of the internal subscriber, until the stream ends
+    sub_id: Cell<Option<u32>>,
+    /// Set by the stream's `start`
+    controller: RefCell<Option<ReadableStreamDefaultController>>,
+    /// ID of the newest message enqueued into the stream
+    last_id: Cell<Option<u64>>,
+    /// The stream was full while the topic buffered messages it has not enqueued yet
+    behind: Cell<bool>,
+    on_start: Callback,
+    on_pull: Callback,
+    on_cancel: Callback,
+    on_message: Callback,
+    on_destroy: Callback,
+}
+
+impl ReadableSource {
+    /// Create a source subscribed to a topic and register it on the queue
+    /// Returns the stream reading from it.
+    pub(crate) fn open(inner: &Rc<RefCell<InnerQueue>>, topic_id: u32, high_water_mark: f64) -> Result<ReadableStream, JsValue> {
+        let id = {
+            let mut queue = inner.borrow_mut();
+            let id = queue.next_readable_id;
+            queue.next_readable_id = queue.next_readable_id.wrapping_add(1);
+            id
+        };
+        let source = Rc::new_cyclic(|weak: &Weak<ReadableSource>| {
+            let callback = |handle: fn(&ReadableSource, JsValue)| {
+                let weak = weak.clone();
+                Closure::new(move |value: JsValue| {
+                    if let Some(source) = weak.upgrade() {
+                        handle(&source, value);
+                    }
+                })
+            };
+            ReadableSource {
+                id,
+                queue: Rc::downgrade(inner),
+                topic_id,
+                sub_id: Cell::new(None),
+                controller: RefCell::new(None),
+                last_id: Cell::new(None),
+                behind: Cell::new(false),
+                on_start: callback(|source, controller| {
+                    *source.controller.borrow_mut() = Some(controller.unchecked_into());
+                }),
+                on_pull: callback(|source, _controller| source.catch_up()),
+                on_cancel: callback(|source, _reason| source.release(true)),
+                on_message: callback(ReadableSource::receive),
+                on_destroy: callback(|source, _reason| {
+                    if let Some(controller) = source.controller.borrow().as_ref() {
+                        let _ = controller.close();
+                    }
+                    source.release(false);
+                }),
+            }
+        });
+
+        // Subscribe first, so a rejected subscribe leaves no stream behind
+        let mut subscriber = Subscriber::new(source.on_message.as_ref().unchecked_ref::<Function>().clone());
+        subscriber.raw = true;
+        subscriber.on_destroy = Some(source.on_destroy.as_ref().unchecked_ref::<Function>().clone());
+        let sub_id = add_subscriber(inner, topic_id, subscriber)?;
+        source.sub_id.set(Some(sub_id));
+        inner.borrow_mut().readable_sources.insert(id, source.clone());
+
+        let stream = source.stream(high_water_mark);
+        if stream.is_err() {
+            source.release(true);
+        }
+        stream
+    }
+
+    /// Create the `ReadableStream` pulling from this source
+    fn stream(&self, high_water_mark: f64) -> Result<ReadableStream, JsValue> {
+        let underlying = Object::new();
+        Reflect::set(&underlying, &"start".into(), self.on_start.as_ref())?;
+        Reflect::set(&underlying, &"pull".into(), self.on_pull.as_ref())?;
+        Reflect::set(&underlying, &"cancel".into(), self.on_cancel.as_ref())?;
+        let strategy = QueuingStrategy::new();
+        strategy.set_high_water_mark(high_water_mark);
+        ReadableStream::new_with_underlying_source_and_strategy(&underlying, &strategy)
+    }
+
+    /// Enqueue a message delivered to the internal subscriber
+    /// While the stream is full, the message is left to the topic's buffer (see
+    /// `catch_up`), or dropped and counted in `stream_dropped` if it has none.
+    fn receive(&self, msg: JsValue) {
+        if self.behind.get() {
+            return;
+        }
+        let Some(controller) = self.controller.borrow().clone() else {
+            return;
+        };
+        if controller.desired_size().is_some_and(|size| size > 0.0) {
+            self.last_id.set(Reflect::get(&msg, &"id".into()).ok().and_then(|id| u64::try_from(id).ok()));
+            let _ = controller.enqueue_with_chunk(&msg);
+            return;
+        }
+
+        let Some(queue) = self.queue.upgrade() else {
+            return;
+        };
+        let Ok(mut queue) = queue.try_borrow_mut() else {
+            return;
+        };
+        if let Some(topic) = queue.get_topic_by_id_mut(self.topic_id) {
+            if topic.has_buffer() {
+                self.behind.set(true);
+            } else {
+                topic.stream_dropped += 1;
+            }
+        }
+    }
+
+    /// Enqueue the buffered messages that arrived while the stream was full
+    /// Called by the stream whenever it wants more data.
+    fn catch_up(&self) {
+        if !self.behind.get() {
+            return;
+        }
+        let Some(controller) = self.controller.borrow().clone() else {
+            return;
+        };
+        let (name, messages) = {
+            let Some(queue) = self.queue.upgrade() else {
+                return;
+            };
+            let queue = queue.borrow();
+            let Some(topic) = queue.get_topic_by_id(self.topic_id) else {
+                return;
+            };
+            let messages = topic.get_buffer().map(|buffer| buffer.to_vec()).unwrap_or_default();
+            (topic.name.clone(), messages)
+        };
+
+        // Resume after the newest message already enqueued, or from the oldest
+        // buffered one if it was evicted in the meantime
+        let start = self.last_id.get()
+            .and_then(|last_id| messages.iter().position(|msg| msg.id == last_id))
+            .map_or(0, |position| position + 1);
+        for msg in &messages[start..] {
+            if !controller.desired_size().is_some_and(|size| size > 0.0) {
+                return;
+            }
+            if let Ok(msg_obj) = message_to_js_full(msg, &name) {
+                let _ = controller.enqueue_with_chunk(&msg_obj);
+            }
+            self.last_id.set(Some(msg.id));
+        }
+        self.behind.set(false);
+    }
+
+    /// Stop feeding the stream and remove the source from the queue
+    /// The source is dropped on the next task, as one of its own callbacks may be running.
+    fn release(&self, unsubscribe: bool) {
+        let Some(queue) = self.queue.upgrade() else {
+            return;
+        };
+        let Ok(mut queue) = queue.try_borrow_mut() else {
+            return;
+        };
+        if let Some(sub_id) = self.sub_id.take().filter(|_| unsubscribe) {
+            if let Some(topic) = queue.get_topic_by_id_mut(self.topic_id) {
+                topic.subscribers.remove(sub_id);
+            }
+        }
+        if let Some(source) = queue.readable_sources.remove(&self.id) {
+            let drop_later = Closure::once_into_js(move || drop(source));
+            let _ = set_timeout(drop_later.unchecked_ref(), 0.0);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::constants::{SUBSCRIBER_GENERATION_MASK, SUBSCRIBER_SLOT_BITS, SUBSCRIBER_SLOT_MASK};
+use crate::types::Subscriber;
+
+/// End of the subscription order list
+const NONE: u32 = u32::MAX;
+
+/// A subscriber slot, linked to its neighbours in subscription order
+struct Slot {
+    /// None while the slot is free
+    subscriber: Option<Subscriber>,
+    /// Incremented each time the slot is reused, so stale IDs can be detected
+    generation: u32,
+    /// Dispatch order of the subscriber: lower values are called first
+    order: i32,
+    /// Previous occupied slot in subscription order
+    prev: u32,
+    /// Next occupied slot in subscription order
+    next: u32,
+}
+
+/// The subscribers of a topic, kept in dispatch order
+/// A slab indexed by subscriber ID: unsubscribing clears a slot in O(1) and
+/// freed slots are reused. Occupied slots are linked by ascending order value,
+/// and in subscription order within an order value, so iteration (and therefore
+/// dispatch) visits subscribers in that order, whichever slot they reuse.
+pub struct SubscriberList {
+    slots: Vec<Slot>,
+    /// Free slots, most recently freed last
+    free: Vec<u32>,
+    /// Oldest subscriber's slot
+    head: u32,
+    /// Newest subscriber's slot
+    tail: u32,
+    /// Number of subscribers
+    len: usize,
+}
+
+impl Default for SubscriberList {
+    fn default() -> Self {
+        SubscriberList {
+            slots: Vec::new(),
+            free: Vec::new(),
+            head: NONE,
+            tail: NONE,
+            len: 0,
+        }
+    }
+}
+
+impl SubscriberList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of subscribers
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// ID of the subscriber in a slot: the slot index in the low bits, its generation above
+    fn id(&self, slot: u32) -> u32 {
+        (self.slots[slot as usize].generation << SUBSCRIBER_SLOT_BITS) | slot
+    }
+
+    /// Slot of a current subscriber, or None for unknown and stale IDs
+    fn slot(&self, sub_id: u32) -> Option<usize> {
+        let slot = (sub_id & SUBSCRIBER_SLOT_MASK) as usize;
+        let entry = self.slots.get(slot)?;
+        (entry.subscriber.is_some() && entry.generation == sub_id >> SUBSCRIBER_SLOT_BITS).then_some(slot)
+    }
+
+    /// Add a subscriber with the default order (0) and return its ID
+    pub fn insert(&mut self, subscriber: Subscriber) -> u32 {
+        self.insert_ordered(subscriber, 0)
+    }
+
+    /// Add a subscriber after all current ones with an order of at most `order`
+    /// and before those with a higher order, and return its ID
+    pub fn insert_ordered(&mut self, subscriber: Subscriber, order: i32) -> u32 {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                let entry = &mut self.slots[slot as usize];
+                entry.generation = entry.generation.wrapping_add(1) & SUBSCRIBER_GENERATION_MASK;
+                entry.subscriber = Some(subscriber);
+                slot
+            }
+            None => {
+                self.slots.push(Slot { subscriber: Some(subscriber), generation: 0, order, prev: NONE, next: NONE });
+                (self.slots.len() - 1) as u32
+            }
+        };
+
+        self.link(slot, order);
+        self.len += 1;
+        self.id(slot)
+    }
+
+    /// Remove a subscriber, freeing its slot for reuse
+    pub fn remove(&mut self, sub_id: u32) -> Option<Subscriber> {
+        let slot = self.slot(sub_id)?;
+        self.unlink(slot as u32);
+        let subscriber = self.slots[slot].subscriber.take();
+        self.free.push(slot as u32);
+        self.len -= 1;
+        subscriber
+    }
+
+    /// Order value of a subscriber
+    pub fn order(&self, sub_id: u32) -> Option<i32> {
+        let slot = self.slot(sub_id)?;
+        Some(self.slots[slot].order)
+    }
+
+    /// Change a subscriber's order value, moving it after the subscribers
+    /// already at that value. Returns false for unknown and stale IDs.
+    pub fn set_order(&mut self, sub_id: u32, order: i32) -> bool {
+        let Some(slot) = self.slot(sub_id) else {
+            return false;
+        };
+        self.unlink(slot as u32);
+        self.link(slot as u32, order);
+        true
+    }
+
+    /// Link a slot in after the last slot with an order of at most `order`
+    /// Searches from the tail, so appending at the highest order is O(1).
+    fn link(&mut self, slot: u32, order: i32) {
+        let mut prev = self.tail;
+        while prev != NONE && self.slots[prev as usize].order > order {
+            prev = self.slots[prev as usize].prev;
+        }
+        let next = match prev {
+            NONE => self.head,
+            prev => self.slots[prev as usize].next,
+        };
+
+        let entry = &mut self.slots[slot as usize];
+        entry.order = order;
+        entry.prev = prev;
+        entry.next = next;
+        match prev {
+            NONE => self.head = slot,
+            prev => self.slots[prev as usize].next = slot,
+        }
+        match next {
+            NONE => self.tail = slot,
+            next => self.slots[next as usize].prev = slot,
+        }
+    }
+
+    /// Take a slot out of the dispatch order
+    fn unlink(&mut self, slot: u32) {
+        let entry = &self.slots[slot as usize];
+        let (prev, next) = (entry.prev, entry.next);
+        match prev {
+            NONE => self.head = next,
+            prev => self.slots[prev as usize].next = next,
+        }
+        match next {
+            NONE => self.tail = prev,
+            next => self.slots[next as usize].prev = prev,
+        }
+    }
+
+    pub fn get(&self, sub_id: u32) -> Option<&Subscriber> {
+        let slot = self.slot(sub_id)?;
+        self.slots[slot].subscriber.as_ref()
+    }
+
+    pub fn get_mut(&mut self, sub_id: u32) -> Option<&mut Subscriber> {
+        let slot = self.slot(sub_id)?;
+        self.slots[slot].subscriber.as_mut()
+    }
+
+    pub fn contains_key(&self, sub_id: u32) -> bool {
+        self.slot(sub_id).is_some()
+    }
+
+    /// Iterate over (ID, subscriber) in dispatch order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Subscriber)> {
+        let mut slot = self.head;
+        std::iter::from_fn(move || {
+            let entry = self.slots.get(slot as usize)?;
+            let id = self.id(slot);
+            slot = entry.next;
+            entry.subscriber.as_ref().map(|subscriber| (id, subscriber))
+        })
+    }
+
+    /// Call `f` with (ID, subscriber) of each subscriber, in dispatch order
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(u32, &mut Subscriber)) {
+        let mut slot = self.head;
+        while slot != NONE {
+            let id = self.id(slot);
+            let entry = &mut self.slots[slot as usize];
+            slot = entry.next;
+            if let Some(subscriber) = entry.subscriber.as_mut() {
+                f(id, subscriber);
+            }
+        }
+    }
+
+    /// Remove all subscribers, returning them in dispatch order
+    /// Their IDs stay invalid when the slots are reused.
+    pub fn drain(&mut self) -> Vec<Subscriber> {
+        let mut subscribers = Vec::with_capacity(self.len);
+        let mut slot = self.head;
+        while slot != NONE {
+            let entry = &mut self.slots[slot as usize];
+            subscribers.extend(entry.subscriber.take());
+            self.free.push(slot);
+            slot = entry.next;
+        }
+        self.head = NONE;
+        self.tail = NONE;
+        self.len = 0;
+        subscribers
+    }
+
+    /// Remove all subscribers
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Consume the list, yielding subscribers in dispatch order
+    pub fn into_values(mut self) -> impl Iterator<Item = Subscriber> {
+        self.drain().into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::{JsCast, JsValue};
+
+    fn subscriber(skip: u32) -> Subscriber {
+        let mut subscriber = Subscriber::new(JsValue::UNDEFINED.unchecked_into());
+        // Tags the subscriber so tests can tell them apart
+        subscriber.skip_remaining = skip;
+        subscriber
+    }
+
+    fn tags(list: &SubscriberList) -> Vec<u32> {
+        list.iter().map(|(_, sub)| sub.skip_remaining).collect()
+    }
+
+    #[test]
+    fn test_subscription_order_survives_slot_reuse() {
+        let mut list = SubscriberList::new();
+        let ids: Vec<u32> = (0..4).map(|tag| list.insert(subscriber(tag))).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+
+        assert!(list.remove(ids[1]).is_some());
+        assert!(list.remove(ids[0]).is_some());
+        // Reuses the slot freed last, but is called after every older subscriber
+        let reused = list.insert(subscriber(4));
+        assert_eq!(reused & SUBSCRIBER_SLOT_MASK, 0);
+        let appended = list.insert(subscriber(5));
+        assert_eq!(tags(&list), vec![2, 3, 4, 5]);
+        assert_eq!(list.len(), 4);
+
+        // Removing the oldest and newest relinks both ends
+        list.remove(ids[2]);
+        list.remove(appended);
+        assert_eq!(tags(&list), vec![3, 4]);
+        let mut visited = Vec::new();
+        list.for_each_mut(|id, sub| {
+            sub.skip_remaining += 10;
+            visited.push(id);
+        });
+        assert_eq!(visited, vec![ids[3], reused]);
+        assert_eq!(tags(&list), vec![13, 14]);
+    }
+
+    #[test]
+    fn test_lower_order_is_called_first_and_stable() {
+        let mut list = SubscriberList::new();
+        let render = list.insert_ordered(subscriber(3), 10);
+        let validate = list.insert_ordered(subscriber(1), -5);
+        list.insert(subscriber(2));
+        list.insert_ordered(subscriber(4), 10);
+        list.insert_ordered(subscriber(0), -5);
+        assert_eq!(tags(&list), vec![1, 0, 2, 3, 4]);
+        assert_eq!(list.order(validate), Some(-5));
+
+        // Moving a subscriber puts it after the others at its new order
+        assert!(list.set_order(render, -5));
+        assert_eq!(tags(&list), vec![1, 0, 3, 2, 4]);
+        assert!(list.set_order(validate, 100));
+        assert_eq!(tags(&list), vec![0, 3, 2, 4, 1]);
+
+        // A reused slot takes the order it is inserted with
+        list.remove(validate);
+        assert!(!list.set_order(validate, 0));
+        list.insert_ordered(subscriber(5), -10);
+        assert_eq!(tags(&list), vec![5, 0, 3, 2, 4]);
+    }
+
+    #[test]
+    fn test_stale_ids_do_not_resolve() {
+        let mut list = SubscriberList::new();
+        let first = list.insert(subscriber(0));
+        list.remove(first);
+        let second = list.insert(subscriber(1));
+
+        assert_ne!(first, second);
+        assert!(!list.contains_key(first));
+        assert!(list.get(first).is_none());
+        assert!(list.remove(first).is_none());
+        assert_eq!(list.get(second).map(|sub| sub.skip_remaining), Some(1));
+        assert!(!list.contains_key(99));
+
+        // IDs issued before a clear stay invalid
+        list.clear();
+        assert!(list.is_empty());
+        let third = list.insert(subscriber(2));
+        assert!(!list.contains_key(second));
+        assert_eq!(tags(&list), vec![2]);
+        assert_eq!(list.drain().len(), 1);
+        assert!(!list.contains_key(third));
+        assert_eq!(list.iter().count(), 0);
+    }
+}
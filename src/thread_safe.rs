@@ -0,0 +1,634 @@
+//! Thread-safe variant of the message queue (`threads` feature)
+//!
+//! `MessageQueue` keeps its state in `Rc<RefCell<...>>` and stores JS callbacks,
+//! neither of which is `Send`. This variant keeps its state behind
+//! `Arc<Mutex<...>>` and uses Rust closures and `Any` payloads instead, so it can
+//! be shared between threads (multi-threaded WASM with `SharedArrayBuffer`,
+//! SharedWorker backends).
+//!
+//! The methods mirror `MessageQueue`'s topic, subscription, publishing, buffer
+//! and pause/close methods, with the same names, arguments and errors; the
+//! parts that need JS (channels, sync, presence, promises) are left out.
+//!
+//! Trade-off: every operation takes a lock, so under heavy publishing from many
+//! threads the queue serializes on the mutex, and there is no BroadcastChannel
+//! or JS interop. Callbacks are invoked after the lock is released, so they may
+//! publish re-entrantly. Prefer `MessageQueue` for single-threaded JS use.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use wasm_bindgen::JsValue;
+
+use crate::constants::{DEFAULT_MAX_HELD_MESSAGES, ERR_QUEUE_CLOSED};
+
+/// Message payload shared between threads
+pub type Payload = Arc<dyn Any + Send + Sync>;
+
+/// Subscriber callback invoked with each delivered message
+pub type Callback = Arc<dyn Fn(&ThreadSafeMessage) + Send + Sync>;
+
+/// A message delivered by `ThreadSafeMessageQueue`
+pub struct ThreadSafeMessage {
+    /// Unique message identifier (sequential per queue)
+    pub id: u64,
+    /// Topic ID this message belongs to
+    pub topic_id: u32,
+    /// Message payload
+    pub payload: Payload,
+    /// Timestamp when the message was created (milliseconds since epoch)
+    pub timestamp: f64,
+    /// ID of the client that originated this message
+    pub origin_id: Arc<String>,
+}
+
+/// A subscriber of a `ThreadSafeTopic`
+struct ThreadSafeSubscriber {
+    id: u32,
+    callback: Callback,
+    /// Removed after its first delivery
+    once: bool,
+}
+
+/// A topic with its subscribers and optional buffer
+struct ThreadSafeTopic {
+    name: String,
+    /// Subscribers in subscription order
+    subscribers: Vec<ThreadSafeSubscriber>,
+    next_id: u32,
+    buffer: Option<VecDeque<Arc<ThreadSafeMessage>>>,
+    buffer_capacity: usize,
+}
+
+impl ThreadSafeTopic {
+    fn new(name: String) -> Self {
+        ThreadSafeTopic {
+            name,
+            subscribers: Vec::new(),
+            next_id: 0,
+            buffer: None,
+            buffer_capacity: 0,
+        }
+    }
+
+    /// Buffer a message, dropping the oldest one if the buffer is full
+    fn buffer_message(&mut self, message: &Arc<ThreadSafeMessage>) {
+        if let Some(buffer) = self.buffer.as_mut() {
+            if buffer.len() == self.buffer_capacity {
+                buffer.pop_front();
+            }
+            buffer.push_back(message.clone());
+        }
+    }
+
+    /// Collect the callbacks for a message, removing one-shot subscribers
+    fn take_callbacks(&mut self) -> Vec<Callback> {
+        let callbacks = self.subscribers.iter().map(|sub| sub.callback.clone()).collect();
+        self.subscribers.retain(|sub| !sub.once);
+        callbacks
+    }
+}
+
+/// Internal state of `ThreadSafeMessageQueue`
+/// Destroyed topics leave an empty slot, so their IDs are never reused.
+#[derive(Default)]
+pub struct ThreadSafeInnerQueue {
+    topics: Vec<Option<ThreadSafeTopic>>,
+    topic_index: HashMap<String, usize>,
+    client_id: Arc<String>,
+    closed: bool,
+    paused: bool,
+    /// Messages published while paused, delivered on `resume`
+    held_messages: VecDeque<Arc<ThreadSafeMessage>>,
+}
+
+impl ThreadSafeInnerQueue {
+    fn check_open(&self) -> Result<(), JsValue> {
+        if self.closed {
+            Err(JsValue::from_str(ERR_QUEUE_CLOSED))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn topic(&self, topic_id: u32) -> Option<&ThreadSafeTopic> {
+        self.topics.get(topic_id as usize)?.as_ref()
+    }
+
+    fn topic_mut(&mut self, topic_id: u32) -> Option<&mut ThreadSafeTopic> {
+        self.topics.get_mut(topic_id as usize)?.as_mut()
+    }
+
+    fn topic_or_err(&mut self, topic_id: u32) -> Result<&mut ThreadSafeTopic, JsValue> {
+        self.topic_mut(topic_id).ok_or_else(|| JsValue::from_str("Invalid topic ID"))
+    }
+
+    /// Get the ID of a topic, creating it if needed
+    fn get_or_create_topic_id(&mut self, topic_name: &str) -> Result<u32, JsValue> {
+        if let Some(&id) = self.topic_index.get(topic_name) {
+            return Ok(id as u32);
+        }
+        if topic_name.is_empty() {
+            return Err(JsValue::from_str("Topic name must not be empty"));
+        }
+
+        let id = self.topics.len();
+        self.topics.push(Some(ThreadSafeTopic::new(topic_name.to_string())));
+        self.topic_index.insert(topic_name.to_string(), id);
+        Ok(id as u32)
+    }
+
+    fn add_subscriber(&mut self, topic_id: u32, callback: Callback, once: bool) -> Result<u32, JsValue> {
+        self.check_open()?;
+        let topic = self.topic_or_err(topic_id)?;
+        let id = topic.next_id;
+        topic.next_id = topic.next_id.wrapping_add(1);
+        topic.subscribers.push(ThreadSafeSubscriber { id, callback, once });
+        Ok(id)
+    }
+
+    /// Buffer a message and collect its callbacks, or hold it while paused
+    fn dispatch(&mut self, message: &Arc<ThreadSafeMessage>) -> Vec<Callback> {
+        let paused = self.paused;
+        let Some(topic) = self.topic_mut(message.topic_id) else {
+            return Vec::new();
+        };
+        topic.buffer_message(message);
+        if !paused {
+            return topic.take_callbacks();
+        }
+        if self.held_messages.len() >= DEFAULT_MAX_HELD_MESSAGES {
+            self.held_messages.pop_front();
+        }
+        self.held_messages.push_back(message.clone());
+        Vec::new()
+    }
+}
+
+/// Message queue that can be shared between threads
+/// Cloning is cheap and yields a handle to the same queue.
+#[derive(Clone, Default)]
+pub struct ThreadSafeMessageQueue {
+    inner: Arc<Mutex<ThreadSafeInnerQueue>>,
+    next_message_id: Arc<AtomicU64>,
+}
+
+impl ThreadSafeMessageQueue {
+    /// Create a queue identified by `client_id`
+    pub fn new(client_id: &str) -> Self {
+        let queue = ThreadSafeMessageQueue::default();
+        queue.lock().client_id = Arc::new(client_id.to_string());
+        queue
+    }
+
+    /// Lock the inner state, recovering from a panic in another thread
+    fn lock(&self) -> MutexGuard<'_, ThreadSafeInnerQueue> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Create a message originating from this queue
+    fn create_message(&self, queue: &ThreadSafeInnerQueue, topic_id: u32, payload: Payload) -> Arc<ThreadSafeMessage> {
+        Arc::new(ThreadSafeMessage {
+            id: self.next_message_id.fetch_add(1, Ordering::Relaxed),
+            topic_id,
+            payload,
+            timestamp: now_ms(),
+            origin_id: queue.client_id.clone(),
+        })
+    }
+
+    /// Create a topic
+    /// @returns false if the topic already exists
+    pub fn create_topic(&self, topic_name: &str) -> Result<bool, JsValue> {
+        let mut queue = self.lock();
+        queue.check_open()?;
+        if queue.topic_index.contains_key(topic_name) {
+            return Ok(false);
+        }
+        queue.get_or_create_topic_id(topic_name)?;
+        Ok(true)
+    }
+
+    /// Register a topic and get its ID (existing topics return their ID)
+    /// New topics need a non-empty name.
+    pub fn register_topic(&self, topic_name: &str) -> Result<u32, JsValue> {
+        let mut queue = self.lock();
+        queue.check_open()?;
+        queue.get_or_create_topic_id(topic_name)
+    }
+
+    /// Destroy a topic by ID
+    /// Its subscribers are dropped and the name is free for reuse; the old ID
+    /// never refers to a topic created later.
+    /// @returns false if the ID does not refer to a live topic
+    pub fn destroy_topic(&self, topic_id: u32) -> bool {
+        let mut queue = self.lock();
+        let Some(topic) = queue.topics.get_mut(topic_id as usize).and_then(Option::take) else {
+            return false;
+        };
+        queue.topic_index.remove(&topic.name);
+        queue.held_messages.retain(|message| message.topic_id != topic_id);
+        true
+    }
+
+    /// Check if a topic ID refers to a live topic
+    pub fn has_topic(&self, topic_id: u32) -> bool {
+        self.lock().topic(topic_id).is_some()
+    }
+
+    /// Look up the ID of an existing topic
+    pub fn get_topic_id(&self, name: &str) -> Option<u32> {
+        self.lock().topic_index.get(name).map(|&id| id as u32)
+    }
+
+    /// Get the name of a topic
+    pub fn get_topic_name(&self, topic_id: u32) -> Option<String> {
+        self.lock().topic(topic_id).map(|topic| topic.name.clone())
+    }
+
+    /// Subscribe to a topic
+    /// @returns Subscriber ID
+    pub fn subscribe<F>(&self, topic_id: u32, callback: F) -> Result<u32, JsValue>
+    where
+        F: Fn(&ThreadSafeMessage) + Send + Sync + 'static,
+    {
+        self.lock().add_subscriber(topic_id, Arc::new(callback), false)
+    }
+
+    /// Subscribe for a single message
+    /// The subscriber is removed after its first delivery.
+    /// @returns Subscriber ID, usable with `unsubscribe` before delivery
+    pub fn subscribe_once<F>(&self, topic_id: u32, callback: F) -> Result<u32, JsValue>
+    where
+        F: Fn(&ThreadSafeMessage) + Send + Sync + 'static,
+    {
+        self.lock().add_subscriber(topic_id, Arc::new(callback), true)
+    }
+
+    /// Unsubscribe from a topic
+    pub fn unsubscribe(&self, topic_id: u32, sub_id: u32) -> bool {
+        let mut queue = self.lock();
+        let Some(topic) = queue.topic_mut(topic_id) else {
+            return false;
+        };
+        let before = topic.subscribers.len();
+        topic.subscribers.retain(|sub| sub.id != sub_id);
+        topic.subscribers.len() < before
+    }
+
+    /// Unsubscribe all subscribers from a topic
+    /// @returns Number of subscribers removed
+    pub fn unsubscribe_all(&self, topic_id: u32) -> usize {
+        let mut queue = self.lock();
+        queue.topic_mut(topic_id).map_or(0, |topic| std::mem::take(&mut topic.subscribers).len())
+    }
+
+    /// Publish a payload to a topic
+    /// Subscribers are called on the publishing thread after the lock is released.
+    /// While paused, the message is buffered and held for `resume`.
+    pub fn publish<T: Any + Send + Sync>(&self, topic_id: u32, payload: T) -> Result<(), JsValue> {
+        let (message, callbacks) = {
+            let mut queue = self.lock();
+            queue.check_open()?;
+            if queue.topic(topic_id).is_none() {
+                return Err(JsValue::from_str("Invalid topic ID"));
+            }
+            let message = self.create_message(&queue, topic_id, Arc::new(payload));
+            let callbacks = queue.dispatch(&message);
+            (message, callbacks)
+        };
+
+        for callback in callbacks {
+            callback(&message);
+        }
+        Ok(())
+    }
+
+    /// Publish to a topic by name, creating the topic if needed
+    pub fn publish_by_name<T: Any + Send + Sync>(&self, topic_name: &str, payload: T) -> Result<(), JsValue> {
+        let topic_id = self.register_topic(topic_name)?;
+        self.publish(topic_id, payload)
+    }
+
+    /// Publish multiple messages under a single lock
+    /// All topic IDs are checked before anything is published.
+    pub fn publish_batch<T: Any + Send + Sync>(&self, messages: Vec<(u32, T)>) -> Result<(), JsValue> {
+        let deliveries: Vec<_> = {
+            let mut queue = self.lock();
+            queue.check_open()?;
+            if messages.iter().any(|(topic_id, _)| queue.topic(*topic_id).is_none()) {
+                return Err(JsValue::from_str("Invalid topic ID"));
+            }
+            messages.into_iter()
+                .map(|(topic_id, payload)| {
+                    let message = self.create_message(&queue, topic_id, Arc::new(payload));
+                    let callbacks = queue.dispatch(&message);
+                    (message, callbacks)
+                })
+                .collect()
+        };
+
+        for (message, callbacks) in deliveries {
+            for callback in callbacks {
+                callback(&message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Enable message buffering for a topic
+    /// Re-enabling resizes the buffer, keeping the newest messages.
+    /// @param capacity - Maximum number of messages to buffer (default: 100)
+    pub fn enable_topic_buffer(&self, topic_id: u32, capacity: Option<usize>) -> Result<(), JsValue> {
+        let capacity = capacity.unwrap_or(100);
+        if capacity == 0 {
+            return Err(JsValue::from_str("Buffer capacity must be greater than 0"));
+        }
+
+        let mut queue = self.lock();
+        queue.check_open()?;
+        let topic = queue.topic_or_err(topic_id)?;
+        let buffer = topic.buffer.get_or_insert_with(|| VecDeque::with_capacity(capacity));
+        let excess = buffer.len().saturating_sub(capacity);
+        buffer.drain(..excess);
+        topic.buffer_capacity = capacity;
+        Ok(())
+    }
+
+    /// Disable message buffering for a topic, clearing its buffered messages
+    pub fn disable_topic_buffer(&self, topic_id: u32) -> Result<(), JsValue> {
+        let mut queue = self.lock();
+        queue.check_open()?;
+        let topic = queue.topic_or_err(topic_id)?;
+        topic.buffer = None;
+        topic.buffer_capacity = 0;
+        Ok(())
+    }
+
+    /// Get the current size of the message buffer for a topic
+    /// @returns Number of messages currently buffered, or -1 if buffering is not enabled
+    pub fn get_buffer_size(&self, topic_id: u32) -> i32 {
+        let queue = self.lock();
+        queue.topic(topic_id)
+            .and_then(|topic| topic.buffer.as_ref())
+            .map_or(-1, |buffer| buffer.len() as i32)
+    }
+
+    /// Clear all buffered messages for a topic
+    /// @returns Number of messages cleared
+    pub fn clear_buffer(&self, topic_id: u32) -> usize {
+        let mut queue = self.lock();
+        queue.topic_mut(topic_id)
+            .and_then(|topic| topic.buffer.as_mut())
+            .map_or(0, |buffer| buffer.drain(..).count())
+    }
+
+    /// Get the buffered messages of a topic (oldest first)
+    pub fn get_buffered_messages(&self, topic_id: u32) -> Result<Vec<Arc<ThreadSafeMessage>>, JsValue> {
+        let queue = self.lock();
+        let topic = queue.topic(topic_id).ok_or_else(|| JsValue::from_str("Invalid topic ID"))?;
+        Ok(topic.buffer.iter().flatten().cloned().collect())
+    }
+
+    /// Stop invoking subscriber callbacks without removing any subscriptions
+    /// Messages published while paused are still buffered, and are held for
+    /// delivery on `resume`; beyond 10000 held messages the oldest is dropped.
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    /// Resume dispatch, first delivering the held messages in order
+    /// If a callback pauses the queue again, the remaining messages stay held.
+    /// @returns Number of held messages delivered
+    pub fn resume(&self) -> u32 {
+        self.lock().paused = false;
+
+        let mut flushed = 0;
+        loop {
+            let (message, callbacks) = {
+                let mut queue = self.lock();
+                if queue.paused {
+                    break;
+                }
+                let Some(message) = queue.held_messages.pop_front() else {
+                    break;
+                };
+                let callbacks = queue.topic_mut(message.topic_id)
+                    .map_or_else(Vec::new, ThreadSafeTopic::take_callbacks);
+                (message, callbacks)
+            };
+            for callback in callbacks {
+                callback(&message);
+            }
+            flushed += 1;
+        }
+        flushed
+    }
+
+    /// Check if the queue is paused
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Close the queue, dropping every topic, subscriber and held message
+    /// Afterwards publishing, subscribing, creating topics and changing buffers
+    /// fail with "Queue is closed". Closing again does nothing.
+    pub fn close(&self) -> Result<(), JsValue> {
+        let mut queue = self.lock();
+        queue.closed = true;
+        queue.topics.clear();
+        queue.topic_index.clear();
+        queue.held_messages.clear();
+        Ok(())
+    }
+
+    /// Check if `close` has been called
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Get the client ID
+    pub fn get_client_id(&self) -> String {
+        self.lock().client_id.to_string()
+    }
+
+    /// Get the number of topics
+    pub fn topic_count(&self) -> usize {
+        self.lock().topic_index.len()
+    }
+
+    /// Get the number of subscribers of a topic
+    pub fn subscriber_count(&self, topic_id: u32) -> usize {
+        self.lock().topic(topic_id).map_or(0, |topic| topic.subscribers.len())
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch
+fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    /// Subscribe a callback collecting the `u32` payloads it receives
+    fn collect(queue: &ThreadSafeMessageQueue, topic_id: u32) -> Arc<Mutex<Vec<u32>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        queue.subscribe(topic_id, move |msg| {
+            sink.lock().unwrap().push(*msg.payload.downcast_ref::<u32>().unwrap());
+        }).unwrap();
+        received
+    }
+
+    fn payloads(messages: &[Arc<ThreadSafeMessage>]) -> Vec<u32> {
+        messages.iter().map(|msg| *msg.payload.downcast_ref::<u32>().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_publish_from_threads() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        let topic_id = queue.register_topic("events").unwrap();
+        queue.enable_topic_buffer(topic_id, Some(8)).unwrap();
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = received.clone();
+        queue.subscribe(topic_id, move |msg| {
+            counter.fetch_add(*msg.payload.downcast_ref::<usize>().unwrap(), Ordering::SeqCst);
+        }).unwrap();
+
+        let handles: Vec<_> = (1..=4).map(|i| {
+            let queue = queue.clone();
+            thread::spawn(move || queue.publish(topic_id, i as usize).unwrap())
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(received.load(Ordering::SeqCst), 10);
+        assert_eq!(queue.get_buffered_messages(topic_id).unwrap().len(), 4);
+        assert_eq!(*queue.get_buffered_messages(topic_id).unwrap()[0].origin_id, "client1");
+    }
+
+    #[test]
+    fn test_reentrant_publish_and_buffer_overflow() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        let requests = queue.register_topic("requests").unwrap();
+        let responses = queue.register_topic("responses").unwrap();
+        queue.enable_topic_buffer(responses, Some(2)).unwrap();
+
+        let handle = queue.clone();
+        queue.subscribe(requests, move |msg| {
+            let n = *msg.payload.downcast_ref::<u32>().unwrap();
+            handle.publish(responses, n * 10).unwrap();
+        }).unwrap();
+
+        for n in 1..=3u32 {
+            queue.publish(requests, n).unwrap();
+        }
+
+        assert_eq!(payloads(&queue.get_buffered_messages(responses).unwrap()), vec![20, 30]);
+        assert!(!queue.has_topic(99));
+        assert!(queue.unsubscribe(requests, 0));
+        assert_eq!(queue.subscriber_count(requests), 0);
+    }
+
+    #[test]
+    fn test_topics_are_created_looked_up_and_destroyed() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        assert!(queue.create_topic("a").unwrap());
+        assert!(!queue.create_topic("a").unwrap());
+        let a = queue.get_topic_id("a").unwrap();
+        assert_eq!(queue.register_topic("a").unwrap(), a);
+        assert_eq!(queue.get_topic_name(a).as_deref(), Some("a"));
+
+        queue.publish_by_name("b", 1u32).unwrap();
+        assert_eq!(queue.topic_count(), 2);
+
+        assert!(queue.destroy_topic(a));
+        assert!(!queue.destroy_topic(a));
+        assert!(!queue.has_topic(a));
+        assert_eq!(queue.get_topic_id("a"), None);
+        // The name is free again, under a new ID
+        assert_ne!(queue.register_topic("a").unwrap(), a);
+    }
+
+    #[test]
+    fn test_subscribe_once_and_unsubscribe_all() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        let topic = queue.register_topic("events").unwrap();
+        let all = collect(&queue, topic);
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let sink = first.clone();
+        queue.subscribe_once(topic, move |msg| {
+            sink.lock().unwrap().push(*msg.payload.downcast_ref::<u32>().unwrap());
+        }).unwrap();
+
+        queue.publish_batch(vec![(topic, 1u32), (topic, 2u32)]).unwrap();
+        assert_eq!(*all.lock().unwrap(), vec![1, 2]);
+        assert_eq!(*first.lock().unwrap(), vec![1]);
+        assert_eq!(queue.subscriber_count(topic), 1);
+
+        assert_eq!(queue.unsubscribe_all(topic), 1);
+        queue.publish(topic, 3u32).unwrap();
+        assert_eq!(all.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_buffer_resize_clear_and_disable() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        let topic = queue.register_topic("log").unwrap();
+        assert_eq!(queue.get_buffer_size(topic), -1);
+        queue.enable_topic_buffer(topic, None).unwrap();
+        for n in 1..=5u32 {
+            queue.publish(topic, n).unwrap();
+        }
+
+        // Shrinking keeps the newest messages
+        queue.enable_topic_buffer(topic, Some(2)).unwrap();
+        assert_eq!(payloads(&queue.get_buffered_messages(topic).unwrap()), vec![4, 5]);
+        assert_eq!(queue.clear_buffer(topic), 2);
+        assert_eq!(queue.get_buffer_size(topic), 0);
+
+        queue.disable_topic_buffer(topic).unwrap();
+        queue.publish(topic, 6u32).unwrap();
+        assert_eq!(queue.get_buffer_size(topic), -1);
+    }
+
+    #[test]
+    fn test_pause_holds_messages_until_resume() {
+        let queue = ThreadSafeMessageQueue::new("client1");
+        let topic = queue.register_topic("events").unwrap();
+        queue.enable_topic_buffer(topic, Some(4)).unwrap();
+        let received = collect(&queue, topic);
+
+        queue.pause();
+        assert!(queue.is_paused());
+        queue.publish(topic, 1u32).unwrap();
+        queue.publish(topic, 2u32).unwrap();
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(queue.get_buffer_size(topic), 2);
+
+        assert_eq!(queue.resume(), 2);
+        assert_eq!(*received.lock().unwrap(), vec![1, 2]);
+
+        queue.close().unwrap();
+        assert!(queue.is_closed());
+        assert_eq!(queue.topic_count(), 0);
+        assert!(!queue.has_topic(topic));
+    }
+}
@@ -0,0 +1,141 @@
+use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::types::InnerQueue;
+
+/// Operations recorded by a `TransactionQueue`
+#[derive(Default)]
+pub(crate) struct TransactionLog {
+    /// Number of topics in the queue when the transaction started
+    pub base_len: usize,
+    /// Names of topics to create, in order (their IDs follow `base_len`)
+    pub new_topics: Vec<String>,
+    /// Queued publishes as (topic_id, payload)
+    pub publishes: Vec<(u32, JsValue)>,
+    /// Set once the transaction callback has returned
+    pub finished: bool,
+}
+
+/// Proxy passed to `MessageQueue::transaction` callbacks
+/// Records `create_topic` / `register_topic` / `publish` calls without executing
+/// them; they are applied atomically once the callback returns, or not at all.
+#[wasm_bindgen]
+pub struct TransactionQueue {
+    /// Queue state, used read-only to resolve existing topic names
+    inner: Rc<RefCell<InnerQueue>>,
+    /// Recorded operations shared with the owning transaction
+    log: Rc<RefCell<TransactionLog>>,
+}
+
+impl TransactionQueue {
+    pub(crate) fn new(inner: Rc<RefCell<InnerQueue>>, log: Rc<RefCell<TransactionLog>>) -> Self {
+        TransactionQueue { inner, log }
+    }
+
+    /// Normalize a topic name, validating it unless the topic already exists
+    fn normalize_new_name(&self, topic_name: &str) -> Result<String, JsValue> {
+        let queue = self.inner.borrow();
+        let topic_name = queue.normalize_topic_name(topic_name);
+        if queue.topic_id_by_name(&topic_name).is_none() {
+            queue.validate_topic_name(&topic_name)?;
+        }
+        Ok(topic_name.into_owned())
+    }
+
+    fn check_active(&self) -> Result<(), JsValue> {
+        if self.log.borrow().finished {
+            Err(JsValue::from_str("Transaction already finished"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl TransactionQueue {
+    /// Queue the creation of a topic
+    /// The name is normalized and validated like `MessageQueue::register_topic`.
+    /// @returns The ID the topic will have once the transaction is applied
+    pub fn register_topic(&self, topic_name: &str) -> Result<u32, JsValue> {
+        self.check_active()?;
+        let topic_name = self.normalize_new_name(topic_name)?;
+
+        if let Some(id) = self.inner.borrow().topic_id_by_name(&topic_name) {
+            return Ok(id);
+        }
+
+        let mut log = self.log.borrow_mut();
+        let offset = match log.new_topics.iter().position(|name| *name == topic_name) {
+            Some(offset) => offset,
+            None => {
+                log.new_topics.push(topic_name);
+                log.new_topics.len() - 1
+            }
+        };
+        Ok((log.base_len + offset) as u32)
+    }
+
+    /// Queue the creation of a topic, like `MessageQueue::create_topic`
+    /// @returns false if the topic already exists or its creation is already queued
+    pub fn create_topic(&self, topic_name: &str) -> Result<bool, JsValue> {
+        self.check_active()?;
+        let topic_name = self.normalize_new_name(topic_name)?;
+        let exists = self.inner.borrow().topic_id_by_name(&topic_name).is_some()
+            || self.log.borrow().new_topics.contains(&topic_name);
+        if !exists {
+            self.register_topic(&topic_name)?;
+        }
+        Ok(!exists)
+    }
+
+    /// Queue a publish, delivered when the transaction is applied
+    pub fn publish(&self, topic_id: u32, payload: JsValue) -> Result<(), JsValue> {
+        self.check_active()?;
+        self.log.borrow_mut().publishes.push((topic_id, payload));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorded_topics_get_the_ids_they_will_have() {
+        let inner = Rc::new(RefCell::new(InnerQueue::default()));
+        let existing = inner.borrow_mut().get_or_create_topic_id("existing");
+        let log = Rc::new(RefCell::new(TransactionLog {
+            base_len: inner.borrow().topics.len(),
+            ..Default::default()
+        }));
+        let tx = TransactionQueue::new(inner.clone(), log.clone());
+
+        assert_eq!(tx.register_topic("existing").unwrap(), existing);
+        assert!(!tx.create_topic("existing").unwrap());
+        assert!(tx.create_topic("new").unwrap());
+        assert!(!tx.create_topic("new").unwrap());
+        assert_eq!(tx.register_topic("new").unwrap(), 1);
+        assert_eq!(log.borrow().new_topics, vec!["new".to_string()]);
+        // Nothing is created before the transaction is applied
+        assert_eq!(inner.borrow().topics.len(), 1);
+    }
+
+    #[test]
+    fn test_recorded_topic_names_are_normalized() {
+        let inner = Rc::new(RefCell::new(InnerQueue::default()));
+        inner.borrow_mut().trim_topic_names = true;
+        inner.borrow_mut().fold_topic_name_case = true;
+        let existing = inner.borrow_mut().get_or_create_topic_id("existing");
+        let log = Rc::new(RefCell::new(TransactionLog {
+            base_len: inner.borrow().topics.len(),
+            ..Default::default()
+        }));
+        let tx = TransactionQueue::new(inner.clone(), log.clone());
+
+        assert_eq!(tx.register_topic(" Existing ").unwrap(), existing);
+        assert!(tx.create_topic("New").unwrap());
+        assert!(!tx.create_topic(" new").unwrap());
+        assert_eq!(log.borrow().new_topics, vec!["new".to_string()]);
+    }
+}
@@ -1,17 +1,31 @@
 use crate::ring_buffer::RingBuffer;
-use std::collections::HashMap;
-use web_sys::BroadcastChannel;
-use wasm_bindgen::JsValue;
-use js_sys::Function;
+use crate::js_utils::estimate_payload_size;
+use crate::dedup::DedupWindow;
+use crate::pattern::PatternSubscriber;
+use crate::idle::IdleDelivery;
+use crate::storage::StorageTransport;
+use crate::bridge::WebSocketBridge;
+use crate::idb::PersistentBuffer;
+use crate::stream::ReadableSource;
+use crate::subscribers::SubscriberList;
+use crate::constants::{DEFAULT_MAX_DISPATCH_DEPTH, DEFAULT_MAX_HELD_MESSAGES, DEFAULT_MAX_TOPIC_NAME_LEN, DEFAULT_SYNC_RESPONSE_LIMIT, TOPIC_GENERATION_MASK, TOPIC_SLOT_BITS, TOPIC_SLOT_MASK};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use web_sys::{BroadcastChannel, Event, EventTarget, MessagePort};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::closure::Closure;
+use js_sys::{Array, Function, Reflect};
 use std::rc::Rc;
+use std::cell::{Cell, RefCell};
 
 /// A lightweight message struct for internal message queue logic.
 /// The payload is handled as raw JsValue to avoid serialization overhead.
 #[derive(Clone)]
 pub struct Message {
     /// Unique message identifier
-    /// Uses u64 to avoid heap allocation for small messages
-    /// Only converted to string when crossing JS boundary
+    /// A hash of the origin client ID in the high 32 bits and a per-client
+    /// sequence number in the low 32 bits. Crosses the JS boundary as a BigInt.
     pub id: u64,
     /// Topic ID this message belongs to (optimized from String)
     pub topic_id: u32,
@@ -21,39 +35,289 @@ pub struct Message {
     pub timestamp: f64,
     /// ID of the client that originated this message
     pub origin_id: Rc<String>,
+    /// Optional metadata (trace IDs, content type, ...) kept apart from the payload
+    pub headers: Option<js_sys::Object>,
+    /// Time (milliseconds since epoch) after which the message is discarded
+    pub expires_at: Option<f64>,
+    /// Per-topic sequence number assigned by the origin client, starting at 1
+    /// 0 for messages that are not sequenced (e.g. `publish_async`)
+    pub seq: u64,
+}
+
+/// Optional per-message settings for publishing
+#[derive(Default)]
+pub struct PublishOptions {
+    /// Headers attached to the message
+    pub headers: Option<js_sys::Object>,
+    /// Time to live in milliseconds
+    pub ttl_ms: Option<f64>,
+    /// Stamp the topic's next sequence number (set for broadcast publishes)
+    pub sequenced: bool,
+}
+
+// JsValue is only a stub on non-wasm targets, so payload and origin are omitted
+#[cfg(not(target_arch = "wasm32"))]
+impl std::fmt::Debug for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Message")
+            .field("id", &self.id)
+            .field("topic_id", &self.topic_id)
+            .field("timestamp", &self.timestamp)
+            .finish()
+    }
+}
+
+impl Message {
+    /// JS value of the message's headers, or undefined without headers
+    pub fn headers_js(&self) -> JsValue {
+        self.headers.as_ref().map_or(JsValue::UNDEFINED, JsValue::from)
+    }
+
+    /// Check if the message's TTL has run out at time `now`
+    pub fn is_expired_at(&self, now: f64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+    /// Check if the message's TTL has run out
+    /// Only reads the clock for messages that have a TTL
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some() && self.is_expired_at(js_sys::Date::now())
+    }
+}
+
+/// A subscriber registered on a topic
+pub struct Subscriber {
+    /// Callback function invoked for each delivered message
+    pub callback: Function,
+    /// Number of upcoming messages to skip before delivering
+    pub skip_remaining: u32,
+    /// Remove the subscriber after its first delivery
+    pub once: bool,
+    /// Called with a reason string if the topic is destroyed while subscribed
+    pub on_destroy: Option<Function>,
+    /// Deliver the message as a single {payload, topic_id, topic, timestamp, id, origin_id}
+    /// object instead of positional arguments
+    pub raw: bool,
+    /// Deliver messages during browser idle time instead of synchronously
+    pub idle: Option<IdleDelivery>,
+    /// Only deliver messages whose payload passes this filter
+    pub filter: Option<SubscriberFilter>,
+    /// Set while the subscriber's buffer replay is running: live messages are
+    /// queued here and delivered once the replay completes, preserving order
+    pub deferred: Option<Rc<RefCell<VecDeque<Rc<Message>>>>>,
+    /// Skip deliveries until resumed; other subscribers are unaffected
+    pub paused: bool,
+    /// Newest buffered message when the subscriber was paused, marking where
+    /// a replay of missed messages starts
+    pub paused_after: Option<Rc<Message>>,
+    /// `abort` listener of the signal given to `subscribe_with_signal`
+    /// Removed from the signal together with the subscriber.
+    pub abort_listener: Option<EventListener>,
+}
+
+/// A condition a message payload must meet to be delivered to a subscriber
+#[derive(Clone)]
+pub enum SubscriberFilter {
+    /// User predicate called with the payload; delivers if it returns truthy
+    /// Evaluated at delivery time, after the queue borrow is released
+    Predicate(Function),
+    /// Delivers if `payload[key] === expected`, evaluated without calling user code
+    KeyEquals { key: JsValue, expected: JsValue },
+}
+
+impl SubscriberFilter {
+    /// Evaluate the parts of the filter that run inside `dispatch_local`
+    /// Predicates always pass here and are checked by `accepts` on delivery
+    pub fn accepts_key(&self, payload: &JsValue) -> bool {
+        match self {
+            SubscriberFilter::Predicate(_) => true,
+            SubscriberFilter::KeyEquals { key, expected } => {
+                payload.is_object()
+                    && Reflect::get(payload, key).is_ok_and(|value| value == *expected)
+            }
+        }
+    }
+
+    /// Evaluate the filter, calling the predicate if there is one
+    /// A predicate that throws rejects the message
+    pub fn accepts(&self, payload: &JsValue) -> bool {
+        match self {
+            SubscriberFilter::Predicate(predicate) => predicate
+                .call1(&JsValue::NULL, payload)
+                .is_ok_and(|result| result.is_truthy()),
+            SubscriberFilter::KeyEquals { .. } => self.accepts_key(payload),
+        }
+    }
+
+    /// The predicate to call on delivery, if any
+    pub fn predicate(&self) -> Option<&Function> {
+        match self {
+            SubscriberFilter::Predicate(predicate) => Some(predicate),
+            SubscriberFilter::KeyEquals { .. } => None,
+        }
+    }
+}
+
+impl Subscriber {
+    pub fn new(callback: Function) -> Self {
+        Subscriber {
+            callback,
+            skip_remaining: 0,
+            once: false,
+            on_destroy: None,
+            raw: false,
+            idle: None,
+            filter: None,
+            deferred: None,
+            paused: false,
+            paused_after: None,
+            abort_listener: None,
+        }
+    }
+
+    /// Check if deliveries to this subscriber are filtered
+    pub fn has_filter(&self) -> bool {
+        self.filter.is_some()
+    }
 }
 
 /// Represents a topic with its subscribers
 pub struct Topic {
     /// The name of the topic
     pub name: String,
-    /// Map of subscriber ID to callback function
-    pub subscribers: HashMap<u32, Function>,
-    /// Next subscriber ID to assign
-    pub next_id: u32,
+    /// Subscribers, in dispatch order
+    pub subscribers: SubscriberList,
     /// Optional message buffer (ring buffer) for caching messages
     /// If None, messages are not buffered
     buffer: Option<RingBuffer>,
+    /// Console label for debug logging of dispatched messages
+    /// Only used when built with the `console-logging` feature
+    pub log_label: Option<String>,
+    /// Replay buffered messages to every new subscriber (RxJS `ReplaySubject` semantics)
+    pub is_replay_subject: bool,
+    /// Number of messages dropped because the buffer was full
+    /// Counts displaced, skipped and rejected messages, depending on `overflow_policy`
+    pub overflow_count: u64,
+    /// Number of messages `readable_stream`s dropped because they were full
+    /// and the topic has no buffer to catch up from
+    pub stream_dropped: u64,
+    /// What happens when a message arrives at a full buffer
+    pub overflow_policy: OverflowPolicy,
+    /// Called with each message lost to a full buffer, set with `on_overflow`
+    pub overflow_hook: Option<Function>,
+    /// Maximum age of buffered messages in milliseconds, set with `set_buffer_retention`
+    pub retention_ms: Option<f64>,
+    /// Maximum estimated size of buffered payloads in bytes, set with `set_buffer_max_bytes`
+    pub max_bytes: Option<usize>,
+    /// Estimates the size of object payloads for the byte budget
+    pub sizer: Option<Function>,
+    /// IndexedDB store this topic's messages are written to, set with `enable_persistent_buffer`
+    pub persistence: Option<Rc<PersistentBuffer>>,
+    /// Target re-emitting this topic's messages as events, created by `as_event_target`,
+    /// and the ID of the subscriber feeding it
+    pub event_target: Option<(EventTarget, u32)>,
+    /// The topic ID as a JS number, created on the first delivery and passed
+    /// to every subscriber callback after that
+    pub id_value: Option<JsValue>,
+    /// Number of messages dispatched on this topic
+    pub total_dispatched: u64,
+    /// Timestamp of the most recently dispatched message
+    pub last_message_time: Option<f64>,
+    /// Application-defined tags for logical grouping
+    pub tags: Vec<String>,
+    /// Last message published with `publish_retained`, delivered to late subscribers
+    pub retained: Option<Rc<Message>>,
+    /// Handler answering `request` calls on this topic, registered with `respond`
+    pub responder: Option<Function>,
+    /// Pattern subscribers matching this topic's name, as (sub_id, callback)
+    /// Computed on first dispatch; None after patterns change or the topic is renamed
+    pub pattern_matches: Option<Vec<(u32, Function)>>,
+    /// Incremented each time the slot is reused, so stale IDs can be detected
+    pub generation: u32,
+    /// False once the topic has been destroyed (the slot is a tombstone)
+    pub alive: bool,
+    /// Sequence number of the last sequenced message published in this client
+    pub last_seq: u64,
+    /// Message counters for this topic (kept when the buffer is toggled)
+    pub stats: Stats,
+    /// Never broadcast this topic's messages nor include them in SYNC_RESP,
+    /// set with `set_topic_local_only`
+    pub local_only: bool,
+    /// Broadcast this topic's messages without dispatching them in this tab,
+    /// set with `set_topic_broadcast_only`
+    pub broadcast_only: bool,
+    /// Handle of the channel this topic's messages are posted on instead of the
+    /// primary transports, set with `route_topic`
+    pub route: Option<u32>,
+    /// Maximum number of subscribers, set with `set_max_subscribers`
+    pub max_subscribers: Option<usize>,
 }
 
 impl Topic {
     pub fn new(name: String) -> Self {
         Topic {
             name,
-            subscribers: HashMap::new(),
-            next_id: 0,
+            subscribers: SubscriberList::new(),
             buffer: None,
+            log_label: None,
+            is_replay_subject: false,
+            overflow_count: 0,
+            stream_dropped: 0,
+            overflow_policy: OverflowPolicy::default(),
+            overflow_hook: None,
+            retention_ms: None,
+            max_bytes: None,
+            sizer: None,
+            persistence: None,
+            event_target: None,
+            id_value: None,
+            total_dispatched: 0,
+            last_message_time: None,
+            tags: Vec::new(),
+            retained: None,
+            responder: None,
+            pattern_matches: None,
+            generation: 0,
+            alive: true,
+            last_seq: 0,
+            stats: Stats::default(),
+            local_only: false,
+            broadcast_only: false,
+            route: None,
+            max_subscribers: None,
         }
     }
 
+    /// Create the tombstone left behind in a destroyed topic's slot
+    pub fn tombstone(generation: u32) -> Self {
+        let mut topic = Topic::new(String::new());
+        topic.generation = generation;
+        topic.alive = false;
+        topic
+    }
+
     /// Create a topic with a message buffer
     pub fn with_buffer(name: String, capacity: usize) -> Self {
-        Topic {
-            name,
-            subscribers: HashMap::new(),
-            next_id: 0,
-            buffer: Some(RingBuffer::new(capacity)),
-        }
+        let mut topic = Topic::new(name);
+        topic.buffer = Some(RingBuffer::new(capacity));
+        topic
+    }
+
+    /// Register a subscriber and return its assigned ID
+    pub fn add_subscriber(&mut self, subscriber: Subscriber) -> u32 {
+        self.subscribers.insert(subscriber)
+    }
+
+    /// Register a subscriber with a dispatch order (lower is called first)
+    pub fn add_subscriber_ordered(&mut self, subscriber: Subscriber, order: i32) -> u32 {
+        self.subscribers.insert_ordered(subscriber, order)
+    }
+
+    /// ID of the first subscriber whose callback is `callback` (`Object.is`)
+    pub fn find_subscriber(&self, callback: &Function) -> Option<u32> {
+        self.subscribers.iter()
+            .find(|(_, subscriber)| js_sys::Object::is(&subscriber.callback, callback))
+            .map(|(sub_id, _)| sub_id)
     }
 
     /// Enable message buffering with the given capacity
@@ -61,6 +325,12 @@ impl Topic {
         self.buffer.replace(RingBuffer::new(capacity))
     }
 
+    /// Resize the buffer in place, keeping the newest messages that fit
+    /// Returns the number of dropped messages, or None without a buffer
+    pub fn resize_buffer(&mut self, capacity: usize) -> Option<usize> {
+        self.buffer.as_mut().map(|buffer| buffer.resize(capacity))
+    }
+
     /// Disable message buffering
     pub fn disable_buffer(&mut self) -> Option<RingBuffer> {
         self.buffer.take()
@@ -80,21 +350,766 @@ impl Topic {
     pub fn get_buffer_mut(&mut self) -> Option<&mut RingBuffer> {
         self.buffer.as_mut()
     }
+
+    /// Estimated size of a payload in bytes, or 0 without a byte budget
+    pub fn payload_size(&self, payload: &JsValue) -> usize {
+        if self.max_bytes.is_none() {
+            return 0;
+        }
+        estimate_payload_size(payload, self.sizer.as_ref())
+    }
+
+    /// Re-estimate the buffered payloads and evict the oldest messages beyond the byte budget
+    /// Returns the evicted messages, which are counted in the overflow count
+    pub fn measure_buffer(&mut self) -> Vec<Rc<Message>> {
+        let (max_bytes, sizer) = (self.max_bytes, self.sizer.clone());
+        let Some(buffer) = self.buffer.as_mut() else {
+            return Vec::new();
+        };
+        buffer.measure(|msg| match max_bytes {
+            Some(_) => estimate_payload_size(&msg.payload, sizer.as_ref()),
+            None => 0,
+        });
+        let evicted = max_bytes.map_or_else(Vec::new, |max_bytes| buffer.evict_to_bytes(max_bytes));
+        self.overflow_count += evicted.len() as u64;
+        evicted
+    }
+
+    /// Check if a message is past its TTL or older than the buffer retention at `now`
+    pub fn is_stale_at(&self, msg: &Message, now: f64) -> bool {
+        msg.is_expired_at(now) || self.retention_ms.is_some_and(|max_age| msg.timestamp < now - max_age)
+    }
+
+    /// Drop buffered messages whose TTL ran out or that outlived the retention before `now`
+    /// Returns the number of messages dropped
+    pub fn purge_expired_at(&mut self, now: f64) -> usize {
+        let cutoff = self.retention_ms.map_or(f64::NEG_INFINITY, |max_age| now - max_age);
+        self.buffer.as_mut().map_or(0, |buffer| {
+            buffer.retain(|msg| !msg.is_expired_at(now) && msg.timestamp >= cutoff)
+        })
+    }
+
+    /// Pop messages older than the retention from the front of the buffer at `now`
+    /// Cheaper than `purge_expired_at`, since buffered messages are mostly in
+    /// timestamp order; any left behind a newer one are dropped when the buffer is read.
+    pub fn evict_aged_at(&mut self, now: f64) -> usize {
+        match (self.retention_ms, self.buffer.as_mut()) {
+            (Some(max_age), Some(buffer)) => buffer.evict_older_than(now - max_age),
+            _ => 0,
+        }
+    }
+
+    /// Pop messages older than the retention from the front of the buffer
+    /// Only reads the clock if the topic has a retention
+    pub fn evict_aged(&mut self) -> usize {
+        if self.retention_ms.is_some() && self.has_buffer() {
+            self.evict_aged_at(js_sys::Date::now())
+        } else {
+            0
+        }
+    }
+
+    /// Drop buffered messages whose TTL has run out or that outlived the retention
+    /// Only reads the clock if the topic has a retention or a buffered message has a TTL
+    pub fn purge_expired(&mut self) -> usize {
+        let has_ttl = self.buffer.as_ref()
+            .is_some_and(|buffer| buffer.iter().any(|msg| msg.expires_at.is_some()));
+        if has_ttl || (self.retention_ms.is_some() && self.has_buffer()) {
+            self.purge_expired_at(js_sys::Date::now())
+        } else {
+            0
+        }
+    }
+}
+
+/// What `publish_async` does when the pending limit set with `set_max_pending` is reached
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the oldest pending message to make room; its Promise resolves undelivered
+    DropOldest,
+    /// Drop the new message; its Promise resolves undelivered
+    DropNewest,
+    /// Reject the new message's Promise with "Queue full"
+    #[default]
+    Reject,
+}
+
+/// What a full topic buffer does with a new message
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Overwrite the oldest buffered message
+    #[default]
+    DropOldest,
+    /// Keep the buffer as is; the new message is still delivered
+    DropNewest,
+    /// Reject publishing in this tab; messages from other tabs are dropped as with `DropNewest`
+    Error,
+}
+
+impl OverflowPolicy {
+    /// Parse the policy names accepted by `enable_topic_buffer_with_policy`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "drop-oldest" => Some(OverflowPolicy::DropOldest),
+            "drop-newest" => Some(OverflowPolicy::DropNewest),
+            "error" => Some(OverflowPolicy::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Async publishes collected for the next coalesced flush (see `AsyncMode::Coalesced`)
+pub struct CoalescedFlush {
+    /// (topic ID, payload) of each publish, in call order
+    pub publishes: VecDeque<(u32, JsValue)>,
+    /// Promise returned to every publisher of this flush
+    pub promise: js_sys::Promise,
+    /// Resolves the shared Promise
+    pub resolve: Function,
+    /// Rejects the shared Promise
+    pub reject: Function,
 }
 
+impl CoalescedFlush {
+    pub fn new() -> Self {
+        let mut settle = None;
+        let promise = js_sys::Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        let (resolve, reject) = settle.expect("Promise executor runs synchronously");
+        CoalescedFlush { publishes: VecDeque::new(), promise, resolve, reject }
+    }
+}
+
+/// A message waiting in a topic's priority queue for asynchronous delivery
+/// Ordered by priority, then by publish order (earlier first)
+pub struct QueuedPublish {
+    /// Delivery priority (higher is delivered first)
+    pub priority: u8,
+    /// Publish order among queued messages
+    pub seq: u64,
+    /// Message payload
+    pub payload: JsValue,
+    /// Resolves the Promise returned to the publisher
+    pub resolve: Function,
+    /// Rejects the Promise returned to the publisher
+    pub reject: Function,
+}
+
+impl Ord for QueuedPublish {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedPublish {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedPublish {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedPublish {}
+
 /// Internal queue state
-#[derive(Default)]
 pub struct InnerQueue {
-    /// List of topics, accessible by index (ID)
+    /// Topic slots, addressed by the slot bits of a topic ID
+    /// Destroyed topics leave a tombstone that is reused by the next new topic
     pub topics: Vec<Topic>,
-    /// Map of topic name to topic index (ID)
+    /// Map of topic name to slot index (live topics only)
     pub topic_index: HashMap<String, usize>,
-    /// Optional broadcast channel for cross-tab communication
-    pub channel: Option<BroadcastChannel>,
+    /// Tombstoned slots available for reuse
+    pub free_slots: Vec<usize>,
+    /// Channels to other tabs and workers: the BroadcastChannel and attached ports
+    /// Published packets are posted on every transport
+    pub transports: Vec<Transport>,
+    /// Additional named channels attached with `attach_channel`, as (handle, transport)
+    /// Only topics routed to a channel post on it; packets from all channels are handled alike.
+    pub channels: Vec<(u32, Transport)>,
+    /// Next channel handle to assign (0 is the primary channel)
+    pub next_channel_handle: u32,
+    /// Name of the broadcast channel, if any
+    /// Kept after `disconnect`, so `reconnect` can rejoin the channel
+    pub channel_name: Option<String>,
+    /// Use localStorage for the channel even if BroadcastChannel exists
+    pub force_storage: bool,
     /// Unique client identifier
     pub client_id: Rc<String>,
-    /// Set of seen message IDs to prevent duplicates (especially during sync)
-    pub seen_ids: std::collections::HashSet<u64>,
+    /// High 32 bits of every message ID created by this client (hash of `client_id`)
+    pub message_id_prefix: u64,
+    /// Sequence number of the next message created by this client
+    pub next_message_seq: Cell<u32>,
+    /// Recently seen message IDs to prevent duplicates (especially during sync)
+    pub seen_ids: DedupWindow,
+    /// Map of tag to the IDs of topics carrying it
+    pub tag_index: HashMap<String, Vec<u32>>,
+    /// Callbacks fired when the backpressure indicator crosses a threshold
+    pub backpressure_hooks: Vec<BackpressureHook>,
+    /// Interceptors applied to incoming broadcast messages, keyed by handle
+    pub broadcast_interceptors: Vec<(u32, Function)>,
+    /// Next interceptor handle to assign
+    pub next_interceptor_id: u32,
+    /// DOM event listeners that publish events to topics
+    pub event_listeners: Vec<EventListener>,
+    /// Minimum interval between broadcasts in milliseconds (0 = unthrottled)
+    pub broadcast_throttle_ms: f64,
+    /// Message objects waiting for the next throttled broadcast
+    pub pending_broadcasts: Vec<(Option<u32>, JsValue)>,
+    /// Whether a throttled flush is already scheduled
+    pub broadcast_flush_scheduled: bool,
+    /// Diagnostic log level (0 = off ... 4 = debug)
+    /// Only used when built with the `wasm-logging` feature
+    pub log_level: Cell<u8>,
+    /// Number of `publish_async` deliveries scheduled but not yet run
+    pub pending_async: Rc<Cell<usize>>,
+    /// Set by `MessageQueue::close`; mutating methods fail afterwards
+    pub closed: bool,
+    /// Whether local dispatch is paused (see `MessageQueue::pause`)
+    pub paused: bool,
+    /// Messages held while paused, oldest first
+    pub held_messages: VecDeque<Rc<Message>>,
+    /// Maximum number of held messages; the oldest is dropped beyond it
+    pub max_held_messages: usize,
+    /// Maximum number of pending async publishes (0 = unlimited)
+    pub max_pending: usize,
+    /// What to do with async publishes beyond `max_pending`
+    pub drop_policy: DropPolicy,
+    /// Number of async publishes dropped by the drop policy
+    pub dropped_async: u64,
+    /// Asynchronous publishes awaiting delivery, per topic ID
+    /// A topic has an entry while a drain microtask is scheduled for it
+    pub priority_queues: HashMap<u32, BinaryHeap<QueuedPublish>>,
+    /// Sequence number of the next queued publish
+    pub next_queued_seq: u64,
+    /// How `publish_async` schedules delivery
+    pub async_mode: AsyncMode,
+    /// Publishes waiting for the coalesced flush scheduled this tick, if any
+    pub coalesced: Option<CoalescedFlush>,
+    /// Dispatch this tab's publishes from a later task instead of inline,
+    /// the way other tabs receive them (see `MessageQueue::set_echo`)
+    pub echo: bool,
+    /// Published messages waiting for the echo task, in publish order
+    pub pending_echoes: Vec<Rc<Message>>,
+    /// Whether the echo task is already scheduled
+    pub echo_scheduled: bool,
+    /// Current nesting depth of `dispatch_local`
+    pub dispatch_depth: Cell<usize>,
+    /// Nesting depth above which dispatch is skipped
+    pub max_dispatch_depth: usize,
+    /// Called with (error, topic_name, sub_id, message_id) when dispatch fails
+    pub error_handler: Option<Function>,
+    /// Write dispatch errors to `console.error` when no error handler is set
+    pub debug: bool,
+    /// Whether `publish_by_name` creates unknown topics instead of failing
+    pub auto_create_topics: bool,
+    /// Wildcard subscribers, keyed by handle, in subscription order
+    pub pattern_subscribers: Vec<(u32, PatternSubscriber)>,
+    /// Next pattern subscriber handle to assign
+    pub next_pattern_sub_id: u32,
+    /// Topic lifecycle hooks with their handles, in registration order
+    pub topic_hooks: Vec<(u32, TopicEvent, Function)>,
+    /// Next topic hook handle to assign
+    pub next_topic_hook_id: u32,
+    /// Lifecycle changes not yet passed to the topic hooks: (event, topic ID, name)
+    pub topic_events: Vec<(TopicEvent, u32, String)>,
+    /// Sources of `readable_stream`s, keyed by handle, until cancelled or ended
+    pub readable_sources: HashMap<u32, Rc<ReadableSource>>,
+    /// Next readable stream handle to assign
+    pub next_readable_id: u32,
+    /// Subscribers to every message on every topic, keyed by handle
+    pub global_subscribers: HashMap<u32, Function>,
+    /// Next global subscriber handle to assign
+    pub next_global_sub_id: u32,
+    /// RPC requests made by this client awaiting a reply, keyed by correlation ID
+    pub pending_requests: HashMap<u64, PendingRequest>,
+    /// Syncs started with `sync_topics` or `request_sync_since` that are still collecting responses
+    pub pending_syncs: Vec<PendingSync>,
+    /// Maximum number of messages in a response to an incremental SYNC_REQ
+    pub sync_response_limit: usize,
+    /// SYNC_REQ answers waiting out their random delay, by ID
+    pub pending_sync_answers: HashMap<u64, PendingSyncAnswer>,
+    /// Answer every SYNC_REQ right away instead of electing one responder
+    pub sync_respond_always: bool,
+    /// Other clients on the channel and when they were last heard from
+    pub peers: HashMap<String, f64>,
+    /// Called with (peer_id, "joined" | "left") when the peer set changes
+    pub peer_change_handler: Option<Function>,
+    /// Heartbeat timer, while presence is enabled
+    pub presence: Option<PresenceTimer>,
+    /// Client ID of the current leader, if known
+    pub leader_id: Option<String>,
+    /// Whether this client takes part in leader elections
+    pub leader_candidate: bool,
+    /// Election in progress, if any
+    pub election: Option<Election>,
+    /// Called with whether this client is the leader when that changes
+    pub leadership_handler: Option<Function>,
+    /// Client that last answered a request, by topic name
+    pub rpc_responders: HashMap<String, String>,
+    /// Called with the client ID of a peer that sent a goodbye packet
+    pub peer_closed_handler: Option<Function>,
+    /// Last sequence number received per origin client and topic ID
+    pub remote_seqs: HashMap<String, HashMap<u32, u64>>,
+    /// Called with {topic, origin, expected, received} when a sequence gap is detected
+    pub gap_handler: Option<Function>,
+    /// Gaps detected but not yet passed to `gap_handler`
+    pub sequence_gaps: Vec<SequenceGap>,
+    /// Topic receiving undeliverable and failed messages, if set
+    pub dead_letter: Option<DeadLetterConfig>,
+    /// Delayed and repeating publishes that have not fired yet, keyed by handle
+    pub scheduled_publishes: HashMap<u32, ScheduledPublish>,
+    /// Next schedule handle to assign
+    pub next_schedule_id: u32,
+    /// Message counters across all topics
+    pub stats: Stats,
+    /// Encoding of published messages sent to other tabs
+    pub wire_format: WireFormat,
+    /// Whether attaching a transport sends a SYNC_REQ for the other side's buffers
+    pub sync_on_connect: bool,
+    /// Buffer capacity given to newly created topics, if any
+    pub default_buffer_capacity: Option<usize>,
+    /// Buffer capacity given to topics created by messages from other tabs, if any
+    pub remote_topic_buffer: Option<usize>,
+    /// Maximum length of new topic names, in characters
+    pub max_topic_name_len: usize,
+    /// Trim whitespace around topic names given to `register_topic`, `create_topic`
+    /// and `publish_by_name`
+    pub trim_topic_names: bool,
+    /// Lowercase topic names given to `register_topic`, `create_topic` and `publish_by_name`
+    pub fold_topic_name_case: bool,
+    /// Application-specific check of new topic names, set with `set_topic_name_validator`
+    pub topic_name_validator: Option<Function>,
+    /// Received messages dropped while the queue was borrowed, as (error, message ID)
+    /// Reported to the error handler once the borrow is released.
+    pub receive_errors: Vec<(JsValue, u64)>,
+    /// Publishes rejected by a full buffer under the "error" policy, with the topic's overflow hook
+    /// Passed to the hooks by `notify_rejected` once the borrow is released.
+    pub rejected_publishes: Vec<(Function, Rc<Message>)>,
+    /// WebSocket connecting selected topics to a server, if bridged
+    pub bridge: Option<WebSocketBridge>,
+}
+
+impl Default for InnerQueue {
+    fn default() -> Self {
+        InnerQueue {
+            topics: Vec::new(),
+            topic_index: HashMap::new(),
+            free_slots: Vec::new(),
+            transports: Vec::new(),
+            channels: Vec::new(),
+            next_channel_handle: 1,
+            channel_name: None,
+            force_storage: false,
+            client_id: Rc::default(),
+            message_id_prefix: 0,
+            next_message_seq: Cell::new(0),
+            seen_ids: Default::default(),
+            tag_index: HashMap::new(),
+            backpressure_hooks: Vec::new(),
+            broadcast_interceptors: Vec::new(),
+            next_interceptor_id: 0,
+            event_listeners: Vec::new(),
+            broadcast_throttle_ms: 0.0,
+            pending_broadcasts: Vec::new(),
+            broadcast_flush_scheduled: false,
+            log_level: Cell::new(0),
+            pending_async: Rc::default(),
+            closed: false,
+            paused: false,
+            held_messages: VecDeque::new(),
+            max_held_messages: DEFAULT_MAX_HELD_MESSAGES,
+            max_pending: 0,
+            drop_policy: DropPolicy::default(),
+            dropped_async: 0,
+            priority_queues: HashMap::new(),
+            next_queued_seq: 0,
+            async_mode: AsyncMode::default(),
+            coalesced: None,
+            echo: false,
+            pending_echoes: Vec::new(),
+            echo_scheduled: false,
+            dispatch_depth: Cell::new(0),
+            max_dispatch_depth: DEFAULT_MAX_DISPATCH_DEPTH,
+            error_handler: None,
+            debug: false,
+            auto_create_topics: true,
+            pattern_subscribers: Vec::new(),
+            next_pattern_sub_id: 0,
+            topic_hooks: Vec::new(),
+            next_topic_hook_id: 0,
+            topic_events: Vec::new(),
+            readable_sources: HashMap::new(),
+            next_readable_id: 0,
+            global_subscribers: HashMap::new(),
+            next_global_sub_id: 0,
+            pending_requests: HashMap::new(),
+            pending_syncs: Vec::new(),
+            sync_response_limit: DEFAULT_SYNC_RESPONSE_LIMIT,
+            pending_sync_answers: HashMap::new(),
+            sync_respond_always: false,
+            peers: HashMap::new(),
+            peer_change_handler: None,
+            presence: None,
+            leader_id: None,
+            leader_candidate: false,
+            election: None,
+            leadership_handler: None,
+            rpc_responders: HashMap::new(),
+            peer_closed_handler: None,
+            remote_seqs: HashMap::new(),
+            gap_handler: None,
+            sequence_gaps: Vec::new(),
+            dead_letter: None,
+            scheduled_publishes: HashMap::new(),
+            next_schedule_id: 0,
+            stats: Stats::default(),
+            wire_format: WireFormat::default(),
+            sync_on_connect: true,
+            default_buffer_capacity: None,
+            max_topic_name_len: DEFAULT_MAX_TOPIC_NAME_LEN,
+            trim_topic_names: false,
+            fold_topic_name_case: false,
+            topic_name_validator: None,
+            receive_errors: Vec::new(),
+            rejected_publishes: Vec::new(),
+            remote_topic_buffer: None,
+            bridge: None,
+        }
+    }
+}
+
+/// Encoding of messages broadcast to other tabs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    /// Message objects, structured-cloned by the browser
+    #[default]
+    Json,
+    /// Single `Uint8Array` packets for string and binary payloads (see `wire`)
+    Binary,
+}
+
+/// How `publish_async` schedules delivery
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AsyncMode {
+    /// Each publish is queued by priority and drained in a microtask per topic
+    #[default]
+    Immediate,
+    /// Publishes of a tick are delivered together in a single microtask
+    Coalesced,
+}
+
+/// Message counters kept for the whole queue and for each topic
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Messages created by publishes in this client
+    pub published: u64,
+    /// Subscriber callback invocations
+    pub delivered: u64,
+    /// Subscriber callbacks that threw
+    pub errors: u64,
+    /// Messages sent to other tabs
+    pub broadcast: u64,
+    /// New messages received from other tabs
+    pub received: u64,
+    /// Buffered messages overwritten because the buffer was full
+    pub overwrites: u64,
+    /// Subscribes rejected because the topic had reached its subscriber limit
+    pub rejected_subscribes: u64,
+}
+
+/// Messages from another client were skipped on a topic
+pub struct SequenceGap {
+    /// Name of the topic
+    pub topic: String,
+    /// Client ID of the publisher
+    pub origin: String,
+    /// Sequence number that should have arrived next
+    pub expected: u64,
+    /// Sequence number that arrived instead
+    pub received: u64,
+}
+
+/// Topic lifecycle change reported to `on_topic_created` / `on_topic_destroyed` hooks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopicEvent {
+    Created,
+    Destroyed,
+}
+
+/// Why a message was routed to the dead-letter topic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadLetterReason {
+    /// A subscriber callback threw
+    Error,
+    /// Published with no subscribers and no buffer to receive it
+    NoSubscribers,
+    /// Displaced from a full ring buffer
+    Overflow,
+}
+
+impl DeadLetterReason {
+    /// Value of the `reason` field of dead-letter records
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeadLetterReason::Error => "error",
+            DeadLetterReason::NoSubscribers => "no_subscribers",
+            DeadLetterReason::Overflow => "overflow",
+        }
+    }
+}
+
+/// Dead-letter topic set with `set_dead_letter_topic`
+pub struct DeadLetterConfig {
+    /// Topic receiving the dead-letter records
+    pub topic_id: u32,
+    pub on_error: bool,
+    pub on_no_subscribers: bool,
+    pub on_overflow: bool,
+}
+
+impl DeadLetterConfig {
+    /// Check if messages from `topic_id` are dead-lettered for `reason`
+    /// The dead-letter topic itself never is, so records cannot loop.
+    pub fn accepts(&self, topic_id: u32, reason: DeadLetterReason) -> bool {
+        topic_id != self.topic_id && match reason {
+            DeadLetterReason::Error => self.on_error,
+            DeadLetterReason::NoSubscribers => self.on_no_subscribers,
+            DeadLetterReason::Overflow => self.on_overflow,
+        }
+    }
+}
+
+/// Subscriber callbacks due for one dispatched message
+/// Produced by `InnerQueue::dispatch_local` and invoked by `dispatch::deliver`
+/// once the queue borrow has been released.
+#[must_use]
+pub struct Delivery {
+    /// The dispatched message
+    pub message: Rc<Message>,
+    /// Subscribers to invoke, in dispatch order
+    pub targets: Vec<DeliveryTarget>,
+    /// The topic's cached ID value, present when there are callbacks to invoke
+    pub topic_id_val: Option<JsValue>,
+    /// Message object for raw subscribers (if any)
+    pub msg_obj: Option<JsValue>,
+    /// Dispatch was skipped because the nesting limit was reached
+    pub depth_exceeded: bool,
+    /// Messages to route to the dead-letter topic
+    pub dead_letters: Vec<(Rc<Message>, DeadLetterReason)>,
+    /// Messages lost to a full buffer or the held-message limit, each with its topic's overflow hook
+    pub overflow: Vec<(Function, Rc<Message>)>,
+    /// The topic name and the global subscribers to invoke after the topic's own
+    pub globals: Option<(String, Vec<(u32, Function)>)>,
+    /// Time dispatch started, for debug logging
+    #[cfg(feature = "wasm-logging")]
+    pub started: Option<f64>,
+}
+
+impl Delivery {
+    /// A delivery that only reports "Max dispatch depth exceeded"
+    pub fn depth_exceeded(message: Rc<Message>) -> Self {
+        Delivery {
+            depth_exceeded: true,
+            ..Delivery::losses(message, Vec::new(), Vec::new())
+        }
+    }
+
+    /// A delivery without subscribers that only reports lost messages
+    pub fn losses(message: Rc<Message>, dead_letters: Vec<(Rc<Message>, DeadLetterReason)>, overflow: Vec<(Function, Rc<Message>)>) -> Self {
+        Delivery {
+            message,
+            targets: Vec::new(),
+            topic_id_val: None,
+            msg_obj: None,
+            depth_exceeded: false,
+            dead_letters,
+            overflow,
+            globals: None,
+            #[cfg(feature = "wasm-logging")]
+            started: None,
+        }
+    }
+}
+
+/// A subscriber callback collected for delivery
+pub struct DeliveryTarget {
+    pub sub_id: u32,
+    pub callback: Function,
+    /// Pass the message object instead of positional arguments
+    pub raw: bool,
+    /// Predicate the payload must pass before the callback is invoked
+    pub filter: Option<Function>,
+}
+
+/// A channel packets are exchanged over
+#[derive(Clone)]
+pub enum Transport {
+    /// Every same-origin context listening on the channel name
+    Broadcast(BroadcastChannel),
+    /// The other end of a MessagePort (e.g. a dedicated Worker)
+    Port(MessagePort),
+    /// localStorage `storage` events, where BroadcastChannel is unavailable
+    Storage(Rc<StorageTransport>),
+}
+
+impl Transport {
+    /// Post a packet (structured clone, or JSON for localStorage)
+    pub fn post(&self, packet: &JsValue) -> Result<(), JsValue> {
+        match self {
+            Transport::Broadcast(channel) => channel.post_message(packet).map_err(|_| broadcast_failed()),
+            Transport::Port(port) => port.post_message(packet).map_err(|_| broadcast_failed()),
+            Transport::Storage(storage) => storage.post(packet),
+        }
+    }
+
+    /// Post a packet, transferring the objects in `transfer_list`
+    /// Transports that cannot transfer clone the packet instead.
+    pub fn post_with_transfer(&self, packet: &JsValue, transfer_list: &Array) -> Result<(), JsValue> {
+        match self {
+            Transport::Port(port) => port.post_message_with_transferable(packet, transfer_list)
+                .map_err(|_| broadcast_failed()),
+            _ => self.post(packet),
+        }
+    }
+
+    /// Whether this is the named channel to other tabs rather than an attached port
+    pub fn is_channel(&self) -> bool {
+        !matches!(self, Transport::Port(_))
+    }
+
+    /// Whether `target` (the `currentTarget` of a received event) is this transport's channel or port
+    pub fn is_target(&self, target: &JsValue) -> bool {
+        match self {
+            Transport::Broadcast(channel) => AsRef::<JsValue>::as_ref(channel) == target,
+            Transport::Port(port) => AsRef::<JsValue>::as_ref(port) == target,
+            Transport::Storage(_) => false,
+        }
+    }
+
+    /// Whether `post_with_transfer` actually transfers
+    pub fn supports_transfer(&self) -> bool {
+        matches!(self, Transport::Port(_))
+    }
+
+    /// Install (or with None, remove) the handler for incoming packets
+    /// The handler is called with a MessageEvent (or a `{data}` object for localStorage)
+    pub fn set_onmessage(&self, handler: Option<&Function>) {
+        match self {
+            Transport::Broadcast(channel) => channel.set_onmessage(handler),
+            Transport::Port(port) => port.set_onmessage(handler),
+            Transport::Storage(storage) => storage.set_onmessage(handler),
+        }
+    }
+
+    /// Stop receiving packets and close the underlying channel
+    pub fn close(&self) {
+        self.set_onmessage(None);
+        match self {
+            Transport::Broadcast(channel) => channel.close(),
+            Transport::Port(port) => port.close(),
+            Transport::Storage(storage) => storage.close(),
+        }
+    }
+}
+
+fn broadcast_failed() -> JsValue {
+    JsValue::from_str("Failed to broadcast message")
+}
+
+/// A DOM event listener wired to a topic
+pub struct EventListener {
+    /// Target the listener is attached to
+    pub target: EventTarget,
+    /// Event type being listened for
+    pub event_type: String,
+    /// Listener closure (kept alive until removed)
+    pub closure: Closure<dyn FnMut(Event)>,
+}
+
+impl Drop for EventListener {
+    /// Detach the closure from its target before it is freed
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            &self.event_type,
+            self.closure.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+/// An RPC request awaiting its reply
+pub struct PendingRequest {
+    /// Resolves the Promise returned by `request`
+    pub resolve: Function,
+    /// Rejects the Promise returned by `request`
+    pub reject: Function,
+    /// Handle of the timeout that rejects the request
+    pub timeout: i32,
+    /// Name of the requested topic
+    pub topic_name: String,
+    /// Client expected to answer: the last one that answered on the topic, if known
+    pub target: Option<String>,
+}
+
+/// A `sync_topics` or `request_sync_since` call waiting for responses from other tabs
+pub struct PendingSync {
+    pub id: u64,
+    /// Names of the requested topics, or None for all topics
+    pub topics: Option<Vec<String>>,
+    /// Whether only messages newer than a timestamp were requested
+    pub incremental: bool,
+    /// New messages of the requested topics received so far
+    pub received: u64,
+    /// Set when a response arrived or a follow-up request was sent, which extends the wait
+    pub extended: bool,
+}
+
+/// A SYNC_REQ answer delayed so that only one tab responds
+pub struct PendingSyncAnswer {
+    /// Client ID of the requesting tab
+    pub requester_id: String,
+    /// Handle of the timeout that sends the answer
+    pub timeout: i32,
+}
+
+/// Heartbeat timer of `enable_presence`
+pub struct PresenceTimer {
+    /// Handle of the `setInterval` timer
+    pub timer: i32,
+    /// Timer callback, dropped when presence is disabled
+    pub callback: Closure<dyn FnMut()>,
+}
+
+/// A leader election waiting for lower client IDs to answer
+pub struct Election {
+    /// Resolve the Promises of `elect_leader` calls with the outcome
+    pub resolvers: Vec<Function>,
+    /// Set once a lower client ID took part or claimed leadership
+    pub lost: bool,
+}
+
+/// A publish scheduled with `publish_delayed` or `publish_interval`
+pub struct ScheduledPublish {
+    /// Handle of the `setTimeout` / `setInterval` timer
+    pub timer: i32,
+    /// Whether the timer is an interval
+    pub repeating: bool,
+    /// Timer callback, dropped when the schedule fires (once) or is cancelled
+    pub callback: Closure<dyn FnMut()>,
+}
+
+/// A registered backpressure threshold callback
+pub struct BackpressureHook {
+    /// Utilization level (0.0 - 1.0) that triggers the callback
+    pub threshold: f64,
+    /// Callback invoked with the current utilization
+    pub callback: Function,
+    /// Whether utilization was last observed above the threshold
+    pub above: bool,
 }
 
 impl InnerQueue {
@@ -106,22 +1121,275 @@ impl InnerQueue {
         self.topic_index.get(name).map(|&idx| &mut self.topics[idx])
     }
     
-    pub fn get_topic_by_id(&self, id: usize) -> Option<&Topic> {
-        self.topics.get(id)
+    /// Topic ID for a slot: the slot generation in the high bits, the slot index in the low bits
+    /// Topics in never-reused slots have IDs equal to their slot index.
+    pub fn topic_handle(&self, slot: usize) -> u32 {
+        (self.topics[slot].generation << TOPIC_SLOT_BITS) | slot as u32
+    }
+
+    /// Iterate over (topic ID, topic) for every live topic, in slot order
+    pub fn live_topics(&self) -> impl Iterator<Item = (u32, &Topic)> {
+        self.topics.iter().enumerate()
+            .filter(|(_, topic)| topic.alive)
+            .map(|(slot, topic)| (self.topic_handle(slot), topic))
     }
 
-    pub fn get_topic_by_id_mut(&mut self, id: usize) -> Option<&mut Topic> {
-        self.topics.get_mut(id)
+    /// Resolve a topic ID to its slot index
+    /// Returns None for unknown IDs, destroyed topics and stale IDs of a reused slot
+    pub fn topic_slot(&self, topic_id: u32) -> Option<usize> {
+        let slot = (topic_id & TOPIC_SLOT_MASK) as usize;
+        let topic = self.topics.get(slot)?;
+        (topic.alive && topic.generation == topic_id >> TOPIC_SLOT_BITS).then_some(slot)
     }
 
-    pub fn get_or_create_topic_id(&mut self, name: &str) -> usize {
-        if let Some(&id) = self.topic_index.get(name) {
-            id
+    /// Check if a topic ID refers to a live topic
+    pub fn has_topic_id(&self, topic_id: u32) -> bool {
+        self.topic_slot(topic_id).is_some()
+    }
+
+    /// Look up the ID of a live topic by name
+    pub fn topic_id_by_name(&self, name: &str) -> Option<u32> {
+        self.topic_index.get(name).map(|&slot| self.topic_handle(slot))
+    }
+
+    pub fn get_topic_by_id(&self, topic_id: u32) -> Option<&Topic> {
+        self.topic_slot(topic_id).map(|slot| &self.topics[slot])
+    }
+
+    pub fn get_topic_by_id_mut(&mut self, topic_id: u32) -> Option<&mut Topic> {
+        self.topic_slot(topic_id).map(|slot| &mut self.topics[slot])
+    }
+
+    /// A new topic, with a buffer if the queue has a default buffer capacity
+    fn new_topic(&self, name: &str) -> Topic {
+        let mut topic = Topic::new(name.to_string());
+        if let Some(capacity) = self.default_buffer_capacity {
+            topic.enable_buffer(capacity);
+        }
+        topic
+    }
+
+    pub fn get_or_create_topic_id(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.topic_id_by_name(name) {
+            return id;
+        }
+
+        let topic = self.new_topic(name);
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                let generation = self.topics[slot].generation.wrapping_add(1) & TOPIC_GENERATION_MASK;
+                self.topics[slot] = topic;
+                self.topics[slot].generation = generation;
+                slot
+            }
+            None => {
+                self.topics.push(topic);
+                self.topics.len() - 1
+            }
+        };
+        self.topic_index.insert(name.to_string(), slot);
+        let id = self.topic_handle(slot);
+        self.record_topic_event(TopicEvent::Created, id, name);
+
+        #[cfg(feature = "wasm-logging")]
+        if self.logs_at(crate::constants::LOG_INFO) {
+            crate::logging::log_group(crate::constants::LOG_INFO, "Topic created", &[
+                ("name", JsValue::from_str(name)),
+                ("id", JsValue::from(id)),
+            ]);
+        }
+
+        id
+    }
+
+    /// Apply the queue's topic name normalization (trimming, lowercasing)
+    pub fn normalize_topic_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let name = if self.trim_topic_names { name.trim() } else { name };
+        if self.fold_topic_name_case && name.chars().any(char::is_uppercase) {
+            Cow::Owned(name.to_lowercase())
         } else {
-            let id = self.topics.len();
-            self.topics.push(Topic::new(name.to_string()));
-            self.topic_index.insert(name.to_string(), id);
-            id
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Check the name of a topic about to be created
+    /// Rejects empty names, names longer than `max_topic_name_len` characters
+    /// and names the validator returns a falsy value for or throws on.
+    pub fn validate_topic_name(&self, name: &str) -> Result<(), JsValue> {
+        if name.is_empty() {
+            return Err(JsValue::from_str("Topic name must not be empty"));
+        }
+        if name.chars().nth(self.max_topic_name_len).is_some() {
+            return Err(JsValue::from_str(&format!("Topic name longer than {} characters", self.max_topic_name_len)));
+        }
+        if let Some(validator) = &self.topic_name_validator {
+            if !validator.call1(&JsValue::NULL, &JsValue::from_str(name))?.is_truthy() {
+                return Err(JsValue::from_str(&format!("Invalid topic name: {}", name)));
+            }
         }
+        Ok(())
+    }
+
+    /// Get the ID of the topic named `name`, creating it if the name is valid
+    /// Existing topics are returned without validating their name again.
+    pub fn get_or_create_valid_topic_id(&mut self, name: &str) -> Result<u32, JsValue> {
+        if let Some(id) = self.topic_id_by_name(name) {
+            return Ok(id);
+        }
+        self.validate_topic_name(name)?;
+        Ok(self.get_or_create_topic_id(name))
+    }
+
+    /// Append a new topic without reusing a free slot
+    /// Used by transactions, whose provisional IDs follow the current slot count
+    pub fn push_topic(&mut self, name: &str) -> u32 {
+        let topic = self.new_topic(name);
+        self.topics.push(topic);
+        let slot = self.topics.len() - 1;
+        self.topic_index.insert(name.to_string(), slot);
+        slot as u32
+    }
+
+    /// Destroy a topic, leaving a tombstone so its slot can be reused
+    /// Returns the removed topic, or None if the ID is not a live topic
+    pub fn remove_topic(&mut self, topic_id: u32) -> Option<Topic> {
+        let slot = self.topic_slot(topic_id)?;
+        self.clear_tags(topic_id);
+
+        let generation = self.topics[slot].generation;
+        let topic = std::mem::replace(&mut self.topics[slot], Topic::tombstone(generation));
+        self.topic_index.remove(&topic.name);
+        self.free_slots.push(slot);
+        self.record_topic_event(TopicEvent::Destroyed, topic_id, &topic.name);
+        Some(topic)
+    }
+
+    /// Remember a topic lifecycle change for `notify_topic_events`
+    /// Nothing is recorded unless a hook listens for the event.
+    pub fn record_topic_event(&mut self, event: TopicEvent, topic_id: u32, name: &str) {
+        if self.topic_hooks.iter().any(|(_, hook_event, _)| *hook_event == event) {
+            self.topic_events.push((event, topic_id, name.to_string()));
+        }
+    }
+}
+
+/// Builders shared by the native unit tests of the modules working on `InnerQueue`
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    /// A message from another tab on `topic_id`, timestamped 0
+    pub fn message(id: u64, topic_id: u32) -> Message {
+        Message {
+            id,
+            topic_id,
+            payload: JsValue::UNDEFINED,
+            timestamp: 0.0,
+            origin_id: Rc::new("other-tab".to_string()),
+            headers: None,
+            expires_at: None,
+            seq: 0,
+        }
+    }
+
+    pub fn msg(id: u64, topic_id: u32) -> Rc<Message> {
+        Rc::new(message(id, topic_id))
+    }
+
+    pub fn msg_at(id: u64, topic_id: u32, timestamp: f64) -> Rc<Message> {
+        Rc::new(Message { timestamp, ..message(id, topic_id) })
+    }
+
+    /// A queued publish whose promise callbacks are never called
+    pub fn queued(priority: u8, seq: u64) -> QueuedPublish {
+        QueuedPublish {
+            priority,
+            seq,
+            payload: JsValue::UNDEFINED,
+            resolve: JsValue::UNDEFINED.unchecked_into(),
+            reject: JsValue::UNDEFINED.unchecked_into(),
+        }
+    }
+
+    /// Register a topic with a buffer of `capacity`
+    pub fn buffered_topic(queue: &mut InnerQueue, name: &str, capacity: usize) -> u32 {
+        let topic_id = queue.get_or_create_topic_id(name);
+        queue.get_topic_by_id_mut(topic_id).unwrap().enable_buffer(capacity);
+        topic_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::{message, msg_at, queued};
+
+    fn buffered_ids(topic: &Topic) -> Vec<u64> {
+        topic.get_buffer().unwrap().iter().map(|msg| msg.id).collect()
+    }
+
+    #[test]
+    fn test_purge_expired_messages() {
+        let mut topic = Topic::with_buffer("ttl".to_string(), 8);
+        for (id, expires_at) in [(1, Some(100.0)), (2, None), (3, Some(300.0)), (4, Some(100.0))] {
+            topic.get_buffer_mut().unwrap().push(Rc::new(Message { expires_at, ..message(id, 0) }));
+        }
+
+        assert_eq!(topic.purge_expired_at(99.0), 0);
+        assert_eq!(topic.purge_expired_at(100.0), 2);
+        assert_eq!(buffered_ids(&topic), vec![2, 3]);
+        assert_eq!(topic.purge_expired_at(1_000.0), 1);
+        assert_eq!(topic.get_buffer().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_buffer_retention() {
+        let mut topic = Topic::with_buffer("recent".to_string(), 3);
+        topic.retention_ms = Some(100.0);
+        // Message 3 arrived late from another tab, behind newer ones
+        for (id, timestamp) in [(1, 0.0), (2, 150.0), (3, 50.0), (4, 200.0)] {
+            topic.get_buffer_mut().unwrap().push(msg_at(id, 0, timestamp));
+        }
+        // The capacity displaced message 1 before it aged out
+        assert_eq!(buffered_ids(&topic), vec![2, 3, 4]);
+
+        // Eviction on push stops at the first recent message...
+        assert_eq!(topic.evict_aged_at(200.0), 0);
+        let late = topic.get_buffer().unwrap().iter().nth(1).unwrap().clone();
+        assert!(topic.is_stale_at(&late, 200.0));
+        // ...while a full purge drops the late one too
+        assert_eq!(topic.purge_expired_at(200.0), 1);
+        assert_eq!(buffered_ids(&topic), vec![2, 4]);
+        assert_eq!(topic.evict_aged_at(260.0), 1);
+        assert_eq!(buffered_ids(&topic), vec![4]);
+
+        topic.retention_ms = None;
+        assert_eq!(topic.purge_expired_at(1_000.0), 0);
+    }
+
+    #[test]
+    fn test_queued_publish_order() {
+        let mut heap = BinaryHeap::new();
+        for (seq, priority) in [10, 200, 10, 128, 200].into_iter().enumerate() {
+            heap.push(queued(priority, seq as u64));
+        }
+
+        // Highest priority first, publish order within a priority
+        let order: Vec<(u8, u64)> = std::iter::from_fn(|| heap.pop())
+            .map(|q| (q.priority, q.seq))
+            .collect();
+        assert_eq!(order, vec![(200, 1), (200, 4), (128, 3), (10, 0), (10, 2)]);
+    }
+
+    #[test]
+    fn test_dead_letter_config() {
+        let config = DeadLetterConfig { topic_id: 7, on_error: true, on_no_subscribers: false, on_overflow: true };
+        assert!(config.accepts(1, DeadLetterReason::Error));
+        assert!(config.accepts(1, DeadLetterReason::Overflow));
+        assert!(!config.accepts(1, DeadLetterReason::NoSubscribers));
+
+        // The dead-letter topic never dead-letters its own messages
+        assert!(!config.accepts(7, DeadLetterReason::Error));
+        assert!(!config.accepts(7, DeadLetterReason::Overflow));
     }
 }
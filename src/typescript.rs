@@ -0,0 +1,116 @@
+//! TypeScript declarations for callback signatures, message objects and options
+//!
+//! The generated `.d.ts` types every `Function` as `Function` and every `JsValue`
+//! as `any`. Methods marked `skip_typescript` in `queue.rs` are declared here
+//! instead; the `MessageQueue` interface merges with the generated class.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_DECLARATIONS: &'static str = r#"
+/**
+ * Called with each message delivered to a subscriber.
+ * `headers` is undefined for messages published without headers.
+ */
+export type MessageCallback<T = unknown> = (
+  payload: T,
+  topicId: number,
+  timestamp: number,
+  messageId: bigint,
+  headers?: Record<string, unknown>,
+) => void;
+
+/** A message as returned by `get_buffered_messages` */
+export interface RippleMessage<T = unknown> {
+  id: bigint;
+  topic: string;
+  payload: T;
+  timestamp: number;
+  origin_id: string;
+  headers?: Record<string, unknown>;
+  expires_at?: number;
+  seq?: number;
+}
+
+/** Called with (error, topic_name, sub_id, message_id) for errors raised during dispatch */
+export type ErrorHandler = (
+  error: unknown,
+  topicName: string | undefined,
+  subId: number | undefined,
+  messageId: bigint | undefined,
+) => void;
+
+export interface SubscribeOptions {
+  /** Immediately invoke the callback with the topic's retained message */
+  replay_retained?: boolean;
+}
+
+export interface DeadLetterOptions {
+  errors?: boolean;
+  no_subscribers?: boolean;
+  overflow?: boolean;
+}
+
+export interface TopicConfig {
+  name: string;
+  buffer_capacity?: number;
+  tags?: string[];
+}
+
+export interface QueueConfig {
+  channel?: string;
+  transport?: "broadcast" | "storage";
+  client_id?: string;
+  topics?: TopicConfig[];
+  error_handler?: ErrorHandler;
+  dedup_window?: number;
+}
+
+export interface QueueOptions {
+  channel?: string;
+  clientId?: string;
+  syncOnConnect?: boolean;
+  dedupCapacity?: number;
+  wireFormat?: "json" | "binary";
+  debug?: boolean;
+  defaultBufferCapacity?: number;
+  maxTopicNameLength?: number;
+  trimTopicNames?: boolean;
+  foldTopicNameCase?: boolean;
+  signal?: AbortSignal;
+}
+
+export interface MessageQueue {
+  /**
+   * Subscribe to a topic using its ID
+   * @returns Subscriber ID
+   */
+  subscribe<T = unknown>(topic_id: number, callback: MessageCallback<T>): number;
+  /**
+   * Subscribe to a topic with options
+   * @returns Subscriber ID
+   */
+  subscribe_with_options<T = unknown>(topic_id: number, callback: MessageCallback<T>, options?: SubscribeOptions): number;
+  /**
+   * Subscribe until the signal is aborted
+   * @returns Subscriber ID
+   */
+  subscribe_with_signal<T = unknown>(topic_id: number, callback: MessageCallback<T>, signal: AbortSignal): number;
+  /**
+   * Subscribe unless the same callback is already subscribed to the topic
+   * @returns ID of the new subscriber, or of the existing one
+   */
+  subscribe_unique<T = unknown>(topic_id: number, callback: MessageCallback<T>): number;
+  /** Publish using a topic ID (handle) */
+  publish<T = unknown>(topic_id: number, payload: T): void;
+  /**
+   * Get buffered messages for a topic
+   * @returns Buffered messages (oldest first), or an empty array if no buffer
+   */
+  get_buffered_messages<T = unknown>(topic_id: number): RippleMessage<T>[];
+  /** Route failed and undeliverable messages to a dead-letter topic */
+  set_dead_letter_topic(topic_id: number, options?: DeadLetterOptions): void;
+  /** Register a handler for errors raised during dispatch */
+  set_error_handler(callback: ErrorHandler): void;
+}
+"#;
@@ -1,30 +1,138 @@
 use wasm_bindgen::prelude::*;
-use crate::constants::ERR_WINDOW_NOT_AVAILABLE;
 use crate::constants::ERR_CRYPTO_NOT_AVAILABLE;
 use std::cell::RefCell;
+use js_sys::{Function, Reflect};
 
 thread_local! {
-    static CRYPTO: RefCell<Option<web_sys::Crypto>> = RefCell::new(None);
+    static CRYPTO: RefCell<Option<web_sys::Crypto>> = const { RefCell::new(None) };
 }
 
-/// Generate a UUID using the browser's crypto API
+/// Generate a UUID using the Web Crypto API of the global scope
+/// Works in windows and workers alike. Falls back to `getRandomValues`
+/// where `randomUUID` is unavailable (insecure contexts), and to `Math.random`
+/// where there is no `crypto` at all.
 pub fn generate_uuid() -> Result<String, JsValue> {
     CRYPTO.with(|crypto_cell| {
         let mut crypto_opt = crypto_cell.borrow_mut();
-        
-        if let Some(crypto) = crypto_opt.as_ref() {
-            return Ok(crypto.random_uuid());
+
+        if crypto_opt.is_none() {
+            let crypto = Reflect::get(&js_sys::global(), &"crypto".into())?;
+            if crypto.is_undefined() || crypto.is_null() {
+                return Ok(fallback_uuid(None));
+            }
+            *crypto_opt = Some(crypto.unchecked_into());
         }
 
-        let window = web_sys::window()
-            .ok_or_else(|| JsValue::from_str(ERR_WINDOW_NOT_AVAILABLE))?;
+        let crypto = crypto_opt.as_ref().ok_or_else(|| JsValue::from_str(ERR_CRYPTO_NOT_AVAILABLE))?;
+        let has_random_uuid = Reflect::get(crypto, &"randomUUID".into())?.is_function();
+        if has_random_uuid {
+            Ok(crypto.random_uuid())
+        } else {
+            Ok(fallback_uuid(Some(crypto)))
+        }
+    })
+}
 
-        let crypto = window.crypto()
-            .map_err(|_| JsValue::from_str(ERR_CRYPTO_NOT_AVAILABLE))?;
+/// Build a version 4 UUID from `getRandomValues`, or `Math.random` without crypto
+fn fallback_uuid(crypto: Option<&web_sys::Crypto>) -> String {
+    let mut bytes = [0u8; 16];
+    let filled = crypto.is_some_and(|crypto| crypto.get_random_values_with_u8_array(&mut bytes).is_ok());
+    if !filled {
+        for byte in bytes.iter_mut() {
+            *byte = (js_sys::Math::random() * 256.0) as u8;
+        }
+    }
+    format_uuid_v4(bytes)
+}
 
-        let uuid = crypto.random_uuid();
-        *crypto_opt = Some(crypto);
-        
-        Ok(uuid)
-    })
+/// Format 16 random bytes as a version 4 (random) UUID string
+pub fn format_uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+    let mut uuid = String::with_capacity(36);
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            uuid.push('-');
+        }
+        uuid.push_str(&format!("{:02x}", byte));
+    }
+    uuid
+}
+
+/// Schedule a callback with the global `setTimeout`
+/// Uses the global scope so it works in windows and workers alike
+pub fn set_timeout(callback: &Function, delay_ms: f64) -> Result<i32, JsValue> {
+    let global = js_sys::global();
+    let set_timeout: Function = Reflect::get(&global, &"setTimeout".into())?.dyn_into()?;
+    let handle = set_timeout.call2(&global, callback, &delay_ms.into())?;
+    Ok(handle.as_f64().unwrap_or(0.0) as i32)
+}
+
+/// Cancel a callback scheduled with `set_timeout`
+pub fn clear_timeout(handle: i32) {
+    let global = js_sys::global();
+    if let Ok(clear) = Reflect::get(&global, &"clearTimeout".into()).and_then(|f| f.dyn_into::<Function>()) {
+        let _ = clear.call1(&global, &handle.into());
+    }
+}
+
+/// Call a callback repeatedly with the global `setInterval`
+pub fn set_interval(callback: &Function, interval_ms: f64) -> Result<i32, JsValue> {
+    let global = js_sys::global();
+    let set_interval: Function = Reflect::get(&global, &"setInterval".into())?.dyn_into()?;
+    let handle = set_interval.call2(&global, callback, &interval_ms.into())?;
+    Ok(handle.as_f64().unwrap_or(0.0) as i32)
+}
+
+/// Stop a callback scheduled with `set_interval`
+pub fn clear_interval(handle: i32) {
+    let global = js_sys::global();
+    if let Ok(clear) = Reflect::get(&global, &"clearInterval".into()).and_then(|f| f.dyn_into::<Function>()) {
+        let _ = clear.call1(&global, &handle.into());
+    }
+}
+
+/// Schedule a callback with the global `requestIdleCallback`
+/// Falls back to `setTimeout(callback, 0)` where idle callbacks are unavailable
+pub fn request_idle_callback(callback: &Function) -> Result<i32, JsValue> {
+    let global = js_sys::global();
+    match Reflect::get(&global, &"requestIdleCallback".into())?.dyn_into::<Function>() {
+        Ok(request) => {
+            let handle = request.call1(&global, callback)?;
+            Ok(handle.as_f64().unwrap_or(0.0) as i32)
+        }
+        Err(_) => set_timeout(callback, 0.0),
+    }
+}
+
+/// Cancel a callback scheduled with `request_idle_callback`
+pub fn cancel_idle_callback(handle: i32) {
+    let global = js_sys::global();
+    let cancel = Reflect::get(&global, &"cancelIdleCallback".into())
+        .ok()
+        .and_then(|f| f.dyn_into::<Function>().ok())
+        .or_else(|| {
+            Reflect::get(&global, &"clearTimeout".into())
+                .ok()
+                .and_then(|f| f.dyn_into::<Function>().ok())
+        });
+
+    if let Some(cancel) = cancel {
+        let _ = cancel.call1(&global, &handle.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uuid_v4() {
+        let uuid = format_uuid_v4([0xff; 16]);
+        assert_eq!(uuid, "ffffffff-ffff-4fff-bfff-ffffffffffff");
+
+        let uuid = format_uuid_v4([0; 16]);
+        assert_eq!(uuid, "00000000-0000-4000-8000-000000000000");
+    }
 }
@@ -0,0 +1,302 @@
+//! Binary wire format for cross-tab PUB packets
+//!
+//! A binary packet is a single `Uint8Array` (little endian):
+//!
+//! | bytes | field                                         |
+//! |-------|-----------------------------------------------|
+//! | 1     | packet type (`PACKET_PUB`)                    |
+//! | 1     | wire version (`WIRE_VERSION`)                 |
+//! | 1     | payload kind (`PayloadKind`)                  |
+//! | 1     | flags (bit 0: expiry present)                 |
+//! | 8     | message ID                                    |
+//! | 8     | timestamp (f64)                               |
+//! | 8     | sequence number                               |
+//! | 8     | expiry (f64), only if flagged                 |
+//! | 2 + n | topic name length and UTF-8 bytes             |
+//! | 2 + n | origin ID length and UTF-8 bytes              |
+//! | rest  | payload bytes                                 |
+//!
+//! Only strings, `ArrayBuffer`s, typed arrays, `null` and `undefined` payloads
+//! are encoded; other messages keep the object format.
+
+use wasm_bindgen::prelude::*;
+use js_sys::{ArrayBuffer, Uint8Array};
+use std::rc::Rc;
+
+use crate::constants::{PACKET_PUB, WIRE_VERSION};
+use crate::types::{Message, WireFormat};
+
+const FLAG_EXPIRES: u8 = 1;
+const FIXED_LEN: usize = 4 + 8 + 8 + 8;
+
+/// How a payload's bytes are turned back into a JS value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PayloadKind {
+    Undefined = 0,
+    Null = 1,
+    String = 2,
+    ArrayBuffer = 3,
+    Uint8Array = 4,
+    Int8Array = 5,
+    Uint8ClampedArray = 6,
+    Int16Array = 7,
+    Uint16Array = 8,
+    Int32Array = 9,
+    Uint32Array = 10,
+    Float32Array = 11,
+    Float64Array = 12,
+}
+
+impl PayloadKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        use PayloadKind::*;
+        [
+            Undefined, Null, String, ArrayBuffer, Uint8Array, Int8Array, Uint8ClampedArray,
+            Int16Array, Uint16Array, Int32Array, Uint32Array, Float32Array, Float64Array,
+        ].into_iter().find(|kind| *kind as u8 == value)
+    }
+
+    /// Size of one element, which the payload length must be a multiple of
+    fn element_size(self) -> usize {
+        match self {
+            PayloadKind::Int16Array | PayloadKind::Uint16Array => 2,
+            PayloadKind::Int32Array | PayloadKind::Uint32Array | PayloadKind::Float32Array => 4,
+            PayloadKind::Float64Array => 8,
+            _ => 1,
+        }
+    }
+}
+
+/// A PUB packet in its decoded form
+#[derive(Debug, PartialEq)]
+pub struct WirePacket {
+    pub id: u64,
+    pub timestamp: f64,
+    pub seq: u64,
+    pub expires_at: Option<f64>,
+    pub topic: String,
+    pub origin: String,
+    pub kind: PayloadKind,
+    pub payload: Vec<u8>,
+}
+
+impl WirePacket {
+    /// Serialize the packet
+    /// Returns None if the topic name or origin ID is too long for its length prefix
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let topic_len = u16::try_from(self.topic.len()).ok()?;
+        let origin_len = u16::try_from(self.origin.len()).ok()?;
+
+        let mut bytes = Vec::with_capacity(FIXED_LEN + 8 + 4 + self.topic.len() + self.origin.len() + self.payload.len());
+        let flags = if self.expires_at.is_some() { FLAG_EXPIRES } else { 0 };
+        bytes.extend_from_slice(&[PACKET_PUB, WIRE_VERSION, self.kind as u8, flags]);
+        bytes.extend_from_slice(&self.id.to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&self.seq.to_le_bytes());
+        if let Some(expires_at) = self.expires_at {
+            bytes.extend_from_slice(&expires_at.to_le_bytes());
+        }
+        bytes.extend_from_slice(&topic_len.to_le_bytes());
+        bytes.extend_from_slice(self.topic.as_bytes());
+        bytes.extend_from_slice(&origin_len.to_le_bytes());
+        bytes.extend_from_slice(self.origin.as_bytes());
+        bytes.extend_from_slice(&self.payload);
+        Some(bytes)
+    }
+
+    /// Parse a packet produced by `encode`
+    pub fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(1)? != [PACKET_PUB] {
+            return Err("Unsupported binary packet type");
+        }
+        if reader.take(1)? != [WIRE_VERSION] {
+            return Err("Unsupported wire version");
+        }
+        let kind = PayloadKind::from_u8(reader.take(1)?[0]).ok_or("Invalid payload kind")?;
+        let flags = reader.take(1)?[0];
+        let id = u64::from_le_bytes(reader.array()?);
+        let timestamp = f64::from_le_bytes(reader.array()?);
+        let seq = u64::from_le_bytes(reader.array()?);
+        let expires_at = if flags & FLAG_EXPIRES != 0 {
+            Some(f64::from_le_bytes(reader.array()?))
+        } else {
+            None
+        };
+        let topic = reader.string()?;
+        let origin = reader.string()?;
+        let payload = reader.bytes[reader.pos..].to_vec();
+        if !payload.len().is_multiple_of(kind.element_size()) {
+            return Err("Invalid payload length");
+        }
+
+        Ok(WirePacket { id, timestamp, seq, expires_at, topic, origin, kind, payload })
+    }
+}
+
+/// Bounds-checked cursor over a packet
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len())
+            .ok_or("Truncated binary packet")?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], &'static str> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        let len = u16::from_le_bytes(self.array()?) as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "Invalid UTF-8 in binary packet")
+    }
+}
+
+/// Build the broadcast form of a message for the given wire format
+/// Falls back to the object format if the message cannot be encoded.
+pub fn message_to_wire(msg: &Message, topic_name: &str, format: WireFormat) -> Result<JsValue, JsValue> {
+    if format == WireFormat::Binary {
+        if let Some(bytes) = encode_message(msg, topic_name) {
+            return Ok(Uint8Array::from(&bytes[..]).into());
+        }
+    }
+    crate::js_utils::message_to_js(msg, topic_name)
+}
+
+/// Encode a message as a binary packet, or None if it must use the object format
+pub fn encode_message(msg: &Message, topic_name: &str) -> Option<Vec<u8>> {
+    if msg.headers.is_some() {
+        return None;
+    }
+    let (kind, payload) = encode_payload(&msg.payload)?;
+    WirePacket {
+        id: msg.id,
+        timestamp: msg.timestamp,
+        seq: msg.seq,
+        expires_at: msg.expires_at,
+        topic: topic_name.to_string(),
+        origin: msg.origin_id.to_string(),
+        kind,
+        payload,
+    }.encode()
+}
+
+/// Decode a binary packet into a message and its topic name
+/// The topic ID is left at 0 for the caller to resolve, as with `parse_js_message`.
+pub fn decode_message(packet: &Uint8Array) -> Result<(Message, String), JsValue> {
+    let packet = WirePacket::decode(&packet.to_vec()).map_err(JsValue::from_str)?;
+    let payload = decode_payload(packet.kind, &packet.payload);
+    Ok((Message {
+        id: packet.id,
+        topic_id: 0,
+        payload,
+        timestamp: packet.timestamp,
+        origin_id: Rc::new(packet.origin),
+        headers: None,
+        expires_at: packet.expires_at,
+        seq: packet.seq,
+    }, packet.topic))
+}
+
+macro_rules! typed_array_kinds {
+    ($($ty:ident),*) => {
+        /// Copy the bytes of an encodable payload
+        fn encode_payload(payload: &JsValue) -> Option<(PayloadKind, Vec<u8>)> {
+            if payload.is_undefined() {
+                return Some((PayloadKind::Undefined, Vec::new()));
+            }
+            if payload.is_null() {
+                return Some((PayloadKind::Null, Vec::new()));
+            }
+            if let Some(text) = payload.as_string() {
+                return Some((PayloadKind::String, text.into_bytes()));
+            }
+            if let Some(buffer) = payload.dyn_ref::<ArrayBuffer>() {
+                return Some((PayloadKind::ArrayBuffer, Uint8Array::new(buffer).to_vec()));
+            }
+            $(
+                if let Some(array) = payload.dyn_ref::<js_sys::$ty>() {
+                    let view = Uint8Array::new_with_byte_offset_and_length(&array.buffer(), array.byte_offset(), array.byte_length());
+                    return Some((PayloadKind::$ty, view.to_vec()));
+                }
+            )*
+            None
+        }
+
+        /// Rebuild a payload from its bytes; the result owns a fresh buffer
+        fn decode_payload(kind: PayloadKind, bytes: &[u8]) -> JsValue {
+            let buffer = || Uint8Array::from(bytes).buffer();
+            match kind {
+                PayloadKind::Undefined => JsValue::UNDEFINED,
+                PayloadKind::Null => JsValue::NULL,
+                PayloadKind::String => JsValue::from_str(&String::from_utf8_lossy(bytes)),
+                PayloadKind::ArrayBuffer => buffer().into(),
+                $(PayloadKind::$ty => js_sys::$ty::new(&buffer()).into(),)*
+            }
+        }
+    };
+}
+
+typed_array_kinds!(
+    Uint8Array, Int8Array, Uint8ClampedArray, Int16Array, Uint16Array,
+    Int32Array, Uint32Array, Float32Array, Float64Array
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(kind: PayloadKind, payload: Vec<u8>) -> WirePacket {
+        WirePacket {
+            id: 0x1234_5678_0000_0042,
+            timestamp: 1_700_000_000_123.5,
+            seq: 7,
+            expires_at: None,
+            topic: "telemetry/cpu".to_string(),
+            origin: "client-ü".to_string(),
+            kind,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_wire_round_trip() {
+        let string = packet(PayloadKind::String, "héllo".as_bytes().to_vec());
+        assert_eq!(WirePacket::decode(&string.encode().unwrap()).unwrap(), string);
+
+        let floats: Vec<u8> = [1.5f64, -2.25, f64::MAX].iter().flat_map(|f| f.to_le_bytes()).collect();
+        let mut float64 = packet(PayloadKind::Float64Array, floats);
+        float64.expires_at = Some(1_700_000_060_000.0);
+        assert_eq!(WirePacket::decode(&float64.encode().unwrap()).unwrap(), float64);
+
+        let empty = packet(PayloadKind::Undefined, Vec::new());
+        let bytes = empty.encode().unwrap();
+        assert_eq!(bytes[0], PACKET_PUB);
+        assert_eq!(WirePacket::decode(&bytes).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_wire_rejects_malformed_packets() {
+        let bytes = packet(PayloadKind::Float64Array, vec![0; 8]).encode().unwrap();
+        assert_eq!(WirePacket::decode(&bytes[..10]), Err("Truncated binary packet"));
+        assert_eq!(WirePacket::decode(&bytes[..bytes.len() - 1]), Err("Invalid payload length"));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[1] = WIRE_VERSION + 1;
+        assert_eq!(WirePacket::decode(&wrong_version), Err("Unsupported wire version"));
+
+        let mut long_topic = packet(PayloadKind::Null, Vec::new());
+        long_topic.topic = "x".repeat(u16::MAX as usize + 1);
+        assert!(long_topic.encode().is_none());
+    }
+}
@@ -0,0 +1,63 @@
+// Type-level checks of the declarations in src/typescript.rs
+// Run with `npm run typecheck` after `npm run build`.
+import init, { MessageQueue, MessageCallback, RippleMessage, QueueConfig, QueueOptions } from "../../pkg/wasm_ripple";
+
+interface Point {
+  x: number;
+  y: number;
+}
+
+async function main() {
+  await init();
+
+  const config: QueueConfig = {
+    channel: "app",
+    topics: [{ name: "points", buffer_capacity: 10, tags: ["geo"] }],
+    error_handler: (error, topicName, subId, messageId) => {
+      console.error(error, topicName, subId, messageId);
+    },
+  };
+  const mq = MessageQueue.from_js_config(config);
+  const topic = mq.register_topic("points");
+
+  const onPoint: MessageCallback<Point> = (payload, topicId, timestamp, messageId, headers) => {
+    const sum: number = payload.x + payload.y + topicId + timestamp;
+    const id: bigint = messageId;
+    const header: unknown = headers?.["trace"];
+    console.log(sum, id, header);
+  };
+  const subId: number = mq.subscribe<Point>(topic, onPoint);
+  mq.subscribe(topic, (payload) => {
+    // Without a type argument the payload is unknown, not any
+    // @ts-expect-error
+    payload.x;
+  });
+  mq.subscribe_with_options<Point>(topic, (point) => point.x, { replay_retained: true });
+  // @ts-expect-error unknown option
+  mq.subscribe_with_options(topic, () => {}, { replay: true });
+
+  mq.publish<Point>(topic, { x: 1, y: 2 });
+  // @ts-expect-error payload does not match the type argument
+  mq.publish<Point>(topic, { x: 1 });
+
+  const messages: RippleMessage<Point>[] = mq.get_buffered_messages<Point>(topic);
+  for (const message of messages) {
+    const id: bigint = message.id;
+    const name: string = message.topic;
+    const x: number = message.payload.x;
+    const seq: number | undefined = message.seq;
+    console.log(id, name, x, seq, message.origin_id, message.headers, message.expires_at);
+  }
+
+  mq.set_dead_letter_topic(mq.register_topic("dead"), { overflow: false });
+  mq.set_error_handler((error, topicName) => console.error(error, topicName));
+  mq.unsubscribe(topic, subId);
+
+  const options: QueueOptions = { clientId: "tab-1", syncOnConnect: false, wireFormat: "binary" };
+  MessageQueue.with_options(options).free();
+  MessageQueue.with_options(undefined).free();
+  // @ts-expect-error unknown wire format
+  MessageQueue.with_options({ wireFormat: "xml" });
+}
+
+main();
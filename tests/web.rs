@@ -0,0 +1,3174 @@
+//! Browser tests for the exported `MessageQueue` API
+//! Run with `wasm-pack test --headless --chrome` (or `--firefox`)
+
+#![cfg(target_arch = "wasm32")]
+
+use std::rc::Rc;
+use js_sys::{Array, Function};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::*;
+use wasm_ripple::{DropPolicy, MessageQueue};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Subscriber callback that pushes every delivered payload onto `received`
+fn recorder(received: &Array) -> Function {
+    Function::new_with_args("payload", "this.push(payload);").bind0(received)
+}
+
+/// Resolve after `ms` milliseconds
+async fn sleep(ms: i32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let global = js_sys::global();
+        let set_timeout: Function = js_sys::Reflect::get(&global, &"setTimeout".into()).unwrap().unchecked_into();
+        set_timeout.call2(&global, &resolve, &JsValue::from(ms)).unwrap();
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await.unwrap();
+}
+
+#[wasm_bindgen_test]
+fn publish_delivers_to_subscriber() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("events").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.publish(topic, JsValue::from_str("hello")).unwrap();
+
+    assert_eq!(received.length(), 1);
+    assert_eq!(received.get(0).as_string().as_deref(), Some("hello"));
+}
+
+#[wasm_bindgen_test]
+fn subscriber_can_publish_reentrantly() {
+    let mq = Rc::new(MessageQueue::new(None).unwrap());
+    let requests = mq.register_topic("requests").unwrap();
+    let responses = mq.register_topic("responses").unwrap();
+
+    let seen_requests = Array::new();
+    let seen_responses = Array::new();
+    mq.subscribe(requests, recorder(&seen_requests)).unwrap();
+    mq.subscribe(responses, recorder(&seen_responses)).unwrap();
+
+    let handle = mq.clone();
+    let responder = Closure::<dyn FnMut(JsValue)>::new(move |payload: JsValue| {
+        handle.publish(responses, payload).unwrap();
+    });
+    mq.subscribe(requests, responder.as_ref().unchecked_ref::<Function>().clone()).unwrap();
+
+    mq.publish(requests, JsValue::from_str("ping")).unwrap();
+
+    assert_eq!(seen_requests.length(), 1);
+    assert_eq!(seen_responses.length(), 1);
+    assert_eq!(seen_responses.get(0).as_string().as_deref(), Some("ping"));
+}
+
+#[wasm_bindgen_test]
+fn message_ids_are_unique_and_lossless() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("ids").unwrap();
+    let ids = Array::new();
+    let record_id = Function::new_with_args("payload, topic_id, timestamp, id", "this.push(id);").bind0(&ids);
+    mq.subscribe(topic, record_id).unwrap();
+
+    for _ in 0..100_000 {
+        mq.publish(topic, JsValue::UNDEFINED).unwrap();
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for id in ids.iter() {
+        // IDs arrive as BigInt and convert back to u64 exactly
+        assert!(id.is_bigint());
+        assert!(seen.insert(u64::try_from(id).unwrap()));
+    }
+    assert_eq!(seen.len(), 100_000);
+}
+
+#[wasm_bindgen_test]
+fn publish_by_name_resolves_topics() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("events").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.publish_by_name("events", JsValue::from_str("hello")).unwrap();
+    assert_eq!(received.length(), 1);
+
+    assert_eq!(mq.get_topic_id("events"), Some(topic));
+    assert_eq!(mq.get_topic_name(topic).as_deref(), Some("events"));
+    assert_eq!(mq.get_topic_id("missing"), None);
+
+    // Unknown names are created by default, or rejected once disabled
+    mq.publish_by_name("created", JsValue::UNDEFINED).unwrap();
+    assert!(mq.get_topic_id("created").is_some());
+    mq.set_auto_create_topics(false);
+    assert!(mq.publish_by_name("rejected", JsValue::UNDEFINED).is_err());
+    assert_eq!(mq.get_topic_id("rejected"), None);
+}
+
+#[wasm_bindgen_test]
+fn retained_message_reaches_late_subscribers() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("state").unwrap();
+    mq.publish_retained(topic, JsValue::from_str("first")).unwrap();
+    mq.publish_retained(topic, JsValue::from_str("latest")).unwrap();
+
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"replay_retained".into(), &JsValue::TRUE).unwrap();
+    let late = Array::new();
+    mq.subscribe_with_options(topic, recorder(&late), options.into()).unwrap();
+    assert_eq!(late.length(), 1);
+    assert_eq!(late.get(0).as_string().as_deref(), Some("latest"));
+
+    // Plain subscribers and cleared topics get no retained message
+    let plain = Array::new();
+    mq.subscribe(topic, recorder(&plain)).unwrap();
+    assert_eq!(plain.length(), 0);
+
+    assert!(mq.clear_retained(topic));
+    assert!(!mq.clear_retained(topic));
+    let after_clear = Array::new();
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"replay_retained".into(), &JsValue::TRUE).unwrap();
+    mq.subscribe_with_options(topic, recorder(&after_clear), options.into()).unwrap();
+    assert_eq!(after_clear.length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn pattern_subscribers_match_existing_and_new_topics() {
+    let mq = MessageQueue::new(None).unwrap();
+    let kitchen = mq.register_topic("sensor/kitchen/temp").unwrap();
+    let humidity = mq.register_topic("sensor/kitchen/humidity").unwrap();
+
+    let temps = Array::new();
+    let all = Array::new();
+    let temps_id = mq.subscribe_pattern("sensor/+/temp", recorder(&temps)).unwrap();
+    mq.subscribe_pattern("sensor/#", recorder(&all)).unwrap();
+
+    // Created after the patterns were registered
+    let living_room = mq.register_topic("sensor/livingroom/temp").unwrap();
+
+    mq.publish(kitchen, JsValue::from_str("k")).unwrap();
+    mq.publish(humidity, JsValue::from_str("h")).unwrap();
+    mq.publish(living_room, JsValue::from_str("l")).unwrap();
+    assert_eq!(temps.length(), 2);
+    assert_eq!(all.length(), 3);
+
+    assert!(mq.unsubscribe_pattern(temps_id));
+    assert!(!mq.unsubscribe_pattern(temps_id));
+    mq.publish(kitchen, JsValue::from_str("k")).unwrap();
+    assert_eq!(temps.length(), 2);
+    assert_eq!(all.length(), 4);
+
+    assert!(mq.subscribe_pattern("sensor/#/temp", recorder(&temps)).is_err());
+}
+
+#[wasm_bindgen_test]
+fn filtered_subscribers_only_receive_matching_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.create_replay_subject("orders", 10).unwrap();
+    let order = |status: &str| {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"status".into(), &JsValue::from_str(status)).unwrap();
+        JsValue::from(obj)
+    };
+
+    // Buffered before subscribing, so it is replayed through the filters
+    mq.publish(topic, order("paid")).unwrap();
+
+    let paid = Array::new();
+    let id = mq.subscribe_filtered_key(topic, "status", JsValue::from_str("paid"), recorder(&paid)).unwrap();
+    let not_open = Array::new();
+    let predicate = Function::new_with_args("payload", "return payload.status !== 'open';");
+    mq.subscribe_filtered(topic, predicate, recorder(&not_open)).unwrap();
+    assert_eq!(paid.length(), 1);
+    assert_eq!(not_open.length(), 1);
+
+    mq.publish(topic, order("open")).unwrap();
+    mq.publish(topic, order("paid")).unwrap();
+    mq.publish(topic, order("shipped")).unwrap();
+    mq.publish(topic, JsValue::from_str("not an object")).unwrap();
+    assert_eq!(paid.length(), 2);
+    assert_eq!(not_open.length(), 4);
+
+    assert!(mq.subscriber_has_filter(topic, id).unwrap());
+    assert!(mq.unsubscribe(topic, id));
+}
+
+#[wasm_bindgen_test]
+fn subscribe_once_delivers_a_single_message() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("once").unwrap();
+    let received = Array::new();
+    mq.subscribe_once(topic, recorder(&received)).unwrap();
+
+    mq.publish(topic, JsValue::from_str("first")).unwrap();
+    mq.publish(topic, JsValue::from_str("second")).unwrap();
+
+    assert_eq!(received.length(), 1);
+    assert_eq!(mq.subscriber_count(topic), 0);
+}
+
+#[wasm_bindgen_test]
+async fn next_message_resolves_with_payload() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("next").unwrap();
+    let promise = mq.next_message(topic);
+
+    mq.publish(topic, JsValue::from_str("hello")).unwrap();
+
+    let payload = wasm_bindgen_futures::JsFuture::from(promise).await.unwrap();
+    assert_eq!(payload.as_string().as_deref(), Some("hello"));
+    assert_eq!(mq.subscriber_count(topic), 0);
+}
+
+#[wasm_bindgen_test]
+async fn next_message_rejects_on_destroy_and_timeout() {
+    let mq = MessageQueue::new(None).unwrap();
+    let destroyed = mq.register_topic("destroyed").unwrap();
+    let promise = mq.next_message(destroyed);
+    mq.destroy_topic(destroyed);
+    let err = wasm_bindgen_futures::JsFuture::from(promise).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic destroyed"));
+
+    let quiet = mq.register_topic("quiet").unwrap();
+    let err = wasm_bindgen_futures::JsFuture::from(mq.next_message_timeout(quiet, 10.0)).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Timeout"));
+    assert_eq!(mq.subscriber_count(quiet), 0);
+}
+
+#[wasm_bindgen_test]
+fn subscribe_with_replay_delivers_history_then_live_in_order() {
+    let mq = Rc::new(MessageQueue::new(None).unwrap());
+    let topic = mq.register_topic("history").unwrap();
+    mq.enable_topic_buffer(topic, Some(10)).unwrap();
+    for payload in ["a", "b", "c"] {
+        mq.publish(topic, JsValue::from_str(payload)).unwrap();
+    }
+
+    // Publishes "live" while the first replayed message is being handled
+    let received = Array::new();
+    let handle = mq.clone();
+    let log = received.clone();
+    let callback = Closure::<dyn FnMut(JsValue)>::new(move |payload: JsValue| {
+        let first = log.length() == 0;
+        log.push(&payload);
+        if first {
+            handle.publish(topic, JsValue::from_str("live")).unwrap();
+        }
+    });
+    let callback = callback.into_js_value().unchecked_into::<Function>();
+
+    let result = mq.subscribe_with_replay(topic, callback, Some(2)).unwrap();
+    let replayed = js_sys::Reflect::get(&result, &"replayed".into()).unwrap();
+    assert_eq!(replayed.as_f64(), Some(2.0));
+
+    let order: Vec<String> = received.iter().filter_map(|v| v.as_string()).collect();
+    assert_eq!(order, ["b", "c", "live"]);
+    // Replayed messages are not buffered again
+    assert_eq!(mq.get_buffer_size(topic), 4);
+}
+
+#[wasm_bindgen_test]
+async fn request_is_answered_by_local_responder() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("rpc/double").unwrap();
+    mq.respond(topic, Function::new_with_args("n", "return n * 2;")).unwrap();
+
+    let reply = wasm_bindgen_futures::JsFuture::from(mq.request(topic, JsValue::from(21), 1000.0)).await.unwrap();
+    assert_eq!(reply.as_f64(), Some(42.0));
+
+    // Promise-returning handlers are awaited; thrown errors reject the request
+    mq.respond(topic, Function::new_with_args("n", "return Promise.resolve(n + 1);")).unwrap();
+    let reply = wasm_bindgen_futures::JsFuture::from(mq.request(topic, JsValue::from(1), 1000.0)).await.unwrap();
+    assert_eq!(reply.as_f64(), Some(2.0));
+
+    mq.respond(topic, Function::new_with_args("n", "throw 'bad request';")).unwrap();
+    let err = wasm_bindgen_futures::JsFuture::from(mq.request(topic, JsValue::from(1), 1000.0)).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("bad request"));
+}
+
+#[wasm_bindgen_test]
+async fn request_times_out_without_reply() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("rpc/slow").unwrap();
+    mq.respond(topic, Function::new_with_args("n", "return new Promise(function() {});")).unwrap();
+
+    let err = wasm_bindgen_futures::JsFuture::from(mq.request(topic, JsValue::NULL, 10.0)).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("RPC timeout"));
+
+    let unanswered = mq.register_topic("rpc/none").unwrap();
+    let err = wasm_bindgen_futures::JsFuture::from(mq.request(unanswered, JsValue::NULL, 10.0)).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("No responder for topic"));
+}
+
+#[wasm_bindgen_test]
+fn headers_reach_subscribers_and_buffer() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("traced").unwrap();
+    mq.enable_topic_buffer(topic, Some(4)).unwrap();
+    let headers_seen = Array::new();
+    let record_headers = Function::new_with_args("payload, topic_id, timestamp, id, headers", "this.push(headers);")
+        .bind0(&headers_seen);
+    mq.subscribe(topic, record_headers).unwrap();
+
+    let headers = js_sys::Object::new();
+    js_sys::Reflect::set(&headers, &"trace".into(), &"abc".into()).unwrap();
+    mq.publish_with_headers(topic, JsValue::from(1), headers.clone().into()).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    assert!(mq.publish_with_headers(topic, JsValue::from(3), JsValue::from(7)).is_err());
+
+    assert_eq!(headers_seen.get(0), JsValue::from(headers));
+    assert!(headers_seen.get(1).is_undefined());
+
+    let buffered = mq.get_buffered_messages(topic).unwrap();
+    let trace = js_sys::Reflect::get(&js_sys::Reflect::get(&buffered.get(0), &"headers".into()).unwrap(), &"trace".into()).unwrap();
+    assert_eq!(trace.as_string().as_deref(), Some("abc"));
+    assert!(js_sys::Reflect::get(&buffered.get(1), &"headers".into()).unwrap().is_undefined());
+}
+
+#[wasm_bindgen_test]
+async fn expired_messages_leave_the_buffer() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("ttl").unwrap();
+    mq.enable_topic_buffer(topic, Some(4)).unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.publish_with_ttl(topic, JsValue::from(1), 5.0).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    assert!(mq.publish_with_ttl(topic, JsValue::from(3), 0.0).is_err());
+    assert_eq!(received.length(), 2);
+    assert_eq!(mq.get_buffer_size(topic), 2);
+
+    sleep(20).await;
+
+    assert_eq!(mq.get_buffer_size(topic), 1);
+    let buffered = mq.get_buffered_messages(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&buffered.get(0), &"payload".into()).unwrap().as_f64(), Some(2.0));
+    assert_eq!(mq.purge_expired(topic), 0);
+}
+
+#[wasm_bindgen_test]
+async fn queued_publishes_are_delivered_by_priority() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("telemetry").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    let _ = mq.publish_async(topic, JsValue::from_str("bulk-1")).unwrap();
+    let _ = mq.publish_with_priority(topic, JsValue::from_str("bulk-2"), 10).unwrap();
+    let alert = mq.publish_with_priority(topic, JsValue::from_str("alert"), 255).unwrap();
+    let _ = mq.publish_async(topic, JsValue::from_str("bulk-3")).unwrap();
+    assert_eq!(received.length(), 0);
+
+    wasm_bindgen_futures::JsFuture::from(alert).await.unwrap();
+    wasm_bindgen_futures::JsFuture::from(mq.wait_for_pending_async()).await.unwrap();
+    let order: Vec<String> = received.iter().filter_map(|v| v.as_string()).collect();
+    assert_eq!(order, ["alert", "bulk-1", "bulk-3", "bulk-2"]);
+
+    // Batches are reordered the same way
+    received.set_length(0);
+    let payloads: Array = [1, 2, 3, 4].iter().map(|&n| JsValue::from(n)).collect();
+    let priorities = js_sys::Uint8Array::from(&[0u8, 200, 0, 200][..]);
+    mq.publish_batch_by_id(topic, payloads.clone(), Some(priorities)).unwrap();
+    let order: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [2.0, 4.0, 1.0, 3.0]);
+    assert!(mq.publish_batch_by_id(topic, payloads, Some(js_sys::Uint8Array::new_with_length(1))).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn delayed_publishes_fire_unless_cancelled() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("later").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.publish_delayed(topic, JsValue::from(1), 5.0).unwrap();
+    let cancelled = mq.publish_delayed(topic, JsValue::from(2), 5.0).unwrap();
+    assert!(mq.cancel_scheduled(cancelled));
+    assert!(mq.publish_delayed(topic, JsValue::from(3), -1.0).is_err());
+    assert_eq!(received.length(), 0);
+
+    sleep(30).await;
+    assert_eq!(received.length(), 1);
+    assert_eq!(received.get(0).as_f64(), Some(1.0));
+    assert!(!mq.cancel_scheduled(cancelled));
+}
+
+#[wasm_bindgen_test]
+async fn interval_publishes_until_cancelled() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("heartbeat").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    let handle = mq.publish_interval(topic, Function::new_no_args("return 'beat';"), 5.0).unwrap();
+    sleep(40).await;
+    assert!(mq.cancel_scheduled(handle));
+    let beats = received.length();
+    assert!(beats >= 2, "expected several beats, got {}", beats);
+
+    sleep(20).await;
+    assert_eq!(received.length(), beats);
+}
+
+#[wasm_bindgen_test]
+fn dead_letter_topic_receives_failed_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let dlq = mq.register_topic("dead-letters").unwrap();
+    let records = Array::new();
+    mq.subscribe(dlq, recorder(&records)).unwrap();
+    mq.set_dead_letter_topic(dlq, JsValue::UNDEFINED).unwrap();
+    let reason = |i: u32| js_sys::Reflect::get(&records.get(i), &"reason".into()).unwrap().as_string().unwrap();
+
+    // No subscribers and no buffer
+    let orphan = mq.register_topic("orphan").unwrap();
+    mq.publish(orphan, JsValue::from(1)).unwrap();
+    assert_eq!(records.length(), 1);
+    assert_eq!(reason(0), "no_subscribers");
+    let original = js_sys::Reflect::get(&records.get(0), &"original_topic".into()).unwrap();
+    assert_eq!(original.as_string().as_deref(), Some("orphan"));
+
+    // Throwing callback
+    let failing = mq.register_topic("failing").unwrap();
+    mq.subscribe(failing, Function::new_no_args("throw new Error('boom');")).unwrap();
+    mq.publish(failing, JsValue::from(2)).unwrap();
+    assert_eq!(reason(1), "error");
+
+    // Displaced from a full buffer
+    let buffered = mq.register_topic("buffered").unwrap();
+    mq.enable_topic_buffer(buffered, Some(1)).unwrap();
+    mq.publish(buffered, JsValue::from(3)).unwrap();
+    mq.publish(buffered, JsValue::from(4)).unwrap();
+    assert_eq!(records.length(), 3);
+    assert_eq!(reason(2), "overflow");
+    assert_eq!(js_sys::Reflect::get(&records.get(2), &"payload".into()).unwrap().as_f64(), Some(3.0));
+
+    // Reasons can be switched off
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"no_subscribers".into(), &JsValue::FALSE).unwrap();
+    mq.set_dead_letter_topic(dlq, options.into()).unwrap();
+    mq.publish(orphan, JsValue::from(5)).unwrap();
+    assert_eq!(records.length(), 3);
+
+    // A throwing dead-letter subscriber does not loop
+    mq.subscribe(dlq, Function::new_no_args("throw new Error('dlq');")).unwrap();
+    mq.publish(failing, JsValue::from(6)).unwrap();
+    assert_eq!(records.length(), 4);
+}
+
+#[wasm_bindgen_test]
+fn sequence_gaps_from_other_clients_are_reported() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("ordered").unwrap();
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    assert_eq!(mq.get_last_seq(topic), 2.0);
+
+    let gaps = Array::new();
+    mq.on_gap(Function::new_with_args("gap", "this.push(gap);").bind0(&gaps));
+    let remote = Function::new_with_args(
+        "seq",
+        "return {id: 1000 + seq, topic: 'ordered', payload: seq, timestamp: 0, origin_id: 'other-tab', seq: seq};",
+    );
+    for seq in [1, 2, 5] {
+        let msg = remote.call1(&JsValue::NULL, &JsValue::from(seq)).unwrap();
+        mq.receive_from_service_worker(msg).unwrap();
+    }
+
+    assert_eq!(gaps.length(), 1);
+    let field = |name: &str| js_sys::Reflect::get(&gaps.get(0), &name.into()).unwrap();
+    assert_eq!(field("topic").as_string().as_deref(), Some("ordered"));
+    assert_eq!(field("origin").as_string().as_deref(), Some("other-tab"));
+    assert_eq!(field("expected").as_f64(), Some(3.0));
+    assert_eq!(field("received").as_f64(), Some(5.0));
+}
+
+#[wasm_bindgen_test]
+fn batch_is_broadcast_in_one_packet() {
+    /// Puts the original `postMessage` back, even if an assertion fails
+    struct Restore(JsValue, JsValue);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = js_sys::Reflect::set(&self.0, &"postMessage".into(), &self.1);
+        }
+    }
+
+    // Count postMessage calls on every BroadcastChannel
+    let proto = js_sys::eval("BroadcastChannel.prototype").unwrap();
+    let original = js_sys::Reflect::get(&proto, &"postMessage".into()).unwrap();
+    let counter = js_sys::Object::new();
+    js_sys::Reflect::set(&counter, &"calls".into(), &JsValue::from(0)).unwrap();
+    let counting = Function::new_with_args(
+        "original, counter",
+        "return function(data) { counter.calls++; return original.call(this, data); };",
+    ).call2(&JsValue::NULL, &original, &counter).unwrap();
+    let _restore = Restore(proto.clone(), original);
+    js_sys::Reflect::set(&proto, &"postMessage".into(), &counting).unwrap();
+    let calls = || js_sys::Reflect::get(&counter, &"calls".into()).unwrap().as_f64().unwrap() as u32;
+
+    let mq = MessageQueue::new(Some("batch-bench".to_string())).unwrap();
+    let topic = mq.register_topic("points").unwrap();
+    let other = mq.register_topic("other").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    let ids = Array::new();
+    mq.subscribe(topic, Function::new_with_args("payload, topic, timestamp, id", "this.push(id);").bind0(&ids)).unwrap();
+    let before = calls();
+
+    const N: u32 = 1000;
+    let payloads: Array = (0..N).map(JsValue::from).collect();
+    mq.publish_batch_js(topic, payloads).unwrap();
+    assert_eq!(calls() - before, 1);
+    assert_eq!(received.length(), N);
+    assert_eq!(received.get(N - 1).as_f64(), Some((N - 1) as f64));
+
+    // A relayed echo of a batched message is dropped as a duplicate
+    let echo = js_sys::Object::new();
+    for (key, value) in [
+        ("id", ids.get(0)),
+        ("topic", JsValue::from_str("points")),
+        ("payload", JsValue::from_str("echo")),
+        ("timestamp", JsValue::from(0)),
+        ("origin_id", JsValue::from_str("relay")),
+    ] {
+        js_sys::Reflect::set(&echo, &key.into(), &value).unwrap();
+    }
+    mq.receive_from_service_worker(echo.into()).unwrap();
+    assert_eq!(received.length(), N);
+
+    let pairs: Array = [(topic, 1), (other, 2), (topic, 3)].iter()
+        .map(|&(topic_id, payload)| Array::of2(&topic_id.into(), &payload.into()))
+        .collect();
+    mq.publish_batch_pairs(pairs).unwrap();
+    assert_eq!(calls() - before, 2);
+    assert_eq!(received.length(), N + 2);
+}
+
+#[wasm_bindgen_test]
+async fn pending_async_publishes_are_capped() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("flood").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    mq.set_max_pending(2);
+
+    // Reject (default)
+    let _ = mq.publish_async(topic, JsValue::from(1)).unwrap();
+    let _ = mq.publish_async(topic, JsValue::from(2)).unwrap();
+    let err = wasm_bindgen_futures::JsFuture::from(mq.publish_async(topic, JsValue::from(3)).unwrap()).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Queue full"));
+    assert_eq!(mq.pending_count(), 0); // awaiting the rejection let the first two run
+    assert_eq!(received.length(), 2);
+
+    // DropOldest keeps the newest messages
+    received.set_length(0);
+    mq.set_drop_policy(DropPolicy::DropOldest);
+    for n in 1..=4 {
+        let _ = mq.publish_async(topic, JsValue::from(n)).unwrap();
+    }
+    assert_eq!(mq.pending_count(), 2);
+    wasm_bindgen_futures::JsFuture::from(mq.drain()).await.unwrap();
+    let order: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [3.0, 4.0]);
+
+    // DropNewest keeps the oldest messages
+    received.set_length(0);
+    mq.set_drop_policy(DropPolicy::DropNewest);
+    for n in 1..=4 {
+        let _ = mq.publish_async(topic, JsValue::from(n)).unwrap();
+    }
+    wasm_bindgen_futures::JsFuture::from(mq.drain()).await.unwrap();
+    let order: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [1.0, 2.0]);
+    assert_eq!(mq.get_dropped_count(), 4.0);
+}
+
+#[wasm_bindgen_test]
+fn paused_queue_holds_messages_until_resume() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("route").unwrap();
+    mq.enable_topic_buffer(topic, Some(8)).unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.pause();
+    assert!(mq.is_paused());
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    assert_eq!(received.length(), 0);
+    assert_eq!(mq.get_buffer_size(topic), 2);
+
+    assert_eq!(mq.resume(), 2);
+    assert!(!mq.is_paused());
+    let order: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [1.0, 2.0]);
+    assert_eq!(mq.get_buffer_size(topic), 2);
+
+    // The oldest held messages are dropped beyond the limit, and reported as overflow
+    let lost = Array::new();
+    mq.on_overflow(topic, Some(Function::new_with_args("msg", "this.push(msg.payload);").bind0(&lost))).unwrap();
+    mq.set_max_held_messages(1);
+    mq.pause();
+    mq.publish(topic, JsValue::from(3)).unwrap();
+    mq.publish(topic, JsValue::from(4)).unwrap();
+    assert_eq!(lost.length(), 1);
+    assert_eq!(lost.get(0).as_f64(), Some(3.0));
+    let metadata = mq.get_topic_metadata(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&metadata, &"overflow_count".into()).unwrap().as_f64(), Some(1.0));
+    assert_eq!(mq.resume(), 1);
+    assert_eq!(received.get(2).as_f64(), Some(4.0));
+}
+
+#[wasm_bindgen_test]
+fn paused_subscriber_replays_missed_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("feed").unwrap();
+    mq.enable_topic_buffer(topic, Some(8)).unwrap();
+    let paused = Array::new();
+    let live = Array::new();
+    let sub = mq.subscribe(topic, recorder(&paused)).unwrap();
+    mq.subscribe(topic, recorder(&live)).unwrap();
+
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    assert!(mq.pause_subscriber(topic, sub));
+    assert!(!mq.pause_subscriber(topic, sub));
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    mq.publish(topic, JsValue::from(3)).unwrap();
+    assert_eq!(paused.length(), 1);
+    assert_eq!(live.length(), 3);
+
+    // Only the messages published while paused are replayed, to this subscriber only
+    assert_eq!(mq.resume_subscriber(topic, sub, true).unwrap(), 2);
+    let order: Vec<f64> = paused.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [1.0, 2.0, 3.0]);
+    assert_eq!(live.length(), 3);
+
+    // Without replay, missed messages are skipped
+    mq.pause_subscriber(topic, sub);
+    mq.publish(topic, JsValue::from(4)).unwrap();
+    assert_eq!(mq.resume_subscriber(topic, sub, false).unwrap(), 0);
+    mq.publish(topic, JsValue::from(5)).unwrap();
+    assert_eq!(paused.get(3).as_f64(), Some(5.0));
+
+    assert!(mq.resume_subscriber(topic, 999, true).is_err());
+}
+
+#[wasm_bindgen_test]
+fn list_and_find_topics() {
+    let mq = MessageQueue::new(None).unwrap();
+    let a = mq.register_topic("sensor/b").unwrap();
+    let b = mq.register_topic("sensor/a").unwrap();
+    let gone = mq.register_topic("sensor/gone").unwrap();
+    mq.register_topic("other").unwrap();
+    mq.enable_topic_buffer(a, Some(4)).unwrap();
+    mq.subscribe(a, recorder(&Array::new())).unwrap();
+    mq.publish(a, JsValue::from(1)).unwrap();
+    mq.destroy_topic(gone);
+
+    let field = |obj: &JsValue, key: &str| js_sys::Reflect::get(obj, &key.into()).unwrap();
+    assert_eq!(mq.list_topics().unwrap().length(), 3);
+
+    let info = mq.topic_info(a).unwrap();
+    assert_eq!(field(&info, "name").as_string().as_deref(), Some("sensor/b"));
+    assert_eq!(field(&info, "subscribers").as_f64(), Some(1.0));
+    assert_eq!(field(&info, "buffered").as_f64(), Some(1.0));
+    assert_eq!(field(&info, "buffer_capacity").as_f64(), Some(4.0));
+    assert!(mq.topic_info(gone).unwrap().is_undefined());
+
+    let found = mq.find_topics("sensor/").unwrap();
+    let ids: Vec<f64> = found.iter().filter_map(|t| field(&t, "id").as_f64()).collect();
+    assert_eq!(ids, [b as f64, a as f64]);
+}
+
+#[wasm_bindgen_test]
+fn stats_count_publishes_deliveries_and_errors() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("metrics").unwrap();
+    let other = mq.register_topic("other").unwrap();
+    mq.enable_topic_buffer(topic, Some(1)).unwrap();
+    mq.subscribe(topic, recorder(&Array::new())).unwrap();
+    mq.subscribe(topic, Function::new_no_args("throw new Error('boom')")).unwrap();
+
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    mq.publish(other, JsValue::from(3)).unwrap();
+
+    let field = |obj: &JsValue, key: &str| js_sys::Reflect::get(obj, &key.into()).unwrap().as_f64();
+    let stats = mq.get_stats().unwrap();
+    assert_eq!(field(&stats, "published"), Some(3.0));
+    assert_eq!(field(&stats, "delivered"), Some(4.0));
+    assert_eq!(field(&stats, "errors"), Some(2.0));
+    assert_eq!(field(&stats, "overwrites"), Some(1.0));
+    assert_eq!(field(&stats, "broadcast"), Some(0.0));
+
+    let topic_stats = mq.get_topic_stats(topic).unwrap();
+    assert_eq!(field(&topic_stats, "published"), Some(2.0));
+    assert_eq!(field(&mq.get_topic_stats(other).unwrap(), "delivered"), Some(0.0));
+
+    mq.reset_stats();
+    assert_eq!(field(&mq.get_stats().unwrap(), "published"), Some(0.0));
+    assert_eq!(field(&mq.get_topic_stats(topic).unwrap(), "errors"), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+fn subscriber_errors_reach_error_handler() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("fragile").unwrap();
+    mq.enable_topic_buffer(topic, Some(4)).unwrap();
+    let errors = Array::new();
+    // The handler throwing must not stop delivery to later subscribers
+    mq.set_error_handler(
+        Function::new_with_args("error, topic, sub_id", "this.push([topic, sub_id]); throw error;").bind0(&errors),
+    );
+
+    let thrower = Function::new_no_args("throw new Error('boom')");
+    let failing = mq.subscribe(topic, thrower.clone()).unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    assert_eq!(received.length(), 1);
+    assert_eq!(errors.length(), 1);
+    let reported = Array::from(&errors.get(0));
+    assert_eq!(reported.get(0).as_string().as_deref(), Some("fragile"));
+    assert_eq!(reported.get(1).as_f64(), Some(failing as f64));
+
+    // Errors during buffered replay are reported the same way
+    mq.subscribe_with_replay(topic, thrower, None).unwrap();
+    assert_eq!(errors.length(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn binary_wire_format_round_trips_payloads() {
+    let mut sender = MessageQueue::new(Some("wire-binary".to_string())).unwrap();
+    let mut receiver = MessageQueue::new(Some("wire-binary".to_string())).unwrap();
+    sender.set_wire_format("binary").unwrap();
+    assert!(sender.set_wire_format("xml").is_err());
+    let topic = sender.register_topic("telemetry").unwrap();
+    let remote_topic = receiver.register_topic("telemetry").unwrap();
+    let received = Array::new();
+    receiver.subscribe(remote_topic, recorder(&received)).unwrap();
+
+    // A view into the middle of a larger buffer only sends its own bytes
+    let backing = js_sys::Float64Array::new_with_length(4);
+    backing.copy_from(&[0.0, 1.5, -2.25, 0.0]);
+    let floats = js_sys::Float64Array::new_with_byte_offset_and_length(&backing.buffer(), 8, 2);
+
+    sender.publish(topic, JsValue::from_str("héllo")).unwrap();
+    sender.publish(topic, floats.into()).unwrap();
+    sender.publish(topic, JsValue::UNDEFINED).unwrap();
+    // Objects fall back to the object format
+    let object = js_sys::Object::new();
+    js_sys::Reflect::set(&object, &"x".into(), &JsValue::from(1)).unwrap();
+    sender.publish(topic, object.into()).unwrap();
+    sleep(50).await;
+
+    assert_eq!(received.length(), 4);
+    assert_eq!(received.get(0).as_string().as_deref(), Some("héllo"));
+    let floats: js_sys::Float64Array = received.get(1).dyn_into().unwrap();
+    assert_eq!(floats.to_vec(), [1.5, -2.25]);
+    assert!(received.get(2).is_undefined());
+    assert_eq!(js_sys::Reflect::get(&received.get(3), &"x".into()).unwrap().as_f64(), Some(1.0));
+
+    sender.close().unwrap();
+    receiver.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn publish_transfer_falls_back_to_copy_on_broadcast_channel() {
+    let mut sender = MessageQueue::new(Some("transfer-fallback".to_string())).unwrap();
+    let mut receiver = MessageQueue::new(Some("transfer-fallback".to_string())).unwrap();
+    let topic = sender.register_topic("frames").unwrap();
+    let remote_topic = receiver.register_topic("frames").unwrap();
+    let local = Array::new();
+    let remote = Array::new();
+    sender.subscribe(topic, recorder(&local)).unwrap();
+    receiver.subscribe(remote_topic, recorder(&remote)).unwrap();
+
+    let buffer = js_sys::ArrayBuffer::new(16);
+    sender.publish_transfer(topic, buffer.clone().into(), Array::of1(&buffer)).unwrap();
+    let local_buffer: js_sys::ArrayBuffer = local.get(0).dyn_into().unwrap();
+    assert_eq!(local_buffer.byte_length(), 16);
+    sleep(50).await;
+
+    // BroadcastChannel cannot transfer: the payload is copied and stays usable here
+    assert_eq!(buffer.byte_length(), 16);
+    let remote_buffer: js_sys::ArrayBuffer = remote.get(0).dyn_into().unwrap();
+    assert_eq!(remote_buffer.byte_length(), 16);
+
+    sender.close().unwrap();
+    receiver.close().unwrap();
+}
+
+/// Both ends of a new MessageChannel
+fn message_channel() -> (web_sys::MessagePort, web_sys::MessagePort) {
+    let channel = js_sys::eval("new MessageChannel()").unwrap();
+    let port = |name: &str| js_sys::Reflect::get(&channel, &name.into()).unwrap().unchecked_into();
+    (port("port1"), port("port2"))
+}
+
+#[wasm_bindgen_test]
+async fn message_port_transport_exchanges_messages() {
+    let (port1, port2) = message_channel();
+    let main = MessageQueue::with_port(port1).unwrap();
+    main.enable_topic_buffer(main.register_topic("jobs").unwrap(), Some(4)).unwrap();
+    main.publish(main.get_topic_id("jobs").unwrap(), JsValue::from(1)).unwrap();
+
+    // The worker side receives buffered messages through SYNC_REQ, then live ones
+    let worker = MessageQueue::with_port(port2).unwrap();
+    let topic = worker.register_topic("jobs").unwrap();
+    let received = Array::new();
+    worker.subscribe(topic, recorder(&received)).unwrap();
+    sleep(60).await;
+    main.publish(main.get_topic_id("jobs").unwrap(), JsValue::from(2)).unwrap();
+    sleep(20).await;
+    let order: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(order, [1.0, 2.0]);
+
+    // The same message arriving on two ports is delivered once
+    let (extra1, extra2) = message_channel();
+    main.attach_port(extra1).unwrap();
+    worker.attach_port(extra2).unwrap();
+    main.publish(main.get_topic_id("jobs").unwrap(), JsValue::from(3)).unwrap();
+    sleep(20).await;
+    assert_eq!(received.length(), 3);
+}
+
+#[wasm_bindgen_test]
+async fn publish_transfer_detaches_buffer_after_local_delivery() {
+    let (port1, port2) = message_channel();
+    let sender = MessageQueue::with_port(port1).unwrap();
+    let receiver = MessageQueue::with_port(port2).unwrap();
+    let topic = sender.register_topic("frames").unwrap();
+    let remote_topic = receiver.register_topic("frames").unwrap();
+    let local_lengths = Array::new();
+    sender.subscribe(
+        topic,
+        Function::new_with_args("payload", "this.push(payload.byteLength);").bind0(&local_lengths),
+    ).unwrap();
+    let remote = Array::new();
+    receiver.subscribe(remote_topic, recorder(&remote)).unwrap();
+
+    let buffer = js_sys::ArrayBuffer::new(16);
+    sender.publish_transfer(topic, buffer.clone().into(), Array::of1(&buffer)).unwrap();
+
+    // Local subscribers saw the intact buffer; afterwards it is detached here
+    assert_eq!(local_lengths.get(0).as_f64(), Some(16.0));
+    assert_eq!(buffer.byte_length(), 0);
+    sleep(20).await;
+    let remote_buffer: js_sys::ArrayBuffer = remote.get(0).dyn_into().unwrap();
+    assert_eq!(remote_buffer.byte_length(), 16);
+}
+
+#[wasm_bindgen_test]
+fn storage_transport_receives_packets_once() {
+    let config = js_sys::eval("({channel: 'ls-test', transport: 'storage'})").unwrap();
+    let mq = MessageQueue::from_js_config(config).unwrap();
+    let topic = mq.register_topic("prices").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    // Storage events only reach other tabs, so simulate one arriving
+    let deliver = Function::new_with_args(
+        "key, origin",
+        "window.dispatchEvent(new StorageEvent('storage', {key: key, newValue: JSON.stringify(\
+            [0, {id: {__bigint: '42'}, topic: 'prices', payload: 7, timestamp: 0, origin_id: origin}])}));",
+    );
+    deliver.call2(&JsValue::NULL, &"wasm-ripple:ls-test:0".into(), &"other-tab".into()).unwrap();
+    // A replay of the same message is dropped by the dedup set
+    deliver.call2(&JsValue::NULL, &"wasm-ripple:ls-test:1".into(), &"other-tab".into()).unwrap();
+    // Keys of other channels are ignored
+    deliver.call2(&JsValue::NULL, &"wasm-ripple:other:0".into(), &"third-tab".into()).unwrap();
+    assert_eq!(received.length(), 1);
+    assert_eq!(received.get(0).as_f64(), Some(7.0));
+
+    // Published packets do not stay in localStorage
+    mq.publish(topic, JsValue::from(8)).unwrap();
+    let storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+    let keys: Vec<String> = (0..storage.length().unwrap()).filter_map(|i| storage.key(i).unwrap()).collect();
+    assert!(!keys.iter().any(|key| key.starts_with("wasm-ripple:ls-test:")));
+
+    let cyclic = js_sys::eval("(() => { const o = {}; o.self = o; return o; })()").unwrap();
+    let err = mq.publish(topic, cyclic).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Payload is not JSON-serializable"));
+}
+
+#[wasm_bindgen_test]
+fn websocket_bridge_forwards_and_injects_without_echo() {
+    let mq = MessageQueue::new(Some("ws-bridge-test".to_string())).unwrap();
+    let topic = mq.register_topic("prices").unwrap();
+    let other = mq.register_topic("alerts").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    // A stand-in socket recording sent frames; a real one would need a server
+    let ws: web_sys::WebSocket = js_sys::eval(
+        "({url: 'ws://server.test/', sent: [], send(frame) { this.sent.push(frame); }, \
+          onmessage: null, onclose: null, onerror: null})",
+    ).unwrap().unchecked_into();
+    let sent: Array = js_sys::Reflect::get(&ws, &"sent".into()).unwrap().dyn_into().unwrap();
+
+    assert!(mq.bridge_websocket(ws.clone(), Array::of1(&JsValue::from(99)), None).is_err());
+    mq.bridge_websocket(ws.clone(), Array::of1(&JsValue::from(topic)), None).unwrap();
+    assert!(mq.bridge_websocket(ws.clone(), Array::of1(&JsValue::from(topic)), None).is_err());
+
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(other, JsValue::from(2)).unwrap();
+    assert_eq!(sent.length(), 1);
+    let frame = js_sys::JSON::parse(&sent.get(0).as_string().unwrap()).unwrap();
+    assert_eq!(js_sys::Reflect::get(&frame, &"topic".into()).unwrap().as_string().as_deref(), Some("prices"));
+
+    // A frame from the server is delivered locally but not sent back
+    let onmessage: Function = js_sys::Reflect::get(&ws, &"onmessage".into()).unwrap().dyn_into().unwrap();
+    let event = js_sys::eval(
+        "({data: JSON.stringify({topic: 'prices', id: '777', payload: 3, timestamp: 0, origin_id: 'server'})})",
+    ).unwrap();
+    onmessage.call1(&JsValue::NULL, &event).unwrap();
+    assert_eq!(received.length(), 2);
+    assert_eq!(received.get(1).as_f64(), Some(3.0));
+    assert_eq!(sent.length(), 1);
+
+    assert_eq!(mq.unbridge(), 1);
+    assert!(js_sys::Reflect::get(&ws, &"onmessage".into()).unwrap().is_null());
+    mq.publish(topic, JsValue::from(4)).unwrap();
+    assert_eq!(sent.length(), 1);
+    assert_eq!(mq.unbridge(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn sync_topics_fetches_only_requested_topics() {
+    // Responder: a queue with buffered messages on two topics, probed by hand
+    let (port1, port2) = message_channel();
+    let sender = MessageQueue::with_port(port1).unwrap();
+    for (name, values) in [("prices", [1, 2, 3]), ("logs", [10, 20, 30])] {
+        let topic = sender.register_topic(name).unwrap();
+        sender.enable_topic_buffer(topic, Some(8)).unwrap();
+        for value in values {
+            sender.publish(topic, JsValue::from(value)).unwrap();
+        }
+    }
+    let replies = Array::new();
+    let collect = Function::new_with_args("event", "if (event.data[0] === 2) this.push(event.data[2]);").bind0(&replies);
+    port2.set_onmessage(Some(&collect));
+    port2.post_message(&js_sys::eval("[1, 'probe', ['prices']]").unwrap()).unwrap();
+    sleep(60).await;
+    assert_eq!(replies.length(), 1);
+    let msgs = Array::from(&replies.get(0));
+    let payloads: Vec<f64> = msgs.iter()
+        .map(|msg| js_sys::Reflect::get(&msg, &"payload".into()).unwrap().as_f64().unwrap())
+        .collect();
+    assert_eq!(payloads, vec![1.0, 2.0, 3.0]);
+
+    // Requester: a queue syncing one topic from a scripted peer
+    let (port3, port4) = message_channel();
+    let receiver = MessageQueue::with_port(port3).unwrap();
+    let prices = receiver.register_topic("prices").unwrap();
+    let received = Array::new();
+    receiver.subscribe(prices, recorder(&received)).unwrap();
+    let peer = Function::new_with_args(
+        "port",
+        "port.onmessage = (event) => { \
+            const [type, origin, names] = event.data; \
+            if (type !== 1 || !names) return; \
+            const msg = (id, payload) => ({id: BigInt(id), topic: 'prices', payload, timestamp: 0, origin_id: 'peer'}); \
+            port.postMessage([2, names.includes('prices') ? [msg(1, 'a'), msg(2, 'b')] : []]); \
+        };",
+    );
+    peer.call1(&JsValue::NULL, &port4).unwrap();
+
+    let names = Array::of1(&"prices".into());
+    let count = wasm_bindgen_futures::JsFuture::from(receiver.sync_topics(names.clone())).await.unwrap();
+    assert_eq!(count.as_f64(), Some(2.0));
+    let values: Vec<String> = received.iter().filter_map(|v| v.as_string()).collect();
+    assert_eq!(values, vec!["a", "b"]);
+
+    // Messages already seen are not delivered again
+    let count = wasm_bindgen_futures::JsFuture::from(receiver.sync_topics(names)).await.unwrap();
+    assert_eq!(count.as_f64(), Some(0.0));
+    assert_eq!(received.length(), 2);
+
+    let err = wasm_bindgen_futures::JsFuture::from(receiver.sync_topics(Array::of1(&JsValue::from(1)))).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic names must be strings"));
+}
+
+#[wasm_bindgen_test]
+async fn request_sync_since_pages_through_newer_messages() {
+    // Responder: a real queue answering an incremental request by hand
+    let (port1, port2) = message_channel();
+    let sender = MessageQueue::with_port(port1).unwrap();
+    sender.set_sync_response_limit(2).unwrap();
+    assert!(sender.set_sync_response_limit(0).is_err());
+    let topic = sender.register_topic("events").unwrap();
+    sender.enable_topic_buffer(topic, Some(8)).unwrap();
+    for value in 1..=3 {
+        sender.publish(topic, JsValue::from(value)).unwrap();
+        sleep(2).await;
+    }
+    let replies = Array::new();
+    let collect = Function::new_with_args("event", "if (event.data[0] === 2) this.push(event.data);").bind0(&replies);
+    port2.set_onmessage(Some(&collect));
+    port2.post_message(&js_sys::eval("[1, 'probe', null, 0]").unwrap()).unwrap();
+    sleep(60).await;
+    let reply = Array::from(&replies.get(0));
+    assert_eq!(reply.get(1).as_string().as_deref(), Some("probe"));
+    assert_eq!(Array::from(&reply.get(2)).length(), 2);
+    assert_eq!(reply.get(3), JsValue::TRUE);
+
+    // Requester: a scripted peer holding five messages, two per page
+    let (port3, port4) = message_channel();
+    let receiver = MessageQueue::with_port(port3).unwrap();
+    let events = receiver.register_topic("events").unwrap();
+    let received = Array::new();
+    receiver.subscribe(events, recorder(&received)).unwrap();
+    let peer = Function::new_with_args(
+        "port",
+        "port.onmessage = (event) => { \
+            const [type, origin, names, since] = event.data; \
+            if (type !== 1 || typeof since !== 'number') return; \
+            const newer = [100, 200, 300, 400, 500].filter((ts) => ts > since) \
+                .map((ts) => ({id: BigInt(ts), topic: 'events', payload: ts, timestamp: ts, origin_id: 'peer'})); \
+            if (newer.length) port.postMessage([2, newer.slice(0, 2), newer.length > 2]); \
+        };",
+    );
+    peer.call1(&JsValue::NULL, &port4).unwrap();
+
+    // Messages at exactly the cursor are not sent
+    let count = wasm_bindgen_futures::JsFuture::from(receiver.request_sync_since(100.0)).await.unwrap();
+    assert_eq!(count.as_f64(), Some(4.0));
+    let values: Vec<f64> = received.iter().filter_map(|v| v.as_f64()).collect();
+    assert_eq!(values, vec![200.0, 300.0, 400.0, 500.0]);
+
+    // Nothing newer: the peer stays silent and the sync resolves with 0
+    let count = wasm_bindgen_futures::JsFuture::from(receiver.request_sync_since(500.0)).await.unwrap();
+    assert_eq!(count.as_f64(), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+async fn sync_answer_is_suppressed_when_another_tab_answers_first() {
+    let (port1, port2) = message_channel();
+    let responder = MessageQueue::with_port(port1).unwrap();
+    let topic = responder.register_topic("jobs").unwrap();
+    responder.enable_topic_buffer(topic, Some(4)).unwrap();
+    responder.publish(topic, JsValue::from(1)).unwrap();
+
+    let replies = Array::new();
+    let collect = Function::new_with_args("event", "if (event.data[0] === 2) this.push(event.data);").bind0(&replies);
+    port2.set_onmessage(Some(&collect));
+
+    // Another tab's response for the same requester arrives within the jitter window
+    port2.post_message(&js_sys::eval("[1, 'newcomer']").unwrap()).unwrap();
+    port2.post_message(&js_sys::eval("[2, 'newcomer', []]").unwrap()).unwrap();
+    sleep(80).await;
+    assert_eq!(replies.length(), 0);
+
+    // A response for a different requester does not suppress the answer
+    port2.post_message(&js_sys::eval("[1, 'other']").unwrap()).unwrap();
+    port2.post_message(&js_sys::eval("[2, 'newcomer', []]").unwrap()).unwrap();
+    sleep(80).await;
+    assert_eq!(replies.length(), 1);
+
+    // Forced answers are sent right away and cannot be suppressed
+    responder.set_sync_respond_always(true);
+    port2.post_message(&js_sys::eval("[1, 'newcomer']").unwrap()).unwrap();
+    port2.post_message(&js_sys::eval("[2, 'newcomer', []]").unwrap()).unwrap();
+    sleep(20).await;
+    assert_eq!(replies.length(), 2);
+    let reply = Array::from(&replies.get(1));
+    assert_eq!(reply.get(1).as_string().as_deref(), Some("newcomer"));
+    assert_eq!(Array::from(&reply.get(2)).length(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn sync_request_is_answered_on_the_port_it_arrived_on() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("history").unwrap();
+    mq.enable_topic_buffer(topic, Some(4)).unwrap();
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    let (asking, asking_peer) = message_channel();
+    let (other, other_peer) = message_channel();
+    mq.attach_port(asking).unwrap();
+    mq.attach_port(other).unwrap();
+
+    // Packet types seen on each peer end, after the handshakes have arrived
+    let record_types = |port: &web_sys::MessagePort| {
+        let types = Array::new();
+        let handler = Function::new_with_args("event", "this.push(event.data[0]);").bind0(&types);
+        port.set_onmessage(Some(&handler));
+        types
+    };
+    let asking_types = record_types(&asking_peer);
+    let other_types = record_types(&other_peer);
+    sleep(20).await;
+    asking_types.set_length(0);
+    other_types.set_length(0);
+
+    let req = Array::of2(&JsValue::from(1), &JsValue::from_str("worker"));
+    asking_peer.post_message(&req).unwrap();
+    sleep(150).await;
+    assert_eq!(asking_types.to_vec(), vec![JsValue::from(2)]);
+    assert_eq!(other_types.length(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn presence_tracks_peers_joining_and_leaving() {
+    let (port1, port2) = message_channel();
+    let first = MessageQueue::with_port(port1).unwrap();
+    let changes = Array::new();
+    first.on_peer_change(Some(
+        Function::new_with_args("peer, kind", "this.push(kind + ':' + peer);").bind0(&changes),
+    ));
+    let config = js_sys::eval("({client_id: 'second-tab'})").unwrap();
+    let mut second = MessageQueue::from_js_config(config).unwrap();
+    second.attach_port(port2).unwrap();
+    sleep(20).await;
+
+    // The join is answered, so both sides know each other
+    assert_eq!(first.get_peers().get(0).as_string().as_deref(), Some("second-tab"));
+    assert_eq!(second.get_peers().length(), 1);
+    assert_eq!(changes.get(0).as_string().as_deref(), Some("joined:second-tab"));
+    assert!(first.enable_presence(0.0).is_err());
+
+    second.close().unwrap();
+    sleep(20).await;
+    assert_eq!(first.get_peers().length(), 0);
+    assert_eq!(changes.get(1).as_string().as_deref(), Some("left:second-tab"));
+    // Dropping a closed queue does not post (or panic) again
+    drop(second);
+
+    // A peer that stops sending heartbeats is dropped
+    let (port3, port4) = message_channel();
+    first.attach_port(port3).unwrap();
+    first.enable_presence(10.0).unwrap();
+    port4.post_message(&js_sys::eval("[4, 'frozen-tab', 'join']").unwrap()).unwrap();
+    sleep(5).await;
+    assert_eq!(first.get_peers().length(), 1);
+    sleep(60).await;
+    assert_eq!(first.get_peers().length(), 0);
+    assert_eq!(changes.get(3).as_string().as_deref(), Some("left:frozen-tab"));
+    first.disable_presence();
+}
+
+#[wasm_bindgen_test]
+async fn leader_steps_down_for_lower_client_id_and_is_reelected() {
+    let config = js_sys::eval("({client_id: 'tab-b'})").unwrap();
+    let mq = MessageQueue::from_js_config(config).unwrap();
+    let (port1, port2) = message_channel();
+    mq.attach_port(port1).unwrap();
+    let changes = Array::new();
+    mq.on_leadership_change(Some(Function::new_with_args("leader", "this.push(leader);").bind0(&changes)));
+
+    // Nobody with a lower client ID answers, so this tab wins
+    let won = wasm_bindgen_futures::JsFuture::from(mq.elect_leader()).await.unwrap();
+    assert_eq!(won, JsValue::TRUE);
+    assert!(mq.is_leader());
+    assert_eq!(changes.get(0), JsValue::TRUE);
+
+    // The winner of a concurrent election with a lower client ID claims leadership
+    port2.post_message(&js_sys::eval("[4, 'tab-a', 'join']").unwrap()).unwrap();
+    port2.post_message(&js_sys::eval("[9, 'tab-a', 'claim']").unwrap()).unwrap();
+    sleep(20).await;
+    assert!(!mq.is_leader());
+    assert_eq!(changes.get(1), JsValue::FALSE);
+
+    // A higher client ID cannot take over while the lower leader is known
+    port2.post_message(&js_sys::eval("[9, 'tab-c', 'claim']").unwrap()).unwrap();
+    sleep(150).await;
+    assert!(!mq.is_leader());
+    assert_eq!(changes.length(), 2);
+
+    // When the leader leaves, the remaining candidate is re-elected
+    port2.post_message(&js_sys::eval("[4, 'tab-a', 'leave']").unwrap()).unwrap();
+    sleep(150).await;
+    assert!(mq.is_leader());
+    assert_eq!(changes.get(2), JsValue::TRUE);
+}
+
+#[wasm_bindgen_test]
+async fn goodbye_rejects_requests_the_closed_peer_would_answer() {
+    let config = js_sys::eval("({client_id: 'tab-a'})").unwrap();
+    let mq = MessageQueue::from_js_config(config).unwrap();
+    let (port1, port2) = message_channel();
+    mq.attach_port(port1).unwrap();
+    let closed = Array::new();
+    mq.on_peer_closed(Some(Function::new_with_args("peer", "this.push(peer);").bind0(&closed)));
+    let topic = mq.register_topic("rpc/double").unwrap();
+
+    // A scripted peer answering the first request only
+    let peer = Function::new_with_args(
+        "port",
+        "let answered = false; \
+         port.onmessage = (event) => { \
+            const [type, topic, correlation, requester, payload] = event.data; \
+            if (type !== 7 || answered) return; \
+            answered = true; \
+            port.postMessage([8, correlation, requester, true, payload * 2, 'tab-b']); \
+        };",
+    );
+    peer.call1(&JsValue::NULL, &port2).unwrap();
+    let reply = wasm_bindgen_futures::JsFuture::from(mq.request(topic, JsValue::from(21), 1000.0)).await.unwrap();
+    assert_eq!(reply.as_f64(), Some(42.0));
+
+    // Our own goodbye (e.g. echoed by a relay) is ignored
+    port2.post_message(&js_sys::eval("[10, 'tab-a']").unwrap()).unwrap();
+    // The responder closes before answering the next request
+    let pending = mq.request(topic, JsValue::from(1), 1000.0);
+    port2.post_message(&js_sys::eval("[10, 'tab-b']").unwrap()).unwrap();
+    let err = wasm_bindgen_futures::JsFuture::from(pending).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("peer closed"));
+    assert_eq!(closed.length(), 1);
+    assert_eq!(closed.get(0).as_string().as_deref(), Some("tab-b"));
+}
+
+#[wasm_bindgen_test]
+fn publish_to_tagged_checks_every_topic_first() {
+    let mq = MessageQueue::new(None).unwrap();
+    let open = mq.register_topic("open").unwrap();
+    let full = mq.register_topic("full").unwrap();
+    let untagged = mq.register_topic("untagged").unwrap();
+    mq.add_topic_tag(open, "ui").unwrap();
+    mq.add_topic_tag(full, "ui").unwrap();
+    let received = Array::new();
+    for topic in [open, full, untagged] {
+        mq.subscribe(topic, recorder(&received)).unwrap();
+    }
+
+    assert_eq!(mq.publish_to_tagged("ui", JsValue::from(1)).unwrap(), 2);
+    assert_eq!(received.length(), 2);
+    assert_eq!(mq.publish_to_tagged("none", JsValue::from(1)).unwrap(), 0);
+
+    // One full topic rejects the message for all of them
+    mq.enable_topic_buffer_with_policy(full, 1, "error").unwrap();
+    mq.publish(full, JsValue::from(2)).unwrap();
+    let err = mq.publish_to_tagged("ui", JsValue::from(3)).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic buffer full"));
+    assert_eq!(received.length(), 3);
+}
+
+#[wasm_bindgen_test]
+fn transaction_applies_all_or_nothing() {
+    let mq = MessageQueue::new(None).unwrap();
+    let audit = mq.register_topic("audit").unwrap();
+    mq.enable_topic_buffer_with_policy(audit, 2, "error").unwrap();
+    let received = Array::new();
+    mq.subscribe(audit, recorder(&received)).unwrap();
+    let run = |body: &str| mq.transaction(Function::new_with_args("tx", body));
+
+    // create_topic reports whether the topic is new, as on the queue
+    let created = run("return [tx.create_topic('audit'), tx.create_topic('extra'), tx.create_topic('extra')];").unwrap();
+    assert_eq!(Array::from(&created).to_vec(), vec![JsValue::FALSE, JsValue::TRUE, JsValue::FALSE]);
+    assert!(mq.get_topic_id("extra").is_some());
+
+    // Three publishes do not fit the buffer: none is applied, not even the new topic
+    let body = format!("tx.create_topic('rolled-back'); for (let i = 0; i < 3; i++) tx.publish({}, i);", audit);
+    let err = run(&body).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic buffer full"));
+    assert_eq!(received.length(), 0);
+    assert_eq!(mq.get_buffer_size(audit), 0);
+    assert!(mq.get_topic_id("rolled-back").is_none());
+
+    let body = format!("tx.publish({0}, 1); tx.publish({0}, 2);", audit);
+    run(&body).unwrap();
+    assert_eq!(received.to_vec(), vec![JsValue::from(1), JsValue::from(2)]);
+}
+
+#[wasm_bindgen_test]
+fn buffer_overflow_policies() {
+    let mq = MessageQueue::new(None).unwrap();
+    let log = mq.register_topic("audit").unwrap();
+    let metrics = mq.register_topic("metrics").unwrap();
+    assert!(mq.enable_topic_buffer_with_policy(log, 2, "drop-everything").is_err());
+    mq.enable_topic_buffer_with_policy(log, 2, "error").unwrap();
+    mq.enable_topic_buffer_with_policy(metrics, 2, "drop-newest").unwrap();
+    let received = Array::new();
+    mq.subscribe(log, recorder(&received)).unwrap();
+    mq.subscribe(metrics, recorder(&received)).unwrap();
+    let lost = Array::new();
+    let record_lost = Function::new_with_args("msg", "this.push(msg.payload);").bind0(&lost);
+    mq.on_overflow(log, Some(record_lost.clone())).unwrap();
+    mq.on_overflow(metrics, Some(record_lost)).unwrap();
+
+    // "error": the third publish fails and reaches nobody but the overflow hook
+    mq.publish(log, JsValue::from(1)).unwrap();
+    mq.publish(log, JsValue::from(2)).unwrap();
+    let err = mq.publish(log, JsValue::from(3)).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic buffer full"));
+    assert_eq!(received.length(), 2);
+    assert_eq!(lost.length(), 1);
+    assert_eq!(lost.get(0), JsValue::from(3));
+
+    // "drop-newest": the third message is delivered but not buffered
+    for payload in 10..13 {
+        mq.publish(metrics, JsValue::from(payload)).unwrap();
+    }
+    assert_eq!(received.length(), 5);
+    assert_eq!(lost.length(), 2);
+    assert_eq!(lost.get(1), JsValue::from(12));
+    let buffered = mq.get_buffered_messages(metrics).unwrap();
+    assert_eq!(js_sys::Reflect::get(&buffered.get(1), &"payload".into()).unwrap(), JsValue::from(11));
+
+    for (topic, expected) in [(log, 1.0), (metrics, 1.0)] {
+        let metadata = mq.get_topic_metadata(topic).unwrap();
+        assert_eq!(js_sys::Reflect::get(&metadata, &"overflow_count".into()).unwrap().as_f64(), Some(expected));
+    }
+}
+
+#[wasm_bindgen_test]
+async fn buffer_retention_evicts_aged_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("recent").unwrap();
+    mq.enable_topic_buffer(topic, Some(10)).unwrap();
+    assert!(mq.set_buffer_retention(topic, Some(0.0)).is_err());
+    mq.set_buffer_retention(topic, Some(40.0)).unwrap();
+
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    sleep(60).await;
+    mq.publish(topic, JsValue::from(3)).unwrap();
+    let buffered = mq.get_buffered_messages(topic).unwrap();
+    assert_eq!(buffered.length(), 1);
+    assert_eq!(js_sys::Reflect::get(&buffered.get(0), &"payload".into()).unwrap(), JsValue::from(3));
+
+    // Without a push, reads and explicit purges still drop aged messages
+    sleep(60).await;
+    assert_eq!(mq.purge_expired(topic), 1);
+    assert_eq!(mq.get_buffer_size(topic), 0);
+
+    // Retention can be lifted again
+    mq.set_buffer_retention(topic, None).unwrap();
+    mq.publish(topic, JsValue::from(4)).unwrap();
+    sleep(60).await;
+    assert_eq!(mq.get_buffer_size(topic), 1);
+}
+
+#[wasm_bindgen_test]
+fn byte_budget_evicts_oldest_payloads() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("blobs").unwrap();
+    mq.enable_topic_buffer(topic, Some(100)).unwrap();
+    mq.publish(topic, JsValue::from_str("before")).unwrap();
+    assert_eq!(mq.get_buffer_bytes(topic), 0.0);
+    assert!(mq.set_buffer_max_bytes(topic, Some(0), None).is_err());
+
+    // Existing messages are measured when the budget is set
+    let sizer = Function::new_with_args("payload", "if (payload.size === 'bad') throw new Error('no'); return payload.size;");
+    mq.set_buffer_max_bytes(topic, Some(100), Some(sizer)).unwrap();
+    assert_eq!(mq.get_buffer_bytes(topic), 12.0);
+
+    mq.publish(topic, js_sys::ArrayBuffer::new(40).into()).unwrap();
+    mq.publish(topic, js_sys::Uint8Array::new_with_length(30).into()).unwrap();
+    assert_eq!(mq.get_buffer_bytes(topic), 82.0);
+
+    // The sizer measures objects; the oldest payloads make room
+    mq.publish(topic, js_sys::eval("({size: 50})").unwrap()).unwrap();
+    assert_eq!(mq.get_buffer_bytes(topic), 80.0);
+    assert_eq!(mq.get_buffer_size(topic), 2);
+
+    // A throwing sizer falls back to the fixed estimate, which exceeds the budget
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    mq.publish(topic, js_sys::eval("({size: 'bad'})").unwrap()).unwrap();
+    assert_eq!(received.length(), 1);
+    assert_eq!(mq.get_buffer_size(topic), 2);
+
+    let stats = mq.get_topic_stats(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&stats, &"buffer_bytes".into()).unwrap().as_f64(), Some(80.0));
+    let metadata = mq.get_topic_metadata(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&metadata, &"overflow_count".into()).unwrap().as_f64(), Some(3.0));
+}
+
+#[wasm_bindgen_test]
+fn drain_and_consume_buffered_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("backlog").unwrap();
+    let unbuffered = mq.register_topic("live").unwrap();
+    mq.enable_topic_buffer(topic, Some(10)).unwrap();
+    for payload in 1..=6 {
+        mq.publish(topic, JsValue::from(payload)).unwrap();
+    }
+
+    let drained = mq.drain_buffer(topic, Some(2)).unwrap();
+    assert_eq!(drained.length(), 2);
+    assert_eq!(js_sys::Reflect::get(&drained.get(0), &"payload".into()).unwrap(), JsValue::from(1));
+    assert_eq!(mq.get_buffer_size(topic), 4);
+
+    // The callback throws on 5: 3 and 4 are consumed, 5 and 6 stay buffered
+    let seen = Array::new();
+    let callback = Function::new_with_args(
+        "msg",
+        "if (msg.payload === 5) throw new Error('retry later'); this.push(msg.payload);",
+    ).bind0(&seen);
+    assert_eq!(mq.consume_buffer(topic, callback.clone(), None).unwrap(), 2);
+    assert_eq!(seen.length(), 2);
+    let rest = mq.drain_buffer(topic, None).unwrap();
+    assert_eq!(rest.length(), 2);
+    assert_eq!(js_sys::Reflect::get(&rest.get(0), &"payload".into()).unwrap(), JsValue::from(5));
+    assert_eq!(mq.get_buffer_size(topic), 0);
+
+    // Topics without a buffer have nothing to drain
+    assert_eq!(mq.drain_buffer(unbuffered, None).unwrap().length(), 0);
+    assert_eq!(mq.consume_buffer(unbuffered, callback, Some(5)).unwrap(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn query_buffered_messages_by_range_and_predicate() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("timeline").unwrap();
+    mq.enable_topic_buffer(topic, Some(10)).unwrap();
+    for payload in 1..=4 {
+        mq.publish(topic, JsValue::from(payload)).unwrap();
+        sleep(5).await;
+    }
+    let all = mq.get_buffered_messages(topic).unwrap();
+    let timestamp = |index: u32| js_sys::Reflect::get(&all.get(index), &"timestamp".into()).unwrap().as_f64().unwrap();
+    let payloads = |msgs: &Array| msgs.iter()
+        .map(|msg| js_sys::Reflect::get(&msg, &"payload".into()).unwrap().as_f64().unwrap())
+        .collect::<Vec<_>>();
+
+    let range = mq.get_buffered_range(topic, timestamp(1), timestamp(2), None).unwrap();
+    assert_eq!(payloads(&range), vec![2.0, 3.0]);
+    assert!(js_sys::Reflect::get(&range.get(0), &"origin_id".into()).unwrap().is_string());
+    let limited = mq.get_buffered_range(topic, timestamp(0), timestamp(3), Some(1)).unwrap();
+    assert_eq!(payloads(&limited), vec![1.0]);
+
+    let even = Function::new_with_args("payload", "return payload % 2 === 0;");
+    assert_eq!(payloads(&mq.get_buffered_where(topic, even.clone(), None).unwrap()), vec![2.0, 4.0]);
+    assert_eq!(payloads(&mq.get_buffered_where(topic, even, Some(1)).unwrap()), vec![2.0]);
+    let throws = Function::new_with_args("payload", "throw new Error('bad predicate');");
+    assert!(mq.get_buffered_where(topic, throws, None).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn export_and_import_buffer_state() {
+    let source = MessageQueue::new(None).unwrap();
+    let history = source.register_topic("history").unwrap();
+    source.enable_topic_buffer(history, Some(3)).unwrap();
+    source.register_topic("unbuffered").unwrap();
+    for payload in ["a", "b"] {
+        source.publish(history, JsValue::from_str(payload)).unwrap();
+    }
+    // Functions cannot be structured-cloned
+    source.publish(history, Function::new_no_args("").into()).unwrap();
+
+    let state = source.export_state().unwrap();
+    let topics: Array = js_sys::Reflect::get(&state, &"topics".into()).unwrap().unchecked_into();
+    assert_eq!(topics.length(), 1);
+    let messages: Array = js_sys::Reflect::get(&topics.get(0), &"messages".into()).unwrap().unchecked_into();
+    assert_eq!(js_sys::Reflect::get(&messages.get(2), &"skipped".into()).unwrap(), JsValue::TRUE);
+    assert!(!js_sys::Reflect::has(&messages.get(2), &"payload".into()).unwrap());
+
+    // The state survives a structured clone, as when stored in IndexedDB
+    let structured_clone: Function = js_sys::Reflect::get(&js_sys::global(), &"structuredClone".into()).unwrap().unchecked_into();
+    let stored = structured_clone.call1(&JsValue::NULL, &state).unwrap();
+
+    let restored = MessageQueue::new(None).unwrap();
+    assert!(restored.import_state(js_sys::eval("({version: 99, topics: []})").unwrap()).is_err());
+    assert_eq!(restored.import_state(stored).unwrap(), 2);
+    let topic = restored.get_topic_id("history").unwrap();
+    assert_eq!(restored.get_buffer_capacity(topic), 3);
+    let original = source.get_buffered_messages(history).unwrap();
+    let buffered = restored.get_buffered_messages(topic).unwrap();
+    assert_eq!(buffered.length(), 2);
+    for field in ["id", "timestamp", "payload", "origin_id"] {
+        assert_eq!(
+            js_sys::Reflect::get(&buffered.get(1), &field.into()).unwrap(),
+            js_sys::Reflect::get(&original.get(1), &field.into()).unwrap(),
+        );
+    }
+
+    // Restored messages arriving again from another tab are duplicates
+    let (port1, port2) = message_channel();
+    restored.attach_port(port1).unwrap();
+    let received = Array::new();
+    restored.subscribe(topic, recorder(&received)).unwrap();
+    let packet = Array::of2(&JsValue::from(0), &original.get(0));
+    port2.post_message(&packet).unwrap();
+    sleep(20).await;
+    assert_eq!(received.length(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn persistent_buffer_survives_a_new_queue() {
+    let db_name = format!("ripple-test-{}", js_sys::Math::random());
+    let writer = MessageQueue::new(None).unwrap();
+    let outbox = writer.register_topic("outbox").unwrap();
+    let errors = Array::new();
+    writer.set_error_handler(recorder(&errors));
+    wasm_bindgen_futures::JsFuture::from(writer.enable_persistent_buffer(outbox, &db_name, "outbox", 3).unwrap()).await.unwrap();
+    assert!(writer.has_buffer(outbox));
+
+    // Functions cannot be JSON-serialized; the store keeps the newest 3 records
+    writer.publish(outbox, JsValue::from_str("a")).unwrap();
+    writer.publish(outbox, Function::new_no_args("").into()).unwrap();
+    writer.publish(outbox, JsValue::from_str("b")).unwrap();
+    writer.publish(outbox, js_sys::eval("({to: 'c'})").unwrap()).unwrap();
+    sleep(50).await;
+    assert_eq!(errors.length(), 0);
+    let written = writer.get_buffered_messages(outbox).unwrap();
+
+    let reader = MessageQueue::new(None).unwrap();
+    let topic = reader.register_topic("outbox").unwrap();
+    assert!(reader.load_persistent_buffer(topic).is_err());
+    wasm_bindgen_futures::JsFuture::from(reader.enable_persistent_buffer(topic, &db_name, "outbox", 3).unwrap()).await.unwrap();
+    let restored = wasm_bindgen_futures::JsFuture::from(reader.load_persistent_buffer(topic).unwrap()).await.unwrap();
+    assert_eq!(restored.as_f64(), Some(2.0));
+
+    let buffered = reader.get_buffered_messages(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&buffered.get(0), &"payload".into()).unwrap(), JsValue::from_str("b"));
+    let to = js_sys::Reflect::get(&js_sys::Reflect::get(&buffered.get(1), &"payload".into()).unwrap(), &"to".into()).unwrap();
+    assert_eq!(to, JsValue::from_str("c"));
+    for field in ["id", "timestamp", "origin_id"] {
+        assert_eq!(
+            js_sys::Reflect::get(&buffered.get(1), &field.into()).unwrap(),
+            js_sys::Reflect::get(&written.get(3), &field.into()).unwrap(),
+        );
+    }
+
+    // Loading again restores nothing twice
+    let again = wasm_bindgen_futures::JsFuture::from(reader.load_persistent_buffer(topic).unwrap()).await.unwrap();
+    assert_eq!(again.as_f64(), Some(0.0));
+}
+
+#[wasm_bindgen_test]
+fn resizing_a_buffer_keeps_its_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("resize").unwrap();
+    assert!(mq.resize_topic_buffer(topic, 2).is_err());
+
+    mq.enable_topic_buffer(topic, Some(4)).unwrap();
+    for i in 1..=6 {
+        mq.publish(topic, JsValue::from(i)).unwrap();
+    }
+
+    // Re-enabling grows in place
+    mq.enable_topic_buffer(topic, Some(8)).unwrap();
+    assert_eq!(mq.get_buffer_capacity(topic), 8);
+    assert_eq!(mq.get_buffer_size(topic), 4);
+
+    assert_eq!(mq.resize_topic_buffer(topic, 2).unwrap(), 2);
+    let payloads: Vec<f64> = mq.get_buffered_messages(topic).unwrap().iter()
+        .map(|msg| js_sys::Reflect::get(&msg, &"payload".into()).unwrap().as_f64().unwrap())
+        .collect();
+    assert_eq!(payloads, vec![5.0, 6.0]);
+    assert!(mq.resize_topic_buffer(topic, 0).is_err());
+}
+
+#[wasm_bindgen_test]
+fn completed_tasks_can_be_removed_from_the_buffer() {
+    let mq = MessageQueue::new(None).unwrap();
+    let tasks = mq.register_topic("tasks").unwrap();
+    mq.enable_topic_buffer(tasks, Some(10)).unwrap();
+    for task in ["a", "b", "c", "d"] {
+        mq.publish(tasks, JsValue::from_str(task)).unwrap();
+    }
+    let payloads = || -> Vec<String> {
+        mq.get_buffered_messages(tasks).unwrap().iter()
+            .map(|msg| js_sys::Reflect::get(&msg, &"payload".into()).unwrap().as_string().unwrap())
+            .collect()
+    };
+
+    let predicate = Function::new_with_args("payload, id, timestamp", "
+        if (typeof id !== 'bigint' || typeof timestamp !== 'number') throw new Error('bad args');
+        return payload !== 'b';
+    ");
+    assert_eq!(mq.buffer_retain(tasks, predicate).unwrap(), 1);
+    assert_eq!(payloads(), vec!["a", "c", "d"]);
+
+    let throwing = Function::new_no_args("throw new Error('nope')");
+    assert!(mq.buffer_retain(tasks, throwing).is_err());
+    assert_eq!(payloads(), vec!["a", "c", "d"]);
+
+    let id = js_sys::Reflect::get(&mq.get_buffered_messages(tasks).unwrap().get(1), &"id".into()).unwrap();
+    let id = u64::try_from(id).unwrap();
+    assert!(mq.buffer_remove(tasks, id));
+    assert!(!mq.buffer_remove(tasks, id));
+    assert_eq!(payloads(), vec!["a", "d"]);
+}
+
+#[wasm_bindgen_test]
+async fn subscribe_all_sees_every_topic_once() {
+    let mut sender = MessageQueue::new(Some("firehose".to_string())).unwrap();
+    let mut receiver = MessageQueue::new(Some("firehose".to_string())).unwrap();
+    let seen = Array::new();
+    let log = Function::new_with_args("payload, name, topic_id, timestamp, id", "
+        if (typeof topic_id !== 'number' || typeof id !== 'bigint') throw new Error('bad args');
+        this.push(name + ':' + payload);
+    ").bind0(&seen);
+    let global = receiver.subscribe_all(log);
+
+    // Topics created after subscribing are covered, local and remote alike
+    let local = receiver.register_topic("local").unwrap();
+    let remote = receiver.register_topic("remote").unwrap();
+    receiver.enable_topic_buffer(local, None).unwrap();
+    receiver.publish(local, JsValue::from_str("a")).unwrap();
+    sender.publish(sender.register_topic("remote").unwrap(), JsValue::from_str("b")).unwrap();
+    sleep(50).await;
+    assert_eq!(seen.to_vec(), vec![JsValue::from_str("local:a"), JsValue::from_str("remote:b")]);
+
+    // Replaying the buffer to a new subscriber does not reach global subscribers
+    let replayed = Array::new();
+    receiver.subscribe_with_replay(local, recorder(&replayed), None).unwrap();
+    assert_eq!(replayed.length(), 1);
+    assert_eq!(seen.length(), 2);
+
+    assert!(receiver.unsubscribe_all_listener(global));
+    assert!(!receiver.unsubscribe_all_listener(global));
+    receiver.publish(remote, JsValue::from_str("c")).unwrap();
+    assert_eq!(seen.length(), 2);
+
+    sender.close().unwrap();
+    receiver.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn topic_hooks_see_every_way_of_creating_topics() {
+    let mut sender = MessageQueue::new(Some("topic-hooks".to_string())).unwrap();
+    let mut receiver = MessageQueue::new(Some("topic-hooks".to_string())).unwrap();
+    let created = Array::new();
+    let destroyed = Array::new();
+    let record = |events: &Array| Function::new_with_args("id, name", "this.push(name);").bind0(events);
+    let created_hook = receiver.on_topic_created(record(&created));
+    receiver.on_topic_destroyed(record(&destroyed));
+
+    let errors = Array::new();
+    receiver.set_error_handler(recorder(&errors));
+
+    let a = receiver.register_topic("a").unwrap();
+    receiver.register_topic("a").unwrap();
+    assert!(receiver.create_topic("b").unwrap());
+    receiver.create_replay_subject("c", 2).unwrap();
+    receiver.publish_by_name("d", JsValue::from(1)).unwrap();
+    receiver.transaction(Function::new_with_args("tx", "tx.register_topic('e');")).unwrap();
+    sender.publish(sender.register_topic("remote").unwrap(), JsValue::from(1)).unwrap();
+    sleep(50).await;
+    let names: Vec<String> = created.iter().map(|name| name.as_string().unwrap()).collect();
+    assert_eq!(names, vec!["a", "b", "c", "d", "e", "remote"]);
+    assert_eq!(errors.length(), 0);
+
+    assert!(receiver.destroy_topic(a));
+    assert_eq!(destroyed.to_vec(), vec![JsValue::from_str("a")]);
+
+    assert!(receiver.remove_topic_hook(created_hook));
+    assert!(!receiver.remove_topic_hook(created_hook));
+    receiver.register_topic("f").unwrap();
+    assert_eq!(created.length(), 6);
+
+    sender.close().unwrap();
+    receiver.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn publish_and_wait_settles_async_subscribers() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("jobs").unwrap();
+    let done = Array::new();
+    mq.subscribe(topic, Function::new_with_args("payload",
+        "return new Promise((resolve) => setTimeout(() => { this.push(payload); resolve(); }, 20));").bind0(&done)).unwrap();
+    mq.subscribe(topic, Function::new_with_args("payload", "return Promise.reject(new Error('failed'));")).unwrap();
+    mq.subscribe(topic, Function::new_no_args("throw new Error('sync');")).unwrap();
+    mq.subscribe(topic, Function::new_no_args("return 42;")).unwrap();
+    mq.set_error_handler(Function::new_no_args(""));
+
+    let counts = wasm_bindgen_futures::JsFuture::from(mq.publish_and_wait(topic, JsValue::from_str("job"), None).unwrap()).await.unwrap();
+    // The slow handler finished before the promise resolved
+    assert_eq!(done.length(), 1);
+    assert_eq!(js_sys::Reflect::get(&counts, &"fulfilled".into()).unwrap().as_f64(), Some(2.0));
+    assert_eq!(js_sys::Reflect::get(&counts, &"rejected".into()).unwrap().as_f64(), Some(2.0));
+
+    let stuck = mq.register_topic("stuck").unwrap();
+    mq.subscribe(stuck, Function::new_no_args("return new Promise(() => {});")).unwrap();
+    let timed_out = wasm_bindgen_futures::JsFuture::from(mq.publish_and_wait(stuck, JsValue::NULL, Some(10.0)).unwrap()).await;
+    assert_eq!(timed_out.unwrap_err(), JsValue::from_str("Timeout"));
+
+    // Nothing to wait for
+    let idle = mq.register_topic("idle").unwrap();
+    let counts = wasm_bindgen_futures::JsFuture::from(mq.publish_and_wait(idle, JsValue::NULL, Some(10.0)).unwrap()).await.unwrap();
+    assert_eq!(js_sys::Reflect::get(&counts, &"fulfilled".into()).unwrap().as_f64(), Some(0.0));
+    assert!(mq.publish_and_wait(9999, JsValue::NULL, None).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn stream_iterates_topic_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("ticks").unwrap();
+    let stream = mq.stream(topic, Some(2)).unwrap();
+    assert!(mq.stream(9999, None).is_err());
+
+    let call = |method: &str| -> js_sys::Promise {
+        let f: Function = js_sys::Reflect::get(&stream, &method.into()).unwrap().unchecked_into();
+        f.call0(&stream).unwrap().unchecked_into()
+    };
+    let payload = |result: JsValue| {
+        assert_eq!(js_sys::Reflect::get(&result, &"done".into()).unwrap(), JsValue::FALSE);
+        let value = js_sys::Reflect::get(&result, &"value".into()).unwrap();
+        js_sys::Reflect::get(&value, &"payload".into()).unwrap().as_f64().unwrap()
+    };
+
+    // The stream is its own async iterator
+    let iterator_fn: Function = js_sys::Reflect::get(&stream, &js_sys::Symbol::async_iterator()).unwrap().unchecked_into();
+    assert_eq!(iterator_fn.call0(&stream).unwrap(), stream);
+
+    // A waiting next() gets the next message
+    let waiting = call("next");
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    assert_eq!(payload(wasm_bindgen_futures::JsFuture::from(waiting).await.unwrap()), 1.0);
+
+    // Beyond the buffer limit the oldest queued message is dropped
+    for i in 2..=4 {
+        mq.publish(topic, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(payload(wasm_bindgen_futures::JsFuture::from(call("next")).await.unwrap()), 3.0);
+    assert_eq!(payload(wasm_bindgen_futures::JsFuture::from(call("next")).await.unwrap()), 4.0);
+
+    // return() ends the stream and unsubscribes
+    let waiting = call("next");
+    wasm_bindgen_futures::JsFuture::from(call("return")).await.unwrap();
+    let end = wasm_bindgen_futures::JsFuture::from(waiting).await.unwrap();
+    assert_eq!(js_sys::Reflect::get(&end, &"done".into()).unwrap(), JsValue::TRUE);
+    assert_eq!(mq.subscriber_count(topic), 0);
+
+    // Closing the queue ends a stream waiting in a for-await loop
+    let mut other = MessageQueue::new(None).unwrap();
+    let numbers = other.register_topic("numbers").unwrap();
+    let consume = Function::new_with_args("stream", "
+        return (async () => {
+            const seen = [];
+            for await (const msg of stream) seen.push(msg.payload);
+            return seen;
+        })();
+    ");
+    let seen: js_sys::Promise = consume.call1(&JsValue::NULL, &other.stream(numbers, None).unwrap()).unwrap().unchecked_into();
+    other.publish(numbers, JsValue::from(7)).unwrap();
+    sleep(0).await;
+    other.close().unwrap();
+    let seen = wasm_bindgen_futures::JsFuture::from(seen).await.unwrap();
+    assert_eq!(Array::from(&seen).to_vec(), vec![JsValue::from(7)]);
+}
+
+/// Read up to `n` payloads from a ReadableStream, stopping early if it ends
+async fn read_payloads(stream: &web_sys::ReadableStream, n: u32) -> Vec<JsValue> {
+    let read = Function::new_with_args("stream, n", "
+        return (async () => {
+            const reader = stream.getReader();
+            const payloads = [];
+            for (let i = 0; i < n; i++) {
+                const { value, done } = await reader.read();
+                if (done) break;
+                payloads.push(value.payload);
+            }
+            reader.releaseLock();
+            return payloads;
+        })();
+    ");
+    let promise: js_sys::Promise = read.call2(&JsValue::NULL, stream, &JsValue::from(n)).unwrap().unchecked_into();
+    Array::from(&wasm_bindgen_futures::JsFuture::from(promise).await.unwrap()).to_vec()
+}
+
+#[wasm_bindgen_test]
+async fn readable_stream_applies_backpressure() {
+    let mut mq = MessageQueue::new(None).unwrap();
+    let nums = |range: std::ops::RangeInclusive<i32>| range.map(JsValue::from).collect::<Vec<_>>();
+
+    // Without a buffer, messages beyond the high water mark are dropped and counted
+    let unbuffered = mq.register_topic("unbuffered").unwrap();
+    let stream = mq.readable_stream(unbuffered, Some(1.0)).unwrap();
+    for i in 1..=3 {
+        mq.publish(unbuffered, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(read_payloads(&stream, 1).await, nums(1..=1));
+    let metadata = mq.get_topic_metadata(unbuffered).unwrap();
+    assert_eq!(js_sys::Reflect::get(&metadata, &"stream_dropped".into()).unwrap().as_f64(), Some(2.0));
+
+    // With a buffer, the stream catches up in order once read
+    let buffered = mq.register_topic("buffered").unwrap();
+    mq.enable_topic_buffer(buffered, Some(10)).unwrap();
+    let stream = mq.readable_stream(buffered, Some(1.0)).unwrap();
+    for i in 1..=4 {
+        mq.publish(buffered, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(read_payloads(&stream, 4).await, nums(1..=4));
+    mq.publish(buffered, JsValue::from(5)).unwrap();
+    assert_eq!(read_payloads(&stream, 1).await, nums(5..=5));
+
+    // Cancelling unsubscribes
+    wasm_bindgen_futures::JsFuture::from(stream.cancel()).await.unwrap();
+    assert_eq!(mq.subscriber_count(buffered), 0);
+    assert!(mq.readable_stream(9999, None).is_err());
+    assert!(mq.readable_stream(buffered, Some(-1.0)).is_err());
+
+    // Closing the queue ends the stream
+    let open = mq.readable_stream(unbuffered, None).unwrap();
+    mq.close().unwrap();
+    assert!(read_payloads(&open, 1).await.is_empty());
+}
+
+#[wasm_bindgen_test]
+fn event_target_reemits_topic_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("clicks").unwrap();
+    let target = mq.as_event_target(topic).unwrap();
+    // The same target is reused
+    assert_eq!(JsValue::from(mq.as_event_target(topic).unwrap()), JsValue::from(target.clone()));
+    assert_eq!(mq.subscriber_count(topic), 1);
+    assert!(mq.as_event_target(9999).is_err());
+
+    let details = Array::new();
+    let listener = Function::new_with_args("event", "this.push(event.detail);").bind0(&details);
+    target.add_event_listener_with_callback("clicks", &listener).unwrap();
+    // Listener options behave natively
+    let once = Array::new();
+    Function::new_with_args("target, listener", "target.addEventListener('clicks', listener, {once: true});")
+        .call2(&JsValue::NULL, &target, &recorder(&once))
+        .unwrap();
+
+    mq.publish(topic, JsValue::from_str("a")).unwrap();
+    mq.publish(topic, JsValue::from_str("b")).unwrap();
+    assert_eq!(details.length(), 2);
+    assert_eq!(once.length(), 1);
+    let detail = details.get(1);
+    assert_eq!(js_sys::Reflect::get(&detail, &"payload".into()).unwrap(), JsValue::from_str("b"));
+    for field in ["id", "timestamp", "origin_id"] {
+        assert!(!js_sys::Reflect::get(&detail, &field.into()).unwrap().is_undefined());
+    }
+
+    // Removing the subscriber behind the target resubscribes it on the next call
+    mq.unsubscribe_all(topic);
+    mq.as_event_target(topic).unwrap();
+    mq.publish(topic, JsValue::from_str("c")).unwrap();
+    assert_eq!(details.length(), 3);
+}
+
+#[wasm_bindgen_test]
+async fn with_options_applies_settings_before_connecting() {
+    let options = js_sys::eval(
+        "({clientId: 'pinned-tab', syncOnConnect: false, defaultBufferCapacity: 2, wireFormat: 'binary', debug: true})",
+    ).unwrap();
+    let mq = MessageQueue::with_options(options).unwrap();
+    assert_eq!(mq.get_client_id(), "pinned-tab");
+    let topic = mq.register_topic("auto-buffered").unwrap();
+    assert_eq!(mq.get_buffer_capacity(topic), 2);
+
+    // Only the presence join is posted, no SYNC_REQ
+    let (port1, port2) = message_channel();
+    let packets = Array::new();
+    let record = Function::new_with_args("packets, event", "packets.push(event.data[0]);").bind1(&JsValue::NULL, &packets);
+    port2.set_onmessage(Some(record.unchecked_ref()));
+    mq.attach_port(port1).unwrap();
+    sleep(20).await;
+    assert_eq!(packets.length(), 1);
+    assert_eq!(packets.get(0).as_f64(), Some(4.0));
+
+    // The constructor still syncs on connect
+    let (port3, port4) = message_channel();
+    let packets = Array::new();
+    let record = Function::new_with_args("packets, event", "packets.push(event.data[0]);").bind1(&JsValue::NULL, &packets);
+    port4.set_onmessage(Some(record.unchecked_ref()));
+    MessageQueue::new(None).unwrap().attach_port(port3).unwrap();
+    sleep(20).await;
+    assert_eq!(packets.get(0).as_f64(), Some(1.0));
+
+    let err = |js: &str| MessageQueue::with_options(js_sys::eval(js).unwrap()).err().and_then(|e| e.as_string());
+    assert_eq!(err("({syncOnConnect: 'no'})").as_deref(), Some("syncOnConnect must be a boolean"));
+    assert_eq!(err("({clientId: 7})").as_deref(), Some("clientId must be a string"));
+    assert_eq!(err("({wireFormat: 'xml'})").as_deref(), Some("Unknown wire format"));
+    assert_eq!(err("({defaultBufferCapacity: 0})").as_deref(), Some("defaultBufferCapacity must be an integer of at least 1"));
+    assert!(MessageQueue::with_options(JsValue::UNDEFINED).is_ok());
+}
+
+#[wasm_bindgen_test]
+async fn request_sync_waits_for_quiet_period_and_buffers_unknown_topics() {
+    let options = js_sys::eval("({syncOnConnect: false, defaultBufferCapacity: 4})").unwrap();
+    let mq = MessageQueue::with_options(options).unwrap();
+    let (port1, port2) = message_channel();
+    // A scripted peer answering every SYNC_REQ with two responses 60ms apart
+    let requests = Array::new();
+    let peer = Function::new_with_args(
+        "requests, port",
+        "port.onmessage = (event) => { \
+            if (event.data[0] !== 1) return; \
+            requests.push(event.data[1]); \
+            const msg = (id) => ({id: BigInt(id), topic: 'history', payload: id, timestamp: id, origin_id: 'peer'}); \
+            port.postMessage([2, event.data[1], [msg(1), msg(2)]]); \
+            setTimeout(() => port.postMessage([2, event.data[1], [msg(3)]]), 60); \
+        };",
+    );
+    peer.call2(&JsValue::NULL, &requests, &port2).unwrap();
+    mq.attach_port(port1).unwrap();
+    sleep(20).await;
+    assert_eq!(requests.length(), 0);
+
+    // The late response arrives within the quiet period and is counted
+    let count = wasm_bindgen_futures::JsFuture::from(mq.request_sync(Some(100.0))).await.unwrap();
+    assert_eq!(count.as_f64(), Some(3.0));
+    assert_eq!(requests.length(), 1);
+    let topic = mq.get_topic_id("history").unwrap();
+    let buffered: Vec<f64> = mq.get_buffered_messages(topic).unwrap().iter()
+        .map(|msg| js_sys::Reflect::get(&msg, &"payload".into()).unwrap().as_f64().unwrap())
+        .collect();
+    assert_eq!(buffered, vec![1.0, 2.0, 3.0]);
+
+    let err = wasm_bindgen_futures::JsFuture::from(mq.request_sync(Some(-1.0))).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("quiet_ms must be a non-negative finite number"));
+}
+
+#[wasm_bindgen_test]
+async fn default_buffer_keeps_remote_messages_for_late_subscribers() {
+    let (port1, port2) = message_channel();
+    let mq = MessageQueue::with_port(port1).unwrap();
+    mq.set_default_buffer(5, None);
+
+    // A message for a topic this tab has never heard of arrives first
+    let msg = js_sys::eval("[0, {id: 77n, topic: 'late', payload: 'early bird', timestamp: 1, origin_id: 'peer'}]").unwrap();
+    port2.post_message(&msg).unwrap();
+    sleep(20).await;
+
+    let topic = mq.register_topic("late").unwrap();
+    assert_eq!(mq.get_buffer_capacity(topic), 5);
+    let received = Array::new();
+    let result = mq.subscribe_with_replay(topic, recorder(&received), None).unwrap();
+    assert_eq!(js_sys::Reflect::get(&result, &"replayed".into()).unwrap().as_f64(), Some(1.0));
+    assert_eq!(received.get(0).as_string().as_deref(), Some("early bird"));
+
+    // Topics created by the application are not buffered unless asked for
+    assert_eq!(mq.get_buffer_capacity(mq.register_topic("local").unwrap()), 0);
+    mq.set_default_buffer(3, Some(true));
+    assert_eq!(mq.get_buffer_capacity(mq.register_topic("local-buffered").unwrap()), 3);
+    mq.set_default_buffer(0, None);
+    assert_eq!(mq.get_buffer_capacity(mq.register_topic("unbuffered").unwrap()), 0);
+}
+
+#[wasm_bindgen_test]
+async fn publish_async_broadcasts_to_other_tabs() {
+    let sender = MessageQueue::new(Some("publish-async-test".into())).unwrap();
+    let receiver = MessageQueue::new(Some("publish-async-test".into())).unwrap();
+    let topic = sender.register_topic("jobs").unwrap();
+    let remote_topic = receiver.register_topic("jobs").unwrap();
+    let local = Array::new();
+    sender.subscribe(topic, recorder(&local)).unwrap();
+    let remote = Array::new();
+    receiver.subscribe(remote_topic, recorder(&remote)).unwrap();
+
+    wasm_bindgen_futures::JsFuture::from(sender.publish_async(topic, JsValue::from(1)).unwrap()).await.unwrap();
+    assert_eq!(local.length(), 1);
+    sleep(20).await;
+    assert_eq!(remote.length(), 1);
+    assert_eq!(remote.get(0).as_f64(), Some(1.0));
+
+    // An echo of an async-published message is not delivered again
+    let (port1, port2) = message_channel();
+    let echoing = MessageQueue::with_port(port1).unwrap();
+    let echo_topic = echoing.register_topic("jobs").unwrap();
+    let echoed = Array::new();
+    echoing.subscribe(echo_topic, recorder(&echoed)).unwrap();
+    let echo = Function::new_with_args("event", "if (event.data[0] === 0) this.postMessage(event.data);").bind0(&port2);
+    port2.set_onmessage(Some(&echo));
+    wasm_bindgen_futures::JsFuture::from(echoing.publish_async(echo_topic, JsValue::from(2)).unwrap()).await.unwrap();
+    sleep(20).await;
+    assert_eq!(echoed.length(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn coalesced_async_publishes_flush_together_in_call_order() {
+    let (port1, port2) = message_channel();
+    let mq = MessageQueue::with_port(port1).unwrap();
+    assert!(mq.set_async_mode("eventually").is_err());
+    mq.set_async_mode("coalesced").unwrap();
+    let a = mq.register_topic("a").unwrap();
+    let b = mq.register_topic("b").unwrap();
+    let order = Array::new();
+    mq.subscribe(a, recorder(&order)).unwrap();
+    mq.subscribe(b, recorder(&order)).unwrap();
+    sleep(20).await;
+    let packets = Array::new();
+    let collect = Function::new_with_args("event", "this.push(event.data);").bind0(&packets);
+    port2.set_onmessage(Some(&collect));
+
+    // A subscriber publishing during the flush lands in the next one
+    let mq = Rc::new(mq);
+    let reentrant = {
+        let mq = mq.clone();
+        Closure::<dyn FnMut(JsValue)>::new(move |payload: JsValue| {
+            if payload.as_string().as_deref() == Some("a1") {
+                let _ = mq.publish_async(b, "b-late".into()).unwrap();
+            }
+        })
+    };
+    mq.subscribe(a, reentrant.as_ref().unchecked_ref::<Function>().clone()).unwrap();
+
+    let first = mq.publish_async(a, "a1".into()).unwrap();
+    let second = mq.publish_async(b, "b1".into()).unwrap();
+    let _ = mq.publish_async(a, "a2".into()).unwrap();
+    assert!(js_sys::Object::is(&first, &second));
+    assert_eq!(mq.get_pending_async_publishes(), 3);
+    assert!(mq.publish_async(99, JsValue::NULL).is_err());
+    assert_eq!(order.length(), 0);
+
+    wasm_bindgen_futures::JsFuture::from(first).await.unwrap();
+    wasm_bindgen_futures::JsFuture::from(mq.drain()).await.unwrap();
+    let values: Vec<String> = order.iter().filter_map(|v| v.as_string()).collect();
+    assert_eq!(values, vec!["a1", "b1", "a2", "b-late"]);
+
+    // One batch packet per flush
+    sleep(20).await;
+    let batches: Vec<u32> = packets.iter()
+        .map(|packet| Array::from(&packet))
+        .filter(|packet| packet.get(0).as_f64() == Some(5.0))
+        .map(|packet| Array::from(&packet.get(1)).length())
+        .collect();
+    assert_eq!(batches, vec![3, 1]);
+}
+
+#[wasm_bindgen_test]
+fn subscribers_are_called_in_subscription_order() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("ordered").unwrap();
+    let calls = Array::new();
+    let tagged = |tag: &str| Function::new_with_args("tag", "return () => this.push(tag);")
+        .call1(&calls, &tag.into()).unwrap().dyn_into::<Function>().unwrap();
+    let first = mq.subscribe(topic, tagged("first")).unwrap();
+    let second = mq.subscribe(topic, tagged("second")).unwrap();
+    mq.subscribe(topic, tagged("third")).unwrap();
+
+    // A new subscriber reusing a freed slot still comes last
+    assert!(mq.unsubscribe(topic, first));
+    let fourth = mq.subscribe(topic, tagged("fourth")).unwrap();
+    mq.publish(topic, JsValue::NULL).unwrap();
+    let order: Vec<String> = calls.iter().filter_map(|v| v.as_string()).collect();
+    assert_eq!(order, vec!["second", "third", "fourth"]);
+
+    // The stale ID does not remove the subscriber now in its slot
+    assert_ne!(first, fourth);
+    assert!(!mq.unsubscribe(topic, first));
+    assert!(mq.unsubscribe(topic, second));
+    assert_eq!(mq.subscriber_count(topic), 2);
+}
+
+#[wasm_bindgen_test]
+fn subscriber_order_decides_invocation_sequence() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("pipeline").unwrap();
+    let calls = Array::new();
+    let tagged = |tag: &str| Function::new_with_args("tag", "return () => this.push(tag);")
+        .call1(&calls, &tag.into()).unwrap().dyn_into::<Function>().unwrap();
+    let sequence = || -> Vec<String> {
+        let order = calls.iter().filter_map(|v| v.as_string()).collect();
+        calls.set_length(0);
+        order
+    };
+
+    let render = mq.subscribe_with_order(topic, tagged("render"), 10).unwrap();
+    mq.subscribe(topic, tagged("transform")).unwrap();
+    let validate = mq.subscribe_with_order(topic, tagged("validate"), -10).unwrap();
+    mq.subscribe_with_order(topic, tagged("audit"), -10).unwrap();
+    for _ in 0..3 {
+        mq.publish(topic, JsValue::NULL).unwrap();
+        assert_eq!(sequence(), vec!["validate", "audit", "transform", "render"]);
+    }
+
+    // Removing a middle subscriber keeps the others in order
+    mq.unsubscribe(topic, validate);
+    mq.publish(topic, JsValue::NULL).unwrap();
+    assert_eq!(sequence(), vec!["audit", "transform", "render"]);
+
+    mq.set_subscriber_order(topic, render, -10).unwrap();
+    mq.publish(topic, JsValue::NULL).unwrap();
+    assert_eq!(sequence(), vec!["audit", "render", "transform"]);
+    assert!(mq.set_subscriber_order(topic, validate, 0).is_err());
+}
+
+#[wasm_bindgen_test]
+fn publishing_to_an_unobserved_topic_allocates_nothing() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("unobserved").unwrap();
+    let payload = JsValue::from_str("frame");
+    let memory_size = || {
+        let buffer = js_sys::Reflect::get(&wasm_bindgen::memory(), &"buffer".into()).unwrap();
+        js_sys::Reflect::get(&buffer, &"byteLength".into()).unwrap().as_f64().unwrap()
+    };
+
+    // Warm up, so lazily allocated state is in place before measuring
+    mq.publish(topic, payload.clone()).unwrap();
+    let before = memory_size();
+    for _ in 0..100_000 {
+        mq.publish(topic, payload.clone()).unwrap();
+    }
+
+    assert_eq!(memory_size(), before);
+    let stats = mq.get_topic_stats(topic).unwrap();
+    let published = js_sys::Reflect::get(&stats, &"published".into()).unwrap().as_f64();
+    assert_eq!(published, Some(100_001.0));
+    assert_eq!(mq.get_last_seq(topic), 100_001.0);
+
+    // A subscriber turns normal dispatch back on
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    mq.publish(topic, payload).unwrap();
+    assert_eq!(received.length(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn local_only_and_broadcast_only_topics() {
+    let sender = MessageQueue::new(Some("broadcast-mode".into())).unwrap();
+    let receiver = MessageQueue::new(Some("broadcast-mode".into())).unwrap();
+    let frames = sender.register_topic("frames").unwrap();
+    let relayed = sender.register_topic("relayed").unwrap();
+    let local = Array::new();
+    sender.subscribe(frames, recorder(&local)).unwrap();
+    sender.subscribe(relayed, recorder(&local)).unwrap();
+    let remote = Array::new();
+    for name in ["frames", "relayed"] {
+        let topic = receiver.register_topic(name).unwrap();
+        receiver.subscribe(topic, recorder(&remote)).unwrap();
+    }
+
+    sender.set_topic_local_only(frames, true).unwrap();
+    sender.set_topic_broadcast_only(relayed, None).unwrap();
+    let info = sender.topic_info(frames).unwrap();
+    assert_eq!(js_sys::Reflect::get(&info, &"local_only".into()).unwrap().as_bool(), Some(true));
+    assert_eq!(js_sys::Reflect::get(&info, &"broadcast_only".into()).unwrap().as_bool(), Some(false));
+
+    sender.publish(frames, JsValue::from("frame")).unwrap();
+    sender.publish_batch_js(frames, Array::of1(&JsValue::from("frame"))).unwrap();
+    wasm_bindgen_futures::JsFuture::from(sender.publish_async(frames, JsValue::from("frame")).unwrap()).await.unwrap();
+    sender.publish(relayed, JsValue::from("relayed")).unwrap();
+    sleep(20).await;
+    assert_eq!(local.iter().filter_map(|v| v.as_string()).collect::<Vec<_>>(), vec!["frame"; 3]);
+    assert_eq!(remote.iter().filter_map(|v| v.as_string()).collect::<Vec<_>>(), vec!["relayed"]);
+
+    // Turning one flag on clears the other
+    sender.set_topic_broadcast_only(frames, None).unwrap();
+    let info = sender.topic_info(frames).unwrap();
+    assert_eq!(js_sys::Reflect::get(&info, &"local_only".into()).unwrap().as_bool(), Some(false));
+    assert!(sender.set_topic_local_only(999, true).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn echo_mode_dispatches_own_publishes_later_in_order() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("state").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    mq.set_echo(true);
+    assert!(mq.is_echo());
+
+    // Without a channel, dispatch is deferred to a microtask
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish_batch_js(topic, Array::of2(&JsValue::from(2), &JsValue::from(3))).unwrap();
+    assert_eq!(received.length(), 0);
+    wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&JsValue::UNDEFINED)).await.unwrap();
+    assert_eq!(received.iter().filter_map(|v| v.as_f64()).collect::<Vec<_>>(), vec![1.0, 2.0, 3.0]);
+
+    // With a channel, this tab sees its messages when and in the order other tabs do
+    let sender = MessageQueue::new(Some("echo-mode".into())).unwrap();
+    let receiver = MessageQueue::new(Some("echo-mode".into())).unwrap();
+    sender.set_echo(true);
+    let local = Array::new();
+    let remote = Array::new();
+    sender.subscribe(sender.register_topic("state").unwrap(), recorder(&local)).unwrap();
+    receiver.subscribe(receiver.register_topic("state").unwrap(), recorder(&remote)).unwrap();
+    let state = sender.register_topic("state").unwrap();
+    sender.publish(state, JsValue::from("a")).unwrap();
+    sender.publish(state, JsValue::from("b")).unwrap();
+    assert_eq!(local.length(), 0);
+    sleep(20).await;
+    let values = |arr: &Array| arr.iter().filter_map(|v| v.as_string()).collect::<Vec<_>>();
+    assert_eq!(values(&local), vec!["a", "b"]);
+    assert_eq!(values(&remote), values(&local));
+
+    // Turning echo off restores inline dispatch
+    sender.set_echo(false);
+    sender.publish(state, JsValue::from("c")).unwrap();
+    assert_eq!(local.length(), 3);
+}
+
+#[wasm_bindgen_test]
+async fn disconnect_and_reconnect_keep_topics_and_subscribers() {
+    let local = MessageQueue::new(None).unwrap();
+    assert_eq!(local.channel_name(), None);
+    assert!(!local.is_connected());
+    assert!(local.reconnect(None).is_err());
+
+    let sender = MessageQueue::new(Some("reconnect-a".into())).unwrap();
+    let receiver = MessageQueue::new(Some("reconnect-a".into())).unwrap();
+    assert_eq!(receiver.channel_name().as_deref(), Some("reconnect-a"));
+    assert!(receiver.is_connected());
+    let topic = sender.register_topic("news").unwrap();
+    let received = Array::new();
+    receiver.subscribe(receiver.register_topic("news").unwrap(), recorder(&received)).unwrap();
+
+    receiver.disconnect();
+    assert!(!receiver.is_connected());
+    assert_eq!(receiver.channel_name().as_deref(), Some("reconnect-a"));
+    sender.publish(topic, JsValue::from(1)).unwrap();
+    sleep(20).await;
+    assert_eq!(received.length(), 0);
+
+    // Rejoining the same channel keeps the subscriber
+    receiver.reconnect(None).unwrap();
+    assert!(receiver.is_connected());
+    sender.publish(topic, JsValue::from(2)).unwrap();
+    sleep(20).await;
+    assert_eq!(received.iter().filter_map(|v| v.as_f64()).collect::<Vec<_>>(), vec![2.0]);
+
+    // Switching channels leaves the old one
+    receiver.reconnect(Some("reconnect-b".into())).unwrap();
+    assert_eq!(receiver.channel_name().as_deref(), Some("reconnect-b"));
+    sender.publish(topic, JsValue::from(3)).unwrap();
+    sleep(20).await;
+    assert_eq!(received.length(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn routed_topics_post_on_their_own_channel() {
+    let mut sender = MessageQueue::new(Some("routing-main".into())).unwrap();
+    let heavy = sender.attach_channel("routing-heavy").unwrap();
+    let main_tab = MessageQueue::new(Some("routing-main".into())).unwrap();
+    let heavy_tab = MessageQueue::new(None).unwrap();
+    heavy_tab.attach_channel("routing-heavy").unwrap();
+
+    let control = sender.register_topic("control").unwrap();
+    let telemetry = sender.register_topic("telemetry").unwrap();
+    assert!(sender.route_topic(telemetry, heavy + 1).is_err());
+    sender.route_topic(telemetry, heavy).unwrap();
+
+    let main_received = Array::new();
+    let heavy_received = Array::new();
+    for name in ["control", "telemetry"] {
+        main_tab.subscribe(main_tab.register_topic(name).unwrap(), recorder(&main_received)).unwrap();
+        heavy_tab.subscribe(heavy_tab.register_topic(name).unwrap(), recorder(&heavy_received)).unwrap();
+    }
+
+    sender.publish(control, JsValue::from("stop")).unwrap();
+    sender.publish(telemetry, JsValue::from("sample")).unwrap();
+    sender.publish_batch_js(telemetry, Array::of2(&JsValue::from("s1"), &JsValue::from("s2"))).unwrap();
+    sleep(20).await;
+    let values = |arr: &Array| arr.iter().filter_map(|v| v.as_string()).collect::<Vec<_>>();
+    assert_eq!(values(&main_received), vec!["stop"]);
+    assert_eq!(values(&heavy_received), vec!["sample", "s1", "s2"]);
+
+    // Routing back to the primary channel
+    sender.route_topic(telemetry, 0).unwrap();
+    sender.publish(telemetry, JsValue::from("s3")).unwrap();
+    sleep(20).await;
+    assert_eq!(values(&main_received), vec!["stop", "s3"]);
+
+    // A queue without a primary channel still posts routed topics
+    let relay = MessageQueue::new(None).unwrap();
+    let relay_heavy = relay.attach_channel("routing-heavy").unwrap();
+    let relay_topic = relay.register_topic("telemetry").unwrap();
+    relay.route_topic(relay_topic, relay_heavy).unwrap();
+    relay.publish(relay_topic, JsValue::from("relayed")).unwrap();
+    sleep(20).await;
+    assert_eq!(values(&heavy_received), vec!["sample", "s1", "s2", "relayed"]);
+    sender.close().unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn closed_queue_rejects_mutations() {
+    let mut mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("jobs").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    let pending = mq.publish_async(topic, JsValue::from(1)).unwrap();
+    mq.set_async_mode("coalesced").unwrap();
+    let coalesced = mq.publish_async(topic, JsValue::from(2)).unwrap();
+
+    assert!(!mq.is_closed());
+    mq.close().unwrap();
+    assert!(mq.is_closed());
+    let closed = Some("Queue is closed".to_string());
+
+    // Async publishes scheduled before the close reject instead of dispatching
+    for promise in [pending, coalesced] {
+        let err = wasm_bindgen_futures::JsFuture::from(promise).await.unwrap_err();
+        assert_eq!(err.as_string(), closed);
+    }
+    assert_eq!(received.length(), 0);
+
+    assert_eq!(mq.publish(topic, JsValue::NULL).unwrap_err().as_string(), closed);
+    assert_eq!(mq.publish_async(topic, JsValue::NULL).unwrap_err().as_string(), closed);
+    assert_eq!(mq.publish_by_name("jobs", JsValue::NULL).unwrap_err().as_string(), closed);
+    assert_eq!(mq.publish_batch_js(topic, Array::new()).unwrap_err().as_string(), closed);
+    assert_eq!(mq.subscribe(topic, recorder(&received)).unwrap_err().as_string(), closed);
+    assert_eq!(mq.register_topic("jobs").unwrap_err().as_string(), closed);
+    assert_eq!(mq.create_topic("other").unwrap_err().as_string(), closed);
+    assert_eq!(mq.enable_topic_buffer(topic, Some(4)).unwrap_err().as_string(), closed);
+    assert_eq!(mq.topic_count(), 0);
+
+    // Closing again is a no-op
+    mq.close().unwrap();
+    assert!(mq.is_closed());
+
+    // A closed queue cannot rejoin its channel or open new connections
+    let mut connected = MessageQueue::new(Some("closed-reconnect".to_string())).unwrap();
+    connected.close().unwrap();
+    assert_eq!(connected.reconnect(None).unwrap_err().as_string(), closed);
+    assert!(!connected.is_connected());
+    assert_eq!(connected.attach_channel("closed-extra").unwrap_err().as_string(), closed);
+    assert_eq!(connected.attach_port(message_channel().0).unwrap_err().as_string(), closed);
+    assert_eq!(connected.enable_presence(100.0).unwrap_err().as_string(), closed);
+    let err = wasm_bindgen_futures::JsFuture::from(connected.elect_leader()).await.unwrap_err();
+    assert_eq!(err.as_string(), closed);
+}
+
+#[wasm_bindgen_test]
+fn topic_names_are_validated_and_normalized() {
+    let mq = MessageQueue::new(None).unwrap();
+    let error = |result: Result<u32, JsValue>| result.unwrap_err().as_string().unwrap();
+    assert_eq!(error(mq.register_topic("")), "Topic name must not be empty");
+    assert_eq!(error(mq.register_topic(&"x".repeat(257))), "Topic name longer than 256 characters");
+    mq.register_topic(&"x".repeat(256)).unwrap();
+
+    mq.set_max_topic_name_length(4).unwrap();
+    assert!(mq.set_max_topic_name_length(0).is_err());
+    assert_eq!(error(mq.register_topic("toolong")), "Topic name longer than 4 characters");
+    assert!(mq.publish_by_name("toolong", JsValue::NULL).is_err());
+
+    mq.set_topic_name_validator(Some(Function::new_with_args("name", "return !name.includes(' ');")));
+    assert_eq!(error(mq.register_topic("a b")), "Invalid topic name: a b");
+    assert!(mq.create_topic("a b").is_err());
+    mq.set_topic_name_validator(Some(Function::new_with_args("name", "throw new Error('nope');")));
+    assert!(mq.register_topic("ab").is_err());
+    mq.set_topic_name_validator(None);
+    mq.register_topic("ab").unwrap();
+
+    // Names announced by other tabs are checked too; rejected messages are reported
+    let errors = Array::new();
+    mq.set_error_handler(recorder(&errors));
+    let remote = js_sys::eval(
+        "({id: 1, topic: 'too-long-remote', payload: 1, timestamp: 0, origin_id: 'other-tab', seq: 1})",
+    ).unwrap();
+    mq.receive_from_service_worker(remote).unwrap();
+    assert_eq!(mq.get_topic_id("too-long-remote"), None);
+    assert_eq!(errors.length(), 1);
+
+    let options = js_sys::eval("({trimTopicNames: true, foldTopicNameCase: true})").unwrap();
+    let normalized = MessageQueue::with_options(options).unwrap();
+    let orders = normalized.register_topic(" Orders ").unwrap();
+    assert_eq!(normalized.register_topic("orders").unwrap(), orders);
+    assert_eq!(normalized.get_topic_name(orders).as_deref(), Some("orders"));
+}
+
+#[wasm_bindgen_test]
+fn transactions_imports_and_migrations_check_topic_names() {
+    let options = js_sys::eval("({trimTopicNames: true, foldTopicNameCase: true})").unwrap();
+    let mq = MessageQueue::with_options(options).unwrap();
+    mq.set_topic_name_validator(Some(Function::new_with_args("name", "return !name.includes(' ');")));
+    let run = |body: &str| mq.transaction(Function::new_with_args("tx", body));
+
+    // Transactions record normalized names and reject invalid ones
+    let created = run("return [tx.create_topic(' Audit '), tx.create_topic('audit')];").unwrap();
+    assert_eq!(Array::from(&created).to_vec(), vec![JsValue::TRUE, JsValue::FALSE]);
+    assert!(mq.get_topic_id("audit").is_some());
+    let err = run("tx.create_topic('bad name');").unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Invalid topic name: bad name"));
+    assert!(mq.get_topic_id("bad name").is_none());
+
+    // Imported names are normalized, and an invalid name rejects the snapshot
+    let state = js_sys::eval("({version: 1, topics: [{name: ' History ', capacity: 2, messages: []}]})").unwrap();
+    mq.import_state(state).unwrap();
+    assert!(mq.get_topic_id("history").is_some());
+    let state = js_sys::eval("({version: 1, topics: [{name: 'bad name', capacity: 2, messages: []}]})").unwrap();
+    assert!(mq.import_state(state).is_err());
+    assert!(mq.get_topic_id("bad name").is_none());
+
+    // Migrations normalize both names and validate the new one
+    let audit = mq.get_topic_id("audit").unwrap();
+    assert!(mq.migrate_topic("audit", "bad name").is_err());
+    assert_eq!(mq.migrate_topic(" AUDIT ", "Audit-Log").unwrap(), audit);
+    assert_eq!(mq.get_topic_name(audit).as_deref(), Some("audit-log"));
+}
+
+#[wasm_bindgen_test]
+fn subscriber_limit_and_unique_subscriptions() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("leaky").unwrap();
+    let received = Array::new();
+    let callback = recorder(&received);
+
+    let first = mq.subscribe_unique(topic, callback.clone()).unwrap();
+    assert_eq!(mq.subscribe_unique(topic, callback.clone()).unwrap(), first);
+    assert_eq!(mq.find_subscription(topic, &callback), Some(first));
+    assert_eq!(mq.find_subscription(topic, &recorder(&received)), None);
+    assert_eq!(mq.subscriber_count(topic), 1);
+
+    mq.set_max_subscribers(topic, Some(2)).unwrap();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    let err = mq.subscribe(topic, recorder(&received)).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Subscriber limit reached"));
+    // An already subscribed callback is not rejected
+    assert_eq!(mq.subscribe_unique(topic, callback.clone()).unwrap(), first);
+    assert!(mq.subscribe_unique(topic, recorder(&received)).is_err());
+
+    let rejected = |stats: JsValue| js_sys::Reflect::get(&stats, &"rejected_subscribes".into()).unwrap().as_f64();
+    assert_eq!(rejected(mq.get_topic_stats(topic).unwrap()), Some(2.0));
+    assert_eq!(rejected(mq.get_stats().unwrap()), Some(2.0));
+
+    mq.set_max_subscribers(topic, None).unwrap();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+    assert_eq!(mq.subscriber_count(topic), 3);
+    assert!(mq.set_max_subscribers(9999, Some(1)).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn subscribe_once_async_respects_the_subscriber_limit() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("limited").unwrap();
+    mq.set_max_subscribers(topic, Some(1)).unwrap();
+    mq.subscribe(topic, Function::new_no_args("")).unwrap();
+
+    let err = wasm_bindgen_futures::JsFuture::from(mq.subscribe_once_async(topic)).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Subscriber limit reached"));
+    let stats = mq.get_topic_stats(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&stats, &"rejected_subscribes".into()).unwrap().as_f64(), Some(1.0));
+    assert_eq!(mq.subscriber_count(topic), 1);
+}
+
+#[wasm_bindgen_test]
+fn streams_respect_the_subscriber_limit() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("limited").unwrap();
+    mq.set_max_subscribers(topic, Some(1)).unwrap();
+    let _stream = mq.stream(topic, None).unwrap();
+
+    let err = mq.stream(topic, None).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Subscriber limit reached"));
+    let stats = mq.get_topic_stats(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&stats, &"rejected_subscribes".into()).unwrap().as_f64(), Some(1.0));
+    assert_eq!(mq.subscriber_count(topic), 1);
+}
+
+#[wasm_bindgen_test]
+fn readable_streams_respect_the_subscriber_limit() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("limited").unwrap();
+    mq.set_max_subscribers(topic, Some(1)).unwrap();
+    let _stream = mq.readable_stream(topic, None).unwrap();
+
+    let err = mq.readable_stream(topic, None).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Subscriber limit reached"));
+    let stats = mq.get_topic_stats(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&stats, &"rejected_subscribes".into()).unwrap().as_f64(), Some(1.0));
+    assert_eq!(mq.subscriber_count(topic), 1);
+    assert!(mq.readable_stream(9999, None).is_err());
+}
+
+#[wasm_bindgen_test]
+fn unsubscribe_by_callback_reference() {
+    let mq = MessageQueue::new(None).unwrap();
+    let a = mq.register_topic("a").unwrap();
+    let b = mq.register_topic("b").unwrap();
+    let received = Array::new();
+    let callback = recorder(&received);
+    let other = recorder(&received);
+
+    mq.subscribe(a, callback.clone()).unwrap();
+    mq.subscribe(a, callback.clone()).unwrap();
+    mq.subscribe(a, other.clone()).unwrap();
+    mq.subscribe(b, callback.clone()).unwrap();
+
+    // A callback subscribed twice takes two calls to remove
+    assert!(mq.unsubscribe_callback(a, &callback));
+    assert_eq!(mq.subscriber_count(a), 2);
+    assert!(mq.unsubscribe_callback(a, &callback));
+    assert!(!mq.unsubscribe_callback(a, &callback));
+    assert_eq!(mq.subscriber_count(a), 1);
+    assert!(!mq.unsubscribe_callback(9999, &callback));
+
+    mq.subscribe(a, callback.clone()).unwrap();
+    mq.subscribe(b, callback.clone()).unwrap();
+    assert_eq!(mq.unsubscribe_callback_all_topics(&callback), 3);
+    assert_eq!(mq.unsubscribe_callback_all_topics(&callback), 0);
+    mq.publish(a, JsValue::from(1)).unwrap();
+    mq.publish(b, JsValue::from(2)).unwrap();
+    assert_eq!(received.to_vec(), vec![JsValue::from(1)]);
+}
+
+#[wasm_bindgen_test]
+fn signal_ends_subscriptions_and_queue() {
+    let controller = || js_sys::eval("new AbortController()").unwrap();
+    let signal = |controller: &JsValue| -> web_sys::AbortSignal {
+        js_sys::Reflect::get(controller, &"signal".into()).unwrap().unchecked_into()
+    };
+    let abort = |controller: &JsValue| {
+        let abort: Function = js_sys::Reflect::get(controller, &"abort".into()).unwrap().unchecked_into();
+        abort.call0(controller).unwrap();
+    };
+
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("mounted").unwrap();
+    let received = Array::new();
+    let unmount = controller();
+    mq.subscribe_with_signal(topic, recorder(&received), signal(&unmount)).unwrap();
+    let manual = controller();
+    let manual_id = mq.subscribe_with_signal(topic, recorder(&received), signal(&manual)).unwrap();
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    assert_eq!(received.length(), 2);
+
+    abort(&unmount);
+    assert_eq!(mq.subscriber_count(topic), 1);
+    // Unsubscribing first makes a later abort a no-op
+    assert!(mq.unsubscribe(topic, manual_id));
+    abort(&manual);
+    mq.publish(topic, JsValue::from(2)).unwrap();
+    assert_eq!(received.length(), 2);
+
+    let err = mq.subscribe_with_signal(topic, recorder(&received), signal(&unmount)).unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Signal is already aborted"));
+    assert_eq!(mq.subscriber_count(topic), 0);
+
+    // A queue-wide signal closes the queue
+    let app = controller();
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"signal".into(), &signal(&app)).unwrap();
+    let scoped = MessageQueue::with_options(options.clone().into()).unwrap();
+    assert!(!scoped.is_closed());
+    abort(&app);
+    assert!(scoped.is_closed());
+    assert!(MessageQueue::with_options(options.into()).is_err());
+    let invalid = js_sys::eval("({signal: 1})").unwrap();
+    assert!(MessageQueue::with_options(invalid).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn signal_aborted_under_the_borrow_still_ends_the_subscription() {
+    let controller = js_sys::eval("new AbortController()").unwrap();
+    let signal: web_sys::AbortSignal = js_sys::Reflect::get(&controller, &"signal".into()).unwrap().unchecked_into();
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("mounted").unwrap();
+    mq.subscribe_with_signal(topic, recorder(&Array::new()), signal).unwrap();
+
+    // The validator runs while the queue is borrowed, so the removal is deferred
+    let abort = Function::new_with_args("name", "this.abort(); return true;").bind0(&controller);
+    mq.set_topic_name_validator(Some(abort));
+    mq.register_topic("other").unwrap();
+    assert_eq!(mq.subscriber_count(topic), 1);
+    sleep(10).await;
+    assert_eq!(mq.subscriber_count(topic), 0);
+}
+
+#[wasm_bindgen_test]
+async fn scopes_share_a_channel_with_separate_namespaces() {
+    let mut sender = MessageQueue::new(Some("scopes".to_string())).unwrap();
+    let receiver = MessageQueue::new(Some("scopes".to_string())).unwrap();
+    let editor = sender.scope("editor/").unwrap();
+    let chat = sender.scope("chat/").unwrap();
+    assert!(sender.scope("").is_err());
+
+    let editor_events = editor.register_topic("events").unwrap();
+    let chat_events = chat.register_topic("events").unwrap();
+    assert_ne!(editor_events, chat_events);
+    assert_eq!(sender.get_topic_id("editor/events"), Some(editor_events));
+    assert_eq!(editor.get_topic_name(editor_events).as_deref(), Some("events"));
+
+    // Scopes cannot see or use each other's topics
+    let names = |list: Array| -> Vec<String> {
+        list.iter().map(|info| js_sys::Reflect::get(&info, &"name".into()).unwrap().as_string().unwrap()).collect()
+    };
+    assert_eq!(names(editor.list_topics().unwrap()), vec!["events"]);
+    assert!(chat.topic_info(editor_events).unwrap().is_undefined());
+    assert!(chat.publish(editor_events, JsValue::NULL).is_err());
+    assert!(chat.subscribe(editor_events, Function::new_no_args("")).is_err());
+    assert!(!chat.destroy_topic(editor_events));
+
+    // The prefixed name is used between tabs
+    let remote = receiver.scope("editor/").unwrap();
+    let received = Array::new();
+    remote.subscribe(remote.register_topic("events").unwrap(), recorder(&received)).unwrap();
+    editor.publish_by_name("events", JsValue::from(1)).unwrap();
+    sleep(50).await;
+    assert_eq!(received.to_vec(), vec![JsValue::from(1)]);
+
+    // Closing a scope destroys its topics only
+    editor.close();
+    assert!(editor.is_closed());
+    assert!(!sender.has_topic(editor_events));
+    assert!(sender.has_topic(chat_events));
+    assert!(editor.register_topic("again").is_err());
+
+    sender.close().unwrap();
+    assert!(chat.is_closed());
+    assert!(chat.register_topic("again").is_err());
+}
+
+#[wasm_bindgen_test]
+fn scopes_buffer_and_subscribe_like_the_queue() {
+    let mq = MessageQueue::new(None).unwrap();
+    let editor = mq.scope("editor.").unwrap();
+    assert_eq!(editor.prefix(), "editor.");
+    let chat = mq.scope("chat/").unwrap();
+    let topic = editor.register_topic("events").unwrap();
+    assert_eq!(mq.get_topic_id("editor.events"), Some(topic));
+
+    editor.enable_topic_buffer(topic, Some(2)).unwrap();
+    let once = Array::new();
+    let even = Array::new();
+    editor.subscribe_once(topic, recorder(&once)).unwrap();
+    let is_even = Function::new_with_args("payload", "return payload % 2 === 0");
+    editor.subscribe_filtered(topic, is_even, recorder(&even)).unwrap();
+    for i in 1..=3 {
+        editor.publish(topic, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(once.to_vec(), vec![JsValue::from(1)]);
+    assert_eq!(even.to_vec(), vec![JsValue::from(2)]);
+
+    // The buffer keeps the newest messages, named with the full topic name
+    assert_eq!(editor.get_buffer_size(topic), 2);
+    let buffered = editor.get_buffered_messages(topic).unwrap();
+    let field = |i: u32, key: &str| js_sys::Reflect::get(&buffered.get(i), &key.into()).unwrap();
+    assert_eq!(field(0, "payload"), JsValue::from(2));
+    assert_eq!(field(1, "topic"), JsValue::from_str("editor.events"));
+
+    // Other scopes cannot touch the buffer
+    assert!(chat.enable_topic_buffer(topic, None).is_err());
+    assert!(chat.get_buffered_messages(topic).is_err());
+    assert_eq!(chat.clear_buffer(topic), 0);
+    assert_eq!(editor.clear_buffer(topic), 2);
+    editor.disable_topic_buffer(topic).unwrap();
+    assert_eq!(editor.get_buffer_size(topic), -1);
+}
+
+#[wasm_bindgen_test]
+fn namespaces_publish_under_their_prefix() {
+    let mq = MessageQueue::new(None).unwrap();
+    let editor = mq.create_namespace("editor");
+    let chat = mq.create_namespace("chat");
+    assert_eq!(editor.prefix(), "editor");
+    let topic = editor.register_topic("events").unwrap();
+    assert_eq!(editor.get_namespaced_topic_id("events"), Some(topic));
+    assert_eq!(mq.get_topic_id("editor.events"), Some(topic));
+
+    let received = Array::new();
+    editor.subscribe(topic, recorder(&received)).unwrap();
+    editor.publish_by_name("events", JsValue::from(1)).unwrap();
+    assert_eq!(received.to_vec(), vec![JsValue::from(1)]);
+
+    // Topics of another namespace are off limits
+    assert!(chat.publish(topic, JsValue::from(2)).is_err());
+    assert!(chat.subscribe(topic, recorder(&received)).is_err());
+    assert_eq!(received.length(), 1);
+}
+
+#[wasm_bindgen_test]
+fn skip_n_subscriber_starts_after_n_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("skip").unwrap();
+    let skipped = Array::new();
+    let all = Array::new();
+    mq.subscribe_skip_n(topic, 2, recorder(&skipped)).unwrap();
+    mq.subscribe(topic, recorder(&all)).unwrap();
+
+    for i in 1..=4 {
+        mq.publish(topic, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(skipped.to_vec(), vec![JsValue::from(3), JsValue::from(4)]);
+    assert_eq!(all.length(), 4);
+}
+
+#[wasm_bindgen_test]
+fn batch_buffer_calls_report_per_topic() {
+    let mq = MessageQueue::new(None).unwrap();
+    let a = mq.register_topic("a").unwrap();
+    let b = mq.register_topic("b").unwrap();
+    let plain = mq.register_topic("plain").unwrap();
+
+    let enabled = mq.batch_enable_topic_buffer(js_sys::Uint32Array::from(&[a, 999, b][..]), 2);
+    assert_eq!(enabled.to_vec(), vec![1, 0, 1]);
+    assert_eq!(mq.batch_enable_topic_buffer(js_sys::Uint32Array::from(&[plain][..]), 0).to_vec(), vec![0]);
+
+    for i in 0..3 {
+        mq.publish(a, JsValue::from(i)).unwrap();
+    }
+    mq.publish(b, JsValue::NULL).unwrap();
+    let sizes = mq.batch_get_buffer_sizes(js_sys::Uint32Array::from(&[a, b, plain, 999][..]));
+    assert_eq!(sizes.to_vec(), vec![2, 1, -1, -1]);
+}
+
+#[wasm_bindgen_test]
+fn topic_logging_logs_unobserved_messages() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("logged").unwrap();
+    assert!(mq.enable_topic_logging(999, "missing").is_err());
+    mq.enable_topic_logging(topic, "orders").unwrap();
+
+    // Capture console.log while publishing to a topic nobody subscribes to
+    let console = js_sys::Reflect::get(&js_sys::global(), &"console".into()).unwrap();
+    let original = js_sys::Reflect::get(&console, &"log".into()).unwrap();
+    let logged = Array::new();
+    let capture = Function::new_with_args("label, topic_id, payload", "this.push([label, topic_id, payload]);").bind0(&logged);
+    js_sys::Reflect::set(&console, &"log".into(), &capture).unwrap();
+    mq.publish(topic, JsValue::from(7)).unwrap();
+    mq.disable_topic_logging(topic).unwrap();
+    mq.publish(topic, JsValue::from(8)).unwrap();
+    js_sys::Reflect::set(&console, &"log".into(), &original).unwrap();
+
+    if cfg!(feature = "console-logging") {
+        let expected = Array::of3(&"orders".into(), &JsValue::from(topic), &JsValue::from(7));
+        assert_eq!(logged.length(), 1);
+        assert_eq!(Array::from(&logged.get(0)).to_vec(), expected.to_vec());
+    } else {
+        assert_eq!(logged.length(), 0);
+    }
+}
+
+#[wasm_bindgen_test]
+async fn subscribe_once_async_resolves_with_the_next_message() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("once-async").unwrap();
+    let next = mq.subscribe_once_async(topic);
+    assert_eq!(mq.subscriber_count(topic), 1);
+
+    mq.publish(topic, JsValue::from_str("first")).unwrap();
+    mq.publish(topic, JsValue::from_str("second")).unwrap();
+    let message = wasm_bindgen_futures::JsFuture::from(next).await.unwrap();
+    let field = |key: &str| js_sys::Reflect::get(&message, &key.into()).unwrap();
+    assert_eq!(field("payload"), JsValue::from_str("first"));
+    assert_eq!(field("topic_id"), JsValue::from(topic));
+    assert!(field("id").as_f64().is_some());
+    assert!(field("timestamp").as_f64().is_some());
+    assert_eq!(mq.subscriber_count(topic), 0);
+
+    // Destroying the topic first rejects the promise
+    let pending = mq.subscribe_once_async(topic);
+    mq.destroy_topic(topic);
+    let err = wasm_bindgen_futures::JsFuture::from(pending).await.unwrap_err();
+    assert_eq!(err.as_string().as_deref(), Some("Topic destroyed"));
+    assert!(wasm_bindgen_futures::JsFuture::from(mq.subscribe_once_async(topic)).await.is_err());
+}
+
+#[wasm_bindgen_test]
+fn replay_subject_replays_its_latest_messages_to_each_subscriber() {
+    let mq = MessageQueue::new(None).unwrap();
+    assert!(mq.create_replay_subject("empty", 0).is_err());
+    let topic = mq.create_replay_subject("prices", 2).unwrap();
+    for i in 1..=3 {
+        mq.publish(topic, JsValue::from(i)).unwrap();
+    }
+
+    // Plain subscribers get the last two messages, then live ones
+    let early = Array::new();
+    let late = Array::new();
+    mq.subscribe(topic, recorder(&early)).unwrap();
+    mq.publish(topic, JsValue::from(4)).unwrap();
+    mq.subscribe(topic, recorder(&late)).unwrap();
+    assert_eq!(early.to_vec(), vec![JsValue::from(2), JsValue::from(3), JsValue::from(4)]);
+    assert_eq!(late.to_vec(), vec![JsValue::from(3), JsValue::from(4)]);
+
+    let metadata = mq.get_topic_metadata(topic).unwrap();
+    assert_eq!(js_sys::Reflect::get(&metadata, &"is_replay_subject".into()).unwrap(), JsValue::TRUE);
+}
+
+#[wasm_bindgen_test]
+fn topic_metadata_describes_the_topic() {
+    let mq = MessageQueue::new(None).unwrap();
+    assert!(mq.get_topic_metadata(999).is_err());
+    let topic = mq.register_topic("described").unwrap();
+    let field = |key: &str| js_sys::Reflect::get(&mq.get_topic_metadata(topic).unwrap(), &key.into()).unwrap();
+
+    assert_eq!(field("id"), JsValue::from(topic));
+    assert_eq!(field("name"), JsValue::from_str("described"));
+    assert_eq!(field("has_buffer"), JsValue::FALSE);
+    assert!(field("buffer_size").is_undefined());
+    assert!(field("last_message_time").is_undefined());
+    assert!(field("tags").is_undefined());
+
+    mq.enable_topic_buffer(topic, Some(1)).unwrap();
+    mq.subscribe(topic, Function::new_no_args("")).unwrap();
+    mq.add_topic_tag(topic, "ui").unwrap();
+    mq.publish(topic, JsValue::from(1)).unwrap();
+    mq.publish(topic, JsValue::from(2)).unwrap();
+
+    assert_eq!(field("subscriber_count").as_f64(), Some(1.0));
+    assert_eq!(field("has_buffer"), JsValue::TRUE);
+    assert_eq!(field("buffer_size").as_f64(), Some(1.0));
+    assert_eq!(field("buffer_capacity").as_f64(), Some(1.0));
+    assert_eq!(field("overflow_count").as_f64(), Some(1.0));
+    assert_eq!(field("total_dispatched").as_f64(), Some(2.0));
+    assert!(field("last_message_time").as_f64().is_some());
+    assert_eq!(field("is_replay_subject"), JsValue::FALSE);
+    assert_eq!(Array::from(&field("tags")).to_vec(), vec![JsValue::from_str("ui")]);
+}
+
+#[wasm_bindgen_test]
+fn topic_tags_can_be_queried_and_removed() {
+    let mq = MessageQueue::new(None).unwrap();
+    let a = mq.register_topic("a").unwrap();
+    let b = mq.register_topic("b").unwrap();
+    assert!(mq.add_topic_tag(999, "ui").is_err());
+
+    mq.add_topic_tag(a, "ui").unwrap();
+    mq.add_topic_tag(a, "ui").unwrap();
+    mq.add_topic_tag(a, "debug").unwrap();
+    mq.add_topic_tag(b, "ui").unwrap();
+    assert!(mq.has_topic_tag(a, "debug"));
+    assert!(!mq.has_topic_tag(b, "debug"));
+    assert_eq!(mq.get_topic_tags(a).to_vec(), vec![JsValue::from_str("ui"), JsValue::from_str("debug")]);
+    assert_eq!(mq.find_topics_by_tag("ui").to_vec(), vec![a, b]);
+
+    assert!(mq.remove_topic_tag(a, "ui"));
+    assert!(!mq.remove_topic_tag(a, "ui"));
+    assert_eq!(mq.find_topics_by_tag("ui").to_vec(), vec![b]);
+
+    // Destroyed topics leave the index
+    assert_eq!(mq.destroy_topics_by_tag("ui"), 1);
+    assert_eq!(mq.find_topics_by_tag("ui").length(), 0);
+    assert_eq!(mq.get_topic_tags(b).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn cloned_queue_keeps_topics_but_not_subscribers() {
+    let mq = MessageQueue::new(None).unwrap();
+    let buffered = mq.register_topic("buffered").unwrap();
+    let destroyed = mq.register_topic("destroyed").unwrap();
+    let plain = mq.register_topic("plain").unwrap();
+    mq.destroy_topic(destroyed);
+    mq.enable_topic_buffer(buffered, Some(4)).unwrap();
+    let received = Array::new();
+    mq.subscribe(buffered, recorder(&received)).unwrap();
+    mq.publish(buffered, JsValue::from(1)).unwrap();
+
+    let clone = mq.clone_message_queue(None).unwrap();
+    assert_ne!(clone.get_client_id(), mq.get_client_id());
+    assert_eq!(clone.get_topic_id("buffered"), Some(buffered));
+    assert_eq!(clone.get_topic_id("plain"), Some(plain));
+    assert!(!clone.has_topic(destroyed));
+    assert_eq!(clone.get_buffer_capacity(buffered), 4);
+    assert_eq!(clone.get_buffer_size(buffered), 0);
+    assert_eq!(clone.subscriber_count(buffered), 0);
+
+    // The queues no longer share state
+    clone.publish(buffered, JsValue::from(2)).unwrap();
+    assert_eq!(received.length(), 1);
+    assert_eq!(mq.get_buffer_size(buffered), 1);
+    assert_eq!(mq.clone_message_queue(Some(true)).unwrap().get_buffer_size(buffered), 1);
+}
+
+#[wasm_bindgen_test]
+async fn broadcast_interceptors_drop_or_replace_incoming_messages() {
+    let sender = MessageQueue::new(Some("intercept".to_string())).unwrap();
+    let receiver = MessageQueue::new(Some("intercept".to_string())).unwrap();
+    let topic = sender.register_topic("secrets").unwrap();
+    let received = Array::new();
+    receiver.subscribe(receiver.register_topic("secrets").unwrap(), recorder(&received)).unwrap();
+
+    let interceptor = Function::new_with_args("msg", "
+        if (msg.payload === 'blocked') return false;
+        if (msg.payload === 'sealed') return Object.assign({}, msg, {payload: 'opened'});
+    ");
+    let handle = receiver.intercept_broadcast(interceptor);
+    for payload in ["plain", "blocked", "sealed"] {
+        sender.publish(topic, JsValue::from_str(payload)).unwrap();
+    }
+    sleep(50).await;
+    assert_eq!(received.to_vec(), vec![JsValue::from_str("plain"), JsValue::from_str("opened")]);
+
+    assert!(receiver.remove_broadcast_interceptor(handle));
+    assert!(!receiver.remove_broadcast_interceptor(handle));
+    sender.publish(topic, JsValue::from_str("blocked")).unwrap();
+    sleep(50).await;
+    assert_eq!(received.get(2), JsValue::from_str("blocked"));
+}
+
+#[wasm_bindgen_test]
+fn subscriber_callback_is_the_registered_function() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("callbacks").unwrap();
+    let callback = Function::new_no_args("");
+    let sub_id = mq.subscribe(topic, callback.clone()).unwrap();
+
+    let stored = mq.get_subscriber_callback(topic, sub_id).unwrap();
+    assert!(js_sys::Object::is(&stored, &callback));
+    assert_eq!(
+        mq.get_subscriber_callback(topic, sub_id + 1).unwrap_err().as_string().as_deref(),
+        Some("Invalid subscriber ID"),
+    );
+    assert!(mq.get_subscriber_callback(999, sub_id).is_err());
+
+    mq.unsubscribe(topic, sub_id);
+    assert!(mq.get_subscriber_callback(topic, sub_id).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn silent_publish_only_fills_the_buffer() {
+    let mq = MessageQueue::new(Some("silent".to_string())).unwrap();
+    let other = MessageQueue::new(Some("silent".to_string())).unwrap();
+    let topic = mq.register_topic("history").unwrap();
+    let unbuffered = mq.register_topic("unbuffered").unwrap();
+    mq.enable_topic_buffer(topic, Some(8)).unwrap();
+    let live = Array::new();
+    let remote = Array::new();
+    mq.subscribe(topic, recorder(&live)).unwrap();
+    other.subscribe(other.register_topic("history").unwrap(), recorder(&remote)).unwrap();
+
+    for i in 1..=3 {
+        mq.publish_silent(topic, JsValue::from(i)).unwrap();
+    }
+    mq.publish_silent(unbuffered, JsValue::NULL).unwrap();
+    assert!(mq.publish_silent(999, JsValue::NULL).is_err());
+    sleep(50).await;
+    assert_eq!(live.length(), 0);
+    assert_eq!(remote.length(), 0);
+    assert_eq!(mq.get_buffer_size(topic), 3);
+    assert_eq!(mq.get_buffer_size(unbuffered), -1);
+
+    // The history is replayed on demand
+    let replayed = Array::new();
+    mq.subscribe_with_replay(topic, recorder(&replayed), None).unwrap();
+    assert_eq!(replayed.to_vec(), vec![JsValue::from(1), JsValue::from(2), JsValue::from(3)]);
+}
+
+#[wasm_bindgen_test]
+fn atomic_publish_subscribe_delivers_the_trigger_first() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("handshake").unwrap();
+    assert!(mq.atomic_publish_subscribe(999, JsValue::NULL, Function::new_no_args("")).is_err());
+    let existing = Array::new();
+    mq.subscribe(topic, recorder(&existing)).unwrap();
+
+    // The new subscriber sees its own trigger, then later messages
+    let received = Array::new();
+    let sub_id = mq.atomic_publish_subscribe(topic, JsValue::from_str("ping"), recorder(&received)).unwrap();
+    mq.publish(topic, JsValue::from_str("pong")).unwrap();
+    assert_eq!(received.to_vec(), vec![JsValue::from_str("ping"), JsValue::from_str("pong")]);
+    assert_eq!(existing.to_vec(), received.to_vec());
+
+    assert!(mq.unsubscribe(topic, sub_id));
+    assert_eq!(mq.subscriber_count(topic), 1);
+}
+
+#[wasm_bindgen_test]
+fn publish_event_sends_a_plain_description() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("dom").unwrap();
+    let received = Array::new();
+    mq.subscribe(topic, recorder(&received)).unwrap();
+
+    let target = web_sys::EventTarget::new().unwrap();
+    let event = web_sys::Event::new("custom").unwrap();
+    target.dispatch_event(&event).unwrap();
+    mq.publish_event(topic, event.clone()).unwrap();
+
+    let payload = received.get(0);
+    let field = |key: &str| js_sys::Reflect::get(&payload, &key.into()).unwrap();
+    assert_eq!(field("type"), JsValue::from_str("custom"));
+    assert!(js_sys::Object::is(&field("target"), &target));
+    assert_eq!(field("timeStamp").as_f64(), Some(event.time_stamp()));
+    assert!(!payload.is_instance_of::<web_sys::Event>());
+}
+
+#[wasm_bindgen_test]
+fn service_worker_forwarding_round_trips() {
+    let sender = MessageQueue::new(None).unwrap();
+    let receiver = MessageQueue::new(None).unwrap();
+    let topic = sender.register_topic("sw").unwrap();
+    let received = Array::new();
+    receiver.subscribe(receiver.register_topic("sw").unwrap(), recorder(&received)).unwrap();
+
+    // Stand-in for a ServiceWorker: only postMessage is used
+    let posted = Array::new();
+    let sw = js_sys::Object::new();
+    js_sys::Reflect::set(&sw, &"postMessage".into(), &Function::new_with_args("msg", "this.push(msg);").bind0(&posted)).unwrap();
+    let sub_id = sender.forward_to_service_worker(sw.unchecked_into(), topic).unwrap();
+    let own = Array::new();
+    sender.subscribe(topic, recorder(&own)).unwrap();
+
+    sender.publish(topic, JsValue::from_str("hello")).unwrap();
+    assert_eq!(posted.length(), 1);
+    let forwarded = posted.get(0);
+    assert_eq!(js_sys::Reflect::get(&forwarded, &"topic".into()).unwrap(), JsValue::from_str("sw"));
+    assert_eq!(js_sys::Reflect::get(&forwarded, &"origin_id".into()).unwrap(), JsValue::from_str(&sender.get_client_id()));
+
+    // The worker relays it to another client; duplicates and the sender's own copy are ignored
+    receiver.receive_from_service_worker(forwarded.clone()).unwrap();
+    receiver.receive_from_service_worker(Array::of2(&JsValue::from(0), &forwarded).into()).unwrap();
+    sender.receive_from_service_worker(forwarded.clone()).unwrap();
+    assert_eq!(received.to_vec(), vec![JsValue::from_str("hello")]);
+    assert_eq!(own.length(), 1);
+    assert!(receiver.receive_from_service_worker(Array::of2(&JsValue::from(3), &forwarded).into()).is_err());
+
+    assert!(sender.unsubscribe(topic, sub_id));
+    sender.publish(topic, JsValue::NULL).unwrap();
+    assert_eq!(posted.length(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn broadcast_throttle_holds_messages_for_the_interval() {
+    let sender = MessageQueue::new(Some("throttle".to_string())).unwrap();
+    let receiver = MessageQueue::new(Some("throttle".to_string())).unwrap();
+    let topic = sender.register_topic("ticks").unwrap();
+    let received = Array::new();
+    receiver.subscribe(receiver.register_topic("ticks").unwrap(), recorder(&received)).unwrap();
+    assert!(sender.set_broadcast_throttle(-1.0).is_err());
+
+    sender.set_broadcast_throttle(200.0).unwrap();
+    for i in 0..5 {
+        sender.publish(topic, JsValue::from(i)).unwrap();
+    }
+    sleep(50).await;
+    assert_eq!(received.length(), 0);
+    sleep(250).await;
+    let expected: Vec<JsValue> = (0..5).map(JsValue::from).collect();
+    assert_eq!(received.to_vec(), expected);
+
+    // Held messages can be flushed early, and disabling the throttle flushes too
+    sender.publish(topic, JsValue::from(5)).unwrap();
+    assert_eq!(sender.flush_broadcast_queue().unwrap(), 1);
+    assert_eq!(sender.flush_broadcast_queue().unwrap(), 0);
+    sender.publish(topic, JsValue::from(6)).unwrap();
+    sender.set_broadcast_throttle(0.0).unwrap();
+    sleep(50).await;
+    assert_eq!(received.length(), 7);
+}
+
+#[wasm_bindgen_test]
+async fn idle_subscriber_is_called_later_in_order() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("analytics").unwrap();
+    let idle = Array::new();
+    let live = Array::new();
+    mq.subscribe_on_idle(topic, recorder(&idle)).unwrap();
+    mq.subscribe(topic, recorder(&live)).unwrap();
+
+    for i in 0..3 {
+        mq.publish(topic, JsValue::from(i)).unwrap();
+    }
+    assert_eq!(live.length(), 3);
+    assert_eq!(idle.length(), 0);
+    sleep(200).await;
+    assert_eq!(idle.to_vec(), live.to_vec());
+
+    // Unsubscribing drops messages still waiting for idle time
+    let dropped = Array::new();
+    let sub_id = mq.subscribe_on_idle(topic, recorder(&dropped)).unwrap();
+    mq.publish(topic, JsValue::from(3)).unwrap();
+    assert!(mq.unsubscribe(topic, sub_id));
+    sleep(200).await;
+    assert_eq!(dropped.length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn wasm_logging_reports_topic_lifecycle_at_info_level() {
+    let mq = MessageQueue::new(None).unwrap();
+    assert!(mq.enable_wasm_logging(5).is_err());
+
+    // Capture the console groups opened while creating topics
+    let console = js_sys::Reflect::get(&js_sys::global(), &"console".into()).unwrap();
+    let original = js_sys::Reflect::get(&console, &"groupCollapsed".into()).unwrap();
+    let groups = Array::new();
+    let capture = Function::new_with_args("title", "this.push(title);").bind0(&groups);
+    js_sys::Reflect::set(&console, &"groupCollapsed".into(), &capture).unwrap();
+    mq.register_topic("quiet").unwrap();
+    mq.enable_wasm_logging(3).unwrap();
+    let topic = mq.register_topic("loud").unwrap();
+    mq.destroy_topic(topic);
+    mq.enable_wasm_logging(0).unwrap();
+    mq.register_topic("quiet-again").unwrap();
+    js_sys::Reflect::set(&console, &"groupCollapsed".into(), &original).unwrap();
+
+    let titles: Vec<String> = groups.iter().filter_map(|title| title.as_string()).collect();
+    if cfg!(feature = "wasm-logging") {
+        assert_eq!(titles, vec!["[wasm-ripple:info] Topic created", "[wasm-ripple:info] Topic destroyed"]);
+    } else {
+        assert!(titles.is_empty());
+    }
+}
+
+#[wasm_bindgen_test]
+async fn pending_async_publishes_are_counted_until_settled() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("async-count").unwrap();
+    let doomed = mq.register_topic("doomed").unwrap();
+    assert_eq!(mq.get_pending_async_publishes(), 0);
+
+    let ok = mq.publish_with_priority(topic, JsValue::from(1), 128).unwrap();
+    let failing = mq.publish_with_priority(doomed, JsValue::from(2), 128).unwrap();
+    mq.destroy_topic(doomed);
+    assert_eq!(mq.get_pending_async_publishes(), 2);
+    assert_eq!(mq.pending_count(), 2);
+
+    // Failed publishes are no longer pending either
+    wasm_bindgen_futures::JsFuture::from(ok).await.unwrap();
+    assert!(wasm_bindgen_futures::JsFuture::from(failing).await.is_err());
+    assert_eq!(mq.get_pending_async_publishes(), 0);
+}
+
+#[wasm_bindgen_test]
+fn raw_subscriber_receives_one_message_object() {
+    let mq = MessageQueue::new(None).unwrap();
+    let topic = mq.register_topic("raw").unwrap();
+    let messages = Array::new();
+    let arg_counts = Array::new();
+    let callback = Function::new_with_args("msg", "this[0].push(msg); this[1].push(arguments.length);")
+        .bind0(&Array::of2(&messages, &arg_counts));
+    mq.subscribe_raw(topic, callback).unwrap();
+
+    mq.publish(topic, JsValue::from_str("plain")).unwrap();
+    let headers = js_sys::Object::new();
+    js_sys::Reflect::set(&headers, &"trace".into(), &"abc".into()).unwrap();
+    mq.publish_with_headers(topic, JsValue::from_str("traced"), headers.clone().into()).unwrap();
+
+    assert_eq!(arg_counts.to_vec(), vec![JsValue::from(1), JsValue::from(1)]);
+    let field = |i: u32, key: &str| js_sys::Reflect::get(&messages.get(i), &key.into()).unwrap();
+    assert_eq!(field(0, "payload"), JsValue::from_str("plain"));
+    assert_eq!(field(0, "topic_id"), JsValue::from(topic));
+    assert_eq!(field(0, "topic"), JsValue::from_str("raw"));
+    assert_eq!(field(0, "origin_id"), JsValue::from_str(&mq.get_client_id()));
+    assert!(field(0, "timestamp").as_f64().is_some());
+    assert!(field(0, "headers").is_undefined());
+    assert!(js_sys::Object::is(&field(1, "headers"), &headers));
+    assert_ne!(field(0, "id"), field(1, "id"));
+}
+
+#[wasm_bindgen_test]
+fn topic_buffer_is_copied_to_the_new_topic() {
+    let mq = MessageQueue::new(None).unwrap();
+    let old = mq.register_topic("orders-v1").unwrap();
+    let new = mq.register_topic("orders-v2").unwrap();
+    let plain = mq.register_topic("plain").unwrap();
+    mq.enable_topic_buffer(old, Some(4)).unwrap();
+    mq.enable_topic_buffer(new, Some(2)).unwrap();
+    for i in 1..=3 {
+        mq.publish(old, JsValue::from(i)).unwrap();
+    }
+
+    assert!(mq.clone_topic_buffer(old, old).is_err());
+    assert!(mq.clone_topic_buffer(plain, new).is_err());
+    assert!(mq.clone_topic_buffer(old, plain).is_err());
+    assert_eq!(mq.clone_topic_buffer(old, new).unwrap(), 3);
+
+    // The destination grew to fit, and replays the copies as its own messages
+    assert_eq!(mq.get_buffer_capacity(new), 4);
+    assert_eq!(mq.get_buffer_size(old), 3);
+    let replayed = Array::new();
+    let topic_ids = Array::new();
+    mq.subscribe_with_replay(new, recorder(&replayed), None).unwrap();
+    mq.subscribe_with_replay(new, Function::new_with_args("payload, topic_id", "this.push(topic_id);").bind0(&topic_ids), Some(1)).unwrap();
+    assert_eq!(replayed.to_vec(), vec![JsValue::from(1), JsValue::from(2), JsValue::from(3)]);
+    assert_eq!(topic_ids.to_vec(), vec![JsValue::from(new)]);
+}
+
+#[wasm_bindgen_test]
+fn dispatch_depth_is_tracked_and_limited() {
+    let mq = Rc::new(MessageQueue::new(None).unwrap());
+    let topic = mq.register_topic("recursive").unwrap();
+    let errors = Array::new();
+    mq.set_error_handler(Function::new_with_args("error", "this.push(error);").bind0(&errors));
+    mq.set_max_dispatch_depth(3);
+    assert_eq!(mq.get_dispatch_queue_depth(), 0);
+
+    // A subscriber that republishes whatever it receives, forever
+    let depths = Array::new();
+    let handle = mq.clone();
+    let seen = depths.clone();
+    let echo = Closure::<dyn FnMut(JsValue)>::new(move |payload: JsValue| {
+        seen.push(&JsValue::from(handle.get_dispatch_queue_depth() as u32));
+        handle.publish(topic, payload).unwrap();
+    });
+    mq.subscribe(topic, echo.as_ref().unchecked_ref::<Function>().clone()).unwrap();
+
+    mq.publish(topic, JsValue::NULL).unwrap();
+    assert_eq!(depths.to_vec(), vec![JsValue::from(1), JsValue::from(2), JsValue::from(3)]);
+    assert_eq!(errors.length(), 1);
+    assert_eq!(mq.get_dispatch_queue_depth(), 0);
+}
@@ -0,0 +1,29 @@
+//! Dedicated worker tests: no `window` is available in this scope
+//! Run with `wasm-pack test --headless --chrome` (or `--firefox`)
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Function};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::*;
+use wasm_ripple::MessageQueue;
+
+wasm_bindgen_test_configure!(run_in_dedicated_worker);
+
+#[wasm_bindgen_test]
+fn queue_works_without_window() {
+    assert!(js_sys::Reflect::get(&js_sys::global(), &"window".into()).unwrap().is_undefined());
+
+    let mq = MessageQueue::new(Some("worker-test".to_string())).unwrap();
+    assert_eq!(mq.get_client_id().len(), 36);
+
+    let topic = mq.register_topic("events").unwrap();
+    let received = Array::new();
+    let recorder = Function::new_with_args("payload", "this.push(payload);").bind0(&received);
+    mq.subscribe(topic, recorder).unwrap();
+
+    mq.publish(topic, JsValue::from_str("hello")).unwrap();
+
+    assert_eq!(received.length(), 1);
+    assert_eq!(received.get(0).as_string().as_deref(), Some("hello"));
+}